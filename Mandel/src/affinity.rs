@@ -0,0 +1,80 @@
+// Thread pinning
+// With --pin-threads, render thread i is bound to the i-th CPU the process may run on
+// (wrapping around), so its slice of the iteration loop stays in one core's caches.
+// With --avoid-smt only one hardware thread per physical core is used.
+
+use std::sync::OnceLock;
+
+static CPUS: OnceLock<Vec<usize>> = OnceLock::new();
+
+// Enables pinning for the rest of the process. Returns the CPUs threads will use.
+pub fn enable(avoid_smt: bool) -> Vec<usize> {
+  let mut cpus = allowed_cpus();
+  if avoid_smt {
+    cpus = physical_cores(&cpus);
+  }
+  CPUS.get_or_init(|| cpus).clone()
+}
+
+// Binds the calling thread to the CPU for pool thread `thread`, if pinning is enabled.
+pub fn pin(thread: usize) {
+  if let Some(cpus) = CPUS.get().filter(|cpus| !cpus.is_empty()) {
+    set_affinity(cpus[thread % cpus.len()]);
+  }
+}
+
+#[cfg(target_os = "linux")]
+fn allowed_cpus() -> Vec<usize> {
+  // SAFETY: cpu_set_t is plain data; sched_getaffinity fills it for the calling process.
+  unsafe {
+    let mut set: libc::cpu_set_t = std::mem::zeroed();
+    if libc::sched_getaffinity(0, std::mem::size_of::<libc::cpu_set_t>(), &mut set) != 0 {
+      return Vec::new();
+    }
+    (0..libc::CPU_SETSIZE as usize).filter(|&cpu| libc::CPU_ISSET(cpu, &set)).collect()
+  }
+}
+
+#[cfg(target_os = "linux")]
+fn set_affinity(cpu: usize) {
+  // SAFETY: as above; pid 0 means the calling thread.
+  unsafe {
+    let mut set: libc::cpu_set_t = std::mem::zeroed();
+    libc::CPU_SET(cpu, &mut set);
+    libc::sched_setaffinity(0, std::mem::size_of::<libc::cpu_set_t>(), &set);
+  }
+}
+
+#[cfg(not(target_os = "linux"))]
+fn allowed_cpus() -> Vec<usize> {
+  crate::log::warn("--pin-threads is only supported on Linux; threads will not be pinned");
+  Vec::new()
+}
+
+#[cfg(not(target_os = "linux"))]
+fn set_affinity(_cpu: usize) {}
+
+// Keeps the first CPU of each physical core, going by the sibling lists in sysfs. CPUs
+// whose topology can't be read are kept.
+fn physical_cores(cpus: &[usize]) -> Vec<usize> {
+  cpus.iter().copied().filter(|&cpu| {
+    let path = format!("/sys/devices/system/cpu/cpu{}/topology/thread_siblings_list", cpu);
+    match std::fs::read_to_string(path) {
+      Ok(list) => first_sibling(list.trim()).is_none_or(|first| first == cpu),
+      Err(_) => true,
+    }
+  }).collect()
+}
+
+// The lowest CPU in a sysfs list such as "2,10" or "0-1".
+fn first_sibling(list: &str) -> Option<usize> {
+  list.split([',', '-']).filter_map(|cpu| cpu.parse().ok()).min()
+}
+
+#[test]
+fn test_first_sibling() {
+  assert_eq!(first_sibling("2,10"), Some(2));
+  assert_eq!(first_sibling("4-5"), Some(4));
+  assert_eq!(first_sibling("7"), Some(7));
+  assert_eq!(first_sibling(""), None);
+}
@@ -0,0 +1,528 @@
+// Animations
+// `mandel animate` renders a numbered PNG per frame, to be joined into a video with a
+// tool such as ffmpeg. The frames either zoom straight toward --target, follow the
+// keyframes of a --spec file (see keyframes.rs), or, with --julia, show the Julia set
+// of a point c travelling along a path: just inside the main cardioid's boundary, or
+// through a list of points read from a file, one RE,IM per line. With --cycle the view
+// at --target and --from-zoom is rendered once and only recolored for each frame, the
+// palette turning one full cycle over the frames. --palette may list several palettes,
+// which the colors fade through in turn over the frames. --rotate turns every frame's view
+// counterclockwise and --spin turns it further, frame by frame, reaching that many more
+// degrees by the last frame; keyframed views rotate as their spec says. --easing paces
+// the zoom and spin along a curve from easing.rs instead of evenly. Zooms are exponential, so the view
+// shrinks by the same factor every frame and the apparent speed stays constant. Zoom 1
+// shows 4 units across, as with `mandel serve`, and iteration limits follow each
+// frame's zoom as with --max-iter auto unless a fixed limit is given. Frames are encoded
+// and written while the next ones render, each under a temporary name until it is
+// complete. A manifest in the output directory records a hash of everything that
+// determines the frames, so --resume can check that the frames already there belong to
+// this animation and carry on from the first one missing.
+
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+
+use num::Complex;
+
+use crate::cache::fnv1a;
+use crate::easing::Easing;
+use crate::keyframes;
+use crate::palette::{self, Blend, Palette, PALETTES};
+use crate::{centered_corners, escape_count, log, parse_complex, parse_pair, parse_threads, pixel_to_point, render_parallel, rotate_about, shades, tune, turn, write_color_image,
+            write_rgb_image, Fractal, MaxIter, Plane, Sampler, THREAD_PANICKED};
+
+// Frame size unless --size says otherwise.
+const DEFAULT_SIZE: (usize, usize) = (1280, 720);
+
+// Frames per second of keyframed animations unless --fps says otherwise.
+const DEFAULT_FPS: f64 = 30.0;
+
+// Julia sweeps around the main cardioid follow its boundary scaled toward its cusp by
+// this much, where the Julia sets are still connected but full of detail.
+const CARDIOID_RADIUS: f64 = 0.99;
+
+// Real-axis extent of Julia sweep frames, centered on the origin.
+const JULIA_WIDTH: f64 = 3.6;
+
+// One frame's view of the plane.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Frame {
+  pub center: Complex<f64>,
+  // Extent of the real axis.
+  pub width: f64,
+  pub limit: usize,
+  // Steps the palette is cycled by.
+  pub offset: u8,
+  // Degrees counterclockwise about the center.
+  pub rotation: f64,
+  // The c of the Julia set shown, or None for the Mandelbrot set.
+  pub julia: Option<Complex<f64>>,
+  pub palette: Blend,
+}
+
+// The settings every frame of an animation shares.
+struct Animation {
+  size: (usize, usize),
+  threads: usize,
+  // Palettes to fade through, first to last.
+  palettes: Vec<Palette>,
+  output: PathBuf,
+  resume: bool,
+}
+
+// Name of the manifest in the output directory, and the first line of its contents.
+const MANIFEST: &str = "animation";
+const MANIFEST_MAGIC: &str = "mandel-animation 1";
+
+// Runs `mandel animate (--target RE,IM [--from-zoom Z] --to-zoom Z --frames N [--max-iter N|auto]
+// | --spec FILE [--fps N] | --julia cardioid|FILE --frames N [--max-iter N]
+// | --cycle --target RE,IM [--from-zoom Z] --frames N [--max-iter N|auto])
+// [--rotate DEGREES] [--spin DEGREES] [--easing CURVE]
+// [--size WxH] [--palette NAME] [--threads N] [--output DIR] [--resume]`.
+pub fn main(arguments: &[String], threads: usize) -> Result<(), String> {
+  let mut target = None;
+  let mut from_zoom = 1.0;
+  let mut to_zoom = None;
+  let mut frames = None;
+  let mut max_iter = MaxIter::Auto;
+  let mut spec = None;
+  let mut julia = None;
+  let mut cycle = false;
+  let mut rotation = 0.0;
+  let mut spin = 0.0;
+  let mut easing = Easing::Linear;
+  let mut fps = DEFAULT_FPS;
+  let mut animation = Animation { size: DEFAULT_SIZE, threads, palettes: vec![Palette::Gray], output: PathBuf::from("frames"), resume: false };
+
+  let zoom = |value: Option<&str>, option: &str| match value.map(f64::from_str) {
+    Some(Ok(zoom)) if zoom.is_finite() && zoom > 0.0 => Ok(zoom),
+    _ => Err(format!("{} expects a positive zoom factor", option)),
+  };
+
+  let degrees = |value: Option<&str>, option: &str| {
+    value.and_then(|value| f64::from_str(value).ok()).filter(|degrees| degrees.is_finite()).ok_or(format!("{} expects a number of degrees", option))
+  };
+
+  let mut options = arguments.iter().map(String::as_str);
+  while let Some(option) = options.next() {
+    match option {
+      "--target" => target = Some(options.next().and_then(parse_complex).ok_or("--target expects a point RE,IM")?),
+      "--from-zoom" => from_zoom = zoom(options.next(), option)?,
+      "--to-zoom" => to_zoom = Some(zoom(options.next(), option)?),
+      "--frames" => match options.next().map(usize::from_str) {
+        Some(Ok(count)) if count > 0 => frames = Some(count),
+        _ => return Err("--frames expects a positive number of frames".to_string()),
+      },
+      "--size" => match options.next().and_then(|size| parse_pair(size, 'x')) {
+        Some((width, height)) if width > 0 && height > 0 => animation.size = (width, height),
+        _ => return Err("--size expects WIDTHxHEIGHT".to_string()),
+      },
+      "--max-iter" => {
+        max_iter = match options.next() {
+          Some("auto") => MaxIter::Auto,
+          Some(value) => match usize::from_str(value) {
+            Ok(limit) if limit > 0 => MaxIter::Fixed(limit),
+            _ => return Err("--max-iter expects a positive number or 'auto'".to_string()),
+          },
+          None => return Err("--max-iter expects a positive number or 'auto'".to_string()),
+        }
+      }
+      "--spec" => spec = Some(options.next().ok_or("--spec expects a file name")?),
+      "--cycle" => cycle = true,
+      "--rotate" => rotation = degrees(options.next(), option)?,
+      "--spin" => spin = degrees(options.next(), option)?,
+      "--easing" => easing = options.next().and_then(Easing::parse)
+        .ok_or("--easing expects linear, ease-in-out, exponential or cubic-bezier:X1,Y1,X2,Y2")?,
+      "--julia" => julia = Some(options.next().ok_or("--julia expects 'cardioid' or a file of points")?),
+      "--fps" => match options.next().map(f64::from_str) {
+        Some(Ok(rate)) if rate.is_finite() && rate > 0.0 => fps = rate,
+        _ => return Err("--fps expects a positive number".to_string()),
+      },
+      "--palette" => {
+        animation.palettes = options.next().unwrap_or_default().split(',')
+          .map(|name| PALETTES.iter().find(|palette| palette.name() == name).copied().ok_or(format!("unknown palette '{}'", name)))
+          .collect::<Result<_, _>>()?;
+      }
+      "--threads" => animation.threads = parse_threads(options.next())?,
+      "--output" => animation.output = PathBuf::from(options.next().ok_or("--output expects a directory")?),
+      "--resume" => animation.resume = true,
+      _ => return Err(format!("unknown animate option '{}'", option)),
+    }
+  }
+
+  if let Some(path) = spec {
+    if target.is_some() || to_zoom.is_some() || frames.is_some() {
+      return Err("--spec gives the whole camera path; leave out --target, --to-zoom and --frames".to_string());
+    }
+    if rotation != 0.0 || spin != 0.0 || easing != Easing::Linear || animation.palettes != [Palette::Gray] {
+      return Err("--spec sets rotation, easing and palettes with its keyframes' rotate, ease and palette settings; leave out --rotate, --spin, --easing and --palette"
+                   .to_string());
+    }
+    let text = std::fs::read_to_string(path).map_err(|e| format!("error reading '{}': {}", path, e))?;
+    let keyframes = keyframes::parse(&text).map_err(|message| format!("{}: {}", path, message))?;
+    return render(&animation, &keyframes::frames(&keyframes, fps));
+  }
+
+  if let Some(path) = julia {
+    let frames = frames.ok_or("--julia needs --frames N")?;
+    if target.is_some() || to_zoom.is_some() {
+      return Err("--julia frames show the whole Julia set; leave out --target and --to-zoom".to_string());
+    }
+    let points = match path {
+      "cardioid" => cardioid_points(frames),
+      _ => along(&read_points(path)?, frames),
+    };
+    return render(&animation, &faded(turned(julia_frames(&points, max_iter), rotation, spin, easing), &animation.palettes));
+  }
+
+  let target = target.ok_or("animate needs --target RE,IM, --spec FILE or --julia PATH")?;
+  if cycle {
+    if to_zoom.is_some() {
+      return Err("--cycle recolors a single view; leave out --to-zoom".to_string());
+    }
+    if spin != 0.0 || easing != Easing::Linear {
+      return Err("--cycle recolors a single view, which can't spin or ease; use --rotate".to_string());
+    }
+    let count = frames.ok_or("--cycle needs --frames N")?;
+    let view = zoom_frames(target, from_zoom, from_zoom, 1, max_iter, easing)[0];
+    let frames = (0..count).map(|i| Frame { offset: (i * 255 / count) as u8, ..view }).collect();
+    return render_cycle(&animation, &faded(turned(frames, rotation, 0.0, easing), &animation.palettes));
+  }
+  let to_zoom = to_zoom.ok_or("animate needs --to-zoom")?;
+  let frames = frames.ok_or("animate needs --frames N")?;
+  render(&animation, &faded(turned(zoom_frames(target, from_zoom, to_zoom, frames, max_iter, easing), rotation, spin, easing), &animation.palettes))
+}
+
+// `frames` colored by `palettes` in turn, each fading into the next over an equal share
+// of the frames.
+fn faded(mut frames: Vec<Frame>, palettes: &[Palette]) -> Vec<Frame> {
+  let last = frames.len().saturating_sub(1).max(1) as f64;
+  let fades = palettes.len() - 1;
+  for (i, frame) in frames.iter_mut().enumerate() {
+    let position = i as f64 / last * fades as f64;
+    let from = (position.floor() as usize).min(fades.saturating_sub(1));
+    frame.palette = Blend { from: palettes[from], to: palettes[(from + 1).min(fades)], t: position - from as f64 };
+  }
+  frames
+}
+
+// `frames` turned by `rotation` degrees, plus a share of `spin` growing along `easing`
+// from none at the first frame to all of it at the last.
+fn turned(mut frames: Vec<Frame>, rotation: f64, spin: f64, easing: Easing) -> Vec<Frame> {
+  let last = frames.len().saturating_sub(1).max(1) as f64;
+  for (i, frame) in frames.iter_mut().enumerate() {
+    frame.rotation += rotation + spin * easing.apply(i as f64 / last);
+  }
+  frames
+}
+
+// Frames zooming from `from` to `to` about `target`, each a constant factor deeper than
+// the last when `easing` is linear.
+fn zoom_frames(target: Complex<f64>, from: f64, to: f64, count: usize, max_iter: MaxIter, easing: Easing) -> Vec<Frame> {
+  (0..count)
+    .map(|i| {
+      let t = easing.apply(if count > 1 { i as f64 / (count - 1) as f64 } else { 0.0 });
+      let width = 4.0 / (from * (to / from).powf(t));
+      Frame { center: target, width, limit: max_iter.resolve(width), offset: 0, rotation: 0.0, julia: None, palette: Blend::solid(Palette::Gray) }
+    })
+    .collect()
+}
+
+// One frame per point, each showing that point's Julia set.
+fn julia_frames(points: &[Complex<f64>], max_iter: MaxIter) -> Vec<Frame> {
+  let center = Complex { re: 0.0, im: 0.0 };
+  points.iter()
+    .map(|&c| Frame { center, width: JULIA_WIDTH, limit: max_iter.resolve(JULIA_WIDTH), offset: 0, rotation: 0.0, julia: Some(c), palette: Blend::solid(Palette::Gray) })
+    .collect()
+}
+
+// `count` points once around the scaled main cardioid, c = w/2 - w²/4 for w on a circle,
+// spaced by angle and ending a step short of the start so the animation loops.
+fn cardioid_points(count: usize) -> Vec<Complex<f64>> {
+  (0..count)
+    .map(|i| {
+      let w = Complex::from_polar(CARDIOID_RADIUS, std::f64::consts::TAU * i as f64 / count as f64);
+      w / 2.0 - w * w / 4.0
+    })
+    .collect()
+}
+
+fn read_points(path: &str) -> Result<Vec<Complex<f64>>, String> {
+  let text = std::fs::read_to_string(path).map_err(|e| format!("error reading '{}': {}", path, e))?;
+  let points: Vec<_> = text.lines().enumerate()
+    .filter(|(_, line)| !line.trim().is_empty())
+    .map(|(i, line)| parse_complex(line.trim()).ok_or(format!("{}:{}: expected a point RE,IM", path, i + 1)))
+    .collect::<Result<_, _>>()?;
+  if points.is_empty() {
+    return Err(format!("{} lists no points", path));
+  }
+  Ok(points)
+}
+
+// `count` points evenly spaced by distance along the path through `points`, from its
+// first point to its last.
+fn along(points: &[Complex<f64>], count: usize) -> Vec<Complex<f64>> {
+  let lengths: Vec<f64> = points.windows(2).map(|pair| (pair[1] - pair[0]).norm()).collect();
+  let total: f64 = lengths.iter().sum();
+  (0..count)
+    .map(|i| {
+      let mut distance = if count > 1 { total * i as f64 / (count - 1) as f64 } else { 0.0 };
+      for (segment, &length) in lengths.iter().enumerate() {
+        if distance <= length && length > 0.0 {
+          return points[segment] + (points[segment + 1] - points[segment]) * (distance / length);
+        }
+        distance -= length;
+      }
+      points[points.len() - 1]
+    })
+    .collect()
+}
+
+// Renders `frames` in order while a separate thread encodes and writes the ones already
+// finished, so the render threads never wait on PNG encoding. Frames too short to give
+// every thread a chunk of rows are rendered several at once, with the threads shared
+// out between them.
+fn render(animation: &Animation, frames: &[Frame]) -> Result<(), String> {
+  let start = prepare(animation, &format!("{:?}", frames), frames.len())?;
+  let frames_left = &frames[start..];
+  let in_flight = frames_in_flight(animation.size, animation.threads);
+  let threads = (animation.threads / in_flight).max(1);
+
+  crossbeam::scope(|spawner| {
+    let (sender, receiver) = crossbeam::channel::bounded::<(usize, Vec<u8>)>(in_flight);
+    let writer = spawner.spawn(move |_| {
+      for (i, pixels) in receiver {
+        let i = start + i;
+        let frame = &frames[i];
+        write_frame(animation, i, &pixels, frame)?;
+        log::info(&format!("frame {}/{}: width {:e}, limit {}", i + 1, frames.len(), frame.width, frame.limit));
+      }
+      Ok(())
+    });
+
+    let mut rendering = Ok(());
+    'render: for (batch, group) in frames_left.chunks(in_flight).enumerate() {
+      let rendered = crossbeam::scope(|spawner| {
+        let handles: Vec<_> = group.iter().map(|frame| spawner.spawn(move |_| render_frame(frame, animation.size, threads))).collect();
+        handles.into_iter().map(|handle| handle.join().map_err(|_| THREAD_PANICKED.to_string()).and_then(|pixels| pixels)).collect::<Result<Vec<_>, _>>()
+      });
+      let rendered = match rendered.map_err(|_| THREAD_PANICKED.to_string()).and_then(|rendered| rendered) {
+        Ok(rendered) => rendered,
+        Err(message) => {
+          rendering = Err(message);
+          break;
+        }
+      };
+      for (i, pixels) in rendered.into_iter().enumerate() {
+        // The writer only hangs up when it has failed, and it reports why below.
+        if sender.send((batch * in_flight + i, pixels)).is_err() {
+          break 'render;
+        }
+      }
+    }
+    drop(sender);
+    let writing = writer.join().map_err(|_| THREAD_PANICKED.to_string()).and_then(|written| written);
+    rendering.and(writing)
+  }).map_err(|_| THREAD_PANICKED.to_string()).and_then(|rendered| rendered)
+}
+
+// How many frames of `size` to render at once so that `threads` all have rows to work on.
+fn frames_in_flight(size: (usize, usize), threads: usize) -> usize {
+  let chunks = size.1.div_ceil(tune::current().chunk_rows);
+  (threads / chunks).max(1)
+}
+
+// Renders the view `frames` share once and writes each of them, differing only in color.
+fn render_cycle(animation: &Animation, frames: &[Frame]) -> Result<(), String> {
+  let start = prepare(animation, &format!("cycle {:?}", frames), frames.len())?;
+  if start == frames.len() {
+    return Ok(());
+  }
+  let pixels = render_frame(&frames[0], animation.size, animation.threads)?;
+  for (i, frame) in frames.iter().enumerate().skip(start) {
+    write_frame(animation, i, &pixels, frame)?;
+  }
+  log::info(&format!("{} frames from one render: width {:e}, limit {}", frames.len(), frames[0].width, frames[0].limit));
+  Ok(())
+}
+
+// Creates the output directory and records the animation's manifest there, returning
+// the number of the first frame to render: 0, or with --resume the first frame missing
+// from an earlier run of the same animation. `frames` describes every frame, and
+// `count` is how many there are.
+fn prepare(animation: &Animation, frames: &str, count: usize) -> Result<usize, String> {
+  let output = &animation.output;
+  std::fs::create_dir_all(output).map_err(|e| format!("error creating '{}': {}", output.display(), e))?;
+  let manifest = format!("{}\nspec {:016x}\nframes {}\n", MANIFEST_MAGIC, spec_hash(animation, frames), count);
+  let path = output.join(MANIFEST);
+
+  let mut start = 0;
+  if animation.resume {
+    let saved = std::fs::read_to_string(&path).map_err(|e| format!("nothing to resume in '{}': {}", output.display(), e))?;
+    if saved != manifest {
+      return Err(format!("the frames in '{}' were rendered with different settings; leave out --resume to start over", output.display()));
+    }
+    start = (0..count).find(|&i| !frame_done(&frame_path(output, i), animation.size)).unwrap_or(count);
+    log::info(&format!("resuming at frame {}/{}", start + 1, count));
+  } else {
+    std::fs::write(&path, manifest).map_err(|e| format!("error writing '{}': {}", path.display(), e))?;
+  }
+  Ok(start)
+}
+
+// A hash of everything that determines the frames written: their size and the view and
+// colors of each.
+fn spec_hash(animation: &Animation, frames: &str) -> u64 {
+  fnv1a(format!("{:?} {}", animation.size, frames).as_bytes())
+}
+
+// Whether `path` holds a finished frame of `size`. Frames are renamed into place once
+// written, so one that is there and reads as a PNG of the right size is complete.
+fn frame_done(path: &Path, size: (usize, usize)) -> bool {
+  let Ok(file) = std::fs::File::open(path) else {
+    return false;
+  };
+  match png::Decoder::new(file).read_info() {
+    Ok(reader) => (reader.info().width as usize, reader.info().height as usize) == size,
+    Err(_) => false,
+  }
+}
+
+// Colors `frame`'s shades, cycled by its offset and in its palette, and writes them as
+// frame number `index`.
+fn write_frame(animation: &Animation, index: usize, pixels: &[u8], frame: &Frame) -> Result<(), String> {
+  let path = frame_path(&animation.output, index);
+  let cycled: Vec<u8>;
+  let pixels = if frame.offset == 0 {
+    pixels
+  } else {
+    cycled = pixels.iter().map(|&pixel| palette::cycle(pixel, frame.offset)).collect();
+    &cycled
+  };
+  let partial = path.with_extension("png.partial");
+  let filename = partial.to_string_lossy();
+  match frame.palette.single() {
+    Some(palette) => write_color_image(&filename, pixels, animation.size, palette),
+    None => {
+      // Blending is slow enough per color that it pays to do each shade only once.
+      let colors: Vec<[u8; 3]> = (0..=255).map(|shade| frame.palette.color(shade)).collect();
+      write_rgb_image(&filename, &pixels.iter().flat_map(|&shade| colors[shade as usize]).collect::<Vec<_>>(), animation.size)
+    }
+  }
+    .and_then(|()| std::fs::rename(&partial, &path))
+    .map_err(|e| format!("error writing '{}': {}", path.display(), e))
+}
+
+// A Julia set frame's view, turned about its center by the frame's rotation.
+struct JuliaFrame {
+  bounds: (usize, usize),
+  center: Complex<f64>,
+  upper_left: Complex<f64>,
+  lower_right: Complex<f64>,
+  turn: Option<Complex<f64>>,
+  limit: usize,
+  c: Complex<f64>,
+}
+
+impl Sampler for JuliaFrame {
+  fn sample(&self, x: f64, y: f64) -> u32 {
+    let mut point = pixel_to_point(self.bounds, (x, y), self.upper_left, self.lower_right);
+    if let Some(turn) = self.turn {
+      point = rotate_about(point, self.center, turn);
+    }
+    escape_count(Fractal::Mandelbrot.julia_escape_time(point, self.c, self.limit), self.limit)
+  }
+
+  fn limit(&self) -> usize {
+    self.limit
+  }
+}
+
+fn render_frame(frame: &Frame, size: (usize, usize), threads: usize) -> Result<Vec<u8>, String> {
+  let (upper_left, lower_right) = centered_corners(frame.center, frame.width, size);
+  let turn = turn(frame.rotation);
+  let sampler: Box<dyn Sampler> = match frame.julia {
+    Some(c) => Box::new(JuliaFrame { bounds: size, center: frame.center, upper_left, lower_right, turn, limit: frame.limit, c }),
+    None => Box::new(Plane { turn, ..Plane::mandelbrot(size, upper_left, lower_right, frame.limit) }),
+  };
+  let mut pixels = vec![0; size.0 * size.1];
+  render_parallel(&mut pixels, size, sampler.as_ref(), threads, 0, 1, true)?;
+  Ok(shades(&pixels, frame.limit))
+}
+
+fn frame_path(output: &Path, index: usize) -> PathBuf {
+  output.join(format!("frame-{:05}.png", index))
+}
+
+#[test]
+fn test_zoom_frames_shrink_geometrically() {
+  let target = Complex { re: -0.7436, im: 0.1318 };
+  let frames = zoom_frames(target, 1.0, 1000.0, 4, MaxIter::Auto, Easing::Linear);
+  let widths: Vec<f64> = frames.iter().map(|frame| frame.width).collect();
+  for (width, expected) in widths.iter().zip([4.0, 0.4, 0.04, 0.004]) {
+    assert!((width / expected - 1.0).abs() < 1e-12);
+  }
+  // Another 255 iterations per decade of zoom.
+  assert_eq!(frames.iter().map(|frame| frame.limit).collect::<Vec<_>>(), [255, 510, 765, 1020]);
+  assert!(frames.iter().all(|frame| frame.center == target));
+  assert_eq!(frame_path(Path::new("out"), 42), Path::new("out/frame-00042.png"));
+
+  // Short frames leave threads idle unless several render at once.
+  assert_eq!(frames_in_flight((1280, 720), 8), 1);
+  assert_eq!(frames_in_flight((160, 64), 8), 4);
+}
+
+#[test]
+fn test_rotation_turns_the_view_about_its_center() {
+  // A quarter turn maps the right edge's midpoint to the top of the view.
+  let frame = Frame { center: Complex { re: -0.5, im: 0.0 }, width: 2.0, limit: 50, offset: 0, rotation: 90.0, julia: None,
+                     palette: Blend::solid(Palette::Gray) };
+  let (upper_left, lower_right) = centered_corners(frame.center, frame.width, (20, 20));
+  let point = rotate_about(pixel_to_point((20, 20), (20.0, 10.0), upper_left, lower_right), frame.center, turn(frame.rotation).unwrap());
+  assert!((point - Complex { re: -0.5, im: 1.0 }).norm() < 1e-12);
+
+  // Spinning adds up to the whole turn by the last frame, on top of the fixed rotation.
+  let frames = turned(zoom_frames(frame.center, 1.0, 10.0, 3, MaxIter::Auto, Easing::Linear), 10.0, 90.0, Easing::Linear);
+  assert_eq!(frames.iter().map(|frame| frame.rotation).collect::<Vec<_>>(), [10.0, 55.0, 100.0]);
+
+  // Three palettes make two fades, the middle one reached halfway.
+  let frames = faded(frames, &[Palette::Gray, Palette::Fire, Palette::Ocean]);
+  assert_eq!(frames[0].palette, Blend { from: Palette::Gray, to: Palette::Fire, t: 0.0 });
+  assert_eq!(frames[1].palette.single(), Some(Palette::Fire));
+  assert_eq!(frames[2].palette.single(), Some(Palette::Ocean));
+  assert_eq!(faded(frames, &[Palette::Fire])[1].palette.single(), Some(Palette::Fire));
+}
+
+#[test]
+fn test_resume_continues_after_the_frames_already_written() {
+  let output = std::env::temp_dir().join(format!("mandel-animate-resume-{}", std::process::id()));
+  let mut animation = Animation { size: (8, 6), threads: 2, palettes: vec![Palette::Gray], output: output.clone(), resume: false };
+  let frames = zoom_frames(Complex { re: -0.75, im: 0.1 }, 1.0, 10.0, 4, MaxIter::Auto, Easing::Linear);
+  render(&animation, &frames).unwrap();
+
+  // Lose the last two frames, one of them cut short.
+  std::fs::remove_file(frame_path(&output, 3)).unwrap();
+  std::fs::write(frame_path(&output, 2), b"\x89PNG").unwrap();
+  animation.resume = true;
+  assert_eq!(prepare(&animation, &format!("{:?}", frames), frames.len()), Ok(2));
+  render(&animation, &frames).unwrap();
+  assert!((0..4).all(|i| frame_done(&frame_path(&output, i), animation.size)));
+
+  // A different animation won't pick up these frames.
+  assert!(render(&animation, &frames[..3]).is_err());
+  std::fs::remove_dir_all(&output).unwrap();
+}
+
+#[test]
+fn test_julia_paths() {
+  // The cardioid's cusp is at c = 1/4 and its far end at c = -3/4.
+  let points = cardioid_points(4);
+  assert!((points[0] - Complex { re: 0.99 / 2.0 - 0.99 * 0.99 / 4.0, im: 0.0 }).norm() < 1e-12);
+  assert!((points[2] - Complex { re: -0.99 / 2.0 - 0.99 * 0.99 / 4.0, im: 0.0 }).norm() < 1e-12);
+
+  // Evenly spaced along a path whose second leg is three times the first.
+  let path = [Complex { re: 0.0, im: 0.0 }, Complex { re: 1.0, im: 0.0 }, Complex { re: 1.0, im: 3.0 }];
+  let points = along(&path, 5);
+  assert_eq!(points[1], Complex { re: 1.0, im: 0.0 });
+  assert_eq!(points[3], Complex { re: 1.0, im: 2.0 });
+  assert_eq!(points[4], Complex { re: 1.0, im: 3.0 });
+}
@@ -0,0 +1,329 @@
+// Render API
+// `mandel serve-api` takes render jobs, as `mandel schema` describes them, over HTTP and
+// renders them in the background:
+//
+//   POST /renders              submit a job; answers 202 with {"id":1,"status":"queued",..}
+//   GET  /renders/{id}         its status: queued, running, done or failed, with progress
+//   GET  /renders/{id}/image   the PNG, once it's done
+//   GET  /metrics              Prometheus metrics
+//
+// Jobs wait in a queue for one of --jobs render slots, and each renders with --threads.
+// The server picks where images go, in --dir, so jobs can't give an output, and it
+// reads no files for them, so location and map aren't accepted either. Jobs are held
+// to `mandel serve`'s limits on image sides and iterations, and to MAX_SAMPLES samples
+// of each pixel. Ctrl-C stops the server, abandoning the renders in progress.
+
+use std::io::{self, BufReader, Read, Write};
+use std::net::{TcpListener, TcpStream};
+use std::path::PathBuf;
+use std::sync::atomic::AtomicUsize;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use crossbeam::channel::{self, Receiver, Sender, TrySendError};
+
+use crate::json::{self, Value};
+use crate::progress::{self, Tracker};
+use crate::server::{read_head, reason, spawn_connection, MAX_ITER, MAX_SIDE};
+use crate::{interrupt, job, log, metrics, parse_arguments, parse_pair, passes, render, render_limit, Arguments};
+
+// Jobs waiting beyond this many are turned away with 503 until the queue drains.
+const MAX_QUEUED: usize = 256;
+
+// Largest job accepted, in bytes; jobs are a few hundred.
+const MAX_BODY: usize = 1 << 20;
+
+// Most times a job may sample each pixel: its passes, jittered samples and supersampled
+// pixels multiplied together.
+const MAX_SAMPLES: usize = 64;
+
+// Fields the server decides, or that would have it read its own files.
+const SERVER_FIELDS: [&str; 9] = ["output", "existing", "location", "map", "formula", "edges", "histogram", "legend", "threads"];
+
+pub struct Settings {
+  // Renders at once, and threads each.
+  pub jobs: usize,
+  pub threads: usize,
+  // Where the images go, as {id}.png.
+  pub dir: PathBuf,
+}
+
+#[derive(Debug, PartialEq)]
+enum State {
+  Queued,
+  Running,
+  Done { seconds: f64 },
+  Failed(String),
+}
+
+struct Render {
+  state: State,
+  size: (usize, usize),
+  // Pixel visits the render makes in all, which its tracker counts up to.
+  total: u64,
+  tracker: Tracker,
+}
+
+struct Service {
+  settings: Settings,
+  // Render n has id n + 1.
+  renders: Mutex<Vec<Render>>,
+  queue: Sender<(usize, Arguments)>,
+}
+
+// An HTTP error status and the message sent with it.
+type Failure = (u16, String);
+
+pub fn serve(address: &str, settings: Settings) -> Result<(), io::Error> {
+  std::fs::create_dir_all(&settings.dir)?;
+  let listener = TcpListener::bind(address)?;
+  log::info(&format!("serving the render API on http://{}, writing images to {}", listener.local_addr()?, settings.dir.display()));
+  interrupt::install();
+  std::thread::spawn(|| {
+    while !interrupt::requested() {
+      std::thread::sleep(Duration::from_millis(100));
+    }
+    log::info("interrupted; stopping the server");
+    std::process::exit(interrupt::EXIT_STATUS as i32);
+  });
+  serve_listener(listener, settings)
+}
+
+fn serve_listener(listener: TcpListener, settings: Settings) -> Result<(), io::Error> {
+  let (sender, receiver) = channel::bounded(MAX_QUEUED);
+  let service = Arc::new(Service { settings, renders: Mutex::new(Vec::new()), queue: sender });
+  for _ in 0..service.settings.jobs {
+    let (service, receiver) = (service.clone(), receiver.clone());
+    std::thread::spawn(move || run_jobs(&service, &receiver));
+  }
+
+  let open = Arc::new(AtomicUsize::new(0));
+  for stream in listener.incoming() {
+    let service = service.clone();
+    spawn_connection(stream?, &open, move |stream| handle_connection(stream, &service));
+  }
+  Ok(())
+}
+
+// Renders jobs from the queue, one at a time, until the server stops.
+fn run_jobs(service: &Service, queue: &Receiver<(usize, Arguments)>) {
+  for (index, args) in queue {
+    metrics::queued(queue.len());
+    let tracker = {
+      let mut renders = service.renders.lock().unwrap();
+      renders[index].state = State::Running;
+      renders[index].tracker.clone()
+    };
+    log::info(&format!("rendering job {} to {}", index + 1, args.file));
+    progress::track(Some(tracker));
+    let start = Instant::now();
+    let result = render(args);
+    progress::track(None);
+
+    let seconds = start.elapsed().as_secs_f64();
+    let size = service.renders.lock().unwrap()[index].size;
+    metrics::rendered((size.0 * size.1) as u64, seconds, result.is_ok());
+    let state = match result {
+      Ok(_) => State::Done { seconds },
+      Err(message) => {
+        log::warn(&format!("job {} failed: {}", index + 1, message));
+        State::Failed(message)
+      }
+    };
+    service.renders.lock().unwrap()[index].state = state;
+  }
+}
+
+fn handle_connection(stream: TcpStream, service: &Service) -> Result<(), io::Error> {
+  let mut reader = BufReader::new(stream.try_clone()?);
+  let (request_line, headers) = match read_head(&mut reader)? {
+    Ok(head) => head,
+    Err(failure) => return respond(stream, Err(failure)),
+  };
+  let length = headers.iter().filter_map(|header| header.split_once(':')).find(|(name, _)| name.eq_ignore_ascii_case("content-length"))
+    .map_or(0, |(_, value)| value.trim().parse().unwrap_or(usize::MAX));
+
+  let response = match request_line.split(' ').collect::<Vec<_>>().as_slice() {
+    ["POST", "/renders", _version] if length > MAX_BODY => Err((413, format!("jobs are limited to {} bytes", MAX_BODY))),
+    ["POST", "/renders", _version] => {
+      let mut body = vec![0; length];
+      reader.read_exact(&mut body)?;
+      String::from_utf8(body).map_err(|_| (400, "the job is not UTF-8 text".to_string()))
+        .and_then(|body| submit(service, &body))
+        .map(|status| (202, "application/json", status.to_string().into_bytes()))
+    }
+    ["GET", target, _version] => get(service, target),
+    [_, target, _version] if route(target).is_some() => Err((405, "use POST /renders, and GET for the rest".to_string())),
+    [_, _, _] => Err((404, "no such endpoint; try POST /renders".to_string())),
+    _ => Err((400, "malformed request line".to_string())),
+  };
+  respond(stream, response)
+}
+
+// Answers with `response`, or with its failure as {"error": ..}.
+fn respond(stream: TcpStream, response: Result<(u16, &str, Vec<u8>), Failure>) -> Result<(), io::Error> {
+  let (status, content_type, body) = response.unwrap_or_else(|(status, message)| {
+    let error = Value::Object(vec![("error".to_string(), message.as_str().into())]);
+    (status, "application/json", error.to_string().into_bytes())
+  });
+  let mut writer = io::BufWriter::new(stream);
+  write!(writer, "HTTP/1.1 {} {}\r\nContent-Type: {}\r\nContent-Length: {}\r\n", status, reason(status), content_type, body.len())?;
+  write!(writer, "Access-Control-Allow-Origin: *\r\nConnection: close\r\n\r\n")?;
+  writer.write_all(&body)?;
+  writer.flush()
+}
+
+// The render a path is about, and whether it asks for the image.
+fn route(target: &str) -> Option<(Option<usize>, bool)> {
+  let path = target.split_once('?').map_or(target, |(path, _query)| path);
+  if path == "/renders" {
+    return Some((None, false));
+  }
+  let rest = path.strip_prefix("/renders/")?;
+  let (id, image) = rest.strip_suffix("/image").map_or((rest, false), |id| (id, true));
+  Some((Some(id.parse().ok()?), image))
+}
+
+fn submit(service: &Service, body: &str) -> Result<Value, Failure> {
+  let invalid = |message: String| (400, message);
+  let mut job = json::parse(body).map_err(|e| invalid(format!("job: {}", e)))?;
+  let Value::Object(fields) = &mut job else {
+    return Err(invalid(format!("job: expected a job object, got {}", job)));
+  };
+  if let Some((name, _)) = fields.iter().find(|(name, _)| SERVER_FIELDS.contains(&name.as_str())) {
+    return Err(invalid(format!("job.{}: set by the server", name)));
+  }
+
+  let mut renders = service.renders.lock().unwrap();
+  let index = renders.len();
+  let file = service.settings.dir.join(format!("{}.png", index + 1));
+  fields.push(("output".to_string(), file.to_string_lossy().as_ref().into()));
+  let mut arguments = job::arguments(&job, "job").map_err(invalid)?;
+  arguments.extend(["--force", "--no-progress", "--threads"].map(String::from));
+  arguments.push(service.settings.threads.to_string());
+  let args = parse_arguments(&arguments).map_err(invalid)?;
+  let size: (usize, usize) = parse_pair(&args.pixels, 'x').filter(|&(w, h)| w <= MAX_SIDE && h <= MAX_SIDE)
+    .ok_or_else(|| invalid(format!("job.size: images are limited to {} pixels a side", MAX_SIDE)))?;
+  if render_limit(&args) > MAX_ITER {
+    return Err(invalid(format!("job.max_iter: renders are limited to {} iterations", MAX_ITER)));
+  }
+  if passes(&args).saturating_mul(args.samples).saturating_mul(args.supersample.saturating_mul(args.supersample)) > MAX_SAMPLES {
+    return Err(invalid(format!("job: passes, samples and supersampling are limited to {} samples of each pixel in all", MAX_SAMPLES)));
+  }
+
+  let total = (size.0 * size.1 * passes(&args)) as u64;
+  match service.queue.try_send((index, args)) {
+    Ok(()) => {}
+    Err(TrySendError::Full(_)) => return Err((503, format!("{} jobs are already waiting; try again later", MAX_QUEUED))),
+    Err(TrySendError::Disconnected(_)) => return Err((500, "the render queue has stopped".to_string())),
+  }
+  renders.push(Render { state: State::Queued, size, total, tracker: Tracker::default() });
+  metrics::queued(service.queue.len());
+  log::info(&format!("queued job {}: {}x{}", index + 1, size.0, size.1));
+  Ok(status(index, &renders[index]))
+}
+
+fn get(service: &Service, target: &str) -> Result<(u16, &'static str, Vec<u8>), Failure> {
+  if target == "/metrics" {
+    return Ok((200, metrics::CONTENT_TYPE, metrics::text().into_bytes()));
+  }
+  let Some((Some(id), image)) = route(target) else {
+    return Err((404, format!("no such endpoint '{}'; try /renders/{{id}} or /renders/{{id}}/image", target)));
+  };
+  let renders = service.renders.lock().unwrap();
+  let index = id.checked_sub(1).filter(|&index| index < renders.len()).ok_or((404, format!("no render {}", id)))?;
+  if !image {
+    return Ok((200, "application/json", status(index, &renders[index]).to_string().into_bytes()));
+  }
+  match &renders[index].state {
+    State::Done { .. } => {}
+    State::Failed(_) => return Err((409, format!("render {} failed, so has no image", id))),
+    _ => return Err((409, format!("render {} isn't done yet", id))),
+  }
+  drop(renders);
+  let file = service.settings.dir.join(format!("{}.png", id));
+  let png = std::fs::read(&file).map_err(|e| (500, format!("error reading {}: {}", file.display(), e)))?;
+  Ok((200, "image/png", png))
+}
+
+// What GET /renders/{id} answers.
+fn status(index: usize, render: &Render) -> Value {
+  let id = index + 1;
+  let (name, progress) = match render.state {
+    State::Queued => ("queued", 0.0),
+    State::Running => ("running", (render.tracker.done() as f64 / render.total as f64).min(1.0)),
+    State::Done { .. } => ("done", 1.0),
+    State::Failed(_) => ("failed", 0.0),
+  };
+  let mut fields = vec![
+    ("id".to_string(), id.into()),
+    ("status".to_string(), name.into()),
+    ("progress".to_string(), progress.into()),
+    ("size".to_string(), Value::Array(vec![render.size.0.into(), render.size.1.into()])),
+    ("url".to_string(), format!("/renders/{}", id).as_str().into()),
+  ];
+  match &render.state {
+    State::Done { seconds } => {
+      fields.push(("seconds".to_string(), (*seconds).into()));
+      fields.push(("image".to_string(), format!("/renders/{}/image", id).as_str().into()));
+    }
+    State::Failed(message) => fields.push(("error".to_string(), message.as_str().into())),
+    _ => {}
+  }
+  Value::Object(fields)
+}
+
+#[test]
+fn test_routes() {
+  assert_eq!(route("/renders"), Some((None, false)));
+  assert_eq!(route("/renders/12"), Some((Some(12), false)));
+  assert_eq!(route("/renders/12/image?x=1"), Some((Some(12), true)));
+  assert_eq!(route("/renders/twelve"), None);
+  assert_eq!(route("/tile/0/0/0.png"), None);
+}
+
+#[test]
+fn test_serves_submitted_renders() {
+  let dir = tempfile::tempdir().unwrap();
+  let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+  let address = listener.local_addr().unwrap();
+  let settings = Settings { jobs: 1, threads: 2, dir: dir.path().to_path_buf() };
+  std::thread::spawn(move || serve_listener(listener, settings));
+
+  let request = |method: &str, path: &str, body: &str| {
+    let mut stream = TcpStream::connect(address).unwrap();
+    write!(stream, "{} {} HTTP/1.1\r\nHost: localhost\r\nContent-Length: {}\r\n\r\n{}", method, path, body.len(), body).unwrap();
+    let mut response = Vec::new();
+    stream.read_to_end(&mut response).unwrap();
+    let split = response.windows(4).position(|window| window == b"\r\n\r\n").unwrap();
+    let status: u16 = String::from_utf8_lossy(&response[9..12]).parse().unwrap();
+    (status, response[split + 4..].to_vec())
+  };
+  let json = |body: Vec<u8>| json::parse(&String::from_utf8(body).unwrap()).unwrap().to_string();
+
+  let (status, body) = request("POST", "/renders", r#"{"schema_version": 1, "size": [40, 30], "upper_left": "-2,1", "lower_right": "1,-1", "max_iter": 50}"#);
+  assert_eq!((status, json(body)), (202, r#"{"id":1,"status":"queued","progress":0,"size":[40,30],"url":"/renders/1"}"#.to_string()));
+  assert_eq!(request("POST", "/renders", r#"{"schema_version": 1, "output": "/etc/x.png", "size": [4, 3]}"#).0, 400);
+  assert_eq!(request("POST", "/renders", r#"{"schema_version": 1, "size": [40, 30]}"#).0, 400);
+  assert_eq!(request("POST", "/renders", "[1,").0, 400);
+  assert_eq!(request("POST", "/renders", &"[".repeat(500_000)).0, 400);
+  let view = r#""schema_version": 1, "size": [40, 30], "upper_left": "-2,1", "lower_right": "1,-1""#;
+  assert_eq!(request("POST", "/renders", &format!("{{{}, \"max_iter\": {}}}", view, MAX_ITER + 1)).0, 400);
+  assert_eq!(request("POST", "/renders", &format!("{{{}, \"supersample\": 3, \"samples\": 8}}", view)).0, 400);
+  assert_eq!(request("POST", "/renders", &format!("{{{}, \"samples\": {}}}", view, MAX_SAMPLES + 1)).0, 400);
+  assert_eq!(request("GET", "/renders/2", "").0, 404);
+  assert_eq!(request("DELETE", "/renders/1", "").0, 405);
+
+  let deadline = Instant::now() + Duration::from_secs(30);
+  while !json(request("GET", "/renders/1", "").1).contains(r#""status":"done""#) {
+    assert!(Instant::now() < deadline, "the render never finished");
+    std::thread::sleep(Duration::from_millis(20));
+  }
+  let (status, png) = request("GET", "/renders/1/image", "");
+  assert_eq!(status, 200);
+  assert!(png.starts_with(b"\x89PNG"));
+  assert_eq!(png, std::fs::read(dir.path().join("1.png")).unwrap());
+  let (status, text) = request("GET", "/metrics", "");
+  assert_eq!(status, 200);
+  assert!(String::from_utf8(text).unwrap().contains("# TYPE mandel_renders_total counter"));
+}
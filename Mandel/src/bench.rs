@@ -0,0 +1,74 @@
+// Benchmark
+// Renders a fixed set of reference scenes with each backend across a range of thread
+// counts, so performance changes show up as numbers rather than impressions.
+
+use std::time::{Duration, Instant};
+
+use num::Complex;
+
+use crate::double_double::DoubleDouble;
+use crate::perturbation::Perturbation;
+use crate::{render_parallel, Plane, Sampler};
+
+const SIZE: (usize, usize) = (800, 600);
+
+struct Scene {
+  name: &'static str,
+  upper_left: Complex<f64>,
+  lower_right: Complex<f64>,
+}
+
+const SCENES: [Scene; 3] = [
+  Scene { name: "full set", upper_left: Complex { re: -2.2, im: 1.2 }, lower_right: Complex { re: 1.0, im: -1.2 } },
+  Scene { name: "seahorse valley", upper_left: Complex { re: -0.80, im: 0.20 }, lower_right: Complex { re: -0.70, im: 0.125 } },
+  Scene { name: "book example", upper_left: Complex { re: -1.20, im: 0.35 }, lower_right: Complex { re: -1.0, im: 0.20 } },
+];
+
+pub fn run(max_threads: usize) -> Result<(), String> {
+  println!("{:<16} {:<13} {:>7} {:>10} {:>10}", "scene", "backend", "threads", "time (ms)", "Mpixel/s");
+
+  for scene in &SCENES {
+    let single = |c: Complex<f64>| Complex { re: c.re as f32, im: c.im as f32 };
+    let dd = |c: Complex<f64>| (DoubleDouble::new(c.re), DoubleDouble::new(c.im));
+    let backends: [(&str, Box<dyn Sampler>); 3] = [
+      ("f64", Box::new(Plane::mandelbrot(SIZE, scene.upper_left, scene.lower_right, 255))),
+      ("f32", Box::new(Plane::mandelbrot(SIZE, single(scene.upper_left), single(scene.lower_right), 255))),
+      ("perturbation", Box::new(Perturbation::new(SIZE, dd(scene.upper_left), dd(scene.lower_right), 255, false))),
+    ];
+
+    for (backend, sampler) in &backends {
+      for threads in thread_counts(max_threads) {
+        let elapsed = time_render(sampler.as_ref(), threads)?;
+        let rate = (SIZE.0 * SIZE.1) as f64 / elapsed.as_secs_f64() / 1e6;
+        println!("{:<16} {:<13} {:>7} {:>10.1} {:>10.2}", scene.name, backend, threads, elapsed.as_secs_f64() * 1e3, rate);
+      }
+    }
+  }
+  Ok(())
+}
+
+// 1, 2, 4, ... up to and always including `max`.
+fn thread_counts(max: usize) -> Vec<usize> {
+  let mut counts: Vec<usize> = std::iter::successors(Some(1), |n| Some(n * 2)).take_while(|&n| n < max).collect();
+  counts.push(max);
+  counts
+}
+
+// Best of three runs, to keep scheduling noise out of the table.
+fn time_render(sampler: &dyn Sampler, threads: usize) -> Result<Duration, String> {
+  let mut pixels = vec![0; SIZE.0 * SIZE.1];
+  let mut best = Duration::MAX;
+  for _ in 0..3 {
+    let start = Instant::now();
+    render_parallel(&mut pixels, SIZE, sampler, threads, 0, 1, true)?;
+    best = best.min(start.elapsed());
+  }
+  Ok(best)
+}
+
+#[test]
+fn test_thread_counts() {
+  assert_eq!(thread_counts(1), [1]);
+  assert_eq!(thread_counts(6), [1, 2, 4, 6]);
+  assert_eq!(thread_counts(8), [1, 2, 4, 8]);
+}
@@ -0,0 +1,87 @@
+// Arbitrary-precision floats
+// A binary big float with a caller-chosen number of significand bits, for reference
+// orbits and coordinates at zooms even double-double can't reach.
+
+use std::ops::{Add, Div, Mul, Sub};
+use std::str::FromStr;
+
+use dashu_float::round::mode::HalfEven;
+use dashu_float::{DBig, FBig};
+
+use crate::perturbation::Real;
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct BigFloat(FBig<HalfEven, 2>);
+
+impl BigFloat {
+  // Parses a decimal string rounded to `bits` of precision. Arithmetic on the result
+  // keeps that precision, since operations use the larger of their operands'.
+  pub fn parse(string: &str, bits: usize) -> Result<BigFloat, String> {
+    let decimal = DBig::from_str(string).map_err(|_| format!("invalid number '{}'", string))?;
+    let binary = decimal.with_rounding::<HalfEven>().with_base_and_precision::<2>(bits).value();
+    Ok(BigFloat(binary))
+  }
+}
+
+impl Add for BigFloat {
+  type Output = BigFloat;
+
+  fn add(self, other: BigFloat) -> BigFloat {
+    BigFloat(self.0 + other.0)
+  }
+}
+
+impl Sub for BigFloat {
+  type Output = BigFloat;
+
+  fn sub(self, other: BigFloat) -> BigFloat {
+    BigFloat(self.0 - other.0)
+  }
+}
+
+impl Mul for BigFloat {
+  type Output = BigFloat;
+
+  fn mul(self, other: BigFloat) -> BigFloat {
+    BigFloat(self.0 * other.0)
+  }
+}
+
+impl Div for BigFloat {
+  type Output = BigFloat;
+
+  fn div(self, other: BigFloat) -> BigFloat {
+    BigFloat(self.0 / other.0)
+  }
+}
+
+impl Real for BigFloat {
+  fn from_f64(value: f64) -> BigFloat {
+    BigFloat(FBig::try_from(value).expect("non-finite value in high-precision arithmetic"))
+  }
+
+  fn to_f64(&self) -> f64 {
+    self.0.to_f64().value()
+  }
+}
+
+#[test]
+fn test_big_float_keeps_requested_precision() {
+  let a = BigFloat::parse("1.00000000000000000000000000000000000000000000000000000000003", 256).unwrap();
+  let b = BigFloat::parse("1.00000000000000000000000000000000000000000000000000000000001", 256).unwrap();
+  assert_eq!(a.0.precision(), 256);
+
+  let difference = (a - b).to_f64();
+  assert!((difference - 2e-59).abs() < 1e-70);
+
+  let scaled = BigFloat::parse("3", 256).unwrap() * BigFloat::from_f64(0.5);
+  assert_eq!(scaled.0.precision(), 256);
+  assert_eq!(scaled.to_f64(), 1.5);
+}
+
+#[test]
+fn test_parse_big_float_rejects_garbage() {
+  assert!(BigFloat::parse("", 128).is_err());
+  assert!(BigFloat::parse("1.2.3", 128).is_err());
+  assert!(BigFloat::parse("-0.75e-3", 128).is_ok());
+}
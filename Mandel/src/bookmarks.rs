@@ -0,0 +1,124 @@
+// Bookmarks
+// Named views saved from the viewer and recalled there or by renders with --bookmark.
+// They live one per line in a per-user text file:
+//
+//   NAME CENTER_RE,CENTER_IM WIDTH LIMIT PALETTE
+//
+// The file is $MANDEL_BOOKMARKS if set, otherwise mandel/bookmarks under
+// $XDG_CONFIG_HOME or ~/.config.
+
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+
+use num::Complex;
+
+use crate::palette::{Palette, PALETTES};
+use crate::parse_complex;
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Bookmark {
+  pub name: String,
+  pub center: Complex<f64>,
+  // Extent of the real axis.
+  pub width: f64,
+  pub limit: usize,
+  pub palette: Palette,
+}
+
+pub fn path() -> Result<PathBuf, String> {
+  if let Some(path) = std::env::var_os("MANDEL_BOOKMARKS") {
+    return Ok(PathBuf::from(path));
+  }
+  let config = std::env::var_os("XDG_CONFIG_HOME").map(PathBuf::from)
+    .or_else(|| std::env::var_os("HOME").map(|home| PathBuf::from(home).join(".config")))
+    .ok_or("can't find the bookmarks file: set MANDEL_BOOKMARKS or HOME")?;
+  Ok(config.join("mandel").join("bookmarks"))
+}
+
+// Every bookmark in the file, which is empty if it doesn't exist yet.
+pub fn load(path: &Path) -> Result<Vec<Bookmark>, String> {
+  let text = match std::fs::read_to_string(path) {
+    Ok(text) => text,
+    Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(Vec::new()),
+    Err(e) => return Err(format!("error reading bookmarks '{}': {}", path.display(), e)),
+  };
+  text.lines().enumerate()
+    .filter(|(_, line)| !line.trim().is_empty())
+    .map(|(i, line)| parse(line).ok_or(format!("{}:{}: malformed bookmark '{}'", path.display(), i + 1, line)))
+    .collect()
+}
+
+// The bookmark called `name` in the user's file.
+pub fn find(name: &str) -> Result<Bookmark, String> {
+  let path = path()?;
+  load(&path)?.into_iter().find(|bookmark| bookmark.name == name)
+    .ok_or(format!("no bookmark named '{}' in {}", name, path.display()))
+}
+
+// Adds `bookmark` to the file, replacing any of the same name, and creating the file
+// and its directory if need be.
+pub fn save(path: &Path, bookmark: &Bookmark) -> Result<(), String> {
+  if !valid_name(&bookmark.name) {
+    return Err(format!("bookmark names can't be empty or contain spaces: '{}'", bookmark.name));
+  }
+  let mut bookmarks = load(path)?;
+  match bookmarks.iter_mut().find(|saved| saved.name == bookmark.name) {
+    Some(saved) => *saved = bookmark.clone(),
+    None => bookmarks.push(bookmark.clone()),
+  }
+
+  let write = || -> Result<(), io::Error> {
+    if let Some(dir) = path.parent() {
+      std::fs::create_dir_all(dir)?;
+    }
+    let mut file = io::BufWriter::new(std::fs::File::create(path)?);
+    for bookmark in &bookmarks {
+      writeln!(file, "{}", format(bookmark))?;
+    }
+    file.flush()
+  };
+  write().map_err(|e| format!("error writing bookmarks '{}': {}", path.display(), e))
+}
+
+fn valid_name(name: &str) -> bool {
+  !name.is_empty() && !name.contains(char::is_whitespace)
+}
+
+// A bookmark as a line of the file, without the newline.
+pub fn format(bookmark: &Bookmark) -> String {
+  format!("{} {},{} {:e} {} {}", bookmark.name, bookmark.center.re, bookmark.center.im, bookmark.width, bookmark.limit, bookmark.palette.name())
+}
+
+pub fn parse(line: &str) -> Option<Bookmark> {
+  let &[name, center, width, limit, palette] = line.split_whitespace().collect::<Vec<_>>().as_slice() else {
+    return None;
+  };
+  Some(Bookmark {
+    name: name.to_string(),
+    center: parse_complex(center).filter(|center| center.re.is_finite() && center.im.is_finite())?,
+    width: f64::from_str(width).ok().filter(|width| width.is_finite() && *width > 0.0)?,
+    limit: usize::from_str(limit).ok().filter(|&limit| limit > 0)?,
+    palette: *PALETTES.iter().find(|candidate| candidate.name() == palette)?,
+  })
+}
+
+#[test]
+fn test_bookmarks_round_trip() {
+  let path = std::env::temp_dir().join(format!("mandel-bookmarks-{}", std::process::id())).join("bookmarks");
+  let seahorse = Bookmark { name: "seahorse-3".to_string(), center: Complex { re: -0.7435, im: 0.1314 }, width: 0.002, limit: 1000, palette: Palette::Fire };
+  let spiral = Bookmark { name: "spiral".to_string(), center: Complex { re: -0.1, im: 0.9 }, width: 1e-9, limit: 4000, palette: Palette::Gray };
+
+  assert_eq!(load(&path).unwrap(), []);
+  save(&path, &seahorse).unwrap();
+  save(&path, &spiral).unwrap();
+  // Saving under an existing name replaces that bookmark in place.
+  let deeper = Bookmark { width: 0.0001, ..seahorse };
+  save(&path, &deeper).unwrap();
+  assert_eq!(load(&path).unwrap(), [deeper.clone(), spiral]);
+
+  assert!(save(&path, &Bookmark { name: "two words".to_string(), ..deeper }).is_err());
+  assert_eq!(parse("broken -0.5,0 nan 100 gray"), None);
+
+  std::fs::remove_dir_all(path.parent().unwrap()).unwrap();
+}
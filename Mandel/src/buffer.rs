@@ -0,0 +1,78 @@
+// Pixel buffers
+// The rendered image lives either in ordinary memory or in a memory-mapped temporary
+// file, which lets the OS page a gigapixel render out to disk instead of exhausting RAM.
+
+use std::io;
+use std::ops::{Deref, DerefMut};
+
+use memmap2::MmapMut;
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum BufferKind {
+  Memory,
+  Mmap,
+}
+
+pub enum PixelBuffer {
+  Memory(Vec<u32>),
+  // The file is unlinked as soon as it's created, so it vanishes with the mapping.
+  Mapped(MmapMut),
+}
+
+impl PixelBuffer {
+  // A zero-filled buffer of `len` pixels.
+  pub fn new(len: usize, kind: BufferKind) -> Result<PixelBuffer, io::Error> {
+    match kind {
+      BufferKind::Memory => Ok(PixelBuffer::Memory(vec![0; len])),
+      BufferKind::Mmap => {
+        let file = tempfile::tempfile()?;
+        file.set_len((len * size_of::<u32>()) as u64)?;
+        let map = unsafe { MmapMut::map_mut(&file)? };
+        Ok(PixelBuffer::Mapped(map))
+      }
+    }
+  }
+}
+
+// Mappings start on a page boundary, so they are aligned for u32.
+impl Deref for PixelBuffer {
+  type Target = [u32];
+
+  fn deref(&self) -> &[u32] {
+    match self {
+      PixelBuffer::Memory(pixels) => pixels,
+      PixelBuffer::Mapped(map) => unsafe { std::slice::from_raw_parts(map.as_ptr().cast(), map.len() / size_of::<u32>()) },
+    }
+  }
+}
+
+impl DerefMut for PixelBuffer {
+  fn deref_mut(&mut self) -> &mut [u32] {
+    match self {
+      PixelBuffer::Memory(pixels) => pixels,
+      PixelBuffer::Mapped(map) => unsafe { std::slice::from_raw_parts_mut(map.as_mut_ptr().cast(), map.len() / size_of::<u32>()) },
+    }
+  }
+}
+
+// Pixels as the little-endian bytes checkpoints, tile caches and workers pass around.
+pub fn to_bytes(pixels: &[u32]) -> Vec<u8> {
+  pixels.iter().flat_map(|pixel| pixel.to_le_bytes()).collect()
+}
+
+pub fn from_bytes(bytes: &[u8]) -> Vec<u32> {
+  bytes.chunks_exact(4).map(|pixel| u32::from_le_bytes(pixel.try_into().unwrap())).collect()
+}
+
+#[test]
+fn test_mapped_buffer_is_zeroed_and_writable() {
+  let mut buffer = PixelBuffer::new(4096 * 3, BufferKind::Mmap).unwrap();
+  assert_eq!(buffer.len(), 4096 * 3);
+  assert!(buffer.iter().all(|&value| value == 0));
+
+  buffer[5000] = 42;
+  assert_eq!(buffer[5000], 42);
+  buffer[4096 * 3 - 1] = u32::MAX;
+
+  assert_eq!(from_bytes(&to_bytes(&[7, 1 << 31, u32::MAX])), [7, 1 << 31, u32::MAX]);
+}
@@ -0,0 +1,78 @@
+// Tile cache
+// Rendered tiles stored on disk under a hash of everything that determines their
+// pixels, so rendering an overlapping or repeated view reuses earlier work.
+
+use std::fs;
+use std::io::{self, Write};
+use std::path::PathBuf;
+
+use crate::buffer::{from_bytes, to_bytes};
+
+// Tiles are squares of this many pixels, aligned to the image's top-left corner.
+pub const TILE_SIZE: usize = 256;
+
+pub struct TileCache {
+  dir: PathBuf,
+}
+
+impl TileCache {
+  pub fn open(dir: &str) -> Result<TileCache, io::Error> {
+    fs::create_dir_all(dir)?;
+    Ok(TileCache { dir: PathBuf::from(dir) })
+  }
+
+  // The cached pixels for `key`, if present and intact.
+  pub fn get(&self, key: &str, len: usize) -> Option<Vec<u32>> {
+    let data = fs::read(self.path(key)).ok()?;
+    let header = format!("{}\n", key);
+    if data.len() != header.len() + 4 * len || !data.starts_with(header.as_bytes()) {
+      return None;
+    }
+    Some(from_bytes(&data[header.len()..]))
+  }
+
+  // Stores a tile. The key is written ahead of the pixels so hash collisions read as
+  // misses, and the file is renamed into place so readers never see half a tile.
+  pub fn put(&self, key: &str, pixels: &[u32]) -> Result<(), io::Error> {
+    let path = self.path(key);
+    let partial = path.with_extension(format!("partial-{}", std::process::id()));
+
+    let mut file = fs::File::create(&partial)?;
+    writeln!(file, "{}", key)?;
+    file.write_all(&to_bytes(pixels))?;
+    drop(file);
+
+    fs::rename(partial, path)
+  }
+
+  fn path(&self, key: &str) -> PathBuf {
+    self.dir.join(format!("{:016x}.tile", fnv1a(key.as_bytes())))
+  }
+}
+
+// FNV-1a: tiny, and unlike std's hasher guaranteed stable across Rust releases,
+// which matters for file names that outlive the process.
+pub fn fnv1a(bytes: &[u8]) -> u64 {
+  bytes.iter().fold(0xcbf29ce484222325, |hash, &byte| (hash ^ byte as u64).wrapping_mul(0x100000001b3))
+}
+
+#[test]
+fn test_fnv1a() {
+  assert_eq!(fnv1a(b""), 0xcbf29ce484222325);
+  assert_eq!(fnv1a(b"a"), 0xaf63dc4c8601ec8c);
+}
+
+#[test]
+fn test_tile_cache_round_trip() {
+  let dir = std::env::temp_dir().join(format!("mandel-cache-{}", std::process::id()));
+  let cache = TileCache::open(dir.to_str().unwrap()).unwrap();
+
+  assert_eq!(cache.get("tile a", 4), None);
+  cache.put("tile a", &[1, 2, 3, 1000]).unwrap();
+  assert_eq!(cache.get("tile a", 4), Some(vec![1, 2, 3, 1000]));
+  // A different size is treated as a miss rather than returning the wrong pixels.
+  assert_eq!(cache.get("tile a", 6), None);
+  assert_eq!(cache.get("tile b", 4), None);
+
+  fs::remove_dir_all(dir).unwrap();
+}
@@ -0,0 +1,117 @@
+// Render checkpoints
+// A checkpoint file holds the command line that started a render followed by the rows
+// finished so far, four little-endian bytes a pixel, appended as they complete.
+// Resuming re-parses the command line and carries on from the first missing row.
+
+use std::fs::{File, OpenOptions};
+use std::io::{self, BufRead, BufReader, Read, Seek, SeekFrom, Write};
+
+use crate::buffer::{from_bytes, to_bytes};
+
+const MAGIC: &str = "mandel-checkpoint 2";
+
+pub struct Checkpoint {
+  file: File,
+}
+
+impl Checkpoint {
+  // Starts a fresh checkpoint for a render invoked with `arguments`.
+  pub fn create(path: &str, arguments: &[String]) -> Result<Checkpoint, io::Error> {
+    let mut file = File::create(path)?;
+    writeln!(file, "{}", MAGIC)?;
+    writeln!(file, "{}", arguments.len())?;
+    for argument in arguments {
+      writeln!(file, "{}", argument)?;
+    }
+    file.sync_data()?;
+    Ok(Checkpoint { file })
+  }
+
+  // Reopens a checkpoint, copying the rows it holds into the start of `pixels` and
+  // returning how many there were. A partially written trailing row is discarded.
+  pub fn resume(path: &str, pixels: &mut [u32], width: usize) -> Result<(Checkpoint, usize), io::Error> {
+    let mut file = OpenOptions::new().read(true).write(true).open(path)?;
+    let (_, data_start) = read_header(&mut file)?;
+
+    let (len, row) = (file.metadata()?.len(), 4 * width);
+    let rows = (((len - data_start) / row as u64) as usize).min(pixels.len() / width);
+
+    file.seek(SeekFrom::Start(data_start))?;
+    let mut bytes = vec![0; rows * row];
+    file.read_exact(&mut bytes)?;
+    pixels[..rows * width].copy_from_slice(&from_bytes(&bytes));
+    file.set_len(data_start + (rows * row) as u64)?;
+    file.seek(SeekFrom::End(0))?;
+
+    Ok((Checkpoint { file }, rows))
+  }
+
+  // Appends finished rows, which must directly follow those already saved.
+  pub fn append(&mut self, rows: &[u32]) -> Result<(), io::Error> {
+    self.file.write_all(&to_bytes(rows))?;
+    self.file.sync_data()
+  }
+}
+
+// The command line a checkpoint was created with.
+pub fn read_arguments(path: &str) -> Result<Vec<String>, io::Error> {
+  let (arguments, _) = read_header(&mut File::open(path)?)?;
+  Ok(arguments)
+}
+
+// Returns the saved arguments and the offset where row data starts.
+fn read_header(file: &mut File) -> Result<(Vec<String>, u64), io::Error> {
+  let invalid = |message: &str| io::Error::new(io::ErrorKind::InvalidData, format!("not a mandel checkpoint: {}", message));
+
+  file.seek(SeekFrom::Start(0))?;
+  let mut reader = BufReader::new(file);
+  let mut offset = 0;
+  let mut next_line = |reader: &mut BufReader<&mut File>| -> Result<String, io::Error> {
+    let mut line = String::new();
+    offset += reader.read_line(&mut line)? as u64;
+    if !line.ends_with('\n') {
+      return Err(invalid("truncated header"));
+    }
+    line.pop();
+    Ok(line)
+  };
+
+  if next_line(&mut reader)? != MAGIC {
+    return Err(invalid("bad magic line"));
+  }
+  let count = next_line(&mut reader)?.parse::<usize>().map_err(|_| invalid("bad argument count"))?;
+  let mut arguments = Vec::with_capacity(count);
+  for _ in 0..count {
+    arguments.push(next_line(&mut reader)?);
+  }
+
+  Ok((arguments, offset))
+}
+
+#[test]
+fn test_checkpoint_round_trip() {
+  let path = std::env::temp_dir().join(format!("mandel-checkpoint-{}.mdl", std::process::id()));
+  let path = path.to_str().unwrap();
+  let arguments = vec!["out.png".to_string(), "4x3".to_string(), "-1,1".to_string(), "1,-1".to_string()];
+
+  let mut checkpoint = Checkpoint::create(path, &arguments).unwrap();
+  checkpoint.append(&[1, 2, 3, 4, 5, 6, 7, 1 << 31 | 8]).unwrap();
+  // Half a row, as if the process died mid-write.
+  checkpoint.append(&[9, 10]).unwrap();
+  drop(checkpoint);
+
+  assert_eq!(read_arguments(path).unwrap(), arguments);
+
+  let mut pixels = vec![0; 12];
+  let (mut checkpoint, rows) = Checkpoint::resume(path, &mut pixels, 4).unwrap();
+  assert_eq!(rows, 2);
+  assert_eq!(pixels, [1, 2, 3, 4, 5, 6, 7, 1 << 31 | 8, 0, 0, 0, 0]);
+
+  checkpoint.append(&[11, 12, 13, 14]).unwrap();
+  drop(checkpoint);
+  let (_, rows) = Checkpoint::resume(path, &mut pixels, 4).unwrap();
+  assert_eq!(rows, 3);
+  assert_eq!(pixels[8..], [11, 12, 13, 14]);
+
+  std::fs::remove_file(path).unwrap();
+}
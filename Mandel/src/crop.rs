@@ -0,0 +1,124 @@
+// Crops
+// `mandel crop --from OLD.png --rect X,Y,W,H` renders again the part of OLD.png inside the
+// W by H pixel rectangle whose top left pixel is (X, Y), as read off in an image viewer,
+// at --size: by default as wide as OLD.png and as tall as keeps the rectangle's shape.
+// The view comes from the arguments recorded in OLD.png's metadata, as for `mandel
+// rerender`, and so do all the other options, which any given after go after and win.
+// The new center is worked out in exact decimal arithmetic from the old one, so crops of
+// deep zooms keep every digit, and a crop of a rotated view is turned the same way.
+
+use std::str::FromStr;
+
+use dashu_float::DBig;
+
+use crate::{location, log, metadata, parse_arguments, parse_complex, parse_pair};
+
+// Pixel rectangle: left and top column and row, then width and height.
+pub type Rect = (usize, usize, usize, usize);
+
+// The render arguments of the crop the command line `options` asks for.
+pub fn arguments(options: &[String]) -> Result<Vec<String>, String> {
+  let (mut from, mut rect, mut size, mut output) = (None, None, None, None);
+  let mut added = Vec::new();
+  let mut options = options.iter();
+  while let Some(option) = options.next() {
+    match option.as_str() {
+      "--from" => from = Some(options.next().ok_or("--from expects a PNG this program rendered")?.as_str()),
+      "--rect" => rect = Some(options.next().and_then(|value| parse_rect(value)).ok_or("--rect expects X,Y,WIDTH,HEIGHT in pixels, the width and height positive")?),
+      "--size" => size = Some(options.next().and_then(|value| parse_pair::<usize>(value, 'x')).filter(|&(w, h)| w > 0 && h > 0).ok_or("--size expects WIDTHxHEIGHT in pixels")?),
+      "--output" => output = Some(options.next().ok_or("--output expects a file name")?.clone()),
+      _ => added.push(option.clone()),
+    }
+  }
+  let from = from.ok_or("crop needs --from OLD.png")?;
+  let (x, y, width, height) = rect.ok_or("crop needs --rect X,Y,WIDTH,HEIGHT")?;
+
+  let (recorded, bounds) = metadata::arguments(from)?;
+  if x + width > bounds.0 || y + height > bounds.1 {
+    return Err(format!("--rect {},{},{},{} reaches outside '{}', which is {}x{}", x, y, width, height, from, bounds.0, bounds.1));
+  }
+  let old = parse_arguments(&[vec![from.to_string()], recorded.clone()].concat())?;
+  let (upper_left, lower_right) = crop_corners(&old.upper_left, &old.lower_right, bounds, (x, y, width, height), old.rotation)?;
+
+  let size = size.unwrap_or((bounds.0, (bounds.0 * height).div_ceil(width)));
+  let (aspect, new_aspect) = (width as f64 / height as f64, size.0 as f64 / size.1 as f64);
+  if (aspect / new_aspect - 1.0).abs() > 0.01 {
+    log::warn(&format!("a {}x{} crop at --size {}x{} stretches its pixels {:.2} times as wide as they are high", width, height, size.0, size.1, aspect / new_aspect));
+  }
+  let output = output.unwrap_or_else(|| {
+    let path = std::path::Path::new(from);
+    let stem = path.file_stem().map_or("mandel".into(), |stem| stem.to_string_lossy());
+    path.with_file_name(format!("{}-crop-{}-{}-{}x{}.png", stem, x, y, width, height)).to_string_lossy().into_owned()
+  });
+
+  let rest = options_but_view(recorded, bounds, from)?;
+  Ok([vec![output, format!("{}x{}", size.0, size.1), upper_left, lower_right], rest, added].concat())
+}
+
+// The arguments `recorded` in the image at `path`, rendered at `bounds`, but its size and
+// corners.
+pub fn options_but_view(mut recorded: Vec<String>, bounds: (usize, usize), path: &str) -> Result<Vec<String>, String> {
+  let pixels = recorded.iter().position(|arg| parse_pair::<usize>(arg, 'x') == Some(bounds))
+    .ok_or(format!("the arguments recorded in '{}' don't give its size", path))?;
+  recorded.remove(pixels);
+  recorded.retain(|arg| parse_complex(arg).is_none());
+  Ok(recorded)
+}
+
+fn parse_rect(text: &str) -> Option<Rect> {
+  let values: Vec<usize> = text.split(',').map(|value| usize::from_str(value.trim()).ok()).collect::<Option<_>>()?;
+  match values[..] {
+    [x, y, width, height] if width > 0 && height > 0 => Some((x, y, width, height)),
+    _ => None,
+  }
+}
+
+// The corners of the part `rect` covers of a `bounds` image of the view from `upper_left`
+// to `lower_right`, turned `rotation` degrees about its center.
+pub fn crop_corners(upper_left: &str, lower_right: &str, bounds: (usize, usize), rect: Rect, rotation: f64) -> Result<(String, String), String> {
+  let (center_re, center_im) = location::center(upper_left, lower_right)?;
+  let (a, b) = (parse_complex(upper_left).unwrap(), parse_complex(lower_right).unwrap());
+  let extent = (b.re - a.re, a.im - b.im);
+  let (x, y, width, height) = (rect.0 as f64, rect.1 as f64, rect.2 as f64, rect.3 as f64);
+  // From the old center to the new one before turning, then turned.
+  let offset = (((x + width / 2.0) / bounds.0 as f64 - 0.5) * extent.0, (0.5 - (y + height / 2.0) / bounds.1 as f64) * extent.1);
+  let (sin, cos) = rotation.to_radians().sin_cos();
+  let offset = (offset.0 * cos - offset.1 * sin, offset.0 * sin + offset.1 * cos);
+  let half = (width / bounds.0 as f64 * extent.0 / 2.0, height / bounds.1 as f64 * extent.1 / 2.0);
+
+  let exact = |value: f64| DBig::from_str(&format!("{:e}", value)).unwrap().with_precision(0).value();
+  let (re, im) = (center_re + exact(offset.0), center_im + exact(offset.1));
+  let (half_re, half_im) = (exact(half.0), exact(half.1));
+  Ok((format!("{},{}", &re - &half_re, &im + &half_im), format!("{},{}", &re + &half_re, &im - &half_im)))
+}
+
+#[test]
+fn test_crops() {
+  assert_eq!(parse_rect("10, 20,30,40"), Some((10, 20, 30, 40)));
+  assert_eq!(parse_rect("10,20,0,40"), None);
+  assert_eq!(parse_rect("10,20,30"), None);
+
+  // The lower right quarter of a view from -2,1 to 2,-1.
+  let (upper_left, lower_right) = crop_corners("-2,1", "2,-1", (400, 200), (200, 100, 200, 100), 0.0).unwrap();
+  assert_eq!((parse_complex(&upper_left), parse_complex(&lower_right)), (parse_complex("0,0"), parse_complex("2,-1")));
+  // Turned a quarter about the center, the same pixels show the lower left quarter.
+  let (upper_left, lower_right) = crop_corners("-2,1", "2,-1", (400, 200), (200, 100, 200, 100), 90.0).unwrap();
+  let center = (parse_complex(&upper_left).unwrap() + parse_complex(&lower_right).unwrap()) / 2.0;
+  assert!((center - num::Complex { re: 0.5, im: 1.0 }).norm() < 1e-12, "{}", center);
+  // Deep views keep every digit of the center.
+  let (upper_left, _) = crop_corners("-0.74364388703715870475,0.13182590420531197051", "-0.74364388703715870465,0.13182590420531197044",
+                                     (100, 70), (0, 0, 50, 35), 0.0).unwrap();
+  assert!(upper_left.starts_with("-0.7436438870371587047"), "{}", upper_left);
+
+  let dir = tempfile::tempdir().unwrap();
+  let old = dir.path().join("old.png");
+  let old = old.to_str().unwrap();
+  crate::render(crate::test_arguments(&[old, "40x30", "-2,1.5", "2,-1.5", "--max-iter", "50", "--no-progress"]).unwrap()).unwrap();
+  let crop = arguments(&["--from", old, "--rect", "20,0,20,15", "--force"].map(String::from)).unwrap();
+  let args = parse_arguments(&crop).unwrap();
+  assert_eq!(args.file, dir.path().join("old-crop-20-0-20x15.png").to_str().unwrap());
+  assert_eq!(args.pixels, "40x30");
+  assert_eq!((parse_complex(&args.upper_left), parse_complex(&args.lower_right)), (parse_complex("0,1.5"), parse_complex("2,0")));
+  assert_eq!(args.max_iter, crate::MaxIter::Fixed(50));
+  assert!(arguments(&["--from", old, "--rect", "30,0,20,15"].map(String::from)).unwrap_err().contains("reaches outside"));
+}
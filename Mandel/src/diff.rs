@@ -0,0 +1,97 @@
+// Diffs
+// `mandel diff A.png B.png` compares two images of the same size pixel by pixel, to check
+// that one way of rendering a view matches another: perturbation against direct
+// iteration, say, or single precision against double. A pixel's difference is the
+// largest between any of its channels, a gray image counting as colored with every
+// channel alike when the other is colored. It prints how many pixels differ and the
+// largest and mean differences; --out HEAT.png draws the differences as a heat map in
+// the fire palette, scaled so the largest is white, and --tolerance N fails the diff if
+// any pixel differs by more than N.
+
+use std::str::FromStr;
+
+use crate::json::Value;
+use crate::palette::Palette;
+use crate::{read_image, report, write_rgb_image};
+
+pub fn main(arguments: &[String]) -> Result<Vec<(String, Value)>, String> {
+  let (mut paths, mut out, mut tolerance) = (Vec::new(), None, None);
+  let mut options = arguments.iter();
+  while let Some(option) = options.next() {
+    match option.as_str() {
+      "--out" => out = Some(options.next().ok_or("--out expects a PNG file name")?.as_str()),
+      "--tolerance" => tolerance = Some(options.next().and_then(|value| u8::from_str(value).ok()).ok_or("--tolerance expects a difference from 0 to 255")?),
+      path if !path.starts_with("--") => paths.push(path),
+      _ => return Err("diff accepts A.png B.png, --out HEAT.png and --tolerance N".to_string()),
+    }
+  }
+  let [a, b] = paths[..] else {
+    return Err(format!("diff expects two images to compare, got {}", paths.len()));
+  };
+
+  let (first, second) = (read_image(a)?, read_image(b)?);
+  if first.bounds != second.bounds {
+    return Err(format!("'{}' is {}x{} but '{}' is {}x{}", a, first.bounds.0, first.bounds.1, b, second.bounds.0, second.bounds.1));
+  }
+  let differences = differences((&first.pixels, first.channels), (&second.pixels, second.channels));
+  let differing = differences.iter().filter(|&&difference| difference > 0).count();
+  let largest = differences.iter().copied().max().unwrap_or(0);
+  let mean = differences.iter().map(|&difference| difference as f64).sum::<f64>() / differences.len() as f64;
+
+  if !report::json() {
+    println!("pixels   {} differ of {} ({:.3}%)", differing, differences.len(), 100.0 * differing as f64 / differences.len() as f64);
+    println!("largest  {}", largest);
+    println!("mean     {:.4}", mean);
+  }
+  if let Some(path) = out {
+    write_rgb_image(path, &heat_map(&differences, largest), first.bounds).map_err(|e| format!("error writing heat map '{}': {}", path, e))?;
+  }
+  if let Some(tolerance) = tolerance.filter(|&tolerance| largest > tolerance) {
+    return Err(format!("'{}' and '{}' differ by up to {}, more than the tolerance of {}", a, b, largest, tolerance));
+  }
+  Ok(vec![
+    ("pixels".to_string(), differences.len().into()),
+    ("differing".to_string(), differing.into()),
+    ("largest".to_string(), (largest as usize).into()),
+    ("mean".to_string(), mean.into()),
+  ])
+}
+
+// The largest difference between the channels of each pixel of two images, each given
+// as its pixels and how many channels they have.
+fn differences(a: (&[u8], usize), b: (&[u8], usize)) -> Vec<u8> {
+  let count = a.0.len() / a.1;
+  (0..count).map(|i| {
+    // A gray pixel's one channel stands for all three.
+    let channel = |image: (&[u8], usize), c: usize| image.0[i * image.1 + c.min(image.1 - 1)];
+    (0..a.1.max(b.1)).map(|c| channel(a, c).abs_diff(channel(b, c))).max().unwrap()
+  }).collect()
+}
+
+fn heat_map(differences: &[u8], largest: u8) -> Vec<u8> {
+  differences.iter().flat_map(|&difference| Palette::Fire.color((difference as usize * 255 / largest.max(1) as usize) as u8)).collect()
+}
+
+#[test]
+fn test_diffs() {
+  let gray = [10, 20, 30];
+  let rgb = [10, 10, 10, 20, 25, 20, 0, 30, 30];
+  assert_eq!(differences((&gray, 1), (&gray, 1)), [0, 0, 0]);
+  assert_eq!(differences((&gray, 1), (&rgb, 3)), [0, 5, 30]);
+  assert_eq!(differences((&rgb, 3), (&gray, 1)), [0, 5, 30]);
+  let heat = heat_map(&[0, 5, 30], 30);
+  assert_eq!((&heat[..3], &heat[6..]), (&[0, 0, 0][..], &[255, 255, 255][..]));
+
+  let dir = tempfile::tempdir().unwrap();
+  let path = |name: &str| dir.path().join(name).to_str().unwrap().to_string();
+  crate::write_image(&path("a.png"), &[0, 100, 200, 255], (2, 2)).unwrap();
+  crate::write_image(&path("b.png"), &[0, 104, 200, 250], (2, 2)).unwrap();
+  crate::write_image(&path("c.png"), &[0; 6], (3, 2)).unwrap();
+  let fields = main(&[path("a.png"), path("b.png"), "--out".to_string(), path("heat.png")]).unwrap();
+  assert_eq!(fields[1].1, Value::from(2usize));
+  assert_eq!(fields[2].1, Value::from(5usize));
+  assert_eq!(read_image(&path("heat.png")).unwrap().bounds, (2, 2));
+  assert!(main(&[path("a.png"), path("b.png"), "--tolerance".to_string(), "5".to_string()]).is_ok());
+  assert!(main(&[path("a.png"), path("b.png"), "--tolerance".to_string(), "4".to_string()]).unwrap_err().contains("up to 5"));
+  assert!(main(&[path("a.png"), path("c.png")]).unwrap_err().contains("3x2"));
+}
@@ -0,0 +1,235 @@
+// Distributed rendering
+// A coordinator farms strips of rows out to `mandel worker` processes over TCP and
+// assembles the results. The protocol is line-based: the coordinator opens with
+// "mandel-job 2", the argument count and the render's arguments one per line; the
+// worker answers "ok" or "error: ..."; then each "rows TOP COUNT" request is answered
+// with exactly COUNT rows of pixels, four little-endian bytes each. Closing the
+// connection ends the job, which counts in the worker's metrics as a render of the rows
+// it served.
+
+use std::io::{self, BufRead, BufReader, BufWriter, Read, Write};
+use std::net::{TcpListener, TcpStream, ToSocketAddrs};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+use crate::buffer::{from_bytes, to_bytes};
+use crate::{build_sampler, log, metrics, parse_arguments, parse_pair, progress, render_parallel, Sampler, THREAD_PANICKED};
+
+const MAGIC: &str = "mandel-job 2";
+
+// Rows per request: small enough to balance load across machines of different speeds,
+// large enough that round trips stay cheap next to the rendering.
+const STRIP_ROWS: usize = 32;
+
+const CONNECT_TIMEOUT: Duration = Duration::from_secs(5);
+
+// Accepts jobs on `address` until the process is killed.
+pub fn serve(address: &str, threads: usize) -> Result<(), io::Error> {
+  let listener = TcpListener::bind(address)?;
+  log::info(&format!("worker listening on {}", listener.local_addr()?));
+  serve_listener(listener, threads)
+}
+
+fn serve_listener(listener: TcpListener, threads: usize) -> Result<(), io::Error> {
+  for stream in listener.incoming() {
+    let stream = stream?;
+    std::thread::spawn(move || {
+      let peer = stream.peer_addr().map_or("unknown peer".to_string(), |address| address.to_string());
+      let (start, mut pixels) = (Instant::now(), 0);
+      let result = handle_job(stream, threads, &mut pixels);
+      metrics::rendered(pixels as u64, start.elapsed().as_secs_f64(), result.is_ok());
+      if let Err(e) = result {
+        log::warn(&format!("job from {} failed: {}", peer, e));
+      }
+    });
+  }
+  Ok(())
+}
+
+// Adds the pixels it sends to `pixels`.
+fn handle_job(stream: TcpStream, threads: usize, pixels: &mut usize) -> Result<(), io::Error> {
+  let mut reader = BufReader::new(stream.try_clone()?);
+  let mut writer = BufWriter::new(stream);
+
+  let arguments = read_job(&mut reader)?;
+  let job = parse_arguments(&arguments).and_then(|args| match parse_pair::<usize>(&args.pixels, 'x') {
+    Some(bounds) => Ok((build_sampler(&args, bounds)?, bounds)),
+    None => Err(format!("invalid image dimensions '{}'", args.pixels)),
+  });
+  let (sampler, bounds) = match job {
+    Ok(job) => job,
+    Err(message) => {
+      writeln!(writer, "error: {}", message)?;
+      return writer.flush();
+    }
+  };
+  writeln!(writer, "ok")?;
+  writer.flush()?;
+
+  let mut line = String::new();
+  while reader.read_line(&mut line)? > 0 {
+    let (top, count) = parse_request(line.trim_end())
+      .filter(|&(top, count)| count > 0 && top + count <= bounds.1)
+      .ok_or_else(|| invalid(&format!("bad request '{}'", line.trim_end())))?;
+
+    let mut strip = vec![0; count * bounds.0];
+    render_parallel(&mut strip, (bounds.0, count), sampler.as_ref(), threads, top, 1, true).map_err(io::Error::other)?;
+    writer.write_all(&to_bytes(&strip))?;
+    writer.flush()?;
+    *pixels += strip.len();
+    line.clear();
+  }
+  Ok(())
+}
+
+fn read_job(reader: &mut impl BufRead) -> Result<Vec<String>, io::Error> {
+  let mut next_line = || -> Result<String, io::Error> {
+    let mut line = String::new();
+    reader.read_line(&mut line)?;
+    if !line.ends_with('\n') {
+      return Err(invalid("truncated job header"));
+    }
+    line.pop();
+    Ok(line)
+  };
+
+  if next_line()? != MAGIC {
+    return Err(invalid("not a mandel job"));
+  }
+  let count = next_line()?.parse::<usize>().map_err(|_| invalid("bad argument count"))?;
+  (0..count).map(|_| next_line()).collect()
+}
+
+fn parse_request(line: &str) -> Option<(usize, usize)> {
+  let mut words = line.split(' ');
+  match (words.next(), words.next(), words.next(), words.next()) {
+    (Some("rows"), Some(top), Some(count), None) => Some((top.parse().ok()?, count.parse().ok()?)),
+    _ => None,
+  }
+}
+
+fn invalid(message: &str) -> io::Error {
+  io::Error::new(io::ErrorKind::InvalidData, message.to_string())
+}
+
+// Renders `pixels` on `workers`, handing each the render's `arguments`. Strips a worker
+// fails to deliver, or that are left over when every worker has dropped out, are
+// rendered locally with `sampler` instead.
+pub fn render(pixels: &mut [u32], bounds: (usize, usize), sampler: &dyn Sampler, threads: usize, workers: &[String], arguments: &[String]) -> Result<(), String> {
+  let strips = bounds.1.div_ceil(STRIP_ROWS);
+  let next = AtomicUsize::new(0);
+  let dropped = Mutex::new(Vec::new());
+  let (sender, receiver) = crossbeam::channel::unbounded();
+
+  crossbeam::scope(|spawner| {
+    for address in workers {
+      let (sender, next, dropped) = (sender.clone(), &next, &dropped);
+      spawner.spawn(move |_| {
+        if let Err(e) = farm(address, bounds, arguments, strips, next, &sender, dropped) {
+          log::warn(&format!("worker {}: {}; rendering its rows locally", address, e));
+        }
+      });
+    }
+    drop(sender);
+
+    for (strip, rows) in receiver {
+      let start = strip * STRIP_ROWS * bounds.0;
+      pixels[start..start + rows.len()].copy_from_slice(&rows);
+      progress::advance((0, strip * STRIP_ROWS), (bounds.0, rows.len() / bounds.0));
+    }
+  }).map_err(|_| THREAD_PANICKED.to_string())?;
+
+  let mut missing = dropped.into_inner().map_err(|_| THREAD_PANICKED.to_string())?;
+  missing.extend(next.into_inner().min(strips)..strips);
+  for strip in missing {
+    let (top, count) = strip_rows(strip, bounds);
+    let band = &mut pixels[top * bounds.0..(top + count) * bounds.0];
+    if render_parallel(band, (bounds.0, count), sampler, threads, top, 1, true)? < count {
+      return Err("interrupted; nothing was saved".to_string());
+    }
+  }
+  Ok(())
+}
+
+// Feeds strips to one worker until none are left, sending each result to the collector.
+// On failure, the strip in flight is recorded as dropped.
+fn farm(address: &str, bounds: (usize, usize), arguments: &[String], strips: usize, next: &AtomicUsize, results: &crossbeam::channel::Sender<(usize, Vec<u32>)>, dropped: &Mutex<Vec<usize>>) -> Result<(), io::Error> {
+  let socket = address.to_socket_addrs()?.next().ok_or_else(|| invalid("address did not resolve"))?;
+  let stream = TcpStream::connect_timeout(&socket, CONNECT_TIMEOUT)?;
+  let mut reader = BufReader::new(stream.try_clone()?);
+  let mut writer = BufWriter::new(stream);
+
+  writeln!(writer, "{}", MAGIC)?;
+  writeln!(writer, "{}", arguments.len())?;
+  for argument in arguments {
+    writeln!(writer, "{}", argument)?;
+  }
+  writer.flush()?;
+
+  let mut reply = String::new();
+  reader.read_line(&mut reply)?;
+  if reply.trim_end() != "ok" {
+    return Err(invalid(&format!("rejected the job: {}", reply.trim_end())));
+  }
+
+  loop {
+    let strip = next.fetch_add(1, Ordering::SeqCst);
+    if strip >= strips {
+      return Ok(());
+    }
+    let (top, count) = strip_rows(strip, bounds);
+
+    let mut rows = vec![0; 4 * count * bounds.0];
+    let delivered = writeln!(writer, "rows {} {}", top, count)
+      .and_then(|_| writer.flush())
+      .and_then(|_| reader.read_exact(&mut rows));
+    if let Err(e) = delivered {
+      dropped.lock().unwrap().push(strip);
+      return Err(e);
+    }
+    // The collector only goes away once every worker thread has finished.
+    results.send((strip, from_bytes(&rows))).unwrap();
+  }
+}
+
+// The first row of `strip` and how many rows it has.
+fn strip_rows(strip: usize, bounds: (usize, usize)) -> (usize, usize) {
+  let top = strip * STRIP_ROWS;
+  (top, STRIP_ROWS.min(bounds.1 - top))
+}
+
+#[cfg(test)]
+fn test_job() -> (Vec<String>, (usize, usize), Vec<u32>) {
+  let arguments: Vec<String> = ["out.png", "40x70", "-1.20,0.35", "-1,0.20"].iter().map(|s| s.to_string()).collect();
+  let args = parse_arguments(&arguments).unwrap();
+  let bounds = (40, 70);
+  let mut local = vec![0; bounds.0 * bounds.1];
+  render_parallel(&mut local, bounds, build_sampler(&args, bounds).unwrap().as_ref(), 2, 0, 1, true).unwrap();
+  (arguments, bounds, local)
+}
+
+#[test]
+fn test_distributed_render_matches_local_render() {
+  let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+  let address = listener.local_addr().unwrap().to_string();
+  std::thread::spawn(move || serve_listener(listener, 2));
+
+  let (arguments, bounds, local) = test_job();
+  let sampler = build_sampler(&parse_arguments(&arguments).unwrap(), bounds).unwrap();
+  let mut pixels = vec![0; bounds.0 * bounds.1];
+  render(&mut pixels, bounds, sampler.as_ref(), 2, &[address.clone(), address], &arguments).unwrap();
+  assert_eq!(pixels, local);
+}
+
+#[test]
+fn test_unreachable_worker_falls_back_to_local_render() {
+  // Bind and release a port so nothing is listening on it.
+  let address = TcpListener::bind("127.0.0.1:0").unwrap().local_addr().unwrap().to_string();
+
+  let (arguments, bounds, local) = test_job();
+  let sampler = build_sampler(&parse_arguments(&arguments).unwrap(), bounds).unwrap();
+  let mut pixels = vec![0; bounds.0 * bounds.1];
+  render(&mut pixels, bounds, sampler.as_ref(), 2, &[address], &arguments).unwrap();
+  assert_eq!(pixels, local);
+}
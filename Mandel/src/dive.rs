@@ -0,0 +1,141 @@
+// Dives
+// `mandel dive` plans a zoom without anyone picking where it goes. From the view given by
+// --center and --width, each step probes sub-windows --factor times narrower, on a GRID by
+// GRID lattice of centers that keeps them within the view, and descends into the one with
+// the densest boundary: the most neighboring probe pixels whose escape counts differ,
+// which is where the detail keeps going as the zoom deepens. The path comes out as a
+// keyframe spec (see keyframes.rs), one keyframe per step --seconds apart with the
+// iteration limit following the zoom, for `mandel animate --spec`. Probes iterate in
+// f64, so a dive ends early once the view gets too narrow for it.
+
+use std::str::FromStr;
+
+use num::Complex;
+
+use crate::explore::{probe, View, PROBE};
+use crate::{log, parse_complex, MaxIter};
+
+const DEFAULT_STEPS: usize = 10;
+const DEFAULT_FACTOR: f64 = 4.0;
+const DEFAULT_SECONDS: f64 = 2.0;
+
+// Sub-window centers tried across and down the view at each step.
+const GRID: usize = 5;
+
+// Relative view widths below this are past what f64 probes can resolve.
+const NARROWEST: f64 = 1e-12;
+
+pub fn main(arguments: &[String]) -> Result<(), String> {
+  let mut view = View { center: Complex { re: -0.5, im: 0.0 }, width: 4.0 };
+  let mut steps = DEFAULT_STEPS;
+  let mut factor = DEFAULT_FACTOR;
+  let mut seconds = DEFAULT_SECONDS;
+  let mut output = None;
+
+  let mut options = arguments.iter().map(String::as_str);
+  while let Some(option) = options.next() {
+    let mut number = |what: &str, least: f64| match options.next().map(f64::from_str) {
+      Some(Ok(value)) if value.is_finite() && value > least => Ok(value),
+      _ => Err(format!("{} expects a number above {}", what, least)),
+    };
+    match option {
+      "--center" => view.center = options.next().and_then(parse_complex).ok_or("--center expects a point RE,IM")?,
+      "--width" => view.width = number("--width", 0.0)?,
+      "--factor" => factor = number("--factor", 1.0)?,
+      "--seconds" => seconds = number("--seconds", 0.0)?,
+      "--steps" => match options.next().map(usize::from_str) {
+        Some(Ok(count)) if count > 0 => steps = count,
+        _ => return Err("--steps expects a positive number of steps".to_string()),
+      },
+      "--output" => output = Some(options.next().ok_or("--output expects a file name")?),
+      _ => return Err("dive accepts --center RE,IM, --width W, --steps N, --factor F, --seconds S and --output FILE".to_string()),
+    }
+  }
+
+  let path = dive(view, steps, factor);
+  if path.len() <= steps {
+    log::warn(&format!("stopped after {} of {} steps, where f64 runs out of digits", path.len() - 1, steps));
+  }
+  let spec = spec(&path, seconds);
+  match output {
+    Some(file) => std::fs::write(file, spec).map_err(|e| format!("error writing '{}': {}", file, e)),
+    None => {
+      print!("{}", spec);
+      Ok(())
+    }
+  }
+}
+
+// `start` and the views each of up to `steps` steps descends into.
+fn dive(start: View, steps: usize, factor: f64) -> Vec<View> {
+  let mut path = vec![start];
+  for _ in 0..steps {
+    let view = path[path.len() - 1];
+    let width = view.width / factor;
+    if width < NARROWEST * view.center.norm().max(1.0) {
+      break;
+    }
+    let limit = MaxIter::Auto.resolve(width);
+    // Centers from one sub-window's half width in from each edge to the other.
+    let (upper_left, lower_right) = view.corners();
+    let span = (lower_right.re - upper_left.re - width, upper_left.im - lower_right.im - width * PROBE.1 as f64 / PROBE.0 as f64);
+    let candidates = (0..GRID * GRID).map(|i| {
+      let (column, row) = ((i % GRID) as f64 / (GRID - 1) as f64, (i / GRID) as f64 / (GRID - 1) as f64);
+      View { center: Complex { re: view.center.re + span.0 * (column - 0.5), im: view.center.im + span.1 * (0.5 - row) }, width }
+    });
+    let best = candidates.map(|candidate| (candidate, boundary_density(&probe(&candidate, limit)))).max_by(|a, b| a.1.total_cmp(&b.1));
+    path.push(best.unwrap().0);
+  }
+  path
+}
+
+// The fraction of pairs of neighboring pixels in the probe `counts` whose escape counts
+// differ.
+fn boundary_density(counts: &[Option<usize>]) -> f64 {
+  let (width, height) = PROBE;
+  let across = (0..height).flat_map(|y| (1..width).map(move |x| (y * width + x - 1, y * width + x)));
+  let down = (1..height).flat_map(|y| (0..width).map(move |x| ((y - 1) * width + x, y * width + x)));
+  let differing = across.chain(down).filter(|&(a, b)| counts[a] != counts[b]).count();
+  differing as f64 / ((width - 1) * height + width * (height - 1)) as f64
+}
+
+// The keyframe spec that visits each view of `path`, `seconds` apart.
+fn spec(path: &[View], seconds: f64) -> String {
+  let mut spec = format!("# mandel dive: {} steps from center {},{} width {:e}\n", path.len() - 1, path[0].center.re, path[0].center.im, path[0].width);
+  for (step, view) in path.iter().enumerate() {
+    spec += &format!("{}  center {},{}  zoom {:e}\n", step as f64 * seconds, view.center.re, view.center.im, 4.0 / view.width);
+  }
+  spec
+}
+
+#[test]
+fn test_dives() {
+  let pixels = PROBE.0 * PROBE.1;
+  assert_eq!(boundary_density(&vec![None; pixels]), 0.0);
+  // Columns alternating between two counts differ across every pair but none down.
+  let stripes: Vec<Option<usize>> = (0..pixels).map(|i| Some(i % 2)).collect();
+  let pairs = ((PROBE.0 - 1) * PROBE.1 + PROBE.0 * (PROBE.1 - 1)) as f64;
+  assert_eq!(boundary_density(&stripes), ((PROBE.0 - 1) * PROBE.1) as f64 / pairs);
+
+  let start = View { center: Complex { re: -0.5, im: 0.0 }, width: 4.0 };
+  let path = dive(start, 4, 4.0);
+  assert_eq!(path.len(), 5);
+  for pair in path.windows(2) {
+    let (upper_left, lower_right) = pair[0].corners();
+    let (inner_upper_left, inner_lower_right) = pair[1].corners();
+    assert_eq!(pair[1].width, pair[0].width / 4.0);
+    assert!(inner_upper_left.re >= upper_left.re - 1e-12 && inner_lower_right.re <= lower_right.re + 1e-12);
+    assert!(inner_upper_left.im <= upper_left.im + 1e-12 && inner_lower_right.im >= lower_right.im - 1e-12);
+  }
+  // The dive should have found its way to somewhere with detail left in it.
+  let last = path[4];
+  assert!(boundary_density(&probe(&last, MaxIter::Auto.resolve(last.width))) > 0.1);
+  // It stops once f64 can't go deeper.
+  assert_eq!(dive(View { width: 1e-11, ..start }, 5, 10.0).len(), 2);
+
+  let keyframes = crate::keyframes::parse(&spec(&path, 1.5)).unwrap();
+  assert_eq!(keyframes.len(), 5);
+  assert_eq!(keyframes[2].time, 3.0);
+  assert!((keyframes[4].zoom - 256.0).abs() < 1e-9);
+  assert!((keyframes[4].center - last.center).norm() < 1e-12);
+}
@@ -0,0 +1,170 @@
+// Double-double arithmetic
+// An unevaluated sum of two f64s, giving roughly 106 bits (about 32 decimal digits)
+// of precision: enough to place a reference orbit at zooms far beyond plain f64.
+
+use std::ops::{Add, Div, Mul, Neg, Sub};
+use std::str::FromStr;
+
+use crate::perturbation::Real;
+
+#[derive(Clone, Copy, Debug, PartialEq, Default)]
+pub struct DoubleDouble {
+  hi: f64,
+  lo: f64,
+}
+
+impl DoubleDouble {
+  pub fn new(value: f64) -> DoubleDouble {
+    DoubleDouble { hi: value, lo: 0.0 }
+  }
+}
+
+impl Real for DoubleDouble {
+  fn from_f64(value: f64) -> DoubleDouble {
+    DoubleDouble::new(value)
+  }
+
+  fn to_f64(&self) -> f64 {
+    self.hi + self.lo
+  }
+}
+
+// Error-free sum: returns (s, e) with s + e == a + b exactly.
+fn two_sum(a: f64, b: f64) -> (f64, f64) {
+  let s = a + b;
+  let v = s - a;
+  (s, (a - (s - v)) + (b - v))
+}
+
+// Error-free product, relying on a fused multiply-add for the rounding error.
+fn two_prod(a: f64, b: f64) -> (f64, f64) {
+  let p = a * b;
+  (p, a.mul_add(b, -p))
+}
+
+fn normalize(hi: f64, lo: f64) -> DoubleDouble {
+  let (hi, lo) = two_sum(hi, lo);
+  DoubleDouble { hi, lo }
+}
+
+impl Add for DoubleDouble {
+  type Output = DoubleDouble;
+
+  fn add(self, other: DoubleDouble) -> DoubleDouble {
+    let (s, e) = two_sum(self.hi, other.hi);
+    normalize(s, e + self.lo + other.lo)
+  }
+}
+
+impl Neg for DoubleDouble {
+  type Output = DoubleDouble;
+
+  fn neg(self) -> DoubleDouble {
+    DoubleDouble { hi: -self.hi, lo: -self.lo }
+  }
+}
+
+impl Sub for DoubleDouble {
+  type Output = DoubleDouble;
+
+  fn sub(self, other: DoubleDouble) -> DoubleDouble {
+    self + -other
+  }
+}
+
+impl Mul for DoubleDouble {
+  type Output = DoubleDouble;
+
+  fn mul(self, other: DoubleDouble) -> DoubleDouble {
+    let (p, e) = two_prod(self.hi, other.hi);
+    normalize(p, e + self.hi * other.lo + self.lo * other.hi)
+  }
+}
+
+impl Div for DoubleDouble {
+  type Output = DoubleDouble;
+
+  fn div(self, other: DoubleDouble) -> DoubleDouble {
+    let q1 = self.hi / other.hi;
+    let r = self - other * DoubleDouble::new(q1);
+    let q2 = r.hi / other.hi;
+    let r = r - other * DoubleDouble::new(q2);
+    let q3 = r.hi / other.hi;
+    normalize(q1, q2) + DoubleDouble::new(q3)
+  }
+}
+
+impl FromStr for DoubleDouble {
+  type Err = String;
+
+  // Parses decimal notation with an optional exponent ("-1.25", "3e-40") digit by digit,
+  // so values keep all the precision the string carries instead of going through f64.
+  fn from_str(string: &str) -> Result<DoubleDouble, String> {
+    let invalid = || format!("invalid number '{}'", string);
+
+    let (mantissa, exponent) = match string.find(['e', 'E']) {
+      Some(index) => (&string[..index], i32::from_str(&string[index + 1..]).map_err(|_| invalid())?),
+      None => (string, 0),
+    };
+
+    let (negative, mantissa) = match mantissa.strip_prefix('-') {
+      Some(rest) => (true, rest),
+      None => (false, mantissa.strip_prefix('+').unwrap_or(mantissa)),
+    };
+
+    let (whole, fraction) = match mantissa.find('.') {
+      Some(index) => (&mantissa[..index], &mantissa[index + 1..]),
+      None => (mantissa, ""),
+    };
+
+    if whole.is_empty() && fraction.is_empty() {
+      return Err(invalid());
+    }
+
+    let ten = DoubleDouble::new(10.0);
+    let mut value = DoubleDouble::default();
+    for c in whole.chars().chain(fraction.chars()) {
+      let digit = c.to_digit(10).ok_or_else(invalid)?;
+      value = value * ten + DoubleDouble::new(digit as f64);
+    }
+
+    // Ten to the scale by repeated squaring, so a huge exponent costs a few dozen steps
+    // rather than one per power of ten.
+    let scale = exponent.checked_sub(fraction.len() as i32).ok_or_else(invalid)?;
+    let (mut power, mut square, mut bits) = (DoubleDouble::new(1.0), ten, scale.unsigned_abs());
+    while bits > 0 {
+      if bits & 1 == 1 {
+        power = power * square;
+      }
+      square = square * square;
+      bits >>= 1;
+    }
+    if !power.to_f64().is_finite() {
+      return Err(format!("number '{}' is out of range", string));
+    }
+    value = if scale < 0 { value / power } else { value * power };
+
+    Ok(if negative { -value } else { value })
+  }
+}
+
+#[test]
+fn test_parse_double_double() {
+  assert_eq!(DoubleDouble::from_str("1.5"), Ok(DoubleDouble::new(1.5)));
+  assert_eq!(DoubleDouble::from_str("-25e-1"), Ok(DoubleDouble::new(-2.5)));
+  assert_eq!(DoubleDouble::from_str(".5"), Ok(DoubleDouble::new(0.5)));
+  assert!(DoubleDouble::from_str("").is_err());
+  assert!(DoubleDouble::from_str("1.2.3").is_err());
+  assert!(DoubleDouble::from_str("1e").is_err());
+  assert_eq!(DoubleDouble::from_str("3e-40").map(|value| value.to_f64()), Ok(3e-40));
+  assert!(DoubleDouble::from_str("1e999999999").is_err());
+  assert!(DoubleDouble::from_str("1e-2147483648").is_err());
+}
+
+#[test]
+fn test_double_double_keeps_digits_beyond_f64() {
+  let a = DoubleDouble::from_str("1.000000000000000000000000000003").unwrap();
+  let b = DoubleDouble::from_str("1.000000000000000000000000000001").unwrap();
+  let difference = (a - b).to_f64();
+  assert!((difference - 2e-30).abs() < 1e-40);
+}
@@ -0,0 +1,87 @@
+// Easing curves
+// An easing maps the fraction of an animation's time that has passed to the fraction of
+// the way its zoom and other settings have come. Linear keeps the apparent speed
+// constant; the others start gently so a video doesn't open at full speed:
+//
+//   linear                        t
+//   ease-in-out                   CSS's ease-in-out, cubic-bezier:0.42,0,0.58,1
+//   exponential                   starts 1000 times slower than it ends, (2^10t - 1) / (2^10 - 1)
+//   cubic-bezier:X1,Y1,X2,Y2      a CSS-style curve from (0,0) to (1,1) with these control
+//                                 points; X1 and X2 must be within 0..1
+
+use std::str::FromStr;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Easing {
+  Linear,
+  Exponential,
+  CubicBezier(f64, f64, f64, f64),
+}
+
+const EASE_IN_OUT: Easing = Easing::CubicBezier(0.42, 0.0, 0.58, 1.0);
+
+// Bisection steps when solving a Bézier curve for x, enough to pin it to f64 precision.
+const BEZIER_STEPS: usize = 60;
+
+impl Easing {
+  pub fn parse(text: &str) -> Option<Easing> {
+    match text {
+      "linear" => Some(Easing::Linear),
+      "ease-in-out" => Some(EASE_IN_OUT),
+      "exponential" => Some(Easing::Exponential),
+      _ => {
+        let numbers: Vec<f64> = text.strip_prefix("cubic-bezier:")?.split(',').map(|n| f64::from_str(n).ok().filter(|n| n.is_finite())).collect::<Option<_>>()?;
+        match numbers.as_slice() {
+          &[x1, y1, x2, y2] if (0.0..=1.0).contains(&x1) && (0.0..=1.0).contains(&x2) => Some(Easing::CubicBezier(x1, y1, x2, y2)),
+          _ => None,
+        }
+      }
+    }
+  }
+
+  // How far along the settings are a fraction `t` of the way through the time.
+  pub fn apply(self, t: f64) -> f64 {
+    match self {
+      Easing::Linear => t,
+      Easing::Exponential => (1024f64.powf(t) - 1.0) / 1023.0,
+      // Flat stretches of x near the ends would leave bisection short of them.
+      Easing::CubicBezier(..) if t <= 0.0 || t >= 1.0 => t.clamp(0.0, 1.0),
+      Easing::CubicBezier(x1, y1, x2, y2) => {
+        // x rises monotonically along the curve when the control points' x lie in 0..1,
+        // so bisect for the parameter where it reaches t.
+        let (mut low, mut high) = (0.0, 1.0);
+        for _ in 0..BEZIER_STEPS {
+          let middle = (low + high) / 2.0;
+          if bezier(x1, x2, middle) < t {
+            low = middle;
+          } else {
+            high = middle;
+          }
+        }
+        bezier(y1, y2, (low + high) / 2.0)
+      }
+    }
+  }
+}
+
+// One coordinate of a cubic Bézier from 0 to 1 with control points `a` and `b`, at `s`.
+fn bezier(a: f64, b: f64, s: f64) -> f64 {
+  let r = 1.0 - s;
+  3.0 * r * r * s * a + 3.0 * r * s * s * b + s * s * s
+}
+
+#[test]
+fn test_easings() {
+  let ease_in_out = Easing::parse("ease-in-out").unwrap();
+  for easing in [Easing::Linear, Easing::Exponential, ease_in_out, Easing::parse("cubic-bezier:0.1,0.7,1,0.1").unwrap()] {
+    assert!(easing.apply(0.0).abs() < 1e-12 && (easing.apply(1.0) - 1.0).abs() < 1e-12, "{:?}", easing);
+  }
+  // Symmetric, so halfway in time is halfway there, but it starts slowly.
+  assert!((ease_in_out.apply(0.5) - 0.5).abs() < 1e-12);
+  assert!(ease_in_out.apply(0.1) < 0.03);
+  assert!((Easing::Exponential.apply(0.1) - 1.0 / 1023.0).abs() < 1e-12);
+
+  assert_eq!(Easing::parse("cubic-bezier:2,0,0.5,1"), None);
+  assert_eq!(Easing::parse("cubic-bezier:0.5,0,0.5"), None);
+  assert_eq!(Easing::parse("bounce"), None);
+}
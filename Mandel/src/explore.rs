@@ -0,0 +1,203 @@
+// Exploring
+// `mandel explore` goes looking for views worth rendering. It draws --candidates views at
+// random from --region, each centered on a point whose orbit takes at least
+// BOUNDARY_ITERS iterations to escape, which puts it close to the set's boundary where
+// the detail is, and with a width log-uniform from a millionth to a tenth of the
+// region's. A probe render of each scores it by the entropy of its escape counts, with
+// the interior counting as one more count, so views of a single shade or all interior
+// score low and views full of shades in even measure score high. The best --count views,
+// skipping any centered inside a better one, are printed best first with the corners to
+// render them at, and with --thumbnails DIR rendered small into DIR as well.
+
+use std::collections::HashMap;
+use std::str::FromStr;
+
+use num::Complex;
+
+use crate::area::{SplitMix, WHOLE_SET};
+use crate::{escape_time, interrupt, log, parse_arguments, parse_complex, parse_threads, pixel_to_point, render};
+
+const DEFAULT_COUNT: usize = 10;
+const DEFAULT_MAX_ITER: usize = 1000;
+
+// Candidates drawn per view reported, unless --candidates says otherwise.
+const CANDIDATES_PER_VIEW: usize = 20;
+
+// Iterations a center's orbit must last before escaping.
+const BOUNDARY_ITERS: usize = 32;
+
+// Points tried for a candidate's center before giving up on the region.
+const TRIES: usize = 100_000;
+
+// Size of the probe renders, which sets the 4:3 aspect of every view.
+pub const PROBE: (usize, usize) = (48, 36);
+
+const THUMBNAIL: &str = "160x120";
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct View {
+  pub center: Complex<f64>,
+  // Across the real axis.
+  pub width: f64,
+}
+
+impl View {
+  pub fn corners(&self) -> (Complex<f64>, Complex<f64>) {
+    let (re, im) = (self.width / 2.0, self.width * PROBE.1 as f64 / PROBE.0 as f64 / 2.0);
+    (Complex { re: self.center.re - re, im: self.center.im + im }, Complex { re: self.center.re + re, im: self.center.im - im })
+  }
+
+  fn contains(&self, point: Complex<f64>) -> bool {
+    let (upper_left, lower_right) = self.corners();
+    (upper_left.re..=lower_right.re).contains(&point.re) && (lower_right.im..=upper_left.im).contains(&point.im)
+  }
+}
+
+pub fn main(arguments: &[String], threads: usize) -> Result<(), String> {
+  let mut count = DEFAULT_COUNT;
+  let mut candidates = None;
+  let mut region = WHOLE_SET;
+  let mut limit = DEFAULT_MAX_ITER;
+  let mut thumbnails = None;
+  let mut threads = threads;
+  let mut seed = 1;
+
+  let mut options = arguments.iter().map(String::as_str);
+  while let Some(option) = options.next() {
+    let mut positive = |what: &str| match options.next().map(usize::from_str) {
+      Some(Ok(value)) if value > 0 => Ok(value),
+      _ => Err(format!("{} expects a positive number", what)),
+    };
+    match option {
+      "--count" => count = positive("--count")?,
+      "--candidates" => candidates = Some(positive("--candidates")?),
+      "--max-iter" => limit = positive("--max-iter")?,
+      "--region" => match (options.next().and_then(parse_complex), options.next().and_then(parse_complex)) {
+        (Some(a), Some(b)) if a.re != b.re && a.im != b.im => {
+          region = (Complex { re: a.re.min(b.re), im: a.im.max(b.im) }, Complex { re: a.re.max(b.re), im: a.im.min(b.im) })
+        }
+        _ => return Err("--region expects two opposite corners RE,IM RE,IM".to_string()),
+      },
+      "--thumbnails" => thumbnails = Some(options.next().ok_or("--thumbnails expects a directory")?),
+      "--threads" => threads = parse_threads(options.next())?,
+      "--seed" => seed = options.next().and_then(|value| u64::from_str(value).ok()).ok_or("--seed expects a whole number")?,
+      _ => return Err("explore accepts --count N, --candidates N, --region UPPERLEFT LOWERRIGHT, --max-iter N, --thumbnails DIR, --threads N and --seed N".to_string()),
+    }
+  }
+
+  interrupt::install();
+  let mut random = SplitMix(seed);
+  let views = (0..candidates.unwrap_or(count * CANDIDATES_PER_VIEW))
+    .map(|_| candidate(&mut random, region, limit).ok_or("no point of --region lies near enough the set's boundary to explore"))
+    .collect::<Result<Vec<View>, _>>()?;
+  let scores = score_all(&views, limit, threads)?;
+  let best = best(&views, &scores, count);
+
+  if let Some(dir) = thumbnails {
+    std::fs::create_dir_all(dir).map_err(|e| format!("error creating thumbnail directory '{}': {}", dir, e))?;
+  }
+  for (rank, &(view, score)) in best.iter().enumerate() {
+    let (upper_left, lower_right) = view.corners();
+    let corners = [format!("{},{}", upper_left.re, upper_left.im), format!("{},{}", lower_right.re, lower_right.im)];
+    println!("{:>3}  score {:.3}  center {},{}  width {:e}  {} {}", rank + 1, score, view.center.re, view.center.im, view.width, corners[0], corners[1]);
+    if let Some(dir) = thumbnails {
+      let path = std::path::Path::new(dir).join(format!("explore-{:02}.png", rank + 1)).to_string_lossy().into_owned();
+      let arguments = [path, THUMBNAIL.to_string(), corners[0].clone(), corners[1].clone(), "--max-iter".to_string(), limit.to_string(),
+                       "--threads".to_string(), threads.to_string(), "--force".to_string(), "--no-progress".to_string()];
+      render(parse_arguments(&arguments)?)?;
+    }
+  }
+  if best.len() < count {
+    log::warn(&format!("found only {} views apart from one another; try more --candidates", best.len()));
+  }
+  Ok(())
+}
+
+// A random view of `region` centered near the set's boundary, or None if no center turns
+// up in TRIES points.
+fn candidate(random: &mut SplitMix, region: (Complex<f64>, Complex<f64>), limit: usize) -> Option<View> {
+  let (upper_left, lower_right) = region;
+  let extent = lower_right.re - upper_left.re;
+  (0..TRIES).find_map(|_| {
+    let center = Complex {
+      re: upper_left.re + random.unit() * extent,
+      im: lower_right.im + random.unit() * (upper_left.im - lower_right.im),
+    };
+    matches!(escape_time(center, limit), Some(count) if count >= BOUNDARY_ITERS).then(|| View { center, width: extent * 10f64.powf(-1.0 - 5.0 * random.unit()) })
+  })
+}
+
+// The escape counts of a PROBE-sized render of `view`, row by row.
+pub fn probe(view: &View, limit: usize) -> Vec<Option<usize>> {
+  let (upper_left, lower_right) = view.corners();
+  (0..PROBE.0 * PROBE.1).map(|i| {
+    let pixel = ((i % PROBE.0) as f64 + 0.5, (i / PROBE.0) as f64 + 0.5);
+    escape_time(pixel_to_point(PROBE, pixel, upper_left, lower_right), limit)
+  }).collect()
+}
+
+// The entropy in bits of the escape counts `counts`, the interior counting as one more.
+fn entropy(counts: &[Option<usize>]) -> f64 {
+  let mut tally: HashMap<Option<usize>, usize> = HashMap::new();
+  for &count in counts {
+    *tally.entry(count).or_default() += 1;
+  }
+  tally.values().map(|&n| n as f64 / counts.len() as f64).map(|p| -p * p.log2()).sum()
+}
+
+// The scores of `views`, probed on `threads` threads.
+fn score_all(views: &[View], limit: usize, threads: usize) -> Result<Vec<f64>, String> {
+  let chunk = views.len().div_ceil(threads);
+  let scores = crossbeam::scope(|spawner| {
+    let handles: Vec<_> = views.chunks(chunk).map(|views| spawner.spawn(move |_| {
+      views.iter().map(|view| (!interrupt::requested()).then(|| entropy(&probe(view, limit)))).collect::<Option<Vec<f64>>>()
+    })).collect();
+    handles.into_iter().map(|handle| handle.join().unwrap()).collect::<Option<Vec<Vec<f64>>>>()
+  }).unwrap();
+  scores.map(|scores| scores.concat()).ok_or("interrupted".to_string())
+}
+
+// Up to `count` of `views`, highest scoring first, leaving out those centered inside a
+// view already taken.
+fn best(views: &[View], scores: &[f64], count: usize) -> Vec<(View, f64)> {
+  let mut ranked: Vec<(View, f64)> = views.iter().copied().zip(scores.iter().copied()).collect();
+  ranked.sort_by(|a, b| b.1.total_cmp(&a.1));
+  let mut taken: Vec<(View, f64)> = Vec::new();
+  for (view, score) in ranked {
+    if taken.len() < count && !taken.iter().any(|(better, _)| better.contains(view.center) || view.contains(better.center)) {
+      taken.push((view, score));
+    }
+  }
+  taken
+}
+
+#[test]
+fn test_exploring() {
+  let view = View { center: Complex { re: -0.5, im: 0.0 }, width: 4.0 };
+  assert_eq!(view.corners(), (Complex { re: -2.5, im: 1.5 }, Complex { re: 1.5, im: -1.5 }));
+  assert!(view.contains(Complex { re: 1.0, im: -1.0 }) && !view.contains(Complex { re: 1.0, im: -2.0 }));
+
+  assert_eq!(entropy(&[None, None, None, None]), 0.0);
+  assert_eq!(entropy(&[Some(1), Some(2), None, None]), 1.5);
+  // Deep inside the main cardioid there's nothing to see; at the boundary there's plenty.
+  let inside = View { center: Complex { re: -0.1, im: 0.0 }, width: 0.1 };
+  assert_eq!(entropy(&probe(&inside, 200)), 0.0);
+  let seahorses = View { center: Complex { re: -0.745, im: 0.1 }, width: 0.02 };
+  assert!(entropy(&probe(&seahorses, 200)) > 3.0);
+
+  let mut random = SplitMix(3);
+  for _ in 0..20 {
+    let view = candidate(&mut random, WHOLE_SET, 200).unwrap();
+    assert!(escape_time(view.center, 200).unwrap() >= BOUNDARY_ITERS);
+    assert!(view.width <= 0.25 && view.width >= 2.5e-6);
+  }
+  assert_eq!(candidate(&mut random, (Complex { re: -0.2, im: 0.1 }, Complex { re: 0.0, im: -0.1 }), 200), None);
+
+  let views = [inside, seahorses, View { width: 0.01, ..seahorses }];
+  let scores = score_all(&views, 200, 2).unwrap();
+  assert_eq!(scores[0], 0.0);
+  // The narrower seahorse view is centered inside the wider one, so only one is kept.
+  let chosen = best(&views, &scores, 3);
+  assert_eq!(chosen.len(), 2);
+  assert_eq!(chosen[1].0, inside);
+}
@@ -0,0 +1,221 @@
+// Fractal formulas
+// Escape-time fractals that differ from the Mandelbrot set only in how z is folded
+// before squaring. Perturbation and the other deep-zoom backends assume z² + c, so only
+// direct rendering offers the others.
+//
+// An orbit escapes once it leaves the fractal's bailout: a radius about the origin, by a
+// norm that need not be the usual one. Manhattan and Chebyshev norms square off the
+// bands of equal escape count; a larger radius rounds them out.
+
+use num::{Complex, Float};
+
+use crate::escape_time;
+
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub enum Fractal {
+  #[default]
+  Mandelbrot,
+  // z = (|re z| + i|im z|)² + c
+  BurningShip,
+  // z = conj(z)² + c, also called the Mandelbar set.
+  Tricorn,
+}
+
+pub const FRACTALS: [Fractal; 3] = [Fractal::Mandelbrot, Fractal::BurningShip, Fractal::Tricorn];
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Bailout {
+  pub norm: Norm,
+  pub radius: f64,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Norm {
+  Euclidean,
+  // |re| + |im|
+  Manhattan,
+  // max(|re|, |im|)
+  Chebyshev,
+}
+
+pub const NORMS: [Norm; 3] = [Norm::Euclidean, Norm::Manhattan, Norm::Chebyshev];
+
+impl Norm {
+  pub fn name(self) -> &'static str {
+    match self {
+      Norm::Euclidean => "euclidean",
+      Norm::Manhattan => "manhattan",
+      Norm::Chebyshev => "chebyshev",
+    }
+  }
+}
+
+impl Bailout {
+  fn escaped<T: Float>(self, re: T, im: T) -> bool {
+    let radius = T::from(self.radius).unwrap();
+    match self.norm {
+      Norm::Euclidean => re * re + im * im > radius * radius,
+      Norm::Manhattan => re.abs() + im.abs() > radius,
+      Norm::Chebyshev => re.abs().max(im.abs()) > radius,
+    }
+  }
+
+  // Whether every orbit that leaves this bailout is sure to escape. Orbits of points in
+  // the set stay within 2 of the origin, so a smaller bailout counts some of them out.
+  pub fn certain(self) -> bool {
+    let least = match self.norm {
+      Norm::Euclidean | Norm::Chebyshev => 2.0,
+      Norm::Manhattan => 2.0 * std::f64::consts::SQRT_2,
+    };
+    self.radius >= least
+  }
+}
+
+impl Fractal {
+  pub fn name(self) -> &'static str {
+    match self {
+      Fractal::Mandelbrot => "mandelbrot",
+      Fractal::BurningShip => "burning-ship",
+      Fractal::Tricorn => "tricorn",
+    }
+  }
+
+  // The bailout orbits escape at unless told otherwise: the smallest circle an orbit is
+  // sure to grow without bound from once outside it, so counts are as low as they can be
+  // without shading any point of the set as escaping.
+  pub fn bailout(self) -> Bailout {
+    match self {
+      // Past |z| = 2 ≥ |c|, |z² + c| ≥ |z|² - |c| > |z|, and the gap widens every step.
+      Fractal::Mandelbrot => Bailout { norm: Norm::Euclidean, radius: 2.0 },
+      // Folding z flips the signs of its parts, which leaves |z| as it was, so the same
+      // bound holds for the folded iterations.
+      Fractal::BurningShip | Fractal::Tricorn => Bailout { norm: Norm::Euclidean, radius: 2.0 },
+    }
+  }
+
+  // The bailout for the Julia set of `c`. Once |c| > 2 the argument above needs a wider
+  // circle: past R = (1 + √(1 + 4|c|)) / 2, |z|² - |c| > |z| again.
+  pub fn julia_bailout<T: Float>(self, c: Complex<T>) -> Bailout {
+    let own = self.bailout();
+    let modulus = c.norm().to_f64().unwrap_or(f64::INFINITY);
+    Bailout { radius: own.radius.max((1.0 + (1.0 + 4.0 * modulus).sqrt()) / 2.0), ..own }
+  }
+
+  pub fn escape_time<T: Float>(self, c: Complex<T>, limit: usize) -> Option<usize> {
+    self.escape_time_within(c, limit, self.bailout())
+  }
+
+  // Escape time of `c` with orbits escaping at `bailout` rather than the fractal's own.
+  pub fn escape_time_within<T: Float>(self, c: Complex<T>, limit: usize, bailout: Bailout) -> Option<usize> {
+    let zero = Complex::new(T::zero(), T::zero());
+    match self {
+      Fractal::Mandelbrot if bailout == self.bailout() => escape_time(c, limit),
+      Fractal::Mandelbrot => folded_escape_time(zero, c, limit, bailout, |re, im| (re, im)),
+      Fractal::BurningShip => folded_escape_time(zero, c, limit, bailout, |re, im| (re.abs(), im.abs())),
+      Fractal::Tricorn => folded_escape_time(zero, c, limit, bailout, |re, im| (re, -im)),
+    }
+  }
+
+  // Escape time of `c` at `bailout`, with the z that left it, for shadings that need
+  // more of the orbit than how long it lasted.
+  pub fn escape_within<T: Float>(self, c: Complex<T>, limit: usize, bailout: Bailout) -> Option<(usize, Complex<T>)> {
+    let zero = Complex::new(T::zero(), T::zero());
+    match self {
+      Fractal::Mandelbrot => folded_escape(zero, c, limit, bailout, |re, im| (re, im)),
+      Fractal::BurningShip => folded_escape(zero, c, limit, bailout, |re, im| (re.abs(), im.abs())),
+      Fractal::Tricorn => folded_escape(zero, c, limit, bailout, |re, im| (re, -im)),
+    }
+  }
+
+  // Escape time of `z` under the same iteration with `c` held fixed: the Julia set
+  // belonging to the point c of this fractal.
+  pub fn julia_escape_time<T: Float>(self, z: Complex<T>, c: Complex<T>, limit: usize) -> Option<usize> {
+    match self {
+      Fractal::Mandelbrot => folded_escape_time(z, c, limit, self.julia_bailout(c), |re, im| (re, im)),
+      Fractal::BurningShip => folded_escape_time(z, c, limit, self.julia_bailout(c), |re, im| (re.abs(), im.abs())),
+      Fractal::Tricorn => folded_escape_time(z, c, limit, self.julia_bailout(c), |re, im| (re, -im)),
+    }
+  }
+}
+
+fn folded_escape_time<T: Float>(z: Complex<T>, c: Complex<T>, limit: usize, bailout: Bailout, fold: impl Fn(T, T) -> (T, T)) -> Option<usize> {
+  folded_escape(z, c, limit, bailout, fold).map(|(i, _)| i)
+}
+
+fn folded_escape<T: Float>(z: Complex<T>, c: Complex<T>, limit: usize, bailout: Bailout, fold: impl Fn(T, T) -> (T, T)) -> Option<(usize, Complex<T>)> {
+  let (mut re, mut im) = (z.re, z.im);
+
+  for i in 0..limit {
+    if bailout.escaped(re, im) {
+      return Some((i, Complex { re, im }));
+    }
+    let (folded_re, folded_im) = fold(re, im);
+    (re, im) = (folded_re * folded_re - folded_im * folded_im + c.re, (folded_re + folded_re) * folded_im + c.im);
+  }
+
+  None
+}
+
+#[test]
+fn test_fractals_differ_off_the_real_axis() {
+  // Conjugating a real z changes nothing, so the tricorn matches on the real axis...
+  let real = Complex { re: -1.5, im: 0.0 };
+  let mandelbrot = Fractal::Mandelbrot.escape_time(real, 100);
+  assert_eq!(Fractal::Tricorn.escape_time(real, 100), mandelbrot);
+
+  // ...but this point in the Mandelbrot set's period-3 bulb escapes from the others.
+  let c = Complex { re: -0.1, im: 0.8 };
+  assert_eq!(Fractal::Mandelbrot.escape_time(c, 100), None);
+  assert!(Fractal::BurningShip.escape_time(c, 100).is_some());
+  assert!(Fractal::Tricorn.escape_time(c, 100).is_some());
+}
+
+#[test]
+fn test_bailouts() {
+  // The general loop with the usual bailout agrees with the fast one.
+  let circle = Fractal::Mandelbrot.bailout();
+  for c in [Complex { re: -0.75, im: 0.1 }, Complex { re: 0.3, im: 0.6 }, Complex { re: -1.5, im: 0.01 }] {
+    assert_eq!(folded_escape_time(Complex { re: 0.0, im: 0.0 }, c, 500, circle, |re, im| (re, im)), Fractal::Mandelbrot.escape_time(c, 500));
+  }
+
+  // z = 0, 1.2 + 1.2i, 1.2 + 4.08i: within the circle and the square of radius 2 after
+  // the first step but outside the diamond, and outside all three after the second.
+  let c = Complex { re: 1.2, im: 1.2 };
+  let within = |norm, radius| Fractal::Mandelbrot.escape_time_within(c, 100, Bailout { norm, radius });
+  assert_eq!(within(Norm::Euclidean, 2.0), Some(2));
+  assert_eq!(within(Norm::Chebyshev, 2.0), Some(2));
+  assert_eq!(within(Norm::Manhattan, 2.0), Some(1));
+  // A bigger radius takes longer to leave.
+  assert_eq!(within(Norm::Euclidean, 1e6), Some(6));
+  // The z that left is the one after the second step.
+  assert_eq!(Fractal::Mandelbrot.escape_within(c, 100, circle), Some((2, Complex { re: 1.2 * 1.2 - 1.2 * 1.2 + 1.2, im: 2.0 * 1.2 * 1.2 + 1.2 })));
+  assert_eq!(Fractal::Mandelbrot.escape_within(Complex { re: -0.5, im: 0.0 }, 100, circle), None);
+
+  assert!(circle.certain() && Bailout { norm: Norm::Chebyshev, radius: 2.0 }.certain());
+  // Every fractal's own bailout is one orbits of its points stay within.
+  for fractal in FRACTALS {
+    assert!(fractal.bailout().certain(), "{}", fractal.name());
+  }
+  assert!(!Bailout { norm: Norm::Manhattan, radius: 2.0 }.certain() && !Bailout { norm: Norm::Euclidean, radius: 1.5 }.certain());
+}
+
+#[test]
+fn test_julia_sets_start_from_z() {
+  // Starting from zero, the Julia iteration is the fractal's own.
+  let c = Complex { re: -0.8, im: 0.2 };
+  let zero = Complex { re: 0.0, im: 0.0 };
+  for fractal in FRACTALS {
+    assert_eq!(fractal.julia_escape_time(zero, c, 100), fractal.escape_time(c, 100));
+  }
+  // c = 0 gives the unit disk.
+  assert_eq!(Fractal::Mandelbrot.julia_escape_time(Complex { re: 0.9, im: 0.3 }, zero, 100), None);
+  assert!(Fractal::Mandelbrot.julia_escape_time(Complex { re: 1.1, im: 0.0 }, zero, 100).is_some());
+
+  // For c = -6, z = 3 goes to 3 again and never escapes, though it starts beyond 2.
+  let far = Complex { re: -6.0, im: 0.0 };
+  assert_eq!(Fractal::Mandelbrot.julia_bailout(far).radius, 3.0);
+  assert_eq!(Fractal::Mandelbrot.julia_escape_time(Complex { re: 3.0, im: 0.0 }, far, 100), None);
+  for fractal in FRACTALS {
+    assert_eq!(fractal.julia_bailout(c), fractal.bailout());
+  }
+}
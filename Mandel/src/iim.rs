@@ -0,0 +1,195 @@
+// Inverse iteration
+// `mandel iim FILE PIXELS UPPERLEFT LOWERRIGHT --c RE,IM` draws the Julia set of z² + c
+// by running the iteration backwards. Every point has two preimages, ±√(z - c), and an
+// orbit that takes one of them at random each step is drawn onto the Julia set itself,
+// the boundary that escape-time renders only show as the edge of their outermost band.
+// The orbit's points after the first WARMUP pile up in the pixels under them, and each
+// pixel is shaded by the log of its count, so the set comes out bright on black.
+//
+// Random preimages visit some parts of the set far more often than others, the tips of
+// dendrites hardly at all, so sparse parts may need more --points. The points are split
+// among ORBITS orbits, each seeded from --seed, so the image doesn't depend on how many
+// threads drew it.
+
+use std::str::FromStr;
+
+use num::Complex;
+
+use crate::area::SplitMix;
+use crate::json::Value;
+use crate::palette::{Palette, PALETTES};
+use crate::{interrupt, log, parse_complex, parse_pair, parse_threads, write_color_image, DEFAULT_THREADS, THREAD_PANICKED};
+
+const DEFAULT_POINTS: u64 = 4_000_000;
+
+// Independent orbits the points are drawn from.
+const ORBITS: u64 = 64;
+
+// Steps an orbit takes before it is near enough the set to plot.
+const WARMUP: usize = 32;
+
+// Points drawn between checks for Ctrl-C.
+const CHECK: u64 = 1 << 16;
+
+// The part of the plane an image covers.
+#[derive(Clone, Copy, Debug)]
+struct View {
+  bounds: (usize, usize),
+  upper_left: Complex<f64>,
+  lower_right: Complex<f64>,
+}
+
+impl View {
+  // The index of the pixel `z` falls in, if it falls in one.
+  fn pixel(&self, z: Complex<f64>) -> Option<usize> {
+    let x = (z.re - self.upper_left.re) / (self.lower_right.re - self.upper_left.re) * self.bounds.0 as f64;
+    let y = (self.upper_left.im - z.im) / (self.upper_left.im - self.lower_right.im) * self.bounds.1 as f64;
+    (x >= 0.0 && y >= 0.0 && x < self.bounds.0 as f64 && y < self.bounds.1 as f64).then(|| y as usize * self.bounds.0 + x as usize)
+  }
+}
+
+pub fn main(arguments: &[String]) -> Result<Vec<(String, Value)>, String> {
+  let (mut c, mut points, mut seed, mut palette, mut force) = (None, DEFAULT_POINTS, 1, Palette::Gray, false);
+  let mut threads = std::thread::available_parallelism().map_or(DEFAULT_THREADS, |n| n.get());
+  let mut positional = Vec::new();
+  let mut options = arguments.iter().map(String::as_str);
+  while let Some(option) = options.next() {
+    match option {
+      "--c" => c = Some(options.next().and_then(parse_complex).ok_or("--c expects a point RE,IM")?),
+      "--points" => points = options.next().and_then(|value| u64::from_str(value).ok()).filter(|&points| points > 0).ok_or("--points expects a positive number")?,
+      "--seed" => seed = options.next().and_then(|value| u64::from_str(value).ok()).ok_or("--seed expects a whole number")?,
+      "--palette" => {
+        let name = options.next().unwrap_or_default();
+        palette = PALETTES.iter().find(|palette| palette.name() == name).copied().ok_or(format!("unknown palette '{}'", name))?;
+      }
+      "--threads" => threads = parse_threads(options.next())?,
+      "--force" => force = true,
+      _ if option.starts_with("--") => {
+        return Err(format!("iim accepts --c RE,IM, --points N, --seed N, --palette NAME, --threads N and --force, not '{}'", option));
+      }
+      _ => positional.push(option),
+    }
+  }
+  let [file, pixels, upper_left, lower_right] = positional[..] else {
+    return Err(format!("iim expects FILE PIXELS UPPERLEFT LOWERRIGHT, got {} positional argument(s)", positional.len()));
+  };
+  let c = c.ok_or("iim needs --c RE,IM, the Julia set's parameter")?;
+  let bounds = parse_pair::<usize>(pixels, 'x').filter(|&(width, height)| width > 0 && height > 0)
+    .ok_or(format!("error parsing image dimensions '{}': expected WIDTHxHEIGHT in whole pixels", pixels))?;
+  let corner = |which: &str, text: &str| parse_complex(text).ok_or(format!("error parsing {} corner '{}'", which, text));
+  let view = View { bounds, upper_left: corner("upper left", upper_left)?, lower_right: corner("lower right", lower_right)? };
+  if !(view.upper_left.re < view.lower_right.re && view.lower_right.im < view.upper_left.im) {
+    return Err(format!("'{}' must be above and left of '{}'", upper_left, lower_right));
+  }
+  if !force && std::path::Path::new(file).exists() {
+    return Err(format!("'{}' already exists; pass --force to overwrite it", file));
+  }
+
+  interrupt::install();
+  let counts = accumulate(view, c, points, seed, threads)?;
+  let plotted: u64 = counts.iter().map(|&count| count as u64).sum();
+  if plotted == 0 {
+    log::warn(&format!("none of the Julia set of {} lies between {} and {}", c, upper_left, lower_right));
+  }
+  write_color_image(file, &shades(&counts), bounds, palette).map_err(|e| format!("error writing '{}': {}", file, e))?;
+  log::info(&format!("wrote {}: {} of {} points in view", file, plotted, points));
+  Ok(vec![
+    ("file".to_string(), file.into()),
+    ("size".to_string(), Value::Array(vec![bounds.0.into(), bounds.1.into()])),
+    ("points".to_string(), (points as usize).into()),
+    ("plotted".to_string(), (plotted as usize).into()),
+  ])
+}
+
+// How many of `points` points, drawn backwards from the Julia set of `c` on `threads`
+// threads, land in each pixel of `view`.
+fn accumulate(view: View, c: Complex<f64>, points: u64, seed: u64, threads: usize) -> Result<Vec<u32>, String> {
+  let orbits: Vec<u64> = (0..ORBITS).collect();
+  let counts = crossbeam::scope(|spawner| {
+    let handles: Vec<_> = orbits.chunks(ORBITS.div_ceil(threads as u64) as usize).map(|orbits| spawner.spawn(move |_| {
+      let mut counts = vec![0u32; view.bounds.0 * view.bounds.1];
+      for &orbit in orbits {
+        let share = points / ORBITS + (orbit < points % ORBITS) as u64;
+        let finished = draw(c, share, SplitMix(seed ^ orbit.wrapping_mul(0x9e3779b97f4a7c15)), |z| {
+          if let Some(pixel) = view.pixel(z) {
+            counts[pixel] = counts[pixel].saturating_add(1);
+          }
+        });
+        if !finished {
+          return None;
+        }
+      }
+      Some(counts)
+    })).collect();
+    handles.into_iter().map(|handle| handle.join().unwrap()).collect::<Option<Vec<Vec<u32>>>>()
+  }).map_err(|_| THREAD_PANICKED.to_string())?;
+  let counts = counts.ok_or("interrupted")?;
+  Ok(counts.into_iter().reduce(|mut total, counts| {
+    for (total, count) in total.iter_mut().zip(counts) {
+      *total = total.saturating_add(count);
+    }
+    total
+  }).unwrap_or_default())
+}
+
+// Hands `plot` `points` points of one orbit running backwards from a random start, or
+// stops early and returns false once Ctrl-C is pressed.
+fn draw(c: Complex<f64>, points: u64, mut random: SplitMix, mut plot: impl FnMut(Complex<f64>)) -> bool {
+  let mut z = Complex { re: random.unit() * 4.0 - 2.0, im: random.unit() * 4.0 - 2.0 };
+  for step in 0..WARMUP as u64 + points {
+    if step % CHECK == 0 && interrupt::requested() {
+      return false;
+    }
+    z = (z - c).sqrt();
+    if random.next() >> 63 == 1 {
+      z = -z;
+    }
+    if step >= WARMUP as u64 {
+      plot(z);
+    }
+  }
+  true
+}
+
+// Each pixel's shade: 0 where no point landed, rising with the log of the count to 255
+// for the most visited.
+fn shades(counts: &[u32]) -> Vec<u8> {
+  let most = (counts.iter().copied().max().unwrap_or(0) as f64).ln_1p().max(f64::MIN_POSITIVE);
+  counts.iter().map(|&count| match count {
+    0 => 0,
+    _ => (1.0 + 254.0 * (count as f64).ln_1p() / most).round().min(255.0) as u8,
+  }).collect()
+}
+
+#[test]
+fn test_inverse_iteration() {
+  // The Julia set of z² is the unit circle, and the orbit keeps to it.
+  let mut random = SplitMix(5);
+  let mut radii = Vec::new();
+  assert!(draw(Complex { re: 0.0, im: 0.0 }, 1000, SplitMix(random.next()), |z| radii.push(z.norm())));
+  assert_eq!(radii.len(), 1000);
+  assert!(radii.iter().all(|r| (r - 1.0).abs() < 1e-6));
+
+  // Points of the basilica's Julia set stay bounded going forward, for a while at least.
+  let basilica = Complex { re: -1.0, im: 0.0 };
+  let mut points = Vec::new();
+  draw(basilica, 200, SplitMix(random.next()), |z| points.push(z));
+  for z in points {
+    assert_eq!(crate::Fractal::Mandelbrot.julia_escape_time(z, basilica, 20), None);
+  }
+
+  let view = View { bounds: (40, 30), upper_left: Complex { re: -2.0, im: 1.5 }, lower_right: Complex { re: 2.0, im: -1.5 } };
+  assert_eq!(view.pixel(Complex { re: -2.0, im: 1.5 }), Some(0));
+  assert_eq!(view.pixel(Complex { re: 1.99, im: -1.49 }), Some(40 * 30 - 1));
+  assert_eq!(view.pixel(Complex { re: 2.0, im: 0.0 }), None);
+
+  // The same points whatever the number of threads.
+  let counts = accumulate(view, basilica, 10_000, 3, 1).unwrap();
+  assert_eq!(counts.iter().map(|&count| count as u64).sum::<u64>(), 10_000);
+  assert_eq!(accumulate(view, basilica, 10_000, 3, 5).unwrap(), counts);
+  let shades = shades(&counts);
+  assert!(shades.iter().zip(&counts).all(|(&shade, &count)| (shade == 0) == (count == 0)));
+  assert_eq!(shades.iter().max(), Some(&255));
+  // The basilica's interior is left dark.
+  assert_eq!(counts[view.pixel(Complex { re: 0.0, im: 0.0 }).unwrap()], 0);
+}
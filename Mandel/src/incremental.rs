@@ -0,0 +1,73 @@
+// Incremental re-rendering
+// Panning by whole pixels leaves most of the image valid, just moved. Shifting the old
+// pixels over and rendering only the newly exposed rows and columns turns a pan into a
+// small update instead of a full re-render.
+
+use crate::{copy_tile, render_parallel, render_tile, Sampler};
+
+// Updates `pixels` after the view moved `dx` pixels right and `dy` pixels down at the
+// same pitch, so new pixel (x, y) is old pixel (x + dx, y + dy). `sampler` renders the
+// new view. Returns how many pixels had to be computed.
+pub fn pan(pixels: &mut [u32], bounds: (usize, usize), dx: isize, dy: isize, sampler: &dyn Sampler, threads: usize) -> Result<usize, String> {
+  let (width, height) = (bounds.0 as isize, bounds.1 as isize);
+  if dx.abs() >= width || dy.abs() >= height {
+    render_parallel(pixels, bounds, sampler, threads, 0, 1, true)?;
+    return Ok(pixels.len());
+  }
+
+  // The rectangle of the new image that the old one still covers.
+  let kept_columns = (-dx).max(0) as usize..(width - dx).min(width) as usize;
+  let kept_rows = (-dy).max(0) as usize..(height - dy).min(height) as usize;
+
+  let old = pixels.to_vec();
+  for y in kept_rows.clone() {
+    let source = (y as isize + dy) as usize * bounds.0 + (kept_columns.start as isize + dx) as usize;
+    let target = y * bounds.0 + kept_columns.start;
+    pixels[target..target + kept_columns.len()].copy_from_slice(&old[source..source + kept_columns.len()]);
+  }
+
+  // Exposed rows span the full width; exposed columns only the kept rows.
+  let exposed_rows = if dy > 0 { kept_rows.end..bounds.1 } else { 0..kept_rows.start };
+  if !exposed_rows.is_empty() {
+    let band = &mut pixels[exposed_rows.start * bounds.0..exposed_rows.end * bounds.0];
+    render_parallel(band, (bounds.0, exposed_rows.len()), sampler, threads, exposed_rows.start, 1, true)?;
+  }
+
+  let exposed_columns = if dx > 0 { kept_columns.end..bounds.0 } else { 0..kept_columns.start };
+  if !exposed_columns.is_empty() {
+    let origin = (exposed_columns.start, kept_rows.start);
+    let size = (exposed_columns.len(), kept_rows.len());
+    copy_tile(pixels, bounds, origin, size, &render_tile(sampler, origin, size));
+  }
+
+  Ok(exposed_rows.len() * bounds.0 + exposed_columns.len() * kept_rows.len())
+}
+
+#[test]
+fn test_pan_matches_full_render_of_moved_view() {
+  use crate::Plane;
+  use num::Complex;
+
+  // A power-of-two pitch keeps the shifted corners exact.
+  let bounds = (64, 48);
+  let pitch = 1.0 / 256.0;
+  let view = |x: isize, y: isize| Plane::mandelbrot(
+    bounds,
+    Complex { re: -1.0 + x as f64 * pitch, im: 0.3 - y as f64 * pitch },
+    Complex { re: -1.0 + (x + 64) as f64 * pitch, im: 0.3 - (y + 48) as f64 * pitch },
+    255,
+  );
+
+  let mut pixels = vec![0; bounds.0 * bounds.1];
+  render_parallel(&mut pixels, bounds, &view(0, 0), 2, 0, 1, true).unwrap();
+
+  for &(dx, dy) in &[(5, -3), (-7, 0), (0, 11), (-2, -9)] {
+    let mut expected = vec![0; bounds.0 * bounds.1];
+    render_parallel(&mut expected, bounds, &view(dx, dy), 2, 0, 1, true).unwrap();
+
+    let mut panned = pixels.clone();
+    let computed = pan(&mut panned, bounds, dx, dy, &view(dx, dy), 2).unwrap();
+    assert_eq!(panned, expected, "pan by ({}, {})", dx, dy);
+    assert_eq!(computed, bounds.0 * bounds.1 - (64 - dx.unsigned_abs()) * (48 - dy.unsigned_abs()));
+  }
+}
@@ -0,0 +1,35 @@
+// Ctrl-C
+// The first SIGINT during a render only sets a flag. Render threads finish the rows they
+// are on and take no more, and the render saves what it has so the work isn't lost. A
+// second SIGINT quits at once.
+
+use std::sync::atomic::{AtomicBool, Ordering};
+
+// Exit status of a process killed by SIGINT, which shells and scripts expect.
+pub const EXIT_STATUS: u8 = 130;
+
+static REQUESTED: AtomicBool = AtomicBool::new(false);
+
+pub fn requested() -> bool {
+  REQUESTED.load(Ordering::SeqCst)
+}
+
+#[cfg(unix)]
+pub fn install() {
+  extern "C" fn on_interrupt(_signal: libc::c_int) {
+    const MESSAGE: &[u8] = b"\ninterrupted: finishing the rows in progress; press Ctrl-C again to quit now\n";
+    if REQUESTED.swap(true, Ordering::SeqCst) {
+      // SAFETY: _exit is async-signal-safe, unlike the cleanup std::process::exit does.
+      unsafe { libc::_exit(EXIT_STATUS as libc::c_int) };
+    }
+    // SAFETY: write is async-signal-safe, and MESSAGE outlives the call.
+    unsafe { libc::write(libc::STDERR_FILENO, MESSAGE.as_ptr() as *const libc::c_void, MESSAGE.len()) };
+  }
+
+  // SAFETY: the handler only touches an atomic and makes async-signal-safe calls.
+  unsafe { libc::signal(libc::SIGINT, on_interrupt as extern "C" fn(libc::c_int) as libc::sighandler_t) };
+}
+
+// Elsewhere Ctrl-C keeps killing the process outright.
+#[cfg(not(unix))]
+pub fn install() {}
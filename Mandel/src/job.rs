@@ -0,0 +1,339 @@
+// Render jobs
+// A render described as a JSON object rather than a command line, for programs that
+// hand the renderer work: `render --stdin` reads one from standard input, `batch` runs
+// a file of them, and `schema` prints the JSON Schema they follow.
+//
+//   {"schema_version": 1, "output": "seahorse.png", "size": [1000, 750],
+//    "center": "-0.745,0.1", "width": 0.02, "max_iter": 1000}
+//
+// The version is required. Later versions may add fields, but never change what a
+// version 1 job means, and a job newer than the build reading it is refused rather than
+// half understood. Fields left out take the command line's defaults. The view is given
+// by exactly one of upper_left and lower_right, center and width, or location.
+//
+// A job turns into the arguments of the command line that would do the same, so it is
+// checked by the same rules; mistakes only JSON can make are reported by field.
+
+use std::io::Read;
+
+use crate::json::{self, Value};
+use crate::location::Location;
+use crate::{parse_complex, DEFAULT_BAND_WIDTH, DEFAULT_EDGE_THRESHOLD, DEFAULT_MAX_ITER, DEFAULT_THREADS};
+
+pub const SCHEMA_VERSION: usize = 1;
+
+// What a field holds.
+#[derive(Clone, Copy)]
+enum Kind {
+  Version,
+  Text,
+  // A complex number as the string "RE,IM", which keeps all its digits.
+  Point,
+  Flag,
+  Whole,
+  Positive,
+  Number,
+  Extent,
+  // [WIDTH, HEIGHT]
+  Size,
+  Choice(&'static [&'static str]),
+  // A whole number of at least the first, or one of the words.
+  WholeOr(usize, &'static [&'static str]),
+}
+
+// A default as the schema states it.
+#[derive(Clone, Copy)]
+enum Literal {
+  Word(&'static str),
+  Count(usize),
+  Flag(bool),
+}
+
+struct Field {
+  name: &'static str,
+  kind: Kind,
+  // The command-line option it becomes; empty for the fields that become positional
+  // arguments or need more than a rename.
+  option: &'static str,
+  default: Option<Literal>,
+  description: &'static str,
+}
+
+const fn field(name: &'static str, kind: Kind, option: &'static str, default: Option<Literal>, description: &'static str) -> Field {
+  Field { name, kind, option, default, description }
+}
+
+const FIELDS: [Field; 43] = [
+  field("schema_version", Kind::Version, "", None, "The version of this schema the job follows."),
+  field("output", Kind::Text, "", None, "The PNG file to write."),
+  field("size", Kind::Size, "", None, "Width and height of the image in pixels."),
+  field("upper_left", Kind::Point, "", None, "Upper left corner of the view, as \"RE,IM\"."),
+  field("lower_right", Kind::Point, "", None, "Lower right corner of the view, as \"RE,IM\"."),
+  field("center", Kind::Point, "", None, "Center of the view, as \"RE,IM\"; give width too."),
+  field("width", Kind::Extent, "", None, "Extent of the real axis across the view."),
+  field("location", Kind::Text, "--location", None, "A Kalles Fraktaler .kfr, Ultra Fractal .upr or Fractint .par file giving the view."),
+  field("entry", Kind::Text, "--entry", None, "The entry of the location file to render, rather than its first."),
+  field("max_iter", Kind::WholeOr(1, &["auto"]), "--max-iter", Some(Literal::Count(DEFAULT_MAX_ITER)), "Iteration limit, or \"auto\" to scale it with the zoom."),
+  field("antialias", Kind::Choice(&["none", "adaptive"]), "--antialias", Some(Literal::Word("none")), "Whether to re-sample high-contrast pixels."),
+  field("supersample", Kind::Positive, "--supersample", Some(Literal::Count(1)), "Render this many times as wide and high, then average the colors down."),
+  field("samples", Kind::Positive, "--samples", Some(Literal::Count(1)), "Samples per pixel, at jittered spots, to average."),
+  field("seed", Kind::Whole, "--seed", None, "The seed that places the samples; 0 unless given."),
+  field("exterior", Kind::Choice(&["escape", "atom", "binary", "binary-levels"]), "--exterior", Some(Literal::Word("escape")), "Whether to shade points outside the set by escape time, by atom domain or by binary decomposition."),
+  field("coloring", Kind::Choice(&["escape", "bands"]), "--coloring", Some(Literal::Word("escape")), "Whether to shade escaping points by escape time or in contour bands."),
+  field("band_width", Kind::Extent, "--band-width", Some(Literal::Count(DEFAULT_BAND_WIDTH as usize)), "Iterations of the smoothed escape count each contour band spans."),
+  field("band_borders", Kind::Flag, "--band-borders", Some(Literal::Flag(false)), "Outline each contour band in a contrasting shade."),
+  field("bailout", Kind::Number, "--bailout", Some(Literal::Count(2)), "The radius orbits escape beyond."),
+  field("bailout_norm", Kind::Choice(&["euclidean", "manhattan", "chebyshev"]), "--bailout-norm", Some(Literal::Word("euclidean")), "How distance from the origin is measured for the bailout."),
+  field("interior", Kind::Choice(&["black", "period"]), "--interior", Some(Literal::Word("black")), "Whether to shade points inside the set black or by their period."),
+  field("precision", Kind::WholeOr(64, &["single", "double", "fixed128"]), "--precision", Some(Literal::Word("double")), "Arithmetic, or bits of an arbitrary-precision reference orbit."),
+  field("perturbation", Kind::Flag, "--perturbation", Some(Literal::Flag(false)), "Iterate offsets from a high-precision reference orbit."),
+  field("series", Kind::Flag, "--series", Some(Literal::Flag(false)), "Skip initial iterations with a series approximation."),
+  field("formula", Kind::Text, "--formula", None, "A WebAssembly module whose iterate export replaces the built-in iteration."),
+  field("rotate", Kind::Number, "--rotate", None, "Degrees to turn the view counterclockwise about its center."),
+  field("progressive", Kind::Flag, "--progressive", Some(Literal::Flag(false)), "Render in coarse-to-fine passes, rewriting the output after each."),
+  field("strip_rows", Kind::Positive, "--strip-rows", None, "Render and encode this many rows at a time."),
+  field("threads", Kind::Positive, "--threads", Some(Literal::Count(DEFAULT_THREADS)), "Number of render threads."),
+  field("map", Kind::Text, "--map", None, "A Fractint .map file to color the image with."),
+  field("edges", Kind::Text, "--edges", None, "A PNG file to draw where the escape count jumps between neighbors in."),
+  field("edge_threshold", Kind::Whole, "--edge-threshold", Some(Literal::Count(DEFAULT_EDGE_THRESHOLD)), "Iterations neighbors must differ by to make an edge."),
+  field("stats_json", Kind::Flag, "--stats-json", Some(Literal::Flag(false)), "Also write OUTPUT.stats.json, recording how the render went."),
+  field("histogram", Kind::Text, "--histogram", None, "A CSV file to write the distribution of escape times to."),
+  field("overlay", Kind::Text, "--overlay", None, "Overlays to draw on the image, comma-separated: \"axes\", \"grid\" for axes with grid lines, and \"scalebar\"."),
+  field("overlay_corner", Kind::Choice(&["top-left", "top-right", "bottom-left", "bottom-right"]), "--overlay-corner", Some(Literal::Word("bottom-right")), "The corner the scale bar goes in."),
+  field("font_scale", Kind::Positive, "--font-scale", None, "Pixels to a pixel of the overlay font; chosen by image size unless given."),
+  field("legend", Kind::Text, "--legend", None, "\"append\" to add a strip below the image showing the escape count of each color, or a PNG file to draw it in."),
+  field("caption", Kind::Text, "--caption", None, "\"auto\" to write the center, zoom and max_iter in a corner, or a format naming fields like {center} and {zoom}."),
+  field("caption_corner", Kind::Choice(&["top-left", "top-right", "bottom-left", "bottom-right"]), "--caption-corner", Some(Literal::Word("bottom-left")), "The corner the caption goes in."),
+  field("title", Kind::Text, "--title", None, "A title to record in the image's metadata."),
+  field("author", Kind::Text, "--author", None, "An author to record in the image's metadata."),
+  field("existing", Kind::Choice(&["refuse", "overwrite", "suffix"]), "", Some(Literal::Word("refuse")),
+        "What to do if the output exists: fail, overwrite it, or write OUTPUT-2 and so on."),
+];
+
+// The ways to give the view, each by the fields that go together.
+const VIEWS: [&[&str]; 3] = [&["upper_left", "lower_right"], &["center", "width"], &["location"]];
+
+impl Kind {
+  // What a value of this kind is, for error messages.
+  fn expected(self) -> String {
+    let words = |words: &[&str]| {
+      let quoted: Vec<String> = words.iter().map(|word| format!("\"{}\"", word)).collect();
+      match quoted.split_last() {
+        Some((last, rest)) if !rest.is_empty() => format!("{} or {}", rest.join(", "), last),
+        _ => quoted.concat(),
+      }
+    };
+    match self {
+      Kind::Version => format!("the schema version, {}", SCHEMA_VERSION),
+      Kind::Text => "a string".to_string(),
+      Kind::Point => "a point as a string \"RE,IM\"".to_string(),
+      Kind::Flag => "true or false".to_string(),
+      Kind::Whole => "a whole number".to_string(),
+      Kind::Positive => "a positive whole number".to_string(),
+      Kind::Number => "a number".to_string(),
+      Kind::Extent => "a positive number".to_string(),
+      Kind::Size => "[WIDTH, HEIGHT] in whole pixels".to_string(),
+      Kind::Choice(choices) => format!("one of {}", words(choices)),
+      Kind::WholeOr(least, choices) => format!("a whole number of at least {}, or {}", least, words(choices)),
+    }
+  }
+
+  // `value` as command-line text, if it is of this kind.
+  fn text(self, value: &Value) -> Option<String> {
+    let whole = |value: &Value, least: usize| match *value {
+      Value::Number(number) if number.fract() == 0.0 && number >= least as f64 && number <= u32::MAX as f64 => Some(number as usize),
+      _ => None,
+    };
+    match (self, value) {
+      (Kind::Version, value) => whole(value, SCHEMA_VERSION).filter(|&version| version == SCHEMA_VERSION).map(|version| version.to_string()),
+      (Kind::Text, Value::String(text)) => Some(text.clone()),
+      (Kind::Point, Value::String(text)) => parse_complex(text).map(|_| text.clone()),
+      (Kind::Flag, Value::Bool(flag)) => Some(flag.to_string()),
+      (Kind::Whole, value) => whole(value, 0).map(|count| count.to_string()),
+      (Kind::Positive, value) => whole(value, 1).map(|count| count.to_string()),
+      (Kind::Number, &Value::Number(number)) => Some(number.to_string()),
+      (Kind::Extent, &Value::Number(number)) if number > 0.0 => Some(number.to_string()),
+      (Kind::Size, Value::Array(sides)) if sides.len() == 2 => Some(format!("{}x{}", whole(&sides[0], 1)?, whole(&sides[1], 1)?)),
+      (Kind::Choice(choices) | Kind::WholeOr(_, choices), Value::String(word)) => choices.contains(&word.as_str()).then(|| word.clone()),
+      (Kind::WholeOr(least, _), value) => whole(value, least).map(|count| count.to_string()),
+      _ => None,
+    }
+  }
+
+  fn schema(self) -> Vec<(String, Value)> {
+    let member = |key: &str, value: Value| (key.to_string(), value);
+    let words = |words: &[&str]| Value::Array(words.iter().map(|&word| word.into()).collect());
+    let whole = |least: usize| Value::Object(vec![member("type", "integer".into()), member("minimum", least.into())]);
+    match self {
+      Kind::Version => vec![member("const", SCHEMA_VERSION.into())],
+      Kind::Text => vec![member("type", "string".into())],
+      Kind::Point => vec![member("type", "string".into()), member("pattern", "^[^,]+,[^,]+$".into())],
+      Kind::Flag => vec![member("type", "boolean".into())],
+      Kind::Whole => vec![member("type", "integer".into()), member("minimum", 0usize.into())],
+      Kind::Positive => vec![member("type", "integer".into()), member("minimum", 1usize.into())],
+      Kind::Number => vec![member("type", "number".into())],
+      Kind::Extent => vec![member("type", "number".into()), member("exclusiveMinimum", 0usize.into())],
+      Kind::Size => vec![member("type", "array".into()), member("items", whole(1)), member("minItems", 2usize.into()), member("maxItems", 2usize.into())],
+      Kind::Choice(choices) => vec![member("enum", words(choices))],
+      Kind::WholeOr(least, choices) => vec![member("oneOf", Value::Array(vec![whole(least), Value::Object(vec![member("enum", words(choices))])]))],
+    }
+  }
+}
+
+// The command-line arguments the job `job`, called `path` in messages, stands for.
+pub fn arguments(job: &Value, path: &str) -> Result<Vec<String>, String> {
+  let Value::Object(members) = job else {
+    return Err(format!("{}: expected a job object, got {}", path, job));
+  };
+  let mut given = Vec::new();
+  for (i, (name, value)) in members.iter().enumerate() {
+    let field = FIELDS.iter().find(|field| field.name == name).ok_or_else(|| format!("{}.{}: not a field of version {} jobs", path, name, SCHEMA_VERSION))?;
+    if members[..i].iter().any(|(earlier, _)| earlier == name) {
+      return Err(format!("{}.{}: given twice", path, name));
+    }
+    if field.name == "schema_version" && matches!(*value, Value::Number(version) if version > SCHEMA_VERSION as f64) {
+      return Err(format!("{}.schema_version: version {} is newer than this build, which reads version {}", path, value, SCHEMA_VERSION));
+    }
+    let text = field.kind.text(value).ok_or_else(|| format!("{}.{}: expected {}, got {}", path, name, field.kind.expected(), value))?;
+    given.push((field, text));
+  }
+  let value = |name: &str| given.iter().find(|(field, _)| field.name == name).map(|(_, text)| text.as_str());
+  for required in ["schema_version", "output", "size"] {
+    if value(required).is_none() {
+      return Err(format!("{}.{}: required", path, required));
+    }
+  }
+
+  let views: Vec<&&[&str]> = VIEWS.iter().filter(|view| view.iter().any(|name| value(name).is_some())).collect();
+  let view = match views.as_slice() {
+    [view] => view,
+    _ => return Err(format!("{}: give the view as upper_left and lower_right, as center and width, or as location", path)),
+  };
+  if let Some(missing) = view.iter().find(|name| value(name).is_none()) {
+    return Err(format!("{}.{}: required with {}", path, missing, view.iter().find(|name| value(name).is_some()).unwrap()));
+  }
+  if value("entry").is_some() && value("location").is_none() {
+    return Err(format!("{}.entry: only applies with location", path));
+  }
+
+  let size = value("size").unwrap();
+  let mut arguments = vec![value("output").unwrap().to_string(), size.to_string()];
+  match (value("upper_left"), value("center")) {
+    (Some(upper_left), _) => arguments.extend([upper_left.to_string(), value("lower_right").unwrap().to_string()]),
+    (None, Some(center)) => {
+      let bounds = crate::parse_pair::<usize>(size, 'x').unwrap();
+      let (re, im) = center.split_once(',').unwrap();
+      let width: f64 = value("width").unwrap().parse().unwrap();
+      let location = Location { center: (re.trim().to_string(), im.trim().to_string()), height: width * bounds.1 as f64 / bounds.0 as f64, limit: None, rotation: 0.0, map: None };
+      let (upper_left, lower_right) = location.corners(bounds);
+      arguments.extend([upper_left, lower_right]);
+    }
+    (None, None) => {}
+  }
+  for (field, text) in &given {
+    match (field.name, field.kind) {
+      ("existing", _) => arguments.extend(match text.as_str() {
+        "overwrite" => Some("--force".to_string()),
+        "suffix" => Some("--auto-suffix".to_string()),
+        _ => None,
+      }),
+      (_, _) if field.option.is_empty() => {}
+      (_, Kind::Flag) => arguments.extend((text == "true").then(|| field.option.to_string())),
+      _ => arguments.extend([field.option.to_string(), text.clone()]),
+    }
+  }
+  Ok(arguments)
+}
+
+// `arguments` with a --stdin among them replaced by those of the job on standard input.
+pub fn with_stdin(arguments: &[String]) -> Result<Vec<String>, String> {
+  let Some(at) = arguments.iter().position(|arg| arg == "--stdin") else {
+    return Ok(arguments.to_vec());
+  };
+  let mut text = String::new();
+  std::io::stdin().read_to_string(&mut text).map_err(|e| format!("error reading the job from standard input: {}", e))?;
+  let job = json::parse(&text).map_err(|e| format!("the job on standard input isn't JSON: {}", e))?;
+  let mut expanded = self::arguments(&job, "job")?;
+  expanded.extend(arguments[..at].iter().chain(&arguments[at + 1..]).cloned());
+  Ok(expanded)
+}
+
+// The jobs of a batch: a JSON array of them, or one on each line.
+pub fn batch(text: &str) -> Result<Vec<Value>, String> {
+  if text.trim_start().starts_with('[') {
+    return match json::parse(text)? {
+      Value::Array(jobs) => Ok(jobs),
+      _ => Err("expected an array of jobs".to_string()),
+    };
+  }
+  text.lines().enumerate().filter(|(_, line)| !line.trim().is_empty())
+    .map(|(i, line)| json::parse(line).map_err(|e| format!("line {}, {}", i + 1, e.strip_prefix("line 1, ").unwrap_or(&e))))
+    .collect()
+}
+
+// The JSON Schema of jobs.
+pub fn schema() -> Value {
+  let member = |key: &str, value: Value| (key.to_string(), value);
+  let properties = FIELDS.iter().map(|field| {
+    let mut property = vec![member("description", field.description.into())];
+    property.extend(field.kind.schema());
+    if let Some(default) = field.default {
+      property.push(member("default", match default {
+        Literal::Word(word) => word.into(),
+        Literal::Count(count) => count.into(),
+        Literal::Flag(flag) => Value::Bool(flag),
+      }));
+    }
+    member(field.name, Value::Object(property))
+  }).collect();
+  let names = |names: &[&str]| Value::Array(names.iter().map(|&name| name.into()).collect());
+  Value::Object(vec![
+    member("$schema", "https://json-schema.org/draft/2020-12/schema".into()),
+    member("title", format!("Mandel render job, version {}", SCHEMA_VERSION).as_str().into()),
+    member("type", "object".into()),
+    member("required", names(&["schema_version", "output", "size"])),
+    member("additionalProperties", Value::Bool(false)),
+    member("properties", Value::Object(properties)),
+    member("oneOf", Value::Array(VIEWS.iter().map(|view| Value::Object(vec![member("required", names(view))])).collect())),
+    member("dependentRequired", Value::Object(vec![member("entry", names(&["location"]))])),
+  ])
+}
+
+#[test]
+fn test_jobs_become_command_lines() {
+  let job = |text: &str| arguments(&json::parse(text).unwrap(), "job");
+  assert_eq!(job(r#"{"schema_version": 1, "output": "a.png", "size": [40, 30], "upper_left": "-1.2,0.35", "lower_right": "-1,0.2",
+                    "max_iter": "auto", "perturbation": true, "series": false, "existing": "overwrite", "threads": 2}"#).unwrap(),
+             ["a.png", "40x30", "-1.2,0.35", "-1,0.2", "--max-iter", "auto", "--perturbation", "--force", "--threads", "2"]);
+  assert_eq!(job(r#"{"schema_version": 1, "output": "b.png", "size": [200, 100], "center": "-0.75,0.1", "width": 0.5, "precision": 128}"#).unwrap(),
+             ["b.png", "200x100", "-1,0.225", "-0.5,-0.025", "--precision", "128"]);
+
+  let error = |text: &str| job(text).unwrap_err();
+  assert_eq!(error(r#"{"output": "a.png"}"#), "job.schema_version: required");
+  assert_eq!(error(r#"{"schema_version": 2}"#), "job.schema_version: version 2 is newer than this build, which reads version 1");
+  assert_eq!(error(r#"{"schema_version": 1, "output": "a.png", "size": [40, -30]}"#), "job.size: expected [WIDTH, HEIGHT] in whole pixels, got [40,-30]");
+  assert_eq!(error(r#"{"schema_version": 1, "colour": "red"}"#), "job.colour: not a field of version 1 jobs");
+  assert_eq!(error(r#"{"schema_version": 1, "output": "a.png", "size": [4, 3], "center": "0,0"}"#), "job.width: required with center");
+  assert_eq!(error(r#"{"schema_version": 1, "output": "a.png", "size": [4, 3], "center": "0,0", "width": 1, "location": "x.kfr"}"#),
+             "job: give the view as upper_left and lower_right, as center and width, or as location");
+  assert_eq!(error(r#"{"schema_version": 1, "output": "a.png", "size": [4, 3], "location": "x.kfr", "precision": 32}"#),
+             "job.precision: expected a whole number of at least 64, or \"single\", \"double\" or \"fixed128\", got 32");
+  assert_eq!(error(r#"[1]"#), "job: expected a job object, got [1]");
+
+  assert_eq!(batch("{\"a\": 1}\n\n{\"b\": 2}\n").unwrap().len(), 2);
+  assert_eq!(batch("{\"a\": 1}\n{\"b\" 2}\n"), Err("line 2, column 6: expected ':' after the member name".to_string()));
+  assert_eq!(batch(" [{}, {}]").unwrap().len(), 2);
+}
+
+#[test]
+fn test_schema_describes_every_field() {
+  let get = |value: &Value, key: &str| match value {
+    Value::Object(members) => members.iter().find(|(name, _)| name == key).map(|(_, value)| value.clone()),
+    _ => None,
+  };
+  let schema = schema();
+  let properties = get(&schema, "properties").unwrap();
+  assert!(FIELDS.iter().all(|field| get(&properties, field.name).and_then(|property| get(&property, "description")).is_some()));
+  assert_eq!(get(&get(&properties, "max_iter").unwrap(), "default"), Some(Value::Number(DEFAULT_MAX_ITER as f64)));
+  assert_eq!(json::parse(&schema.pretty()), Ok(schema));
+}
@@ -0,0 +1,352 @@
+// JSON
+// Just enough JSON for the reports and logs the program writes for other programs, and
+// for the render jobs they send it.
+
+use std::fmt;
+use std::str::FromStr;
+
+// Arrays and objects nested deeper than this are refused, since each level is a call of
+// the parser's and a deep enough document would overflow the stack.
+const MAX_DEPTH: usize = 128;
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum Value {
+  Null,
+  Bool(bool),
+  Number(f64),
+  String(String),
+  Array(Vec<Value>),
+  // Members keep the order they were given in.
+  Object(Vec<(String, Value)>),
+}
+
+impl From<&str> for Value {
+  fn from(text: &str) -> Value {
+    Value::String(text.to_string())
+  }
+}
+
+impl From<f64> for Value {
+  fn from(number: f64) -> Value {
+    Value::Number(number)
+  }
+}
+
+impl From<usize> for Value {
+  fn from(number: usize) -> Value {
+    Value::Number(number as f64)
+  }
+}
+
+// Compact JSON. Numbers JSON can't represent, NaN and the infinities, come out as null.
+impl fmt::Display for Value {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    match self {
+      Value::Null => write!(f, "null"),
+      Value::Bool(value) => write!(f, "{}", value),
+      Value::Number(number) if number.is_finite() => write!(f, "{}", number),
+      Value::Number(_) => write!(f, "null"),
+      Value::String(text) => write!(f, "{}", quote(text)),
+      Value::Array(items) => {
+        write!(f, "[")?;
+        for (i, item) in items.iter().enumerate() {
+          write!(f, "{}{}", if i == 0 { "" } else { "," }, item)?;
+        }
+        write!(f, "]")
+      }
+      Value::Object(members) => {
+        write!(f, "{{")?;
+        for (i, (key, value)) in members.iter().enumerate() {
+          write!(f, "{}{}:{}", if i == 0 { "" } else { "," }, quote(key), value)?;
+        }
+        write!(f, "}}")
+      }
+    }
+  }
+}
+
+impl Value {
+  // Indented two spaces a level, for people to read.
+  pub fn pretty(&self) -> String {
+    let mut text = String::new();
+    self.write_pretty(&mut text, 0);
+    text
+  }
+
+  fn write_pretty(&self, text: &mut String, depth: usize) {
+    let indent = |depth: usize| "  ".repeat(depth);
+    match self {
+      Value::Array(items) if !items.is_empty() => {
+        text.push_str("[\n");
+        for (i, item) in items.iter().enumerate() {
+          text.push_str(&indent(depth + 1));
+          item.write_pretty(text, depth + 1);
+          text.push_str(if i + 1 < items.len() { ",\n" } else { "\n" });
+        }
+        text.push_str(&indent(depth));
+        text.push(']');
+      }
+      Value::Object(members) if !members.is_empty() => {
+        text.push_str("{\n");
+        for (i, (key, value)) in members.iter().enumerate() {
+          text.push_str(&format!("{}{}: ", indent(depth + 1), quote(key)));
+          value.write_pretty(text, depth + 1);
+          text.push_str(if i + 1 < members.len() { ",\n" } else { "\n" });
+        }
+        text.push_str(&indent(depth));
+        text.push('}');
+      }
+      value => text.push_str(&value.to_string()),
+    }
+  }
+}
+
+// Parses a JSON document, or says where in it the first mistake is.
+pub fn parse(text: &str) -> Result<Value, String> {
+  let mut parser = Parser { text, at: 0, depth: 0 };
+  let value = parser.value()?;
+  parser.space();
+  if parser.at < text.len() {
+    return Err(parser.error("unexpected text after the value"));
+  }
+  Ok(value)
+}
+
+struct Parser<'a> {
+  text: &'a str,
+  // Byte offset of the next character.
+  at: usize,
+  // Arrays and objects open around the next character.
+  depth: usize,
+}
+
+impl Parser<'_> {
+  fn error(&self, message: &str) -> String {
+    let before = &self.text[..self.at];
+    let line = before.matches('\n').count() + 1;
+    let column = before.rsplit('\n').next().unwrap_or("").chars().count() + 1;
+    format!("line {}, column {}: {}", line, column, message)
+  }
+
+  fn peek(&self) -> Option<char> {
+    self.text[self.at..].chars().next()
+  }
+
+  fn space(&mut self) {
+    while let Some(c) = self.peek().filter(|c| matches!(c, ' ' | '\t' | '\n' | '\r')) {
+      self.at += c.len_utf8();
+    }
+  }
+
+  fn eat(&mut self, expected: char) -> bool {
+    if self.peek() == Some(expected) {
+      self.at += expected.len_utf8();
+      true
+    } else {
+      false
+    }
+  }
+
+  fn value(&mut self) -> Result<Value, String> {
+    self.space();
+    match self.peek() {
+      Some('{' | '[') if self.depth == MAX_DEPTH => Err(self.error("nested too deeply")),
+      Some(open @ ('{' | '[')) => {
+        self.depth += 1;
+        let value = if open == '{' { self.object() } else { self.array() };
+        self.depth -= 1;
+        value
+      }
+      Some('"') => self.string().map(Value::String),
+      Some('-' | '0'..='9') => self.number(),
+      Some(_) => {
+        for (word, value) in [("true", Value::Bool(true)), ("false", Value::Bool(false)), ("null", Value::Null)] {
+          if self.text[self.at..].starts_with(word) {
+            self.at += word.len();
+            return Ok(value);
+          }
+        }
+        Err(self.error("expected a value"))
+      }
+      None => Err(self.error("expected a value, found the end")),
+    }
+  }
+
+  fn object(&mut self) -> Result<Value, String> {
+    self.eat('{');
+    let mut members = Vec::new();
+    self.space();
+    if self.eat('}') {
+      return Ok(Value::Object(members));
+    }
+    loop {
+      self.space();
+      if self.peek() != Some('"') {
+        return Err(self.error("expected a member name in double quotes"));
+      }
+      let key = self.string()?;
+      self.space();
+      if !self.eat(':') {
+        return Err(self.error("expected ':' after the member name"));
+      }
+      members.push((key, self.value()?));
+      self.space();
+      if self.eat('}') {
+        return Ok(Value::Object(members));
+      }
+      if !self.eat(',') {
+        return Err(self.error("expected ',' or '}'"));
+      }
+    }
+  }
+
+  fn array(&mut self) -> Result<Value, String> {
+    self.eat('[');
+    let mut items = Vec::new();
+    self.space();
+    if self.eat(']') {
+      return Ok(Value::Array(items));
+    }
+    loop {
+      items.push(self.value()?);
+      self.space();
+      if self.eat(']') {
+        return Ok(Value::Array(items));
+      }
+      if !self.eat(',') {
+        return Err(self.error("expected ',' or ']'"));
+      }
+    }
+  }
+
+  fn string(&mut self) -> Result<String, String> {
+    self.eat('"');
+    let mut string = String::new();
+    loop {
+      let Some(c) = self.peek() else {
+        return Err(self.error("unterminated string"));
+      };
+      self.at += c.len_utf8();
+      match c {
+        '"' => return Ok(string),
+        '\\' => {
+          let escape = self.peek().ok_or_else(|| self.error("unterminated string"))?;
+          self.at += escape.len_utf8();
+          string.push(match escape {
+            '"' | '\\' | '/' => escape,
+            'b' => '\u{8}',
+            'f' => '\u{c}',
+            'n' => '\n',
+            'r' => '\r',
+            't' => '\t',
+            'u' => self.unicode_escape()?,
+            _ => return Err(self.error("unknown escape in string")),
+          });
+        }
+        c if (c as u32) < 0x20 => return Err(self.error("control character in string")),
+        c => string.push(c),
+      }
+    }
+  }
+
+  // The character of a \u escape, whose \u has been read, joining surrogate pairs.
+  fn unicode_escape(&mut self) -> Result<char, String> {
+    let first = self.unit()?;
+    let code = if (0xd800..0xdc00).contains(&first) {
+      if !self.text[self.at..].starts_with("\\u") {
+        return Err(self.error("unpaired surrogate in string"));
+      }
+      self.at += 2;
+      let second = self.unit()?;
+      if !(0xdc00..0xe000).contains(&second) {
+        return Err(self.error("unpaired surrogate in string"));
+      }
+      0x10000 + ((first - 0xd800) << 10) + (second - 0xdc00)
+    } else {
+      first
+    };
+    char::from_u32(code).ok_or_else(|| self.error("unpaired surrogate in string"))
+  }
+
+  // The four hexadecimal digits of a UTF-16 code unit.
+  fn unit(&mut self) -> Result<u32, String> {
+    let digits = self.text.get(self.at..self.at + 4).filter(|digits| digits.chars().all(|c| c.is_ascii_hexdigit()));
+    let unit = digits.map(|digits| u32::from_str_radix(digits, 16).unwrap()).ok_or_else(|| self.error("expected four hexadecimal digits"))?;
+    self.at += 4;
+    Ok(unit)
+  }
+
+  fn number(&mut self) -> Result<Value, String> {
+    let start = self.at;
+    let length = self.text[start..].find(|c: char| !matches!(c, '0'..='9' | '-' | '+' | '.' | 'e' | 'E')).unwrap_or(self.text.len() - start);
+    let literal = &self.text[start..start + length];
+    // Rust accepts some forms JSON doesn't, like "1." and ".5"; JSON's leading zeros either.
+    let digits = literal.trim_start_matches('-');
+    let leading_zero = digits.starts_with('0') && digits[1..].starts_with(|c: char| c.is_ascii_digit());
+    let malformed = !digits.starts_with(|c: char| c.is_ascii_digit()) || leading_zero || literal.contains(".e") || literal.contains(".E") || literal.ends_with('.');
+    match f64::from_str(literal) {
+      Ok(number) if !malformed && number.is_finite() => {
+        self.at += length;
+        Ok(Value::Number(number))
+      }
+      _ => Err(self.error(&format!("malformed number '{}'", literal))),
+    }
+  }
+}
+
+// `text` as a JSON string literal.
+pub fn quote(text: &str) -> String {
+  let mut quoted = String::with_capacity(text.len() + 2);
+  quoted.push('"');
+  for c in text.chars() {
+    match c {
+      '"' => quoted.push_str("\\\""),
+      '\\' => quoted.push_str("\\\\"),
+      '\n' => quoted.push_str("\\n"),
+      c if (c as u32) < 0x20 => quoted.push_str(&format!("\\u{:04x}", c as u32)),
+      c => quoted.push(c),
+    }
+  }
+  quoted.push('"');
+  quoted
+}
+
+#[test]
+fn test_writes_json() {
+  assert_eq!(quote("say \"hi\"\n\u{1}"), r#""say \"hi\"\n\u0001""#);
+  let value = Value::Object(vec![
+    ("size".to_string(), Value::Array(vec![640usize.into(), 480usize.into()])),
+    ("seconds".to_string(), 1.5.into()),
+    ("hash".to_string(), Value::Null),
+    ("ok".to_string(), Value::Bool(true)),
+    ("bad".to_string(), f64::NAN.into()),
+    ("file".to_string(), "a \"b\".png".into()),
+  ]);
+  assert_eq!(value.to_string(), r#"{"size":[640,480],"seconds":1.5,"hash":null,"ok":true,"bad":null,"file":"a \"b\".png"}"#);
+}
+
+#[test]
+fn test_parses_json() {
+  let value = parse(" {\"size\": [640, 4.8e2], \"name\": \"a\\\"b\\u00e9\\ud83d\\ude00\", \"ok\": true, \"none\": null, \"empty\": {}}\n").unwrap();
+  assert_eq!(value, Value::Object(vec![
+    ("size".to_string(), Value::Array(vec![640usize.into(), 480usize.into()])),
+    ("name".to_string(), "a\"b\u{e9}\u{1f600}".into()),
+    ("ok".to_string(), Value::Bool(true)),
+    ("none".to_string(), Value::Null),
+    ("empty".to_string(), Value::Object(Vec::new())),
+  ]));
+  assert_eq!(parse(&value.to_string()), Ok(value.clone()));
+
+  assert_eq!(parse("{\"a\": 1,\n  \"b\" 2}"), Err("line 2, column 7: expected ':' after the member name".to_string()));
+  assert_eq!(parse("[1, 2"), Err("line 1, column 6: expected ',' or ']'".to_string()));
+  assert_eq!(parse("[01]"), Err("line 1, column 2: malformed number '01'".to_string()));
+  assert_eq!(parse("{} x"), Err("line 1, column 4: unexpected text after the value".to_string()));
+  assert!(parse("\"\\ud800\"").is_err());
+  let nested = |depth: usize| "[".repeat(depth) + &"]".repeat(depth);
+  assert!(parse(&nested(MAX_DEPTH)).is_ok());
+  assert_eq!(parse(&nested(MAX_DEPTH + 1)), Err(format!("line 1, column {}: nested too deeply", MAX_DEPTH + 1)));
+  assert!(parse(&"[{\"a\":".repeat(500_000)).is_err());
+
+  assert_eq!(Value::Object(vec![("a".to_string(), Value::Array(vec![1usize.into()])), ("b".to_string(), Value::Array(Vec::new()))]).pretty(),
+             "{\n  \"a\": [\n    1\n  ],\n  \"b\": []\n}");
+}
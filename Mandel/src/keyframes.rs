@@ -0,0 +1,172 @@
+// Keyframe animations
+// An animation spec lists keyframes, one per line: a time in seconds, then the settings
+// that change there.
+//
+//   # Comments and blank lines are ignored.
+//   0     center -0.5,0            zoom 1     max-iter 255
+//   8     center -0.7436,0.1318    zoom 1e5   rotate 90
+//   12    zoom 1e8   max-iter auto   offset 128   ease ease-in-out   palette fire
+//
+// Settings left out carry over from the keyframe before, and the first keyframe, which
+// must be at time 0, starts from the full view: center -0.5,0, zoom 1, max-iter auto,
+// offset 0, rotate 0, ease linear and palette gray. Between keyframes the zoom changes
+// exponentially and the center moves in proportion to the change in view width, so a
+// zoom toward a point closes in on it steadily instead of arriving early and then only
+// magnifying. The iteration limit, palette offset and rotation (degrees
+// counterclockwise) change linearly; a limit of auto follows the zoom, and a change of
+// palette fades from one to the other (see palette.rs). A keyframe's ease setting (see
+// easing.rs) paces the changes from it to the next one.
+
+use std::str::FromStr;
+
+use num::Complex;
+
+use crate::animate::Frame;
+use crate::easing::Easing;
+use crate::palette::{Blend, Palette, PALETTES};
+use crate::{parse_complex, MaxIter};
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Keyframe {
+  pub time: f64,
+  pub center: Complex<f64>,
+  pub zoom: f64,
+  pub max_iter: MaxIter,
+  pub offset: f64,
+  pub rotation: f64,
+  pub easing: Easing,
+  pub palette: Palette,
+}
+
+const START: Keyframe = Keyframe { time: 0.0, center: Complex { re: -0.5, im: 0.0 }, zoom: 1.0, max_iter: MaxIter::Auto, offset: 0.0, rotation: 0.0,
+                                   easing: Easing::Linear, palette: Palette::Gray };
+
+pub fn parse(text: &str) -> Result<Vec<Keyframe>, String> {
+  let mut keyframes: Vec<Keyframe> = Vec::new();
+
+  for (number, line) in text.lines().enumerate() {
+    let line = line.split('#').next().unwrap().trim();
+    if line.is_empty() {
+      continue;
+    }
+    let error = |message: String| format!("line {}: {}", number + 1, message);
+    let number = |value: Option<&str>, setting: &str| -> Result<f64, String> {
+      value.and_then(|value| f64::from_str(value).ok()).filter(|value| value.is_finite())
+        .ok_or_else(|| error(format!("{} expects a number", setting)))
+    };
+
+    let mut words = line.split_whitespace();
+    let mut keyframe = Keyframe { time: number(words.next(), "a keyframe")?, ..keyframes.last().copied().unwrap_or(START) };
+    while let Some(setting) = words.next() {
+      let value = words.next();
+      match setting {
+        "center" => keyframe.center = value.and_then(parse_complex).ok_or_else(|| error("center expects RE,IM".to_string()))?,
+        "zoom" => keyframe.zoom = number(value, setting).and_then(|zoom| if zoom > 0.0 { Ok(zoom) } else { Err(error("zoom must be positive".to_string())) })?,
+        "max-iter" => {
+          keyframe.max_iter = match value {
+            Some("auto") => MaxIter::Auto,
+            _ => match value.map(usize::from_str) {
+              Some(Ok(limit)) if limit > 0 => MaxIter::Fixed(limit),
+              _ => return Err(error("max-iter expects a positive number or 'auto'".to_string())),
+            },
+          }
+        }
+        "offset" => keyframe.offset = number(value, setting)?,
+        "rotate" => keyframe.rotation = number(value, setting)?,
+        "palette" => {
+          keyframe.palette = PALETTES.iter().find(|palette| Some(palette.name()) == value).copied()
+            .ok_or_else(|| error(format!("unknown palette '{}'", value.unwrap_or_default())))?
+        }
+        "ease" => keyframe.easing = value.and_then(Easing::parse).ok_or_else(|| error("ease expects an easing curve".to_string()))?,
+        _ => return Err(error(format!("unknown setting '{}'", setting))),
+      }
+    }
+
+    match keyframes.last() {
+      None if keyframe.time != 0.0 => return Err(error("the first keyframe must be at time 0".to_string())),
+      Some(previous) if keyframe.time <= previous.time => return Err(error("keyframe times must increase".to_string())),
+      _ => keyframes.push(keyframe),
+    }
+  }
+
+  if keyframes.is_empty() {
+    return Err("the spec has no keyframes".to_string());
+  }
+  Ok(keyframes)
+}
+
+// The frames showing `keyframes` at `fps` frames per second, through the last one.
+pub fn frames(keyframes: &[Keyframe], fps: f64) -> Vec<Frame> {
+  let last = keyframes.last().unwrap();
+  let count = (last.time * fps).round() as usize + 1;
+  (0..count)
+    .map(|i| {
+      let time = i as f64 / fps;
+      let next = keyframes.iter().position(|keyframe| keyframe.time > time).unwrap_or(keyframes.len() - 1).max(1);
+      match keyframes.get(next - 1..=next) {
+        Some([from, to]) => between(from, to, ((time - from.time) / (to.time - from.time)).min(1.0)),
+        _ => between(last, last, 0.0),
+      }
+    })
+    .collect()
+}
+
+// The frame a fraction `t` of the time from one keyframe to the next.
+fn between(from: &Keyframe, to: &Keyframe, t: f64) -> Frame {
+  let t = from.easing.apply(t);
+  let lerp = |a: f64, b: f64| a + (b - a) * t;
+  let (from_width, to_width) = (4.0 / from.zoom, 4.0 / to.zoom);
+  let width = from_width * (to_width / from_width).powf(t);
+
+  // How far the view has come, measured by its width where that changes.
+  let progress = if (from_width - to_width).abs() > from_width * 1e-9 { (from_width - width) / (from_width - to_width) } else { t };
+  let center = from.center + (to.center - from.center) * progress;
+
+  let limit = match (from.max_iter, to.max_iter) {
+    (MaxIter::Fixed(a), MaxIter::Fixed(b)) => lerp(a as f64, b as f64).round() as usize,
+    _ => MaxIter::Auto.resolve(width),
+  };
+  Frame { center, width, limit, offset: lerp(from.offset, to.offset).rem_euclid(255.0).round() as u8, rotation: lerp(from.rotation, to.rotation), julia: None,
+          palette: Blend { from: from.palette, to: to.palette, t } }
+}
+
+#[test]
+fn test_parse_carries_settings_over() {
+  let keyframes = parse("# zoom in\n0 center -0.7,0.1 max-iter 500\n\n2 zoom 100 rotate 45  # tilt\n3 max-iter auto\n").unwrap();
+  assert_eq!(keyframes.len(), 3);
+  assert_eq!(keyframes[1], Keyframe { time: 2.0, center: Complex { re: -0.7, im: 0.1 }, zoom: 100.0, max_iter: MaxIter::Fixed(500), offset: 0.0, rotation: 45.0,
+                                       easing: Easing::Linear, palette: Palette::Gray });
+  assert_eq!(keyframes[2].max_iter, MaxIter::Auto);
+
+  assert_eq!(parse("1 zoom 2").unwrap_err(), "line 1: the first keyframe must be at time 0");
+  assert_eq!(parse("0\n0 zoom 2").unwrap_err(), "line 2: keyframe times must increase");
+  assert_eq!(parse("0 spin 3").unwrap_err(), "line 1: unknown setting 'spin'");
+}
+
+#[test]
+fn test_frames_interpolate_between_keyframes() {
+  let keyframes = parse("0 center 0,0 zoom 1 max-iter 100\n2 center 1,0 zoom 100 max-iter 300 offset 10 rotate 90").unwrap();
+  let frames = frames(&keyframes, 2.0);
+  assert_eq!(frames.len(), 5);
+
+  // Halfway in time is halfway in zoom, by factors.
+  let middle = frames[2];
+  assert!((middle.width - 0.4).abs() < 1e-12);
+  assert_eq!((middle.limit, middle.offset, middle.rotation), (200, 5, 45.0));
+  // Most of the width is gone by then, and the center has come as far.
+  assert!((middle.center.re - 3.6 / 3.96).abs() < 1e-12);
+
+  assert_eq!(frames[4].center, Complex { re: 1.0, im: 0.0 });
+  assert!((frames[4].width - 0.04).abs() < 1e-12);
+
+  // Easing in holds the view back early on, but it still arrives on time.
+  let eased = parse("0 center 0,0 zoom 1 ease exponential\n2 center 1,0 zoom 100").unwrap();
+  let eased = self::frames(&eased, 2.0);
+  assert!(eased[2].width > 3.0);
+  assert_eq!(eased[4].center, frames[4].center);
+
+  let fading = parse("0 palette ocean\n1 palette fire\n2").unwrap();
+  let fading = self::frames(&fading, 2.0);
+  assert_eq!(fading[1].palette, Blend { from: Palette::Ocean, to: Palette::Fire, t: 0.5 });
+  assert_eq!(fading[4].palette.single(), Some(Palette::Fire));
+}
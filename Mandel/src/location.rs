@@ -0,0 +1,362 @@
+// Location files
+// Views saved by other fractal programs, rendered with --location FILE. Kalles
+// Fraktaler's .kfr parameter files have lines like
+//
+//   Re: -0.74364388703715870475219150611477
+//   Im: 0.13182590420531197049313205638980
+//   Zoom: 2.5E29
+//   Iterations: 20000
+//
+// and Ultra Fractal's .upr parameter sets hold entries like
+//
+//   seahorse {
+//   fractal:
+//     title="seahorse" width=640 height=480 layers=1
+//   mapping:
+//     center=-0.745/0.1 magn=200 angle=30
+//   formula:
+//     maxiter=1000 filename="Standard.ufm" entry="Mandelbrot"
+//   ...
+//   }
+//
+// Fractint's .par files hold entries in the same braces, with settings like
+//
+//   corners=-0.76/-0.73/0.09/0.12 maxiter=1000 map=blues.map   ; a comment
+//
+// or center-mag=X/Y/MAG[/XMAG/ROTATION] in place of the corners. A map file named there
+// is looked for beside the .par file and, if it's there, colors the image.
+//
+// Parameter files usually hold many entries; --entry NAME picks one, and otherwise the
+// first is read. Zoom or magnification 1 shows 4 units of the imaginary axis, top to
+// bottom, in Kalles Fraktaler and Ultra Fractal, and Fractint's magnification 1 shows 2.
+// Corners only set the height: the width follows from the image's shape. What else the
+// files say about formulas and coloring is ignored. --save-location writes the rendered
+// view as an .upr entry, colored with a gray gradient like the image's own shading.
+
+use std::fmt::Write;
+use std::path::Path;
+use std::str::FromStr;
+
+use dashu_float::DBig;
+
+use crate::{log, Precision};
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Location {
+  // Decimal digits as the file gives them, since deep locations need more than f64 keeps.
+  pub center: (String, String),
+  // Extent of the imaginary axis.
+  pub height: f64,
+  pub limit: Option<usize>,
+  // Degrees counterclockwise.
+  pub rotation: f64,
+  // A Fractint color map to color the image with.
+  pub map: Option<String>,
+}
+
+// Reads the location in `path`, or its entry called `entry` if given.
+pub fn load(path: &str, entry: Option<&str>) -> Result<Location, String> {
+  let text = std::fs::read_to_string(path).map_err(|e| format!("error reading location '{}': {}", path, e))?;
+  let path = Path::new(path);
+  let extension = path.extension().map(|extension| extension.to_string_lossy().to_ascii_lowercase());
+  let location = match (extension.as_deref(), entry) {
+    (Some("kfr"), None) => parse_kfr(&text),
+    (Some("kfr"), Some(_)) => Err("a .kfr file holds a single location, so --entry doesn't apply".to_string()),
+    (Some("upr"), _) => parse_upr(&text, entry),
+    (Some("par"), _) => parse_par(&text, entry),
+    _ => Err("expected a Kalles Fraktaler .kfr, Ultra Fractal .upr or Fractint .par file".to_string()),
+  };
+  let mut location = location.map_err(|e| format!("location '{}': {}", path.display(), e))?;
+
+  if let Some(map) = location.map.take() {
+    let beside = path.with_file_name(&map);
+    if beside.is_file() {
+      location.map = Some(beside.to_string_lossy().into_owned());
+    } else {
+      log::warn(&format!("location '{}' uses the color map '{}', which isn't there; rendering in gray", path.display(), beside.display()));
+    }
+  }
+  Ok(location)
+}
+
+fn parse_kfr(text: &str) -> Result<Location, String> {
+  let value = |key: &str| {
+    text.lines().filter_map(|line| line.split_once(':')).find(|(name, _)| name.trim() == key).map(|(_, value)| value.trim())
+  };
+  let coordinate = |key: &str| match value(key) {
+    Some(digits) if is_coordinate(digits) => Ok(digits.to_string()),
+    Some(digits) => Err(format!("{} '{}' is not a number", key, digits)),
+    None => Err(format!("no {} line", key)),
+  };
+  let center = (coordinate("Re")?, coordinate("Im")?);
+  let zoom = value("Zoom").ok_or("no Zoom line")?;
+  let height = f64::from_str(zoom).ok().filter(|zoom| zoom.is_finite() && *zoom > 0.0).map(|zoom| 4.0 / zoom)
+    .ok_or(format!("Zoom '{}' is not a positive number", zoom))?;
+  let limit = match value("Iterations") {
+    Some(limit) => Some(usize::from_str(limit).ok().filter(|&limit| limit > 0).ok_or(format!("Iterations '{}' is not a positive number", limit))?),
+    None => None,
+  };
+  Ok(Location { center, height, limit, rotation: 0.0, map: None })
+}
+
+fn parse_upr(text: &str, name: Option<&str>) -> Result<Location, String> {
+  let settings = Settings(entry(text, name)?);
+  let (re, im) = settings.center("center")?;
+  let magnification = settings.number("magn")?.unwrap_or(1.0);
+  if magnification <= 0.0 {
+    return Err(format!("magn '{}' is not a positive number", magnification));
+  }
+  let rotation = settings.number("angle")?.unwrap_or(0.0);
+  Ok(Location { center: (re, im), height: 4.0 / magnification, limit: settings.limit()?, rotation, map: None })
+}
+
+fn parse_par(text: &str, name: Option<&str>) -> Result<Location, String> {
+  let settings = Settings(entry(text, name)?);
+  if let Some(kind) = settings.value("type").filter(|kind| *kind != "mandel") {
+    return Err(format!("type={} isn't supported; only type=mandel is", kind));
+  }
+  let exact = |digits: &str, key: &str, text: &str| {
+    DBig::from_str(digits).map(|value| value.with_precision(0).value()).map_err(|_| format!("{} '{}' is not numbers separated by /", key, text))
+  };
+  let (center, height, rotation) = match (settings.value("corners"), settings.value("center-mag")) {
+    (Some(corners), _) => {
+      let values = corners.split('/').map(|value| exact(value, "corners", corners)).collect::<Result<Vec<_>, _>>()?;
+      let [x_min, x_max, y_min, y_max, ..] = values.as_slice() else {
+        return Err(format!("corners '{}' should be XMIN/XMAX/YMIN/YMAX", corners));
+      };
+      let half = DBig::from_str("0.5").unwrap().with_precision(0).value();
+      let center = (((x_min + x_max) * &half).to_string(), ((y_min + y_max) * &half).to_string());
+      (center, (y_max - y_min).to_f64().value().abs(), 0.0)
+    }
+    (None, Some(center_mag)) => {
+      let values: Vec<&str> = center_mag.split('/').collect();
+      let number = |value: &str| f64::from_str(value).ok().filter(|value| value.is_finite());
+      let magnification = values.get(2).and_then(|value| number(value)).filter(|&value| value > 0.0);
+      let (&[x, y, ..], Some(magnification)) = (values.as_slice(), magnification) else {
+        return Err(format!("center-mag '{}' should be X/Y/MAG with a positive MAG", center_mag));
+      };
+      if !is_coordinate(x) || !is_coordinate(y) {
+        return Err(format!("center-mag '{}' is not numbers separated by /", center_mag));
+      }
+      let rotation = values.get(4).map(|value| number(value).ok_or(format!("center-mag '{}' has a rotation that is not a number", center_mag))).transpose()?;
+      ((x.to_string(), y.to_string()), 2.0 / magnification, rotation.unwrap_or(0.0))
+    }
+    (None, None) => return Err("no corners or center-mag setting".to_string()),
+  };
+  if height == 0.0 {
+    return Err("the corners enclose no height".to_string());
+  }
+  Ok(Location { center, height, limit: settings.limit()?, rotation, map: settings.value("map").map(str::to_string) })
+}
+
+// Whether `digits` is a coordinate every arithmetic reads alike: DBig takes some, such as
+// 1_0, that f64 doesn't.
+fn is_coordinate(digits: &str) -> bool {
+  DBig::from_str(digits).is_ok() && f64::from_str(digits).is_ok()
+}
+
+// The key=value settings of an entry in an .upr or .par file.
+struct Settings<'a>(Vec<(&'a str, &'a str)>);
+
+impl<'a> Settings<'a> {
+  fn value(&self, key: &str) -> Option<&'a str> {
+    self.0.iter().find(|(name, _)| *name == key).map(|&(_, value)| value)
+  }
+
+  fn number(&self, key: &str) -> Result<Option<f64>, String> {
+    self.value(key).map(|text| f64::from_str(text).ok().filter(|value| value.is_finite()).ok_or(format!("{} '{}' is not a number", key, text))).transpose()
+  }
+
+  fn limit(&self) -> Result<Option<usize>, String> {
+    self.value("maxiter").map(|limit| usize::from_str(limit).ok().filter(|&limit| limit > 0).ok_or(format!("maxiter '{}' is not a positive number", limit))).transpose()
+  }
+
+  // A point written RE/IM, keeping its digits.
+  fn center(&self, key: &str) -> Result<(String, String), String> {
+    let center = self.value(key).ok_or(format!("no {} setting", key))?;
+    center.split_once('/').filter(|(re, im)| is_coordinate(re) && is_coordinate(im))
+      .map(|(re, im)| (re.to_string(), im.to_string()))
+      .ok_or(format!("{} '{}' is not two numbers, as RE/IM", key, center))
+  }
+}
+
+// The settings of the entry called `name` in `text`, or of the first entry, where an
+// entry runs from a line `NAME {` to one with the closing brace.
+fn entry<'a>(text: &'a str, name: Option<&str>) -> Result<Vec<(&'a str, &'a str)>, String> {
+  let mut lines = text.lines().map(uncomment);
+  let opening = lines.by_ref().find_map(|line| {
+    let (title, rest) = line.split_once('{')?;
+    name.is_none_or(|name| title.trim() == name).then_some(rest)
+  });
+  let Some(opening) = opening else {
+    return Err(name.map_or("no parameter set entry".to_string(), |name| format!("no entry named '{}'", name)));
+  };
+  let mut settings = Vec::new();
+  for line in std::iter::once(opening).chain(lines) {
+    let (line, closed) = line.split_once('}').map_or((line, false), |(line, _)| (line, true));
+    settings.extend(key_values(line.trim()));
+    if closed {
+      break;
+    }
+  }
+  Ok(settings)
+}
+
+// `line` without any comment, which runs from a semicolon outside quotes to the end.
+fn uncomment(line: &str) -> &str {
+  let mut quoted = false;
+  for (i, c) in line.char_indices() {
+    match c {
+      '"' => quoted = !quoted,
+      ';' if !quoted => return &line[..i],
+      _ => {}
+    }
+  }
+  line
+}
+
+// The key=value settings on a line, with quoted values unquoted.
+fn key_values(mut line: &str) -> Vec<(&str, &str)> {
+  let mut settings = Vec::new();
+  while let Some((key, rest)) = line.split_once('=') {
+    let (value, rest) = match rest.strip_prefix('"') {
+      Some(quoted) => quoted.split_once('"').unwrap_or((quoted, "")),
+      None => rest.split_once(char::is_whitespace).unwrap_or((rest, "")),
+    };
+    settings.push((key.trim(), value));
+    line = rest.trim_start();
+  }
+  settings
+}
+
+// An .upr entry called `title` for the `bounds` view from `upper_left` to `lower_right`.
+pub fn upr(title: &str, bounds: (usize, usize), upper_left: &str, lower_right: &str, limit: usize, rotation: f64) -> Result<String, String> {
+  let (a, b) = (exact_corner(upper_left)?, exact_corner(lower_right)?);
+  let center = center(upper_left, lower_right)?;
+  let height = (&a.1 - &b.1).to_f64().value();
+
+  let mut entry = String::new();
+  let title = title.replace(['"', '{', '}'], "");
+  // Ultra Fractal expects Windows line endings.
+  let mut line = |text: String| {
+    let _ = write!(entry, "{}\r\n", text);
+  };
+  line(format!("{} {{", title.replace(char::is_whitespace, "_")));
+  line("fractal:".to_string());
+  line(format!("  title=\"{}\" width={} height={} layers=1", title, bounds.0, bounds.1));
+  line("layer:".to_string());
+  line("  caption=\"Background\" opacity=100 method=multipass".to_string());
+  line("mapping:".to_string());
+  line(format!("  center={}/{} magn={:e} angle={}", center.0, center.1, 4.0 / height, rotation));
+  line("formula:".to_string());
+  line(format!("  maxiter={} filename=\"Standard.ufm\" entry=\"Mandelbrot\"", limit));
+  line("inside:".to_string());
+  line("  transfer=none solid=4278190080".to_string());
+  line("outside:".to_string());
+  line("  transfer=linear filename=\"Standard.ucl\" entry=\"Basic\"".to_string());
+  line("gradient:".to_string());
+  line("  smooth=yes index=0 color=16777215 index=399 color=0".to_string());
+  line("}".to_string());
+  Ok(entry)
+}
+
+// The corner written `text` in exact decimal arithmetic.
+pub fn exact_corner(text: &str) -> Result<(DBig, DBig), String> {
+  let (re, im) = text.split_once(',').ok_or(format!("error parsing corner '{}'", text))?;
+  let exact = |digits: &str| DBig::from_str(digits.trim()).map(|value| value.with_precision(0).value()).map_err(|_| format!("error parsing corner '{}'", text));
+  Ok((exact(re)?, exact(im)?))
+}
+
+// The center of the view from `upper_left` to `lower_right`, in exact decimal arithmetic
+// so it keeps every digit the corners have.
+pub fn center(upper_left: &str, lower_right: &str) -> Result<(DBig, DBig), String> {
+  let (a, b) = (exact_corner(upper_left)?, exact_corner(lower_right)?);
+  let half = DBig::from_str("0.5").unwrap().with_precision(0).value();
+  Ok(((&a.0 + &b.0) * &half, (&a.1 + &b.1) * &half))
+}
+
+impl Location {
+  // The upper left and lower right corners of the location on a `bounds` image, worked
+  // out in exact decimal arithmetic so they keep every digit of the center.
+  pub fn corners(&self, bounds: (usize, usize)) -> (String, String) {
+    let exact = |digits: &str| DBig::from_str(digits).unwrap().with_precision(0).value();
+    let half = (self.height * bounds.0 as f64 / bounds.1 as f64 / 2.0, self.height / 2.0);
+    let (re, im) = (exact(&self.center.0), exact(&self.center.1));
+    let (half_re, half_im) = (exact(&format!("{:e}", half.0)), exact(&format!("{:e}", half.1)));
+    (format!("{},{}", &re - &half_re, &im + &half_im), format!("{},{}", &re + &half_re, &im - &half_im))
+  }
+
+  // The arithmetic a `bounds` render of the location needs, or None if direct f64 does:
+  // that holds while a pixel spans plenty of f64 values, a double-double reference orbit
+  // for the next fifty bits or so of depth, and beyond that, enough bits for the depth
+  // with a margin.
+  pub fn precision(&self, bounds: (usize, usize)) -> Option<Precision> {
+    let magnitude = |digits: &str| DBig::from_str(digits).unwrap().to_f64().value().abs();
+    let scale = magnitude(&self.center.0).max(magnitude(&self.center.1)).max(self.height);
+    let depth = (scale / (self.height / bounds.1 as f64)).log2();
+    match depth {
+      depth if depth < 44.0 => None,
+      depth if depth < 90.0 => Some(Precision::Double),
+      depth => Some(Precision::Bits((depth as usize + 64).next_multiple_of(64))),
+    }
+  }
+}
+
+#[test]
+fn test_upr_locations() {
+  let text = "; saved by Ultra Fractal\r\nseahorse {\r\nfractal:\r\n  title=\"sea horse\" width=640 height=480 layers=1\r\nmapping:\r\n  center=-0.745/0.1 magn=200 angle=30\r\nformula:\r\n  maxiter=1000 filename=\"Standard.ufm\" entry=\"Mandelbrot\"\r\n}\r\nother {\r\nmapping:\r\n  center=0/0\r\n}\r\n";
+  let location = parse_upr(text, None).unwrap();
+  assert_eq!(location, Location { center: ("-0.745".to_string(), "0.1".to_string()), height: 0.02, limit: Some(1000), rotation: 30.0, map: None });
+  assert_eq!(parse_upr(text, Some("other")).unwrap().center, ("0".to_string(), "0".to_string()));
+  assert_eq!(key_values("title=\"sea horse\" width=640"), [("title", "sea horse"), ("width", "640")]);
+  assert_eq!(parse_upr("a {\nmapping:\n  center=1\n}\n", None), Err("center '1' is not two numbers, as RE/IM".to_string()));
+  assert_eq!(parse_upr("nothing here", None), Err("no parameter set entry".to_string()));
+  assert_eq!(parse_upr(text, Some("missing")), Err("no entry named 'missing'".to_string()));
+
+  // What --save-location writes reads back as the same view.
+  let (upper_left, lower_right) = location.corners((640, 480));
+  let saved = upr("sea horse", (640, 480), &upper_left, &lower_right, 1000, 30.0).unwrap();
+  assert!(saved.starts_with("sea_horse {\r\nfractal:\r\n  title=\"sea horse\" width=640 height=480 layers=1\r\n"), "{}", saved);
+  let reread = parse_upr(&saved, None).unwrap();
+  assert_eq!((reread.center, reread.limit, reread.rotation), (location.center, Some(1000), 30.0));
+  assert!((reread.height - 0.02).abs() < 1e-15);
+}
+
+#[test]
+fn test_par_locations() {
+  let text = "; classic spots\nfirst    { ; the elephants\n  reset=2004 type=mandel corners=0.25/0.35/-0.05/0.05\n  maxiter=500 map=blues.map inside=0\n  }\n\
+              second { reset=2004 center-mag=-0.7453/0.1127/200/1.25/15 }\n\
+              julia { type=julia params=-0.8/0.156 }\n";
+  let first = parse_par(text, None).unwrap();
+  assert_eq!((first.center, first.height, first.limit, first.map.as_deref()), (("0.3".to_string(), "0".to_string()), 0.1, Some(500), Some("blues.map")));
+  let second = parse_par(text, Some("second")).unwrap();
+  assert_eq!((second.center, second.height, second.limit, second.rotation), (("-0.7453".to_string(), "0.1127".to_string()), 0.01, None, 15.0));
+  assert_eq!(parse_par(text, Some("julia")), Err("type=julia isn't supported; only type=mandel is".to_string()));
+  assert_eq!(parse_par("a { maxiter=9 }", None), Err("no corners or center-mag setting".to_string()));
+  assert_eq!(parse_par("a { corners=1/2/3 }", None), Err("corners '1/2/3' should be XMIN/XMAX/YMIN/YMAX".to_string()));
+  assert_eq!(uncomment("title=\"a;b\" x=1 ; note"), "title=\"a;b\" x=1 ");
+}
+
+#[test]
+fn test_kfr_locations() {
+  let location = parse_kfr("Re: -0.74364388703715870475219150611477\r\nIm: 0.131825904205311970493132056385\r\nZoom: 4E20\r\nIterations: 20000\r\nColorMethod: 7\r\n").unwrap();
+  assert_eq!(location.center, ("-0.74364388703715870475219150611477".to_string(), "0.131825904205311970493132056385".to_string()));
+  assert_eq!((location.height, location.limit, location.map.as_deref()), (1e-20, Some(20000), None));
+  assert_eq!(location.corners((200, 100)),
+             ("-0.74364388703715870476219150611477,0.131825904205311970498132056385".to_string(),
+              "-0.74364388703715870474219150611477,0.131825904205311970488132056385".to_string()));
+  assert_eq!(location.precision((200, 100)), Some(Precision::Double));
+
+  let shallow = parse_kfr("Re: -0.75\nIm: 0.1\nZoom: 1e3\n").unwrap();
+  assert_eq!(shallow.limit, None);
+  assert_eq!(shallow.precision((640, 480)), None);
+  assert_eq!(parse_kfr("Re: -0.75\nIm: 0.1\nZoom: 1e14\n").unwrap().precision((640, 480)), Some(Precision::Double));
+  assert_eq!(parse_kfr("Re: -0.75\nIm: 0.1\nZoom: 1e40\n").unwrap().precision((640, 480)), Some(Precision::Bits(256)));
+
+  assert_eq!(parse_kfr("Re: -0.75\nZoom: 1\n"), Err("no Im line".to_string()));
+  assert_eq!(parse_kfr("Re: -0.75\nIm: x\nZoom: 1\n"), Err("Im 'x' is not a number".to_string()));
+  assert_eq!(parse_kfr("Re: 1_0\nIm: 0\nZoom: 1\n"), Err("Re '1_0' is not a number".to_string()));
+  assert!(parse_par("a { center-mag=1_0/0/1 }", None).is_err());
+  assert!(parse_upr("a {\nmapping:\n  center=0/1_0 magn=1\n}\n", None).is_err());
+  assert_eq!(parse_kfr("Re: 0\nIm: 0\nZoom: -1\n"), Err("Zoom '-1' is not a positive number".to_string()));
+}
@@ -0,0 +1,169 @@
+// Logging
+// Diagnostics go through here rather than straight to standard error, so they can come
+// out as text for people or, with --log-format json, as one JSON object per line for
+// programs driving the renderer, the server or a worker:
+//
+//   {"elapsed":0.012,"level":"info","message":"max iterations: 1000"}
+//   {"elapsed":0.231,"level":"debug","span":"band","top":64,"rows":32,"ms":14.2}
+//
+// Spans time a stage of the work: parsing, rendering a band or tile, encoding. They
+// are debug output, logged when they end, and only shown with --verbose.
+
+use std::fmt::Display;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::OnceLock;
+use std::time::Instant;
+
+use crate::json::quote;
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum Format {
+  Text,
+  Json,
+}
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+enum Level {
+  Error,
+  Warn,
+  Info,
+  Debug,
+}
+
+static JSON: AtomicBool = AtomicBool::new(false);
+static VERBOSE: AtomicBool = AtomicBool::new(false);
+static START: OnceLock<Instant> = OnceLock::new();
+
+pub fn configure(format: Format, verbose: bool) {
+  START.get_or_init(Instant::now);
+  JSON.store(format == Format::Json, Ordering::Relaxed);
+  VERBOSE.store(verbose, Ordering::Relaxed);
+}
+
+// Takes the logging options, which apply to every command, out of `arguments`.
+pub fn options(arguments: &mut Vec<String>) -> Result<(Format, bool), String> {
+  let mut format = Format::Text;
+  let mut verbose = false;
+  let mut i = 0;
+  while i < arguments.len() {
+    match arguments[i].as_str() {
+      "--verbose" => verbose = true,
+      "--log-format" => {
+        format = match arguments.get(i + 1).map(String::as_str) {
+          Some("text") => Format::Text,
+          Some("json") => Format::Json,
+          _ => return Err("--log-format expects 'text' or 'json'".to_string()),
+        };
+        arguments.remove(i);
+      }
+      _ => {
+        i += 1;
+        continue;
+      }
+    }
+    arguments.remove(i);
+  }
+  Ok((format, verbose))
+}
+
+// Whether standard error carries JSON lines, which nothing else should write between.
+pub fn json() -> bool {
+  JSON.load(Ordering::Relaxed)
+}
+
+pub fn error(message: &str) {
+  event(Level::Error, message);
+}
+
+pub fn warn(message: &str) {
+  event(Level::Warn, message);
+}
+
+pub fn info(message: &str) {
+  event(Level::Info, message);
+}
+
+// Only shown with --verbose.
+pub fn debug(message: &str) {
+  if VERBOSE.load(Ordering::Relaxed) {
+    event(Level::Debug, message);
+  }
+}
+
+fn event(level: Level, message: &str) {
+  if JSON.load(Ordering::Relaxed) {
+    eprintln!("{{\"elapsed\":{:.3},\"level\":\"{}\",\"message\":{}}}", elapsed(), level.name(), quote(message));
+  } else {
+    match level {
+      Level::Error => eprintln!("error: {}", message),
+      Level::Warn => eprintln!("warning: {}", message),
+      Level::Info => eprintln!("{}", message),
+      Level::Debug => eprintln!("debug: {}", message),
+    }
+  }
+}
+
+// A stage of work, logged with how long it took when dropped.
+pub struct Span {
+  name: &'static str,
+  fields: Vec<(&'static str, String)>,
+  start: Instant,
+}
+
+pub fn span(name: &'static str, fields: &[(&'static str, &dyn Display)]) -> Span {
+  // Formatting fields for spans nobody sees would cost every band.
+  let fields = if VERBOSE.load(Ordering::Relaxed) { fields.iter().map(|&(key, value)| (key, value.to_string())).collect() } else { Vec::new() };
+  Span { name, fields, start: Instant::now() }
+}
+
+impl Drop for Span {
+  fn drop(&mut self) {
+    if !VERBOSE.load(Ordering::Relaxed) {
+      return;
+    }
+    let ms = self.start.elapsed().as_secs_f64() * 1e3;
+    if JSON.load(Ordering::Relaxed) {
+      eprintln!("{}", span_json(self.name, &self.fields, elapsed(), ms));
+    } else {
+      let fields: String = self.fields.iter().map(|(key, value)| format!(" {}={}", key, value)).collect();
+      eprintln!("debug: {}{} took {:.1} ms", self.name, fields, ms);
+    }
+  }
+}
+
+impl Level {
+  fn name(self) -> &'static str {
+    match self {
+      Level::Error => "error",
+      Level::Warn => "warn",
+      Level::Info => "info",
+      Level::Debug => "debug",
+    }
+  }
+}
+
+// Field values that read as numbers are written as JSON numbers, the rest as strings.
+fn span_json(name: &str, fields: &[(&str, String)], elapsed: f64, ms: f64) -> String {
+  let mut line = format!("{{\"elapsed\":{:.3},\"level\":\"debug\",\"span\":{}", elapsed, quote(name));
+  for (key, value) in fields {
+    let number = value.parse::<f64>().is_ok_and(f64::is_finite) && !value.starts_with(['+', '.']);
+    line += &format!(",{}:{}", quote(key), if number { value.clone() } else { quote(value) });
+  }
+  line + &format!(",\"ms\":{:.1}}}", ms)
+}
+
+// Seconds since logging was configured, at startup.
+pub fn elapsed() -> f64 {
+  START.get_or_init(Instant::now).elapsed().as_secs_f64()
+}
+
+#[test]
+fn test_logging_options_and_json_lines() {
+  let mut arguments: Vec<String> = ["render", "--verbose", "a.png", "--log-format", "json", "10x10"].iter().map(|s| s.to_string()).collect();
+  assert_eq!(options(&mut arguments), Ok((Format::Json, true)));
+  assert_eq!(arguments, ["render", "a.png", "10x10"]);
+  assert!(options(&mut vec!["--log-format".to_string()]).is_err());
+
+  let fields = [("top", "64".to_string()), ("file", "out.png".to_string()), ("weird", "inf".to_string())];
+  assert_eq!(span_json("band", &fields, 1.5, 2.5), r#"{"elapsed":1.500,"level":"debug","span":"band","top":64,"file":"out.png","weird":"inf","ms":2.5}"#);
+}
@@ -2,72 +2,2328 @@
 // Program that plots the mandelbrot set, using multiple processor threads
 
 use core::str::FromStr;
+use std::borrow::Cow;
 use std::env;
 use std::fs::File;
+use std::fmt::LowerExp;
+use std::io::{BufReader, BufWriter, IsTerminal, Write};
+use std::process::ExitCode;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
 
-use num::Complex;
+use num::{Complex, Float};
 use image::ColorType;
 use image::png::PNGEncoder;
-use crossbeam;
+
+mod affinity;
+mod api;
+mod animate;
+mod area;
+mod bench;
+mod big_float;
+mod bookmarks;
+mod buffer;
+mod cache;
+mod checkpoint;
+mod crop;
+mod diff;
+mod distributed;
+mod dive;
+mod double_double;
+mod easing;
+mod explore;
+mod fixed;
+mod fractal;
+mod iim;
+mod incremental;
+mod interrupt;
+mod job;
+mod json;
+mod keyframes;
+mod location;
+mod log;
+mod metadata;
+mod metrics;
+mod montage;
+mod nice;
+mod orbit;
+mod overlay;
+mod palette;
+mod period;
+mod perturbation;
+mod preview;
+mod progress;
+mod queue;
+mod report;
+mod server;
+mod sha256;
+mod split;
+mod stats;
+mod stitch;
+#[cfg(unix)]
+mod terminal;
+mod tune;
+#[cfg(unix)]
+mod viewer;
+mod wallpaper;
+mod wasm;
+
+use big_float::BigFloat;
+use buffer::{BufferKind, PixelBuffer};
+use cache::{TileCache, TILE_SIZE};
+use checkpoint::Checkpoint;
+use double_double::DoubleDouble;
+use fractal::{Bailout, Fractal, Norm, NORMS};
+use json::Value;
+use metadata::Provenance;
+use overlay::Overlay;
+use palette::Palette;
+use perturbation::{Perturbation, Real};
+use preview::Preview;
+use sha256::Sha256;
+
+// Iteration limit unless --max-iter says otherwise.
+const DEFAULT_MAX_ITER: usize = 255;
+
+// Worker threads used unless --threads says otherwise.
+const DEFAULT_THREADS: usize = 8;
+
+// Sampling steps used by progressive rendering, coarsest first.
+const PASSES: [usize; 4] = [8, 4, 2, 1];
+
+// Rows per chunk of work handed to a render thread, unless `mandel tune` found better.
+// Small chunks keep fast cores busy while slower ones finish; each costs only a trip
+// through a channel.
+const CHUNK_ROWS: usize = 32;
+
+// What a render reports when one of its threads panics. That is a bug, but one the
+// command still fails from cleanly.
+const THREAD_PANICKED: &str = "a render thread panicked";
+
+// Widest image --progress-image writes; larger renders are scaled down to it.
+const PROGRESS_WIDTH: usize = 320;
+
+// Rows rendered between checkpoint writes.
+const CHECKPOINT_ROWS: usize = 64;
+
+// Corners further than this from the origin in either coordinate are refused; the set
+// lies within 2 of it, so such views are blank at best and overflow at worst.
+const MAX_COORDINATE: f64 = 1e10;
+
+// Direct renders whose pixels span fewer distinct values than this of the arithmetic in
+// use get a warning that rounding will show.
+const MUSHY_STEPS: f64 = 64.0;
+
+// Pixels whose contrast with a neighbor exceeds this get re-sampled on a 2x2 grid,
+// and on a 3x3 grid when it exceeds four times this.
+const AA_THRESHOLD: u8 = 24;
+
+// Neighbors whose escape counts differ by more than this many iterations mark an edge in
+// an --edges map, unless --edge-threshold says otherwise.
+const DEFAULT_EDGE_THRESHOLD: usize = 10;
+
+// Set in pixels shaded by something other than their escape count, such as a period or
+// an atom domain, which then hold their shade in the low byte.
+const KEYED: u32 = 1 << 31;
+
+// Iterations of the smoothed escape count each --coloring bands band spans, unless
+// --band-width says otherwise.
+const DEFAULT_BAND_WIDTH: f64 = 4.0;
+
+// Rows of the final image a --supersample render holds at once, unless --strip-rows says
+// otherwise.
+const SUPERSAMPLE_ROWS: usize = 16;
 
 struct Arguments {
   file: String,
   pixels: String,
   upper_left: String,
   lower_right: String,
+  antialias: Antialias,
+  // How many times as wide and high to render before scaling down, or 1.
+  supersample: usize,
+  // Jittered samples per pixel, and the seed that places them.
+  samples: usize,
+  seed: u64,
+  interior: Interior,
+  exterior: Exterior,
+  // Contour bands of the smoothed escape count, with --coloring bands.
+  bands: Option<Bands>,
+  // In place of the fractal's own bailout radius and norm.
+  bailout_radius: Option<f64>,
+  bailout_norm: Option<Norm>,
+  progressive: bool,
+  progress_image: Option<String>,
+  // Whether to draw a progress bar when standard error is a terminal.
+  progress_bar: bool,
+  perturbation: bool,
+  series: bool,
+  precision: Precision,
+  // An iteration from a --formula WebAssembly module, in place of the built-in one.
+  formula: Option<Arc<wasm::Formula>>,
+  strip_rows: Option<usize>,
+  buffer: BufferKind,
+  threads: usize,
+  max_iter: MaxIter,
+  // Degrees counterclockwise about the center of the view.
+  rotation: f64,
+  cache: Option<String>,
+  checkpoint: Option<String>,
+  resume: bool,
+  workers: Vec<String>,
+  stats: bool,
+  // Whether to write FILE.stats.json, recording how the render went.
+  stats_json: bool,
+  // A CSV file to write the samples' escape counts to.
+  histogram: Option<String>,
+  // A PNG to draw the edges of the image in, and the difference in escape counts that
+  // makes one.
+  edges: Option<String>,
+  edge_threshold: usize,
+  // What to do when FILE already exists.
+  existing: Existing,
+  // Print a SHA-256 of the raw pixels, and fail unless it matches `expect_hash`.
+  print_hash: bool,
+  expect_hash: Option<String>,
+  // An Ultra Fractal parameter file to save the view to.
+  save_location: Option<String>,
+  // The color of each shade, from a --map file; the image is gray without one.
+  colors: Option<Vec<[u8; 3]>>,
+  // Drawn over the image once it is colored, and how.
+  overlays: Vec<Overlay>,
+  overlay_style: overlay::Style,
+  legend: Option<Legend>,
+  // A --caption format, and where to write it.
+  caption: Option<String>,
+  caption_style: overlay::Style,
+  // Recorded in FILE's metadata.
+  title: Option<String>,
+  author: Option<String>,
+  preview: Option<Preview>,
+  pin_threads: bool,
+  avoid_smt: bool,
+  nice: bool,
+  spare_cores: Option<usize>,
+  // Percent.
+  cpu_share: Option<usize>,
+  // The arguments minus checkpoint and worker options, as saved in checkpoints and
+  // sent to workers.
+  command_line: Vec<String>,
+}
+
+// Where --legend puts the strip showing what the colors mean.
+#[derive(Clone, PartialEq, Debug)]
+enum Legend {
+  // Below the image, in FILE itself.
+  Append,
+  File(String),
+}
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+enum Precision {
+  // f32 throughout: roughly twice as fast, and fine for shallow previews.
+  Single,
+  // f64 points, or a double-double reference orbit when perturbing.
+  Double,
+  // An arbitrary-precision reference orbit with this many significand bits.
+  Bits(usize),
+  // 128-bit fixed-point integers, iterating directly; see fixed.rs.
+  Fixed128,
+}
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+enum MaxIter {
+  Fixed(usize),
+  // Chosen from the zoom depth of the view.
+  Auto,
+}
+
+impl MaxIter {
+  fn resolve(self, view_width: f64) -> usize {
+    match self {
+      MaxIter::Fixed(limit) => limit,
+      MaxIter::Auto => auto_max_iter(view_width),
+    }
+  }
+}
+
+// Grows the limit with the log of the magnification relative to the 4-unit-wide full
+// view: 255 there, and another 255 per decade of zoom.
+fn auto_max_iter(view_width: f64) -> usize {
+  let zoom = 4.0 / view_width.abs();
+  let decades = if zoom.is_finite() { zoom.log10().max(0.0) } else { 300.0 };
+  (DEFAULT_MAX_ITER as f64 * (1.0 + decades)).round() as usize
+}
+
+// Computes the pixel at image coordinates (x, y): how many iterations its point took to
+// escape, the limit if it never did, or a KEYED shade. Fractional coordinates sample
+// inside a pixel, which is how antialiasing takes subpixel samples.
+trait Sampler: Sync {
+  fn sample(&self, x: f64, y: f64) -> u32;
+
+  // The iteration limit, which interior pixels hold and shade scales counts by.
+  fn limit(&self) -> usize;
+
+  // Describes everything that determines the pixels of a tile whose top-left pixel is
+  // (x, y), so equal keys mean interchangeable tiles. None opts out of caching.
+  fn tile_key(&self, _x: usize, _y: usize) -> Option<String> {
+    None
+  }
+}
+
+// Direct f64 iteration of each point on the complex plane.
+struct Plane<T> {
+  bounds: (usize, usize),
+  upper_left: Complex<T>,
+  lower_right: Complex<T>,
+  limit: usize,
+  fractal: Fractal,
+  bailout: Bailout,
+  // Rotation of the grid about the center of the view, as a unit complex number.
+  turn: Option<Complex<T>>,
+}
+
+impl<T: Float + LowerExp + Sync> Sampler for Plane<T> {
+  fn sample(&self, x: f64, y: f64) -> u32 {
+    escape_count(self.fractal.escape_time_within(self.point(x, y), self.limit, self.bailout), self.limit)
+  }
+
+  fn limit(&self) -> usize {
+    self.limit
+  }
+
+  fn tile_key(&self, x: usize, y: usize) -> Option<String> {
+    // A rotated tile depends on where it sits relative to the center, not just its origin.
+    if self.turn.is_some() {
+      return None;
+    }
+    let origin = pixel_to_point(self.bounds, (T::from(x).unwrap(), T::from(y).unwrap()), self.upper_left, self.lower_right);
+    let pitch = (
+      (self.lower_right.re - self.upper_left.re) / T::from(self.bounds.0).unwrap(),
+      (self.upper_left.im - self.lower_right.im) / T::from(self.bounds.1).unwrap(),
+    );
+    let key = format!("{} {} origin {:e},{:e} pitch {:e},{:e} limit {}",
+                      self.fractal.name(), std::any::type_name::<T>(), origin.re, origin.im, pitch.0, pitch.1, self.limit);
+    // Keys from before bailouts could change stay as they were.
+    if self.bailout == self.fractal.bailout() {
+      Some(key)
+    } else {
+      Some(format!("{} bailout {} {:e}", key, self.bailout.norm.name(), self.bailout.radius))
+    }
+  }
+}
+
+impl<T: Float> Plane<T> {
+  // The Mandelbrot set between the corners, unrotated and with its usual bailout.
+  fn mandelbrot(bounds: (usize, usize), upper_left: Complex<T>, lower_right: Complex<T>, limit: usize) -> Plane<T> {
+    Plane { bounds, upper_left, lower_right, limit, fractal: Fractal::Mandelbrot, bailout: Fractal::Mandelbrot.bailout(), turn: None }
+  }
+
+  // The point on the complex plane at image coordinates (x, y).
+  fn point(&self, x: f64, y: f64) -> Complex<T> {
+    let pixel = (T::from(x).unwrap(), T::from(y).unwrap());
+    let point = pixel_to_point(self.bounds, pixel, self.upper_left, self.lower_right);
+    match self.turn {
+      Some(turn) => rotate_about(point, self.center(), turn),
+      None => point,
+    }
+  }
+
+  fn center(&self) -> Complex<T> {
+    let two = T::one() + T::one();
+    Complex { re: (self.upper_left.re + self.lower_right.re) / two, im: (self.upper_left.im + self.lower_right.im) / two }
+  }
+}
+
+// A plane iterated by a --formula module rather than the built-in loop.
+struct FormulaPlane {
+  plane: Plane<f64>,
+  formula: Arc<wasm::Formula>,
+}
+
+impl Sampler for FormulaPlane {
+  fn sample(&self, x: f64, y: f64) -> u32 {
+    escape_count(self.formula.escape_time(self.plane.point(x, y), self.plane.limit, self.plane.bailout.radius), self.plane.limit)
+  }
+
+  fn limit(&self) -> usize {
+    self.plane.limit
+  }
+
+  fn tile_key(&self, x: usize, y: usize) -> Option<String> {
+    self.plane.tile_key(x, y).map(|key| format!("formula {} {}", self.formula.digest, key))
+  }
+}
+
+// The average of --samples samples per pixel at jittered spots: N-rooks sampling, which
+// splits the pixel into an N by N grid and puts each sample at a random place in a cell
+// of its own row and its own column. Filaments finer than a pixel then blur into gray
+// rather than aliasing into the regular patterns a fixed grid leaves. The spots depend
+// only on --seed and the pixel, so a render comes out the same on any number of threads
+// or workers.
+struct Jittered {
+  sampler: Box<dyn Sampler>,
+  samples: usize,
+  seed: u64,
+}
+
+impl Sampler for Jittered {
+  fn sample(&self, x: f64, y: f64) -> u32 {
+    let n = self.samples;
+    let mut random = area::SplitMix(self.seed ^ x.to_bits().wrapping_mul(0x9e3779b97f4a7c15) ^ y.to_bits().rotate_left(32).wrapping_mul(0xc2b2ae3d27d4eb4f));
+    let mut columns: Vec<usize> = (0..n).collect();
+    for i in (1..n).rev() {
+      columns.swap(i, (random.next() % (i as u64 + 1)) as usize);
+    }
+    let samples: Vec<u32> = columns.iter().enumerate().map(|(row, &column)| {
+      let offset = |cell: usize, random: &mut area::SplitMix| (cell as f64 + random.unit()) / n as f64 - 0.5;
+      let dx = offset(column, &mut random);
+      self.sampler.sample(x + dx, y + offset(row, &mut random))
+    }).collect();
+    blend(&samples, self.limit())
+  }
+
+  fn limit(&self) -> usize {
+    self.sampler.limit()
+  }
+
+  fn tile_key(&self, x: usize, y: usize) -> Option<String> {
+    self.sampler.tile_key(x, y).map(|key| format!("jittered {} seed {} {}", self.samples, self.seed, key))
+  }
+}
+
+// A plane shading its interior pixels by period with `--interior period`, and its
+// exterior pixels by atom domain with `--exterior atom` or by binary decomposition with
+// `--exterior binary` and `binary-levels`, or by contour bands with `--coloring bands`.
+struct KeyedPlane<T> {
+  plane: Plane<T>,
+  interior: Interior,
+  exterior: Exterior,
+  bands: Option<Bands>,
+}
+
+impl<T: Float> KeyedPlane<T> {
+  // The escape time of `point` and the band of `width` its smoothed escape count falls in.
+  fn band(&self, point: Complex<T>, width: f64) -> Option<(usize, f64)> {
+    let plane = &self.plane;
+    plane.fractal.escape_within(point, plane.limit, plane.bailout).map(|(escape, z)| {
+      let z = Complex { re: z.re.to_f64().unwrap(), im: z.im.to_f64().unwrap() };
+      (escape, (smooth_escape(escape, z, plane.bailout.radius).max(0.0) / width).floor())
+    })
+  }
+}
+
+impl<T: Float + LowerExp + Sync> Sampler for KeyedPlane<T> {
+  fn sample(&self, x: f64, y: f64) -> u32 {
+    let plane = &self.plane;
+    let point = plane.point(x, y);
+    let keyed = |shade: u8| KEYED | shade as u32;
+    let (escape, domain) = match (self.exterior, self.bands) {
+      (Exterior::Escape, None) => (plane.fractal.escape_time_within(point, plane.limit, plane.bailout), None),
+      (Exterior::Escape, Some(bands)) => match self.band(point, bands.width) {
+        Some((escape, band)) => {
+          let border = bands.borders && [(x + 1.0, y), (x, y + 1.0)].iter().any(|&(x, y)| self.band(plane.point(x, y), bands.width).is_some_and(|(_, other)| other != band));
+          (Some(escape), Some(band_pixel(band * bands.width, plane.limit, border)))
+        }
+        None => (None, None),
+      },
+      (Exterior::Atom, _) => period::atom_domain(point, plane.limit).map_or((None, None), |(escape, domain)| (Some(escape), Some(keyed(period::shade(domain))))),
+      (Exterior::Binary | Exterior::BinaryLevels, _) => match plane.fractal.escape_within(point, plane.limit, plane.bailout) {
+        Some((escape, z)) => (Some(escape), Some(keyed(binary_shade(escape, plane.limit, z.im >= T::zero(), self.exterior == Exterior::BinaryLevels)))),
+        None => (None, None),
+      },
+    };
+    match (escape_count(escape, plane.limit), domain) {
+      (count, _) if escape.is_none() && self.interior == Interior::Period => period::period(point, plane.limit).map_or(count, |period| keyed(period::shade(period))),
+      (_, Some(pixel)) => pixel,
+      (count, None) => count,
+    }
+  }
+
+  fn limit(&self) -> usize {
+    self.plane.limit
+  }
+
+  fn tile_key(&self, x: usize, y: usize) -> Option<String> {
+    let keyed = [(self.interior == Interior::Period, "periods"), (self.exterior == Exterior::Atom, "atoms"), (self.exterior == Exterior::Binary, "binary"),
+                 (self.exterior == Exterior::BinaryLevels, "binary levels")];
+    let bands = self.bands.map(|bands| format!("bands {}{}", bands.width, if bands.borders { " borders" } else { "" }));
+    let prefix: Vec<&str> = keyed.iter().filter(|(on, _)| *on).map(|&(_, name)| name).chain(bands.as_deref()).collect();
+    self.plane.tile_key(x, y).map(|key| format!("{} {}", prefix.join(" "), key))
+  }
+}
+
+// The shade by binary decomposition of a pixel that escaped after `escape` iterations
+// of `limit` with z above the real axis or not: white or as dark as escaping pixels get,
+// or with `levels` its escape shade, halved below the axis.
+fn binary_shade(escape: usize, limit: usize, above: bool, levels: bool) -> u8 {
+  let level = 255 - (escape * 255 / limit) as u8;
+  match (levels, above) {
+    (false, true) => 255,
+    (false, false) => 1,
+    (true, true) => level,
+    (true, false) => (level / 2).max(1),
+  }
+}
+
+// The escape count of an orbit that left a bailout of `radius` after `escape` iterations
+// with `z`, smoothed by how far past the radius z got: n + 1 - log2(ln |z| / ln radius),
+// which runs down from n + 1 at the radius to n at its square.
+fn smooth_escape(escape: usize, z: Complex<f64>, radius: f64) -> f64 {
+  if radius <= 1.0 {
+    return escape as f64;
+  }
+  escape as f64 + 1.0 - (z.norm().ln() / radius.ln()).log2()
+}
+
+// The pixel of a contour band starting `start` iterations in, of `limit`: the escape
+// count there, or on the band's border a shade far from that count's.
+fn band_pixel(start: f64, limit: usize, border: bool) -> u32 {
+  let count = (start as usize).min(limit - 1) as u32;
+  match (border, shade(count, limit) >= 128) {
+    (false, _) => count,
+    (true, true) => KEYED | 1,
+    (true, false) => KEYED | 255,
+  }
+}
+
+// The unit complex number turning points `degrees` counterclockwise, or None for no turn.
+fn turn<T: Float>(degrees: f64) -> Option<Complex<T>> {
+  (degrees != 0.0).then(|| Complex::from_polar(T::one(), T::from(degrees.to_radians()).unwrap()))
+}
+
+fn rotate_about<T: Float>(point: Complex<T>, center: Complex<T>, turn: Complex<T>) -> Complex<T> {
+  center + (point - center) * turn
+}
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+enum Existing {
+  Refuse,
+  // --force
+  Overwrite,
+  // --auto-suffix: write to the first free name of mandel-2.png, mandel-3.png, ...
+  Suffix,
+}
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+enum Antialias {
+  None,
+  Adaptive,
+}
+
+// How pixels inside the set are shaded.
+#[derive(Clone, Copy, PartialEq, Debug)]
+enum Interior {
+  Black,
+  // By the period of the cycle the orbit settles into.
+  Period,
+}
+
+// How pixels outside the set are shaded.
+#[derive(Clone, Copy, PartialEq, Debug)]
+enum Exterior {
+  // By escape time.
+  Escape,
+  // By the iteration at which the orbit came nearest 0.
+  Atom,
+  // By binary decomposition: whether z was above or below the real axis once it
+  // escaped, which splits each escape band into cells along spokes from the set.
+  Binary,
+  // The escape shading, darker where binary decomposition has z below the axis.
+  BinaryLevels,
+}
+
+// How --coloring bands posterizes the escape shading.
+#[derive(Clone, Copy, PartialEq, Debug)]
+struct Bands {
+  // Iterations of the smoothed escape count each band spans.
+  width: f64,
+  // Whether to draw the edge of each band in a shade that stands out from it.
+  borders: bool,
+}
+
+fn main() -> ExitCode {
+  let mut argv: Vec<String> = env::args().collect();
+  let logging = log::options(&mut argv);
+  let reporting = report::options(&mut argv);
+  let program = &argv[0];
+  let (format, verbose) = logging.unwrap_or_else(|message| usage_error(program, &message));
+  log::configure(format, verbose);
+  report::configure(reporting.unwrap_or_else(|message| usage_error(program, &message)), &argv[1..]);
+  tune::load();
+  if report::json() && matches!(argv.get(1).map(String::as_str), Some("bench" | "area" | "explore" | "dive" | "orbit" | "inspect" | "stitch" | "worker" | "serve" | "serve-api" | "view" | "animate" | "bookmarks" | "schema")) {
+    usage_error(program, "--output-format json only applies to rendering");
+  }
+  let parse = |arguments: &[String]| {
+    let _span = log::span("parse", &[]);
+    job::with_stdin(arguments).and_then(|arguments| parse_arguments(&arguments)).unwrap_or_else(|message| usage_error(program, &message))
+  };
+  let available_threads = std::thread::available_parallelism().map_or(DEFAULT_THREADS, |n| n.get());
+
+  let mut summary = ("render", Vec::new());
+  let result = match argv.get(1).map(String::as_str) {
+    Some("bench") => {
+      let max_threads = match argv.get(2).map(String::as_str) {
+        Some("--threads") => parse_threads(argv.get(3).map(String::as_str)).unwrap_or_else(|message| usage_error(program, &message)),
+        Some(_) => usage_error(program, "bench only accepts --threads N"),
+        None => available_threads,
+      };
+      bench::run(max_threads)
+    }
+    Some("worker") => {
+      let mut address = None;
+      let (mut queue, mut key) = (None, "mandel:jobs");
+      let mut metrics_address = None;
+      let mut threads = available_threads;
+      let mut options = argv[2..].iter().map(String::as_str);
+      while let Some(option) = options.next() {
+        match option {
+          "--listen" => address = options.next(),
+          "--queue" => queue = options.next(),
+          "--metrics" => metrics_address = options.next(),
+          "--key" => key = options.next().unwrap_or_else(|| usage_error(program, "--key expects the name of a list")),
+          "--threads" => threads = parse_threads(options.next()).unwrap_or_else(|message| usage_error(program, &message)),
+          _ => usage_error(program, "worker accepts --listen ADDRESS or --queue URL [--key NAME], --threads N and --metrics ADDRESS"),
+        }
+      }
+      if let Some(address) = metrics_address {
+        metrics::serve(address).unwrap_or_else(|e| usage_error(program, &format!("metrics on {}: {}", address, e)));
+      }
+      match (address, queue) {
+        (Some(address), None) => distributed::serve(address, threads).map_err(|e| format!("worker on {}: {}", address, e)),
+        (None, Some(url)) => queue::work(url, key, threads),
+        _ => usage_error(program, "worker needs either --listen ADDRESS or --queue URL"),
+      }
+    }
+    Some("serve") => {
+      let mut host = "127.0.0.1";
+      let mut port = "8080";
+      let mut threads = available_threads;
+      let mut jobs = 1;
+      let mut cache = None;
+      let mut options = argv[2..].iter().map(String::as_str);
+      while let Some(option) = options.next() {
+        match (option, options.next()) {
+          ("--port", Some(value)) if u16::from_str(value).is_ok() => port = value,
+          ("--bind", Some(value)) => host = value,
+          ("--threads", value) => threads = parse_threads(value).unwrap_or_else(|message| usage_error(program, &message)),
+          ("--jobs", Some(value)) if usize::from_str(value).is_ok_and(|jobs| jobs > 0) => jobs = value.parse().unwrap(),
+          ("--cache", Some(dir)) => cache = Some(dir),
+          _ => usage_error(program, "serve accepts --port N, --bind HOST, --threads N, --jobs N and --cache DIR"),
+        }
+      }
+      let address = format!("{}:{}", host, port);
+      cache.map(open_cache).transpose()
+        .and_then(|cache| server::serve(&address, threads, jobs, cache).map_err(|e| format!("server on {}: {}", address, e)))
+    }
+    Some("serve-api") => {
+      let mut host = "127.0.0.1";
+      let mut port = "8080";
+      let mut settings = api::Settings { jobs: 1, threads: available_threads, dir: env::temp_dir().join(format!("mandel-api-{}", std::process::id())) };
+      let mut options = argv[2..].iter().map(String::as_str);
+      while let Some(option) = options.next() {
+        match (option, options.next()) {
+          ("--port", Some(value)) if u16::from_str(value).is_ok() => port = value,
+          ("--bind", Some(value)) => host = value,
+          ("--threads", value) => settings.threads = parse_threads(value).unwrap_or_else(|message| usage_error(program, &message)),
+          ("--jobs", Some(value)) if usize::from_str(value).is_ok_and(|jobs| jobs > 0) => settings.jobs = value.parse().unwrap(),
+          ("--dir", Some(dir)) => settings.dir = dir.into(),
+          _ => usage_error(program, "serve-api accepts --port N, --bind HOST, --threads N, --jobs N and --dir DIR"),
+        }
+      }
+      let address = format!("{}:{}", host, port);
+      api::serve(&address, settings).map_err(|e| format!("render API on {}: {}", address, e))
+    }
+    #[cfg(unix)]
+    Some("view") => viewer::main(&argv[2..], available_threads).or_else(|message| usage_error(program, &message)),
+    #[cfg(not(unix))]
+    Some("view") => usage_error(program, "the viewer needs a Unix terminal"),
+    Some("animate") => animate::main(&argv[2..], available_threads).or_else(|message| usage_error(program, &message)),
+    Some("area") => area::main(&argv[2..], available_threads).or_else(|message| usage_error(program, &message)),
+    Some("explore") => explore::main(&argv[2..], available_threads).or_else(|message| usage_error(program, &message)),
+    Some("dive") => dive::main(&argv[2..]).or_else(|message| usage_error(program, &message)),
+    Some("orbit") => orbit::main(&argv[2..]).or_else(|message| usage_error(program, &message)),
+    Some("inspect") => period::inspect(&argv[2..]).or_else(|message| usage_error(program, &message)),
+    Some("bookmarks") => {
+      let path = bookmarks::path().unwrap_or_else(|message| usage_error(program, &message));
+      for bookmark in bookmarks::load(&path).unwrap_or_else(|message| usage_error(program, &message)) {
+        println!("{:<20} center {},{}  width {:e}  limit {}  palette {}", bookmark.name, bookmark.center.re, bookmark.center.im,
+                 bookmark.width, bookmark.limit, bookmark.palette.name());
+      }
+      Ok(())
+    }
+    Some("schema") => {
+      println!("{}", job::schema().pretty());
+      Ok(())
+    }
+    Some("batch") => match argv.get(2) {
+      Some(path) => batch(path, &argv[3..]).map(|fields| summary = ("batch", fields)),
+      None => usage_error(program, "batch expects a file of jobs, or - for standard input"),
+    },
+    Some("render") => render(parse(&argv[2..])).map(|fields| summary.1 = fields),
+    Some("rerender") => match argv.get(2) {
+      Some(old) => rerender_arguments(old, &argv[3..]).and_then(|arguments| render(parse(&arguments))).map(|fields| summary.1 = fields),
+      None => usage_error(program, "rerender expects a PNG this program rendered"),
+    },
+    Some("stitch") => stitch::main(&argv[2..]).or_else(|message| usage_error(program, &message)),
+    Some("iim") => iim::main(&argv[2..]).map(|fields| summary = ("iim", fields)),
+    Some("split") => split::main(&argv[2..]).map(|fields| summary = ("split", fields)),
+    Some("tune") => tune::main(&argv[2..], available_threads).map(|fields| summary = ("tune", fields)),
+    Some("wallpaper") => wallpaper::main(&argv[2..]).map(|fields| summary = ("wallpaper", fields)),
+    Some("montage") => montage::main(&argv[2..]).map(|fields| summary = ("montage", fields)),
+    Some("diff") => diff::main(&argv[2..]).map(|fields| summary = ("diff", fields)),
+    Some("crop") => crop::arguments(&argv[2..]).and_then(|arguments| render(parse(&arguments))).map(|fields| summary.1 = fields),
+    _ => render(parse(&argv[1..])).map(|fields| summary.1 = fields),
+  };
+
+  match result {
+    Ok(()) => {
+      report::success(summary.0, summary.1);
+      ExitCode::SUCCESS
+    }
+    Err(message) => {
+      log::error(&message);
+      let interrupted = interrupt::requested();
+      report::failure(if interrupted { "interrupted" } else { "failed" }, &message);
+      if interrupted { ExitCode::from(interrupt::EXIT_STATUS) } else { ExitCode::FAILURE }
+    }
+  }
+}
+
+// Does what `args` say to when FILE already exists: fail, overwrite it, or pick a free
+// name instead.
+fn claim_file(args: &mut Arguments) -> Result<(), String> {
+  if !std::path::Path::new(&args.file).exists() {
+    return Ok(());
+  }
+  match args.existing {
+    Existing::Refuse => return Err(format!("'{}' already exists; pass --force to overwrite it or --auto-suffix to pick a free name", args.file)),
+    Existing::Overwrite => {}
+    Existing::Suffix => {
+      let free = free_file_name(&args.file);
+      log::info(&format!("'{}' already exists; writing '{}'", args.file, free));
+      // Checkpoints have to resume into the new name, too.
+      if let Some(file) = args.command_line.iter_mut().find(|arg| **arg == args.file) {
+        file.clone_from(&free);
+      }
+      args.file = free;
+    }
+  }
+  Ok(())
+}
+
+// Renders as `args` say, and returns what --output-format json reports about it.
+fn render(mut args: Arguments) -> Result<Vec<(String, Value)>, String> {
+  let start = Instant::now();
+  let bounds = parse_pair(&args.pixels, 'x').ok_or(format!("error parsing image dimensions '{}'", args.pixels))?;
+  // A resumed render owns whatever it left at FILE.
+  if args.preview.is_none() && !args.resume {
+    claim_file(&mut args)?;
+  }
+  interrupt::install();
+  if args.pin_threads {
+    log::info(&format!("pinning render threads to CPUs {:?}", affinity::enable(args.avoid_smt)));
+  }
+  if args.nice {
+    nice::enable(args.cpu_share);
+    if let Some(spare) = args.spare_cores {
+      let cores = std::thread::available_parallelism().map_or(DEFAULT_THREADS, |n| n.get());
+      args.threads = nice::threads(args.threads, cores, spare);
+      log::info(&format!("leaving {} of {} cores free: rendering on {} threads", spare, cores, args.threads));
+    }
+  }
+  if args.histogram.is_some() {
+    stats::keep_histogram();
+  }
+  stats::begin_render();
+  let hash = {
+    let _span = log::span("render", &[("file", &args.file), ("width", &bounds.0), ("height", &bounds.1)]);
+    render_image(&args, bounds)?
+  };
+  if let Some(path) = &args.save_location {
+    let title = std::path::Path::new(&args.file).file_stem().map_or("mandel".into(), |stem| stem.to_string_lossy());
+    let entry = location::upr(&title, bounds, &args.upper_left, &args.lower_right, render_limit(&args), args.rotation)?;
+    std::fs::write(path, entry).map_err(|e| format!("error writing location '{}': {}", path, e))?;
+  }
+  let seconds = start.elapsed();
+  if args.stats {
+    stats::report(bounds.0 * bounds.1, seconds);
+  }
+  if let Some(path) = &args.histogram {
+    stats::write_histogram(path)?;
+  }
+  if args.stats_json {
+    write_stats_json(&args, bounds, seconds)?;
+  }
+  if let Some(Legend::File(path)) = &args.legend {
+    let (legend, height) = overlay::legend(bounds.0, render_limit(&args), args.colors.as_deref(), args.overlay_style.scale);
+    let written = match args.colors {
+      Some(_) => write_rgb_image(path, &legend, (bounds.0, height)),
+      None => write_image(path, &legend, (bounds.0, height)),
+    };
+    written.map_err(|e| format!("error writing legend '{}': {}", path, e))?;
+  }
+
+  let text = |list: &[String]| Value::Array(list.iter().map(|arg| arg.as_str().into()).collect());
+  let mut summary = vec![
+    ("file".to_string(), args.file.as_str().into()),
+    ("size".to_string(), Value::Array(vec![bounds.0.into(), bounds.1.into()])),
+    ("upper_left".to_string(), args.upper_left.as_str().into()),
+    ("lower_right".to_string(), args.lower_right.as_str().into()),
+    ("arguments".to_string(), text(&args.command_line)),
+    ("resumed".to_string(), Value::Bool(args.resume)),
+    ("timings".to_string(), Value::Object(vec![
+      ("render".to_string(), seconds.as_secs_f64().into()),
+      ("total".to_string(), log::elapsed().into()),
+    ])),
+  ];
+  if let Some(hash) = hash {
+    summary.push(("hash".to_string(), hash.as_str().into()));
+  }
+  if let Some(path) = &args.edges {
+    summary.push(("edges".to_string(), path.as_str().into()));
+  }
+  if let Some(path) = &args.histogram {
+    summary.push(("histogram".to_string(), path.as_str().into()));
+  }
+  if let Some(Legend::File(path)) = &args.legend {
+    summary.push(("legend".to_string(), path.as_str().into()));
+  }
+  Ok(summary)
+}
+
+// Writes FILE.stats.json beside the image: how long the render took and how, what its
+// samples did, and everything that shaped it, for sorting through piles of renders.
+fn write_stats_json(args: &Arguments, bounds: (usize, usize), duration: Duration) -> Result<(), String> {
+  let precision = match args.precision {
+    Precision::Single => "single".into(),
+    Precision::Double => "double".into(),
+    Precision::Bits(bits) => bits.into(),
+    Precision::Fixed128 => "fixed128".into(),
+  };
+  let interior = match args.interior {
+    Interior::Black => "black",
+    Interior::Period => "period",
+  };
+  let exterior = match args.exterior {
+    Exterior::Escape => "escape",
+    Exterior::Atom => "atom",
+    Exterior::Binary => "binary",
+    Exterior::BinaryLevels => "binary-levels",
+  };
+  let mut fields = vec![
+    ("file".to_string(), args.file.as_str().into()),
+    ("duration".to_string(), duration.as_secs_f64().into()),
+    ("backend".to_string(), backend(args).as_str().into()),
+    ("threads".to_string(), args.threads.into()),
+  ];
+  fields.extend(stats::render_counts());
+  fields.push(("parameters".to_string(), Value::Object(vec![
+    ("size".to_string(), Value::Array(vec![bounds.0.into(), bounds.1.into()])),
+    ("upper_left".to_string(), args.upper_left.as_str().into()),
+    ("lower_right".to_string(), args.lower_right.as_str().into()),
+    ("rotate".to_string(), args.rotation.into()),
+    ("max_iter".to_string(), render_limit(args).into()),
+    ("precision".to_string(), precision),
+    ("perturbation".to_string(), Value::Bool(args.perturbation)),
+    ("series".to_string(), Value::Bool(args.series)),
+    ("supersample".to_string(), args.supersample.into()),
+    ("samples".to_string(), args.samples.into()),
+    ("seed".to_string(), (args.seed as usize).into()),
+    ("antialias".to_string(), (if args.antialias == Antialias::Adaptive { "adaptive" } else { "none" }).into()),
+    ("interior".to_string(), interior.into()),
+    ("exterior".to_string(), exterior.into()),
+    ("coloring".to_string(), (if args.bands.is_some() { "bands" } else { "escape" }).into()),
+    ("band_width".to_string(), args.bands.map_or(Value::Null, |bands| bands.width.into())),
+    ("band_borders".to_string(), Value::Bool(args.bands.is_some_and(|bands| bands.borders))),
+    ("bailout".to_string(), bailout(args, Fractal::Mandelbrot).radius.into()),
+    ("bailout_norm".to_string(), bailout(args, Fractal::Mandelbrot).norm.name().into()),
+    ("formula".to_string(), args.formula.as_ref().map_or(Value::Null, |formula| formula.digest.as_str().into())),
+    ("colored".to_string(), Value::Bool(args.colors.is_some())),
+    ("arguments".to_string(), Value::Array(args.command_line.iter().map(|arg| arg.as_str().into()).collect())),
+  ])));
+  let path = format!("{}.stats.json", args.file);
+  std::fs::write(&path, Value::Object(fields).pretty() + "\n").map_err(|e| format!("error writing stats '{}': {}", path, e))
+}
+
+// The arithmetic that renders the view `args` describe, by name.
+fn backend(args: &Arguments) -> String {
+  let direct = !(args.perturbation || args.series);
+  let name = match args.precision {
+    Precision::Single => "direct f32".to_string(),
+    Precision::Double if args.formula.is_some() => "webassembly formula".to_string(),
+    Precision::Double if direct => "direct f64".to_string(),
+    Precision::Double => "perturbation, double-double reference".to_string(),
+    Precision::Bits(bits) => format!("perturbation, {}-bit reference", bits),
+    Precision::Fixed128 => "direct fixed128".to_string(),
+  };
+  if args.series { format!("{} with series approximation", name) } else { name }
+}
+
+// The iteration limit of the render `args` describe.
+fn render_limit(args: &Arguments) -> usize {
+  max_iter(args.max_iter, view_width(args))
+}
+
+// The width across the real axis of the view `args` describe, in enough digits that
+// even the deepest views have one.
+fn view_width(args: &Arguments) -> f64 {
+  let bits = if let Precision::Bits(bits) = args.precision { bits } else { 128 };
+  parse_big_complex(&args.lower_right, bits).zip(parse_big_complex(&args.upper_left, bits)).map_or(4.0, |(b, a)| (b.0 - a.0).to_f64())
+}
+
+// How many times a render goes over each pixel, for reporting its progress.
+fn passes(args: &Arguments) -> usize {
+  (if args.progressive { PASSES.len() } else { 1 }) + if args.antialias == Antialias::Adaptive { 1 } else { 0 }
+}
+
+// The arguments that render the view recorded in the PNG at `old` again. --size WxH
+// replaces its size and --output FILE names the new image, OLD-WxH.png by default; any
+// other options go after the recorded ones, so they win.
+fn rerender_arguments(old: &str, options: &[String]) -> Result<Vec<String>, String> {
+  let (mut recorded, size) = metadata::arguments(old)?;
+  let (mut new_size, mut output) = (size, None);
+  let mut added = Vec::new();
+  let mut options = options.iter();
+  while let Some(option) = options.next() {
+    match option.as_str() {
+      "--size" => new_size = options.next().and_then(|value| parse_pair(value, 'x')).ok_or("--size expects WIDTHxHEIGHT in pixels")?,
+      "--output" => output = Some(options.next().ok_or("--output expects a file name")?.clone()),
+      _ => added.push(option.clone()),
+    }
+  }
+  let pixels = recorded.iter_mut().find(|arg| parse_pair::<usize>(arg, 'x') == Some(size))
+    .ok_or(format!("the arguments recorded in '{}' don't give its size", old))?;
+  *pixels = format!("{}x{}", new_size.0, new_size.1);
+  let output = output.unwrap_or_else(|| {
+    let path = std::path::Path::new(old);
+    let stem = path.file_stem().map_or("mandel".into(), |stem| stem.to_string_lossy());
+    path.with_file_name(format!("{}-{}x{}.png", stem, new_size.0, new_size.1)).to_string_lossy().into_owned()
+  });
+  Ok([vec![output], recorded, added].concat())
+}
+
+// Renders each of the jobs in the file at `path` in turn, with `options` added to each.
+// A job that fails doesn't stop the rest, but fails the batch.
+fn batch(path: &str, options: &[String]) -> Result<Vec<(String, Value)>, String> {
+  let text = if path == "-" {
+    std::io::read_to_string(std::io::stdin()).map_err(|e| format!("error reading jobs from standard input: {}", e))?
+  } else {
+    std::fs::read_to_string(path).map_err(|e| format!("error reading jobs '{}': {}", path, e))?
+  };
+  let jobs = job::batch(&text).map_err(|e| format!("jobs '{}': {}", path, e))?;
+
+  let mut results = Vec::new();
+  let mut failed = 0;
+  for (i, job) in jobs.iter().enumerate() {
+    if interrupt::requested() {
+      return Err(format!("interrupted after {} of {} jobs", i, jobs.len()));
+    }
+    let name = format!("jobs[{}]", i);
+    log::info(&format!("{} of {}: rendering {}", i + 1, jobs.len(), name));
+    let outcome = job::arguments(job, &name).and_then(|mut arguments| {
+      arguments.extend_from_slice(options);
+      render(parse_arguments(&arguments)?)
+    });
+    let mut result = vec![("job".to_string(), i.into())];
+    match outcome {
+      Ok(fields) => {
+        result.push(("status".to_string(), "ok".into()));
+        result.extend(fields);
+      }
+      Err(message) => {
+        // Mistakes in the job itself already say where they are.
+        log::error(&if message.starts_with(&name) { message.clone() } else { format!("{}: {}", name, message) });
+        failed += 1;
+        result.extend([("status".to_string(), "error".into()), ("message".to_string(), message.as_str().into())]);
+      }
+    }
+    results.push(Value::Object(result));
+  }
+  match failed {
+    0 => Ok(vec![("jobs".to_string(), Value::Array(results))]),
+    _ => Err(format!("{} of {} jobs failed", failed, jobs.len())),
+  }
+}
+
+// Returns the hash of the pixels if --print-hash or --expect-hash asked for one.
+fn render_image(args: &Arguments, bounds: (usize, usize)) -> Result<Option<String>, String> {
+  if let Some(preview) = args.preview {
+    return preview::show(args, bounds, preview).map(|()| None);
+  }
+
+  // A supersampled image is rendered this much larger, then scaled down.
+  let factor = args.supersample;
+  let large = (bounds.0 * factor, bounds.1 * factor);
+  let sampler = build_sampler(args, large)?;
+  let writing = |e: std::io::Error| format!("error writing '{}': {}", args.file, e);
+
+  let _bar = (args.progress_bar && std::io::stderr().is_terminal() && !log::json()).then(|| progress::start(progress::CostMap::probe(sampler.as_ref(), large), passes(args)));
+
+  if factor > 1 {
+    let digest = write_supersampled(args, bounds, sampler.as_ref(), factor)?;
+    return check_hash(args, || digest);
+  }
+  if let Some(rows) = args.strip_rows {
+    let digest = write_strips(args, bounds, sampler.as_ref(), rows)?;
+    return check_hash(args, || digest);
+  }
+
+  let mut pixels = PixelBuffer::new(bounds.0 * bounds.1, args.buffer)
+    .map_err(|e| format!("error allocating a {}x{} pixel buffer: {}", bounds.0, bounds.1, e))?;
+  let limit = sampler.limit();
+  let mut streamed = false;
+
+  if let Some(path) = &args.checkpoint {
+    render_checkpointed(&mut pixels, bounds, sampler.as_ref(), path, args)?;
+  } else if let Some(dir) = &args.cache {
+    render_cached(&mut pixels, bounds, sampler.as_ref(), args.threads, &open_cache(dir)?)?;
+  } else if !args.workers.is_empty() {
+    distributed::render(&mut pixels, bounds, sampler.as_ref(), args.threads, &args.workers, &args.command_line)?;
+  } else if args.progressive {
+    // Each pass leaves a complete, progressively sharper image on disk.
+    for (pass, &step) in PASSES.iter().enumerate() {
+      render_parallel(&mut pixels, bounds, sampler.as_ref(), args.threads, 0, step, pass == 0)?;
+      write_output(args, &pixels, limit, bounds).map_err(writing)?;
+      if let Some(path) = &args.progress_image {
+        write_progress_image(path, &shades(&pixels, limit), bounds).map_err(|e| format!("error writing progress image '{}': {}", path, e))?;
+      }
+      if interrupt::requested() {
+        return Err(format!("interrupted during pass {}/{}; '{}' holds the image as far as it got", pass + 1, PASSES.len(), args.file));
+      }
+      log::info(&format!("pass {}/{} done (every {} pixel(s))", pass + 1, PASSES.len(), step));
+    }
+  } else if streams(args) {
+    let rows = render_streamed(&mut pixels, bounds, sampler.as_ref(), args)?;
+    if rows < bounds.1 {
+      return Err(checkpoint_interrupted(args, &pixels, limit, bounds, rows)?);
+    }
+    streamed = true;
+  } else {
+    let rows = render_parallel(&mut pixels, bounds, sampler.as_ref(), args.threads, 0, 1, true)?;
+    if rows < bounds.1 {
+      return Err(checkpoint_interrupted(args, &pixels, limit, bounds, rows)?);
+    }
+  }
+
+  // Antialiasing blurs the counts that tell the interior from the rest.
+  if let Some(path) = &args.edges {
+    write_image(path, &edge_map(&pixels, bounds, args.edge_threshold, limit), bounds)
+      .map_err(|e| format!("error writing edge map '{}': {}", path, e))?;
+  }
+
+  if args.antialias == Antialias::Adaptive && antialias(&mut pixels, bounds, 0, sampler.as_ref(), args.threads)? < bounds.1 {
+    write_output(args, &pixels, limit, bounds).map_err(writing)?;
+    return Err(format!("interrupted while antialiasing; '{}' holds the image with only some edges smoothed", args.file));
+  }
+
+  if !streamed {
+    write_output(args, &pixels, limit, bounds).map_err(writing)?;
+  }
+
+  if let Some(path) = &args.checkpoint {
+    std::fs::remove_file(path).map_err(|e| format!("error removing finished checkpoint '{}': {}", path, e))?;
+  }
+  check_hash(args, || {
+    let mut sha = Sha256::new();
+    sha.update(&shades(&pixels, limit));
+    sha.finish()
+  })
+}
+
+// Prints and checks the hash of the pixels as --print-hash and --expect-hash ask,
+// computing it with `digest` only if one of them was given. Under --output-format json
+// the hash goes in the report instead of on a line of its own.
+fn check_hash(args: &Arguments, digest: impl FnOnce() -> [u8; 32]) -> Result<Option<String>, String> {
+  if !args.print_hash && args.expect_hash.is_none() {
+    return Ok(None);
+  }
+  let hash = sha256::hex(&digest());
+  if args.print_hash && !report::json() {
+    println!("{}", hash);
+  }
+  match &args.expect_hash {
+    Some(expected) if *expected != hash => Err(format!("the pixels hash to {}, not the expected {}", hash, expected)),
+    _ => Ok(Some(hash)),
+  }
+}
+
+// The first of `file` with -2, -3, ... added to its stem that doesn't exist yet.
+fn free_file_name(file: &str) -> String {
+  let path = std::path::Path::new(file);
+  let stem = path.file_stem().map_or(String::new(), |stem| stem.to_string_lossy().into_owned());
+  let extension = path.extension().map_or(String::new(), |extension| format!(".{}", extension.to_string_lossy()));
+  (2..).map(|n| path.with_file_name(format!("{}-{}{}", stem, n, extension)))
+    .find(|candidate| !candidate.exists())
+    .unwrap()
+    .to_string_lossy()
+    .into_owned()
 }
 
-fn main() {
-  let args = parse_args();
+// Writes the first `rows` rows of an interrupted render to FILE, the rest left black,
+// and returns the message saying so and how to resume from `checkpoint`.
+fn save_interrupted(args: &Arguments, pixels: &[u32], limit: usize, bounds: (usize, usize), rows: usize, checkpoint: &str) -> Result<String, String> {
+  write_output(args, pixels, limit, bounds).map_err(|e| format!("error writing '{}': {}", args.file, e))?;
+  Ok(format!("interrupted with {} of {} rows done; they are in '{}', and --resume {} finishes the render", rows, bounds.1, args.file, checkpoint))
+}
+
+// Saves an interrupted render that had no checkpoint, starting one at FILE.checkpoint.
+fn checkpoint_interrupted(args: &Arguments, pixels: &[u32], limit: usize, bounds: (usize, usize), rows: usize) -> Result<String, String> {
+  let path = format!("{}.checkpoint", args.file);
+  Checkpoint::create(&path, &args.command_line).and_then(|mut checkpoint| checkpoint.append(&pixels[..rows * bounds.0]))
+    .map_err(|e| format!("error writing checkpoint '{}': {}", path, e))?;
+  save_interrupted(args, pixels, limit, bounds, rows, &path)
+}
+
+fn open_cache(dir: &str) -> Result<TileCache, String> {
+  TileCache::open(dir).map_err(|e| format!("error opening tile cache '{}': {}", dir, e))
+}
+
+fn build_sampler(args: &Arguments, bounds: (usize, usize)) -> Result<Box<dyn Sampler>, String> {
+  let sampler = view_sampler(args, bounds)?;
+  Ok(match args.samples {
+    1 => sampler,
+    samples => Box::new(Jittered { sampler, samples, seed: args.seed }),
+  })
+}
+
+fn view_sampler(args: &Arguments, bounds: (usize, usize)) -> Result<Box<dyn Sampler>, String> {
+  let corner = |which: &str, text: &str| format!("error parsing {} corner '{}'", which, text);
+  let upper_left = parse_complex(&args.upper_left).ok_or_else(|| corner("upper left", &args.upper_left))?;
+  let lower_right = parse_complex(&args.lower_right).ok_or_else(|| corner("lower right", &args.lower_right))?;
+
+  // Perturbation re-parses the corners so they keep the digits f64 would have dropped.
+  match args.precision {
+    Precision::Single => {
+      let limit = max_iter(args.max_iter, lower_right.re - upper_left.re);
+      let single = |c: Complex<f64>| Complex { re: c.re as f32, im: c.im as f32 };
+      let plane = Plane { bounds, upper_left: single(upper_left), lower_right: single(lower_right), limit, fractal: Fractal::Mandelbrot, bailout: bailout(args, Fractal::Mandelbrot), turn: turn(args.rotation) };
+      match (args.interior, args.exterior, args.bands) {
+        (Interior::Black, Exterior::Escape, None) => Ok(Box::new(plane)),
+        (interior, exterior, bands) => Ok(Box::new(KeyedPlane { plane, interior, exterior, bands })),
+      }
+    }
+    Precision::Double if !(args.perturbation || args.series) => {
+      let limit = max_iter(args.max_iter, lower_right.re - upper_left.re);
+      let plane = Plane { bounds, upper_left, lower_right, limit, fractal: Fractal::Mandelbrot, bailout: bailout(args, Fractal::Mandelbrot), turn: turn(args.rotation) };
+      match &args.formula {
+        Some(formula) => Ok(Box::new(FormulaPlane { plane, formula: formula.clone() })),
+        None if args.interior != Interior::Black || args.exterior != Exterior::Escape || args.bands.is_some() => {
+          Ok(Box::new(KeyedPlane { plane, interior: args.interior, exterior: args.exterior, bands: args.bands }))
+        }
+        None => Ok(Box::new(plane)),
+      }
+    }
+    Precision::Double => {
+      let upper_left = parse_pair::<DoubleDouble>(&args.upper_left, ',').ok_or_else(|| corner("upper left", &args.upper_left))?;
+      let lower_right = parse_pair::<DoubleDouble>(&args.lower_right, ',').ok_or_else(|| corner("lower right", &args.lower_right))?;
+      let limit = max_iter(args.max_iter, (lower_right.0 - upper_left.0).to_f64());
+      Ok(perturbation_sampler(Perturbation::new(bounds, upper_left, lower_right, limit, args.series).rotated(args.rotation), args.series))
+    }
+    Precision::Bits(bits) => {
+      let upper_left = parse_big_complex(&args.upper_left, bits).ok_or_else(|| corner("upper left", &args.upper_left))?;
+      let lower_right = parse_big_complex(&args.lower_right, bits).ok_or_else(|| corner("lower right", &args.lower_right))?;
+      let limit = max_iter(args.max_iter, (lower_right.0.clone() - upper_left.0.clone()).to_f64());
+      Ok(perturbation_sampler(Perturbation::new(bounds, upper_left, lower_right, limit, args.series).rotated(args.rotation), args.series))
+    }
+    Precision::Fixed128 => {
+      let limit = max_iter(args.max_iter, view_width(args));
+      Ok(Box::new(fixed::Plane::new(bounds, &args.upper_left, &args.lower_right, limit, bailout(args, Fractal::Mandelbrot))?))
+    }
+  }
+}
+
+// Renders the image a strip at a time, appending each finished strip to the checkpoint
+// so an interrupted render can pick up where it stopped.
+fn render_checkpointed(pixels: &mut [u32], bounds: (usize, usize), sampler: &dyn Sampler, path: &str, args: &Arguments) -> Result<(), String> {
+  let failed = |e: std::io::Error| format!("error writing checkpoint '{}': {}", path, e);
+  let (mut checkpoint, done) = if args.resume {
+    Checkpoint::resume(path, pixels, bounds.0).map_err(|e| format!("error resuming from checkpoint '{}': {}", path, e))?
+  } else {
+    (Checkpoint::create(path, &args.command_line).map_err(failed)?, 0)
+  };
+
+  if done > 0 {
+    log::info(&format!("resuming from row {} of {}", done, bounds.1));
+    progress::advance((0, 0), (bounds.0, done));
+  }
+
+  for top in (done..bounds.1).step_by(CHECKPOINT_ROWS) {
+    let rows = CHECKPOINT_ROWS.min(bounds.1 - top);
+    let strip = &mut pixels[top * bounds.0..(top + rows) * bounds.0];
+    let finished = render_parallel(strip, (bounds.0, rows), sampler, args.threads, top, 1, true)?;
+    checkpoint.append(&strip[..finished * bounds.0]).map_err(failed)?;
+    if finished < rows {
+      return Err(save_interrupted(args, pixels, sampler.limit(), bounds, top + finished, path)?);
+    }
+  }
+
+  Ok(())
+}
+
+// Renders the image tile by tile, copying tiles the cache already has and storing the
+// ones it had to compute.
+fn render_cached(pixels: &mut [u32], bounds: (usize, usize), sampler: &dyn Sampler, threads: usize, cache: &TileCache) -> Result<(), String> {
+  let mut missing = Vec::new();
+  let mut hits = 0;
+
+  for top in (0..bounds.1).step_by(TILE_SIZE) {
+    for left in (0..bounds.0).step_by(TILE_SIZE) {
+      let size = (TILE_SIZE.min(bounds.0 - left), TILE_SIZE.min(bounds.1 - top));
+      let key = sampler.tile_key(left, top).map(|key| format!("{} size {}x{}", key, size.0, size.1));
+      match key.as_ref().and_then(|key| cache.get(key, size.0 * size.1)) {
+        Some(tile) => {
+          copy_tile(pixels, bounds, (left, top), size, &tile);
+          progress::advance((left, top), size);
+          hits += 1;
+        }
+        None => missing.push(((left, top), size, key)),
+      }
+    }
+  }
+
+  // Deal the missing tiles out to the threads round-robin.
+  let tracker = progress::tracker();
+  let rendered: Vec<Vec<Vec<u32>>> = crossbeam::scope(|spawner| {
+    let handles: Vec<_> = (0..threads).map(|thread| {
+      let (missing, tracker) = (&missing, tracker.clone());
+      spawner.spawn(move |_| {
+        affinity::pin(thread);
+        progress::track(tracker);
+        let start = Instant::now();
+        let tiles = missing.iter().skip(thread).step_by(threads)
+          .take_while(|_| !interrupt::requested())
+          .map(|&(origin, size, _)| {
+            let _span = log::span("tile", &[("left", &origin.0), ("top", &origin.1)]);
+            let begun = Instant::now();
+            let tile = render_tile(sampler, origin, size);
+            progress::advance(origin, size);
+            nice::throttle(begun.elapsed());
+            tile
+          })
+          .collect::<Vec<_>>();
+        stats::flush(thread, start.elapsed());
+        tiles
+      })
+    }).collect();
+    handles.into_iter().map(|handle| handle.join().map_err(|_| THREAD_PANICKED.to_string())).collect::<Result<_, _>>()
+  }).map_err(|_| THREAD_PANICKED.to_string())??;
+
+  for (thread, tiles) in rendered.into_iter().enumerate() {
+    for (i, tile) in tiles.into_iter().enumerate() {
+      let (origin, size, key) = &missing[thread + i * threads];
+      copy_tile(pixels, bounds, *origin, *size, &tile);
+      if let Some(key) = key {
+        cache.put(key, &tile).map_err(|e| format!("error writing to tile cache: {}", e))?;
+      }
+    }
+  }
+
+  if interrupt::requested() {
+    return Err("interrupted; the tiles finished so far are in the cache, so rerunning the render picks up from them".to_string());
+  }
+  log::info(&format!("tile cache: {} hit(s), {} tile(s) rendered", hits, missing.len()));
+  Ok(())
+}
+
+fn render_tile(sampler: &dyn Sampler, origin: (usize, usize), size: (usize, usize)) -> Vec<u32> {
+  let mut tile = Vec::with_capacity(size.0 * size.1);
+  for y in 0..size.1 {
+    for x in 0..size.0 {
+      tile.push(sampler.sample((origin.0 + x) as f64, (origin.1 + y) as f64));
+    }
+  }
+  tile
+}
+
+fn copy_tile(pixels: &mut [u32], bounds: (usize, usize), origin: (usize, usize), size: (usize, usize), tile: &[u32]) {
+  for (row, line) in tile.chunks(size.0).enumerate() {
+    let start = (origin.1 + row) * bounds.0 + origin.0;
+    pixels[start..start + size.0].copy_from_slice(line);
+  }
+}
+
+// Renders and encodes the image `rows` rows at a time, so memory use is bounded by the
+// strip size rather than the whole image. With antialiasing each strip is rendered with
+// a row of margin on either side, so pixels on strip edges still see their neighbors.
+// Returns the SHA-256 of the pixels, which can't be taken afterwards.
+fn write_strips(args: &Arguments, bounds: (usize, usize), sampler: &dyn Sampler, rows: usize) -> Result<[u8; 32], String> {
+  let (filename, threads, antialias_mode, colors) = (&args.file, args.threads, args.antialias, args.colors.as_deref());
+  let failed = |e: &dyn std::fmt::Display| format!("error writing '{}': {}", filename, e);
+  let mut writer = stream_writer(args, bounds, bounds.1).map_err(|e| failed(&e))?;
+
+  let margin = if antialias_mode == Antialias::Adaptive { 1 } else { 0 };
+  let mut strip = vec![0; bounds.0 * (rows + 2 * margin)];
+  let mut sha = Sha256::new();
+
+  for top in (0..bounds.1).step_by(rows) {
+    let first = top.saturating_sub(margin);
+    let last = (top + rows + margin).min(bounds.1);
+    let strip_bounds = (bounds.0, last - first);
+    let pixels = &mut strip[..strip_bounds.0 * strip_bounds.1];
+
+    render_parallel(pixels, strip_bounds, sampler, threads, first, 1, true)?;
+    if antialias_mode == Antialias::Adaptive {
+      antialias(pixels, strip_bounds, first, sampler, threads)?;
+    }
+    if interrupt::requested() {
+      return Err(format!("interrupted; '{}' is incomplete", filename));
+    }
+
+    let height = rows.min(bounds.1 - top);
+    let finished = shades(&pixels[(top - first) * bounds.0..(top - first + height) * bounds.0], sampler.limit());
+    match colors {
+      Some(colors) => writer.write_all(&finished.iter().flat_map(|&shade| colors[shade as usize]).collect::<Vec<_>>()),
+      None => writer.write_all(&finished),
+    }
+    .map_err(|e| failed(&e))?;
+    sha.update(&finished);
+  }
+
+  writer.finish().map_err(|e| failed(&e))?;
+  Ok(sha.finish())
+}
+
+// Starts FILE, `height` rows high, for writing a row at a time; `height` is more than
+// the render's rows when a legend goes below them.
+fn stream_writer(args: &Arguments, bounds: (usize, usize), height: usize) -> Result<png::StreamWriter<'static, BufWriter<File>>, std::io::Error> {
+  let output = BufWriter::new(File::create(&args.file)?);
+  let mut encoder = png::Encoder::new(output, bounds.0 as u32, height as u32);
+  encoder.set_color(if args.colors.is_some() { png::ColorType::Rgb } else { png::ColorType::Grayscale });
+  encoder.set_depth(png::BitDepth::Eight);
+  provenance(args, bounds).add_to(&mut encoder)?;
+  Ok(encoder.write_header()?.into_stream_writer()?)
+}
+
+// Renders `factor` times as wide and high with `sampler`, strip by strip so only a strip
+// of the large image is ever held, and writes each `factor` x `factor` block of it as one
+// pixel of the blocks' average color. Colors are averaged rather than shades, so --map
+// palettes blend as they would scaling a full-size render down.
+fn write_supersampled(args: &Arguments, bounds: (usize, usize), sampler: &dyn Sampler, factor: usize) -> Result<[u8; 32], String> {
+  let failed = |e: &dyn std::fmt::Display| format!("error writing '{}': {}", args.file, e);
+  let mut writer = stream_writer(args, bounds, bounds.1).map_err(|e| failed(&e))?;
+  let rows = args.strip_rows.unwrap_or(SUPERSAMPLE_ROWS);
+  let width = bounds.0 * factor;
+  let mut strip = vec![0; width * rows * factor];
+  let mut sha = Sha256::new();
+
+  for top in (0..bounds.1).step_by(rows) {
+    let height = rows.min(bounds.1 - top) * factor;
+    let samples = &mut strip[..width * height];
+    if render_parallel(samples, (width, height), sampler, args.threads, top * factor, 1, true)? < height || interrupt::requested() {
+      return Err(format!("interrupted; '{}' is incomplete", args.file));
+    }
+    let finished = downsample(&shades(samples, sampler.limit()), width, factor, args.colors.as_deref());
+    writer.write_all(&finished).map_err(|e| failed(&e))?;
+    sha.update(&finished);
+  }
+
+  writer.finish().map_err(|e| failed(&e))?;
+  Ok(sha.finish())
+}
+
+// The average of each `factor` x `factor` block of the rows of shades `samples`, `width`
+// wide: gray, or RGB through `colors`.
+fn downsample(samples: &[u8], width: usize, factor: usize, colors: Option<&[[u8; 3]]>) -> Vec<u8> {
+  let channels = if colors.is_some() { 3 } else { 1 };
+  let (columns, rows) = (width / factor, samples.len() / width / factor);
+  let mut pixels = Vec::with_capacity(columns * rows * channels);
+  for row in 0..rows {
+    for column in 0..columns {
+      let mut total = [0; 3];
+      for y in row * factor..(row + 1) * factor {
+        for &shade in &samples[y * width + column * factor..y * width + (column + 1) * factor] {
+          let color = colors.map_or([shade; 3], |colors| colors[shade as usize]);
+          for (total, channel) in total.iter_mut().zip(color) {
+            *total += channel as usize;
+          }
+        }
+      }
+      pixels.extend(total[..channels].iter().map(|&total| (total / (factor * factor)) as u8));
+    }
+  }
+  pixels
+}
+
+// The bailout --bailout and --bailout-norm make of `fractal`'s own.
+fn bailout(args: &Arguments, fractal: Fractal) -> Bailout {
+  let default = fractal.bailout();
+  Bailout { norm: args.bailout_norm.unwrap_or(default.norm), radius: args.bailout_radius.unwrap_or(default.radius) }
+}
+
+fn max_iter(max_iter: MaxIter, view_width: f64) -> usize {
+  let limit = max_iter.resolve(view_width);
+  if max_iter == MaxIter::Auto {
+    log::info(&format!("max iterations: {}", limit));
+  }
+  limit
+}
+
+fn perturbation_sampler(perturbation: Perturbation, series: bool) -> Box<dyn Sampler> {
+  if series {
+    log::info(&format!("series approximation skips {} iterations", perturbation.skipped_iterations()));
+  }
+  Box::new(perturbation)
+}
+
+// Renders `pixels`, which hold rows `origin..origin + bounds.1` of the image, and returns
+// how many of those rows are done: all of them unless the render was interrupted.
+fn render_parallel(pixels: &mut [u32], bounds: (usize, usize), sampler: &dyn Sampler, threads: usize, origin: usize, step: usize, first: bool) -> Result<usize, String> {
+  // Chunks span whole step x step blocks so each pass can fill its blocks in place.
+  let rows = tune::current().chunk_rows.div_ceil(step) * step;
+  for_each_chunk(pixels, bounds.0, origin, rows, threads, |top, band| render_band(band, bounds.0, sampler, top, step, first))
+}
+
+fn render_band(band: &mut [u32], width: usize, sampler: &dyn Sampler, top: usize, step: usize, first: bool) {
+  let _span = log::span("band", &[("top", &top), ("rows", &(band.len() / width)), ("step", &step)]);
+  render_pass(band, (width, band.len() / width), sampler, top, step, first);
+  progress::advance((0, top), (width, band.len() / width));
+}
+
+// Renders the image into `pixels` as render_parallel does, encoding each band into FILE
+// as soon as those above it are done, so the encoder keeps up with the render rather
+// than starting once it's over, and colored images are never held whole in RGB. Returns
+// how many rows are done; FILE is finished only if all of them are.
+fn render_streamed(pixels: &mut [u32], bounds: (usize, usize), sampler: &dyn Sampler, args: &Arguments) -> Result<usize, String> {
+  let failed = |e: &dyn std::fmt::Display| format!("error writing '{}': {}", args.file, e);
+  let mut writer = stream_writer(args, bounds, bounds.1).map_err(|e| failed(&e))?;
+  let rows = for_each_chunk_in_order(pixels, bounds.0, 0, tune::current().chunk_rows, args.threads, |top, band| render_band(band, bounds.0, sampler, top, 1, true), |_, band| {
+    let _span = log::span("encode", &[("rows", &(band.len() / bounds.0))]);
+    let band = shades(band, sampler.limit());
+    match args.colors.as_deref() {
+      Some(colors) => writer.write_all(&band.iter().flat_map(|&shade| colors[shade as usize]).collect::<Vec<_>>()),
+      None => writer.write_all(&band),
+    }.map_err(|e| failed(&e))
+  })?;
+  if rows == bounds.1 {
+    writer.finish().map_err(|e| failed(&e))?;
+  }
+  Ok(rows)
+}
+
+// Whether write_output would only encode the pixels, so render_streamed can do it.
+fn streams(args: &Arguments) -> bool {
+  args.overlays.is_empty() && args.caption.is_none() && args.legend != Some(Legend::Append) && args.edges.is_none() && args.antialias != Antialias::Adaptive
+}
+
+// Splits `pixels` into chunks of `rows` rows, aligned to multiples of `rows` in the full
+// image whose row `origin` is the first in `pixels`, and feeds them through a channel to
+// `threads` threads that each call `work` with a chunk's first row and its pixels.
+// Threads take the next chunk as soon as they finish one, so none sits idle while
+// another grinds through the expensive rows around the set, unless the tuning has each
+// take every threads'th chunk instead.
+//
+// Once Ctrl-C is pressed the threads take no more chunks. Returns how many rows from the
+// start of `pixels` are done, not counting chunks finished after the first skipped one.
+fn for_each_chunk<P: Send + Sync, F: Fn(usize, &mut [P]) + Sync>(pixels: &mut [P], width: usize, origin: usize, rows: usize, threads: usize, work: F) -> Result<usize, String> {
+  for_each_chunk_in_order(pixels, width, origin, rows, threads, work, |_, _| Ok(()))
+}
+
+// for_each_chunk, also handing each finished chunk to `done` on the calling thread, in
+// the order of the image, once every chunk above it is finished too. If `done` fails,
+// the threads take no more chunks and its error is returned.
+fn for_each_chunk_in_order<P, F, D>(pixels: &mut [P], width: usize, origin: usize, rows: usize, threads: usize, work: F, mut done: D) -> Result<usize, String>
+where
+  P: Send + Sync,
+  F: Fn(usize, &mut [P]) + Sync,
+  D: FnMut(usize, &[P]) -> Result<(), String>,
+{
+  let lead = ((rows - origin % rows) % rows).min(pixels.len() / width);
+  let (head, rest) = pixels.split_at_mut(lead * width);
+  let queues = if tune::current().schedule == tune::Schedule::Interleaved { threads } else { 1 };
+  let (senders, receivers): (Vec<_>, Vec<_>) = (0..queues).map(|_| crossbeam::channel::unbounded()).unzip();
+
+  let mut top = origin;
+  let mut heights = Vec::new();
+  for chunk in std::iter::once(head).filter(|head| !head.is_empty()).chain(rest.chunks_mut(rows * width)) {
+    let height = chunk.len() / width;
+    senders[heights.len() % queues].send((heights.len(), top, chunk)).unwrap();
+    heights.push(height);
+    top += height;
+  }
+  drop(senders);
+  let finished: Vec<AtomicBool> = heights.iter().map(|_| AtomicBool::new(false)).collect();
+  let (finishing, finished_chunks) = crossbeam::channel::unbounded();
+  let stop = AtomicBool::new(false);
+
+  let tracker = progress::tracker();
+  let outcome = crossbeam::scope(|spawner| {
+    for thread in 0..threads {
+      let (receiver, work, finishing) = (receivers[thread % queues].clone(), &work, finishing.clone());
+      let (finished, stop, tracker) = (&finished, &stop, tracker.clone());
+      spawner.spawn(move |_| {
+        affinity::pin(thread);
+        progress::track(tracker);
+        let mut busy = Duration::ZERO;
+        for (index, top, chunk) in receiver {
+          if interrupt::requested() || stop.load(Ordering::Relaxed) {
+            break;
+          }
+          let start = Instant::now();
+          work(top, chunk);
+          busy += start.elapsed();
+          nice::throttle(start.elapsed());
+          finished[index].store(true, Ordering::Relaxed);
+          let _ = finishing.send((index, top, &*chunk));
+        }
+        stats::flush(thread, busy);
+      });
+    }
+    drop(finishing);
+
+    // Chunks finished out of order wait here for those above them.
+    let mut waiting = std::collections::BTreeMap::new();
+    let mut next = 0;
+    for (index, top, chunk) in finished_chunks {
+      waiting.insert(index, (top, chunk));
+      while let Some((top, chunk)) = waiting.remove(&next) {
+        if let Err(message) = done(top, chunk) {
+          stop.store(true, Ordering::Relaxed);
+          return Err(message);
+        }
+        next += 1;
+      }
+    }
+    Ok(())
+  }).map_err(|_| THREAD_PANICKED.to_string())?;
+  outcome?;
+
+  Ok(heights.iter().zip(&finished).take_while(|(_, finished)| finished.load(Ordering::Relaxed)).map(|(height, _)| height).sum())
+}
+
+fn parse_threads(value: Option<&str>) -> Result<usize, String> {
+  match value.map(usize::from_str) {
+    Some(Ok(threads)) if threads > 0 => Ok(threads),
+    _ => Err("--threads expects a positive number of threads".to_string()),
+  }
+}
+
+// Parses the options and positional arguments of a render, without the program name.
+fn parse_arguments(arguments: &[String]) -> Result<Arguments, String> {
+  let mut checkpoint = None;
+  let mut resume = None;
+  let mut workers = Vec::new();
+  // Where in `arguments` those three are given, since the command line checkpoints and
+  // workers are sent leaves them out.
+  let mut unrecorded = Vec::new();
+
+  let mut positional = Vec::new();
+  let mut antialias = Antialias::None;
+  let mut supersample = 1;
+  let mut samples = 1;
+  let mut seed = None;
+  let mut interior = Interior::Black;
+  let mut exterior = Exterior::Escape;
+  let (mut banded, mut band_width, mut band_borders) = (false, None, false);
+  let mut bailout_radius = None;
+  let mut bailout_norm = None;
+  let mut progressive = false;
+  let mut progress_image = None;
+  let mut progress_bar = true;
+  let mut stats = false;
+  let mut stats_json = false;
+  let mut histogram = None;
+  let mut edges = None;
+  let mut edge_threshold = DEFAULT_EDGE_THRESHOLD;
+  let mut existing = Existing::Refuse;
+  let mut print_hash = false;
+  let mut expect_hash = None;
+  let mut save_location = None;
+  let (mut title, mut author) = (None, None);
+  let mut preview = None;
+  let mut pin_threads = false;
+  let mut avoid_smt = false;
+  let mut nice = false;
+  let mut spare_cores = None;
+  let mut cpu_share = None;
+  let mut perturbation = false;
+  let mut series = false;
+  let mut precision = Precision::Double;
+  let mut formula = None;
+  let mut strip_rows = None;
+  let mut buffer = BufferKind::Memory;
+  let mut threads = DEFAULT_THREADS;
+  let mut max_iter = None;
+  let mut rotation = 0.0;
+  let mut cache = None;
+  let mut bookmark = None;
+  let mut location = None;
+  let mut entry = None;
+  let mut colors = None;
+  let mut overlays = Vec::new();
+  let mut overlay_corner = None;
+  let mut font_scale = None;
+  let mut legend = None;
+  let mut caption = None;
+  let mut caption_corner = None;
+
+  let mut options = arguments.iter().map(String::as_str);
+  while let Some(arg) = options.next() {
+    match arg {
+      "--checkpoint" | "--resume" | "--workers" => {
+        unrecorded.push(arguments.len() - options.len() - 1);
+        match (arg, options.next()) {
+          ("--checkpoint", value) => checkpoint = Some(value.ok_or("--checkpoint expects a file name")?.to_string()),
+          ("--resume", value) => resume = Some(value.ok_or("--resume expects a checkpoint file")?.to_string()),
+          (_, list) => {
+            let list = list.ok_or("--workers expects a comma-separated list of host:port addresses")?;
+            workers = list.split(',').filter(|address| !address.is_empty()).map(str::to_string).collect();
+          }
+        }
+      }
+      "--antialias" => {
+        antialias = match options.next() {
+          Some("none") => Antialias::None,
+          Some("adaptive") => Antialias::Adaptive,
+          _ => return Err("--antialias expects 'none' or 'adaptive'".to_string()),
+        }
+      }
+      "--supersample" => {
+        supersample = options.next().and_then(|value| usize::from_str(value).ok()).filter(|&factor| factor > 0)
+          .ok_or("--supersample expects a positive whole factor")?
+      }
+      "--samples" => {
+        samples = options.next().and_then(|value| usize::from_str(value).ok()).filter(|&count| count > 0)
+          .ok_or("--samples expects a positive number of samples per pixel")?
+      }
+      "--seed" => seed = Some(options.next().and_then(|value| u64::from_str(value).ok()).ok_or("--seed expects a whole number")?),
+      "--exterior" => {
+        exterior = match options.next() {
+          Some("escape") => Exterior::Escape,
+          Some("atom") => Exterior::Atom,
+          Some("binary") => Exterior::Binary,
+          Some("binary-levels") => Exterior::BinaryLevels,
+          _ => return Err("--exterior expects 'escape', 'atom', 'binary' or 'binary-levels'".to_string()),
+        }
+      }
+      "--coloring" => {
+        banded = match options.next() {
+          Some("escape") => false,
+          Some("bands") => true,
+          _ => return Err("--coloring expects 'escape' or 'bands'".to_string()),
+        }
+      }
+      "--band-width" => {
+        band_width = Some(options.next().and_then(|value| f64::from_str(value).ok()).filter(|width| width.is_finite() && *width > 0.0)
+          .ok_or("--band-width expects a positive number of iterations")?)
+      }
+      "--band-borders" => band_borders = true,
+      "--bailout" => {
+        bailout_radius = Some(options.next().and_then(|value| f64::from_str(value).ok()).filter(|radius| radius.is_finite() && *radius > 0.0)
+          .ok_or("--bailout expects a positive radius")?)
+      }
+      "--bailout-norm" => {
+        let name = options.next();
+        bailout_norm = Some(NORMS.into_iter().find(|norm| Some(norm.name()) == name).ok_or("--bailout-norm expects 'euclidean', 'manhattan' or 'chebyshev'")?)
+      }
+      "--interior" => {
+        interior = match options.next() {
+          Some("black") => Interior::Black,
+          Some("period") => Interior::Period,
+          _ => return Err("--interior expects 'black' or 'period'".to_string()),
+        }
+      }
+      "--progressive" => progressive = true,
+      "--progress-image" => {
+        progress_image = Some(options.next().ok_or("--progress-image expects a file name")?.to_string());
+        progressive = true;
+      }
+      "--no-progress" => progress_bar = false,
+      "--stats" => stats = true,
+      "--stats-json" => stats_json = true,
+      "--edges" => edges = Some(options.next().ok_or("--edges expects a PNG file name")?.to_string()),
+      "--edge-threshold" => {
+        edge_threshold = options.next().and_then(|value| usize::from_str(value).ok()).ok_or("--edge-threshold expects a number of iterations")?
+      }
+      "--histogram" => histogram = Some(options.next().ok_or("--histogram expects a CSV file name")?.to_string()),
+      "--force" => existing = Existing::Overwrite,
+      "--auto-suffix" => existing = Existing::Suffix,
+      "--print-hash" => print_hash = true,
+      "--expect-hash" => {
+        expect_hash = match options.next() {
+          Some(hash) if hash.len() == 64 && hash.chars().all(|c| c.is_ascii_hexdigit()) => Some(hash.to_ascii_lowercase()),
+          _ => return Err("--expect-hash expects a SHA-256 as 64 hexadecimal digits".to_string()),
+        }
+      }
+      "--preview" => {
+        preview = match options.next() {
+          Some("term") => Some(Preview::Term),
+          Some("ascii") => Some(Preview::Ascii),
+          Some("sixel") => Some(Preview::Sixel),
+          Some("kitty") => Some(Preview::Kitty),
+          _ => return Err("--preview expects 'term', 'ascii', 'sixel' or 'kitty'".to_string()),
+        }
+      }
+      "--pin-threads" => pin_threads = true,
+      "--avoid-smt" => avoid_smt = true,
+      "--nice" => nice = true,
+      "--spare-cores" => spare_cores = Some(options.next().and_then(|value| usize::from_str(value).ok()).ok_or("--spare-cores expects a number of cores")?),
+      "--cpu-share" => cpu_share = Some(options.next().and_then(|value| usize::from_str(value).ok()).filter(|share| (1..=100).contains(share))
+        .ok_or("--cpu-share expects a percentage from 1 to 100")?),
+      "--perturbation" => perturbation = true,
+      "--series" => series = true,
+      "--precision" => {
+        precision = match options.next() {
+          Some("single") => Precision::Single,
+          Some("double") => Precision::Double,
+          Some("fixed128") => Precision::Fixed128,
+          Some(value) => match usize::from_str(value) {
+            Ok(bits) if bits >= 64 => Precision::Bits(bits),
+            _ => return Err("--precision expects 'single', 'double', 'fixed128' or a number of bits, at least 64".to_string()),
+          },
+          None => return Err("--precision expects 'single', 'double', 'fixed128' or a number of bits, at least 64".to_string()),
+        }
+      }
+      "--formula" => formula = Some(Arc::new(wasm::Formula::load(options.next().ok_or("--formula expects a WebAssembly module")?)?)),
+      "--strip-rows" => {
+        strip_rows = match options.next().map(usize::from_str) {
+          Some(Ok(rows)) if rows > 0 => Some(rows),
+          _ => return Err("--strip-rows expects a positive number of rows".to_string()),
+        }
+      }
+      "--buffer" => {
+        buffer = match options.next() {
+          Some("memory") => BufferKind::Memory,
+          Some("mmap") => BufferKind::Mmap,
+          _ => return Err("--buffer expects 'memory' or 'mmap'".to_string()),
+        }
+      }
+      "--threads" => threads = parse_threads(options.next())?,
+      "--max-iter" => {
+        max_iter = match options.next() {
+          Some("auto") => Some(MaxIter::Auto),
+          Some(value) => match usize::from_str(value) {
+            Ok(limit) if limit > 0 => Some(MaxIter::Fixed(limit)),
+            _ => return Err("--max-iter expects a positive number or 'auto'".to_string()),
+          },
+          None => return Err("--max-iter expects a positive number or 'auto'".to_string()),
+        }
+      }
+      "--rotate" => {
+        rotation = options.next().and_then(|value| f64::from_str(value).ok()).filter(|degrees| degrees.is_finite())
+          .ok_or("--rotate expects a number of degrees")?
+      }
+      "--cache" => cache = Some(options.next().ok_or("--cache expects a directory")?.to_string()),
+      "--bookmark" => bookmark = Some(bookmarks::find(options.next().ok_or("--bookmark expects a bookmark name")?)?),
+      "--location" => location = Some(options.next().ok_or("--location expects a .kfr, .upr or .par file")?),
+      "--entry" => entry = Some(options.next().ok_or("--entry expects the name of an entry in the --location file")?),
+      "--map" => colors = Some(palette::load_map(options.next().ok_or("--map expects a Fractint .map file")?)?),
+      "--overlay" => {
+        overlays.extend(options.next().and_then(overlay::parse).ok_or("--overlay expects a comma-separated list of 'axes', 'grid' and 'scalebar'")?)
+      }
+      "--overlay-corner" => {
+        let name = options.next();
+        overlay_corner = Some(overlay::CORNERS.into_iter().find(|&(corner, _)| Some(corner) == name).map(|(_, corner)| corner)
+          .ok_or("--overlay-corner expects 'top-left', 'top-right', 'bottom-left' or 'bottom-right'")?)
+      }
+      "--font-scale" => {
+        font_scale = Some(options.next().and_then(|value| usize::from_str(value).ok()).filter(|&scale| scale > 0)
+          .ok_or("--font-scale expects a positive whole number of pixels per font pixel")?)
+      }
+      "--legend" => {
+        legend = match options.next() {
+          Some("append") => Some(Legend::Append),
+          Some(path) => Some(Legend::File(path.to_string())),
+          None => return Err("--legend expects 'append' or a PNG file name".to_string()),
+        }
+      }
+      "--caption" => {
+        caption = match options.next() {
+          Some("auto") => Some(overlay::AUTO_CAPTION.to_string()),
+          Some(format) => {
+            overlay::expand(format, &overlay::CAPTION_FIELDS.map(|field| (field, String::new())))?;
+            Some(format.to_string())
+          }
+          None => return Err("--caption expects 'auto' or a format naming fields like {center} and {zoom}".to_string()),
+        }
+      }
+      "--caption-corner" => {
+        let name = options.next();
+        caption_corner = Some(overlay::CORNERS.into_iter().find(|&(corner, _)| Some(corner) == name).map(|(_, corner)| corner)
+          .ok_or("--caption-corner expects 'top-left', 'top-right', 'bottom-left' or 'bottom-right'")?)
+      }
+      "--title" => title = Some(options.next().ok_or("--title expects the image's title")?.to_string()),
+      "--author" => author = Some(options.next().ok_or("--author expects the name of the image's author")?.to_string()),
+      "--save-location" => {
+        save_location = match options.next() {
+          Some(path) if path.to_ascii_lowercase().ends_with(".upr") => Some(path.to_string()),
+          _ => return Err("--save-location expects an Ultra Fractal .upr file name".to_string()),
+        }
+      }
+      _ => positional.push(arg.to_string()),
+    }
+  }
+
+  let mut command_line: Vec<String> = arguments.iter().enumerate().filter(|&(i, _)| !unrecorded.iter().any(|&at| i == at || i == at + 1))
+    .map(|(_, arg)| arg.clone()).collect();
+  if let Some(path) = resume {
+    if !command_line.is_empty() || checkpoint.is_some() || !workers.is_empty() {
+      return Err("--resume takes the checkpoint file and nothing else".to_string());
+    }
+    let saved = checkpoint::read_arguments(&path).map_err(|e| format!("error reading checkpoint '{}': {}", path, e))?;
+    let mut args = parse_arguments(&saved)?;
+    args.checkpoint = Some(path);
+    args.resume = true;
+    return Ok(args);
+  }
+
+  if precision == Precision::Single && (perturbation || series) {
+    return Err("--precision single cannot be combined with perturbation".to_string());
+  }
+  if precision == Precision::Fixed128 && (perturbation || series) {
+    return Err("--precision fixed128 iterates directly, so it cannot be combined with perturbation".to_string());
+  }
+  if precision == Precision::Fixed128 && rotation != 0.0 {
+    return Err("--precision fixed128 cannot be combined with --rotate".to_string());
+  }
+
+  if progressive && strip_rows.is_some() {
+    return Err("--progressive rewrites the whole image and cannot be combined with --strip-rows".to_string());
+  }
+
+  if checkpoint.is_some() && (progressive || strip_rows.is_some()) {
+    return Err("--checkpoint cannot be combined with --progressive or --strip-rows".to_string());
+  }
+
+  if cache.is_some() && (progressive || strip_rows.is_some() || checkpoint.is_some()) {
+    return Err("--cache cannot be combined with --progressive, --strip-rows or --checkpoint".to_string());
+  }
+
+  if preview.is_some() && (print_hash || expect_hash.is_some()) {
+    return Err("--print-hash and --expect-hash hash the pixels written to FILE, so they cannot be combined with --preview".to_string());
+  }
+  if preview.is_some() && report::json() {
+    return Err("--preview prints to standard output, which --output-format json keeps for its report".to_string());
+  }
+
+  if avoid_smt && !pin_threads {
+    return Err("--avoid-smt only applies with --pin-threads".to_string());
+  }
+  if (spare_cores.is_some() || cpu_share.is_some()) && !nice {
+    return Err(format!("{} only applies with --nice", if spare_cores.is_some() { "--spare-cores" } else { "--cpu-share" }));
+  }
+
+  if !workers.is_empty() && (progressive || strip_rows.is_some() || checkpoint.is_some() || cache.is_some()) {
+    return Err("--workers cannot be combined with --progressive, --strip-rows, --checkpoint or --cache".to_string());
+  }
+
+  if edges.is_some() && (strip_rows.is_some() || preview.is_some()) {
+    return Err("--edges needs the whole image at once, so it cannot be combined with --strip-rows or --preview".to_string());
+  }
+
+  if stats_json && (!workers.is_empty() || cache.is_some() || preview.is_some()) {
+    return Err("--stats-json counts the samples this process iterates for FILE, so it cannot be combined with --workers, --cache or --preview".to_string());
+  }
+
+  if histogram.is_some() && (!workers.is_empty() || cache.is_some()) {
+    return Err("--histogram counts the samples this process iterates, so it cannot be combined with --workers or --cache".to_string());
+  }
+
+  if entry.is_some() && location.is_none() {
+    return Err("--entry picks an entry of the --location file, so it needs one".to_string());
+  }
+  let location = location.map(|path| location::load(path, entry)).transpose()?;
+
+  let bookmark_given = bookmark.is_some();
+  if let Some(bookmark) = bookmark {
+    if positional.len() != 2 {
+      return Err(format!("with --bookmark, expected FILE PIXELS, got {} positional argument(s)", positional.len()));
+    }
+    let bounds = parse_pair(&positional[1], 'x').ok_or("error parsing image dimensions")?;
+    let (upper_left, lower_right) = centered_corners(bookmark.center, bookmark.width, bounds);
+    let corners = [format!("{},{}", upper_left.re, upper_left.im), format!("{},{}", lower_right.re, lower_right.im)];
+    positional.extend(corners.clone());
+
+    // Checkpoints and workers get the view itself, not a name only this user's file knows.
+    let at = command_line.iter().position(|arg| arg == "--bookmark").unwrap();
+    command_line.drain(at..at + 2);
+    if max_iter.is_none() {
+      max_iter = Some(MaxIter::Fixed(bookmark.limit));
+      command_line.extend(["--max-iter".to_string(), bookmark.limit.to_string()]);
+    }
+    command_line.extend(corners);
+  }
+
+  if let Some(location) = location {
+    if bookmark_given {
+      return Err("--location and --bookmark both give the view; pass only one".to_string());
+    }
+    if positional.len() != 2 {
+      return Err(format!("with --location, expected FILE PIXELS, got {} positional argument(s)", positional.len()));
+    }
+    let bounds = parse_pair(&positional[1], 'x').ok_or("error parsing image dimensions")?;
+    let (upper_left, lower_right) = location.corners(bounds);
+    positional.extend([upper_left.clone(), lower_right.clone()]);
+
+    // As with bookmarks, checkpoints and workers get the view rather than the file.
+    for option in ["--location", "--entry"] {
+      if let Some(at) = command_line.iter().position(|arg| arg == option) {
+        command_line.drain(at..at + 2);
+      }
+    }
+    if let (None, Some(limit)) = (max_iter, location.limit) {
+      max_iter = Some(MaxIter::Fixed(limit));
+      command_line.extend(["--max-iter".to_string(), limit.to_string()]);
+    }
+    if let (None, Some(map)) = (&colors, &location.map) {
+      colors = Some(palette::load_map(map)?);
+      command_line.extend(["--map".to_string(), map.clone()]);
+    }
+    if location.rotation != 0.0 && !command_line.iter().any(|arg| arg == "--rotate") {
+      rotation = location.rotation;
+      command_line.extend(["--rotate".to_string(), rotation.to_string()]);
+    }
+    // Most such files are deep zooms; unless told otherwise, use what their depth needs.
+    if !command_line.iter().any(|arg| ["--precision", "--perturbation", "--series"].contains(&arg.as_str())) {
+      match location.precision(bounds) {
+        Some(Precision::Bits(bits)) => {
+          precision = Precision::Bits(bits);
+          command_line.extend(["--precision".to_string(), bits.to_string()]);
+        }
+        Some(_) => {
+          perturbation = true;
+          command_line.push("--perturbation".to_string());
+        }
+        None => {}
+      }
+    }
+    command_line.extend([upper_left, lower_right]);
+  }
+
+  // Pixels hold escape counts below KEYED, whichever way the limit was given.
+  if let Some(MaxIter::Fixed(limit)) = max_iter.filter(|&max_iter| matches!(max_iter, MaxIter::Fixed(limit) if limit >= KEYED as usize)) {
+    return Err(format!("an iteration limit of {} is more than the {} a pixel can count", limit, KEYED - 1));
+  }
+
+  if supersample > 1 && (antialias == Antialias::Adaptive || progressive || checkpoint.is_some() || cache.is_some() || !workers.is_empty() || edges.is_some() || preview.is_some()) {
+    return Err("--supersample renders strips of a larger image, so it cannot be combined with --antialias adaptive, --progressive, --checkpoint, --cache, --workers, --edges or --preview".to_string());
+  }
+
+  if !overlays.is_empty() && (strip_rows.is_some() || supersample > 1 || preview.is_some()) {
+    return Err("--overlay draws on the whole image, so it cannot be combined with --strip-rows, --supersample or --preview".to_string());
+  }
+  if overlays.is_empty() && legend.is_none() && caption.is_none() && font_scale.is_some() {
+    return Err("--font-scale only applies with --overlay, --legend or --caption".to_string());
+  }
+  if caption.is_none() && caption_corner.is_some() {
+    return Err("--caption-corner only applies with --caption".to_string());
+  }
+  if caption.is_some() && (strip_rows.is_some() || supersample > 1 || preview.is_some()) {
+    return Err("--caption draws on the whole image, so it cannot be combined with --strip-rows, --supersample or --preview".to_string());
+  }
+  if overlays.is_empty() && overlay_corner.is_some() {
+    return Err("--overlay-corner only applies with --overlay".to_string());
+  }
+  if legend == Some(Legend::Append) && (strip_rows.is_some() || supersample > 1 || preview.is_some()) {
+    return Err("--legend append adds to the whole image, so it cannot be combined with --strip-rows, --supersample or --preview; give a file name instead".to_string());
+  }
+  if legend.is_some() && (interior != Interior::Black || exterior != Exterior::Escape) {
+    return Err("--legend maps colors to escape counts, so it cannot be combined with --interior period or --exterior other than escape".to_string());
+  }
+  if !overlays.is_empty() && rotation != 0.0 {
+    return Err("--overlay labels the view's own axes, so it cannot be combined with --rotate".to_string());
+  }
 
-  let bounds = parse_pair(&args.pixels, 'x').expect("error parsing image dimensions");
-  let upper_left = parse_complex(&args.upper_left).expect("error parsing upper left corner point");
-  let lower_right = parse_complex(&args.lower_right).expect("error parsing lower right corner point");
+  if samples > 1 && antialias == Antialias::Adaptive {
+    return Err("--samples already takes several samples per pixel, so it cannot be combined with --antialias adaptive".to_string());
+  }
+  if seed.is_some() && samples == 1 {
+    return Err("--seed places the samples --samples takes, so it needs --samples".to_string());
+  }
 
-  let mut pixels = vec![0; bounds.0 * bounds.1];
+  if (bailout_radius.is_some() || bailout_norm.is_some()) && (perturbation || series || matches!(precision, Precision::Bits(_))) {
+    return Err("--bailout and --bailout-norm apply to direct iteration, so they cannot be combined with --perturbation, --series or --precision BITS".to_string());
+  }
+  if let Some(formula) = &formula {
+    if bailout_norm.is_some() {
+      return Err("--formula modules measure how far orbits are from the origin themselves, so --bailout-norm cannot be combined with --formula".to_string());
+    }
+    if bailout_radius.is_some() && !formula.takes_bailout {
+      return Err("--bailout needs a --formula whose iterate takes the radius, as (f64, f64, i32, f64)".to_string());
+    }
+  }
+  let chosen = Bailout {
+    norm: bailout_norm.unwrap_or(Fractal::Mandelbrot.bailout().norm),
+    radius: bailout_radius.unwrap_or(Fractal::Mandelbrot.bailout().radius),
+  };
+  if !chosen.certain() {
+    log::warn(&format!("orbits of points in the set can leave a {} bailout of radius {}, so some of them will be shaded as escaping", chosen.norm.name(), chosen.radius));
+  }
 
-  let threads = 8;
-  let rows_per_band = bounds.1 / threads + 1;
+  if interior == Interior::Period && (formula.is_some() || perturbation || series || matches!(precision, Precision::Bits(_) | Precision::Fixed128)) {
+    return Err("--interior period follows orbits directly, so it cannot be combined with --formula, --perturbation, --series, --precision BITS or fixed128".to_string());
+  }
+  if exterior == Exterior::Atom && (formula.is_some() || perturbation || series || matches!(precision, Precision::Bits(_) | Precision::Fixed128) || bailout_radius.is_some() || bailout_norm.is_some()) {
+    return Err("--exterior atom follows orbits directly to radius 2, so it cannot be combined with --formula, --perturbation, --series, --precision BITS or fixed128, or --bailout".to_string());
+  }
+  if !banded && (band_width.is_some() || band_borders) {
+    return Err(format!("{} only applies with --coloring bands", if band_width.is_some() { "--band-width" } else { "--band-borders" }));
+  }
+  if banded && exterior != Exterior::Escape {
+    return Err("--coloring bands posterizes the escape shading, so it cannot be combined with --exterior".to_string());
+  }
+  if banded && (formula.is_some() || perturbation || series || matches!(precision, Precision::Bits(_) | Precision::Fixed128)) {
+    return Err("--coloring bands needs the z each orbit escapes with, so it cannot be combined with --formula, --perturbation, --series, --precision BITS or fixed128".to_string());
+  }
+  let bands = banded.then(|| Bands { width: band_width.unwrap_or(DEFAULT_BAND_WIDTH), borders: band_borders });
+  if matches!(exterior, Exterior::Binary | Exterior::BinaryLevels) && (formula.is_some() || perturbation || series || matches!(precision, Precision::Bits(_) | Precision::Fixed128)) {
+    return Err("--exterior binary needs the z each orbit escapes with, so it cannot be combined with --formula, --perturbation, --series, --precision BITS or fixed128".to_string());
+  }
 
-  {
-    let bands: Vec<&mut [u8]> = pixels.chunks_mut(rows_per_band * bounds.0).collect();
-    
-    crossbeam::scope(|spawner| {
-      for (i, band) in bands.into_iter().enumerate() {
-        let top = rows_per_band * i;
-        let height = band.len() / bounds.0;
-        let band_bounds = (bounds.0, height);
-        let band_upper_left = pixel_to_point(bounds, (0, top), upper_left, lower_right);
-        let band_lower_right = pixel_to_point(bounds, (bounds.0, top + height), upper_left, lower_right);
+  if formula.is_some() && (perturbation || series || precision != Precision::Double) {
+    return Err("--formula iterates in double precision, so it cannot be combined with --perturbation, --series or --precision".to_string());
+  }
 
-        spawner.spawn(move |_| {
-          render(band, band_bounds, band_upper_left, band_lower_right);
-        });
-      }
-    }).unwrap();
+  if positional.len() != 4 {
+    return Err(format!("expected FILE PIXELS UPPERLEFT LOWERRIGHT, got {} positional argument(s)", positional.len()));
+  }
+  if let Some((upper_left, lower_right)) = normalize_corners(&positional[2], &positional[3]) {
+    log::debug(&format!("corners {} and {} aren't upper left and lower right; rendering from {} to {}", positional[2], positional[3], upper_left, lower_right));
+    (positional[2], positional[3]) = (upper_left, lower_right);
+  }
+  // Views too deep for f64 that leave the arithmetic open get the tuned deep backend, if
+  // it can render them as asked.
+  let deep = precision == Precision::Double && !(perturbation || series || formula.is_some()) && interior == Interior::Black && exterior == Exterior::Escape && !banded;
+  if let Some(backend) = tune::current().deep.filter(|_| deep && check_view(&positional[1], &positional[2], &positional[3], precision, false).is_err()) {
+    let fixed = backend == tune::Deep::Fixed128 && rotation == 0.0 && check_view(&positional[1], &positional[2], &positional[3], Precision::Fixed128, false).is_ok();
+    if fixed {
+      precision = Precision::Fixed128;
+      log::info("too deep for double precision; using --precision fixed128, as tuned");
+    } else if bailout_radius.is_none() && bailout_norm.is_none() {
+      perturbation = true;
+      log::info("too deep for double precision; using --perturbation, as tuned");
+    }
   }
+  let perturbing = perturbation || series || matches!(precision, Precision::Bits(_));
+  if let Some(warning) = check_view(&positional[1], &positional[2], &positional[3], precision, perturbing)? {
+    log::warn(&warning);
+  }
+
+  let font_scale = font_scale.unwrap_or_else(|| overlay::default_scale(parse_pair(&positional[1], 'x').unwrap_or((1, 1))));
+  Ok(Arguments {
+    file: positional[0].clone(),
+    pixels: positional[1].clone(),
+    upper_left: positional[2].clone(),
+    lower_right: positional[3].clone(),
+    antialias,
+    supersample,
+    samples,
+    seed: seed.unwrap_or(0),
+    interior,
+    exterior,
+    bands,
+    bailout_radius,
+    bailout_norm,
+    progressive,
+    progress_image,
+    progress_bar,
+    perturbation,
+    series,
+    precision,
+    formula,
+    strip_rows,
+    buffer,
+    threads,
+    max_iter: max_iter.unwrap_or(MaxIter::Fixed(DEFAULT_MAX_ITER)),
+    rotation,
+    cache,
+    checkpoint,
+    resume: false,
+    workers,
+    stats,
+    stats_json,
+    histogram,
+    edges,
+    edge_threshold,
+    existing,
+    print_hash,
+    expect_hash,
+    save_location,
+    colors,
+    overlays,
+    overlay_style: overlay::Style { scale: font_scale, corner: overlay_corner.unwrap_or(overlay::Corner::BottomRight) },
+    legend,
+    caption,
+    caption_style: overlay::Style { scale: font_scale, corner: caption_corner.unwrap_or(overlay::Corner::BottomLeft) },
+    title,
+    author,
+    preview,
+    pin_threads,
+    avoid_smt,
+    nice,
+    spare_cores,
+    cpu_share,
+    command_line,
+  })
+}
+
+// The upper left and lower right corners of the rectangle with corners `a` and `b`,
+// which may be any two opposite corners in either order, or None if they already are
+// upper left and lower right. The digits given are kept as written, so corners precise
+// beyond f64 survive; coordinates f64 can't tell apart are left where they are.
+fn normalize_corners(a: &str, b: &str) -> Option<(String, String)> {
+  let (a_re, a_im) = a.split_once(',').map(|(re, im)| (re.trim(), im.trim()))?;
+  let (b_re, b_im) = b.split_once(',').map(|(re, im)| (re.trim(), im.trim()))?;
+  let value = |text: &str| f64::from_str(text).ok();
+  let (swap_re, swap_im) = (value(a_re)? > value(b_re)?, value(a_im)? < value(b_im)?);
+  if !swap_re && !swap_im {
+    return None;
+  }
+  let (left, right) = if swap_re { (b_re, a_re) } else { (a_re, b_re) };
+  let (top, bottom) = if swap_im { (b_im, a_im) } else { (a_im, b_im) };
+  Some((format!("{},{}", left, top), format!("{},{}", right, bottom)))
+}
+
+// Rejects image sizes and regions that can't be rendered, before any work starts:
+// empty or oversized images, corners that aren't finite or are absurdly far out, and
+// regions with no area or with pixels closer together than the arithmetic direct
+// rendering uses can tell apart. Returns a warning for regions only just within reach
+// of that arithmetic, whose pixels will come out visibly rounded.
+fn check_view(pixels: &str, upper_left_text: &str, lower_right_text: &str, precision: Precision, perturbation: bool) -> Result<Option<String>, String> {
+  let bounds: (usize, usize) = parse_pair(pixels, 'x').ok_or(format!("error parsing image dimensions '{}': expected WIDTHxHEIGHT in whole pixels", pixels))?;
+  if bounds.0 == 0 || bounds.1 == 0 {
+    return Err(format!("image dimensions must be at least 1x1, got {}", pixels));
+  }
+  if bounds.0 > u32::MAX as usize || bounds.1 > u32::MAX as usize {
+    return Err(format!("PNG images are at most {} pixels on a side, got {}", u32::MAX, pixels));
+  }
+  if bounds.0.checked_mul(bounds.1).is_none() {
+    return Err(format!("a {} image has more pixels than fit in memory", pixels));
+  }
+
+  let corner = |which: &str, text: &str| match parse_complex(text) {
+    None => Err(format!("error parsing {} corner '{}'", which, text)),
+    Some(c) if !c.re.is_finite() || !c.im.is_finite() => Err(format!("the {} corner '{}' is not a finite number", which, text)),
+    Some(c) if c.re.abs() > MAX_COORDINATE || c.im.abs() > MAX_COORDINATE => {
+      Err(format!("the {} corner '{}' is more than {:e} from the origin; the set lies within 2 of it", which, text, MAX_COORDINATE))
+    }
+    Some(c) => Ok(c),
+  };
+  let (upper_left, lower_right) = (corner("upper left", upper_left_text)?, corner("lower right", lower_right_text)?);
+  let mut extent = (lower_right.re - upper_left.re, upper_left.im - lower_right.im);
+  if perturbation || precision == Precision::Fixed128 {
+    // Deep views can be narrower than f64 can tell the corners apart by, so measure them
+    // with the digits the reference orbit or the fixed-point corners will get.
+    let bits = if let Precision::Bits(bits) = precision { bits } else { 128 };
+    let (Some(a), Some(b)) = (parse_big_complex(upper_left_text, bits), parse_big_complex(lower_right_text, bits)) else {
+      return Err(format!("error parsing the corners '{}' and '{}'", upper_left_text, lower_right_text));
+    };
+    extent = ((b.0 - a.0).to_f64(), (a.1 - b.1).to_f64());
+  }
+  if extent.0 == 0.0 || extent.1 == 0.0 {
+    return Err(format!("the region from {} to {} has no {}", upper_left_text, lower_right_text, if extent.0 == 0.0 { "width" } else { "height" }));
+  }
+
+  let pitch = (extent.0 / bounds.0 as f64, extent.1 / bounds.1 as f64);
+  let distinct = match precision {
+    Precision::Single => (upper_left.re as f32 + pitch.0 as f32 != upper_left.re as f32) && (upper_left.im as f32 - pitch.1 as f32 != upper_left.im as f32),
+    Precision::Fixed128 => pitch.0 >= fixed::RESOLUTION && pitch.1 >= fixed::RESOLUTION,
+    _ => (upper_left.re + pitch.0 != upper_left.re) && (upper_left.im - pitch.1 != upper_left.im),
+  };
+  let name = match precision {
+    Precision::Single => "single",
+    Precision::Fixed128 => "fixed128",
+    _ => "double",
+  };
+  if perturbation {
+    return Ok(None);
+  }
+  if !distinct {
+    return Err(format!("at {} the region's pixels are closer together than {} precision can tell apart; try --perturbation", pixels, name));
+  }
+
+  // How many representable values one pixel spans along each axis, near the corners.
+  // Fixed-point values are evenly spaced wherever they are.
+  let steps = |pitch: f64, a: f64, b: f64| match precision {
+    Precision::Single => pitch / (a.abs().max(b.abs()) * f32::EPSILON as f64),
+    Precision::Fixed128 => pitch / fixed::RESOLUTION,
+    _ => pitch / (a.abs().max(b.abs()) * f64::EPSILON),
+  };
+  let steps = steps(pitch.0, upper_left.re, lower_right.re).min(steps(pitch.1, upper_left.im, lower_right.im));
+  Ok((steps < MUSHY_STEPS).then(|| {
+    format!("at {} each pixel spans only about {:.0} distinct {} precision values, so the image will look rounded; --perturbation avoids this",
+            pixels, steps, name)
+  }))
+}
 
-  write_image(&args.file, &pixels, bounds).expect("error writing PNG file");
+fn print_usage(program: &str) {
+  eprintln!("Usage: {} [OPTIONS] FILE PIXELS UPPERLEFT LOWERRIGHT", program);
+  eprintln!("       {} render [OPTIONS] FILE PIXELS UPPERLEFT LOWERRIGHT", program);
+  eprintln!("       {} render [OPTIONS] --stdin", program);
+  eprintln!("       {} batch JOBS|- [OPTIONS]", program);
+  eprintln!("       {} rerender OLD.png [--size WxH] [--output FILE] [OPTIONS]", program);
+  eprintln!("       {} crop --from OLD.png --rect X,Y,W,H [--size WxH] [--output FILE] [OPTIONS]", program);
+  eprintln!("       {} stitch TILE.png... --out FILE.png [--force]", program);
+  eprintln!("       {} split JOB --shards N [--out DIR] [--force]", program);
+  eprintln!("       {} diff A.png B.png [--out HEAT.png] [--tolerance N]", program);
+  eprintln!("       {} montage JOBS --out SHEET.png [--grid CxR] [--cell WxH] [--force] [OPTIONS]", program);
+  eprintln!("       {} wallpaper --monitors WxH+X+Y,... [--center RE,IM] [--width W] [--rotate DEGREES]", program);
+  eprintln!("            [--output DIR] [--set COMMAND] [OPTIONS]");
+  eprintln!("       {} iim FILE PIXELS UPPERLEFT LOWERRIGHT --c RE,IM [--points N] [--seed N] [--palette NAME] [--threads N] [--force]", program);
+  eprintln!("       {} schema", program);
+  eprintln!("       {} bench [--threads N]", program);
+  eprintln!("       {} tune [--threads N] [--out FILE]", program);
+  eprintln!("       {} orbit --point RE,IM [--iters N] [--csv FILE|-] [[OPTIONS] FILE PIXELS UPPERLEFT LOWERRIGHT]", program);
+  eprintln!("       {} explore [--count N] [--candidates N] [--region UPPERLEFT LOWERRIGHT] [--max-iter N] [--thumbnails DIR] [--threads N] [--seed N]", program);
+  eprintln!("       {} dive [--center RE,IM] [--width W] [--steps N] [--factor F] [--seconds S] [--output FILE]", program);
+  eprintln!("       {} inspect --point RE,IM [--max-iter N]", program);
+  eprintln!("       {} area [--samples N] [--region UPPERLEFT LOWERRIGHT] [--max-iter N] [--threads N] [--seed N]", program);
+  eprintln!("       {} worker --listen ADDRESS [--threads N] [--metrics ADDRESS]", program);
+  eprintln!("       {} worker --queue redis://[:PASSWORD@]HOST[:PORT][/DB] [--key NAME] [--threads N] [--metrics ADDRESS]", program);
+  eprintln!("       {} serve [--port N] [--bind HOST] [--threads N] [--jobs N] [--cache DIR]", program);
+  eprintln!("       {} serve-api [--port N] [--bind HOST] [--threads N] [--jobs N] [--dir DIR]", program);
+  eprintln!("       {} view [--threads N] [--max-iter N] [--history FILE] [--export-size WxH] [--export-max-iter N]", program);
+  eprintln!("            [--bookmark NAME | UPPERLEFT LOWERRIGHT]");
+  eprintln!("       {} bookmarks", program);
+  eprintln!("       {} animate --target RE,IM [--from-zoom Z] --to-zoom Z --frames N [--max-iter N|auto]", program);
+  eprintln!("       {} animate --spec FILE [--fps N]", program);
+  eprintln!("       {} animate --julia cardioid|FILE --frames N [--max-iter N]", program);
+  eprintln!("       {} animate --cycle --target RE,IM [--from-zoom Z] --frames N [--max-iter N|auto]", program);
+  eprintln!("            with either: [--rotate DEGREES] [--spin DEGREES] [--easing CURVE]");
+  eprintln!("            [--size WxH] [--palette NAME] [--threads N] [--output DIR] [--resume]");
+  eprintln!("Example: {} mandel.png 1000x750 -1.20,0.35 -1,0.20", program);
+  eprintln!();
+  eprintln!("Options:");
+  eprintln!("  --stdin                     read the render as a JSON job, as `schema` describes, from standard input");
+  eprintln!("  --antialias none|adaptive   re-sample high-contrast pixels on a 2x2 or 3x3 grid");
+  eprintln!("  --supersample K             render K times as wide and high and average the colors down, a strip at a time");
+  eprintln!("  --samples N                 average N samples per pixel at jittered spots");
+  eprintln!("  --seed S                    with --samples, seed the spots (default 0)");
+  eprintln!("  --bailout R                 radius orbits escape beyond (default 2)");
+  eprintln!("  --bailout-norm NORM         euclidean, manhattan or chebyshev distance from the origin (default euclidean)");
+  eprintln!("  --exterior escape|atom      shade points outside the set by escape time, or by atom domain: the iteration nearest 0");
+  eprintln!("  --exterior binary           shade them white or dark by the sign of Im(z) at escape; binary-levels over the escape shading");
+  eprintln!("  --coloring bands            posterize the smoothed escape count into contour bands");
+  eprintln!("  --band-width N              iterations each band spans (default {})", DEFAULT_BAND_WIDTH);
+  eprintln!("  --band-borders              outline each band in a contrasting shade");
+  eprintln!("  --interior black|period     shade points inside the set black, or by the period of their orbit's cycle");
+  eprintln!("  --progressive               render in coarse-to-fine passes, rewriting FILE after each");
+  eprintln!("  --progress-image PREVIEW    with --progressive, also write a small copy to PREVIEW after each pass");
+  eprintln!("  --perturbation              iterate offsets from a high-precision reference orbit (deep zooms)");
+  eprintln!("  --series                    skip initial iterations with a series approximation (implies --perturbation)");
+  eprintln!("  --precision single|double   arithmetic for direct rendering; single is a fast f32 preview");
+  eprintln!("  --precision BITS            arbitrary-precision reference orbit and corners (implies --perturbation)");
+  eprintln!("  --precision fixed128        direct rendering in 128-bit fixed point, to about 1e-25 wide");
+  eprintln!("  --formula FILE.wasm         iterate with the `iterate` export of a WebAssembly module, sandboxed");
+  eprintln!("  --strip-rows N              render and encode N rows at a time to bound memory use");
+  eprintln!("  --buffer memory|mmap        keep the image in RAM or in a memory-mapped temporary file");
+  eprintln!("  --threads N                 number of worker threads (default {})", DEFAULT_THREADS);
+  eprintln!("  --pin-threads               bind each render thread to its own CPU");
+  eprintln!("  --avoid-smt                 with --pin-threads, use one hardware thread per physical core");
+  eprintln!("  --nice                      render at the lowest priority, to keep the machine usable meanwhile");
+  eprintln!("  --spare-cores N             with --nice, start no more threads than leave N cores free");
+  eprintln!("  --cpu-share P               with --nice, have each thread rest enough to work only P% of the time");
+  eprintln!("  --max-iter N|auto           iteration limit, or one scaled to the zoom depth (default {})", DEFAULT_MAX_ITER);
+  eprintln!("  --rotate DEGREES            turn the view counterclockwise about its center");
+  eprintln!("  --bookmark NAME             render a view saved in the viewer; give only FILE and PIXELS");
+  eprintln!("  --location FILE             render a Kalles Fraktaler .kfr, Ultra Fractal .upr or Fractint .par location,");
+  eprintln!("                              picking the precision it needs; give only FILE and PIXELS");
+  eprintln!("  --entry NAME                with --location, render the entry NAME rather than the file's first");
+  eprintln!("  --map FILE.map              color the image with a Fractint color map (a .par's own map by default)");
+  eprintln!("  --overlay axes|grid,...     draw the axes and tick labels in complex coordinates, with grid lines for 'grid'");
+  eprintln!("  --overlay scalebar          draw a bar of round length in complex units, and the zoom, in a corner");
+  eprintln!("  --overlay-corner CORNER     top-left, top-right, bottom-left or bottom-right (default) for the scale bar");
+  eprintln!("  --font-scale N              draw overlay and caption text N pixels to a font pixel (default by image size)");
+  eprintln!("  --caption auto|FORMAT       write the center, zoom and max-iter, or FORMAT's {{center}}, {{zoom}}, {{width}},");
+  eprintln!("                              {{max_iter}} and {{size}}, in a corner of the image");
+  eprintln!("  --caption-corner CORNER     where the caption goes: bottom-left by default");
+  eprintln!("  --legend append|FILE.png    draw which color means which escape count below the image, or in FILE.png");
+  eprintln!("  --save-location FILE.upr    also save the view as an Ultra Fractal parameter set");
+  eprintln!("  --title TEXT, --author NAME record a title and author in FILE's metadata, beside the render's arguments");
+  eprintln!("  --cache DIR                 reuse rendered tiles stored in DIR, and store new ones there");
+  eprintln!("  --preview term|ascii        print a terminal-sized preview instead of writing FILE");
+  eprintln!("  --preview sixel|kitty       print the full-size image as terminal graphics instead");
+  eprintln!("  --verbose                   explain adjustments made to the request, and time each stage of the work");
+  eprintln!("  --log-format text|json      write diagnostics as text or as one JSON object per line (any command)");
+  eprintln!("  --output-format text|json   with json, end by printing a JSON report of the render or its error to standard output");
+  eprintln!("  --no-progress               don't draw a progress bar on standard error while rendering");
+  eprintln!("  --force                     overwrite FILE if it already exists");
+  eprintln!("  --auto-suffix               if FILE exists, write FILE-2, FILE-3, ... instead, whichever is free");
+  eprintln!("  --print-hash                print the SHA-256 of the raw pixels, before encoding, to standard output");
+  eprintln!("  --expect-hash HASH          fail unless the raw pixels have this SHA-256");
+  eprintln!("  --edges FILE.png            also write a black and white map of where the escape count jumps, for masks");
+  eprintln!("  --edge-threshold N          with --edges, iterations neighbors must differ by to make an edge (default {})", DEFAULT_EDGE_THRESHOLD);
+  eprintln!("  --histogram FILE.csv        write how many samples escaped at each iteration, and the interior, to FILE.csv");
+  eprintln!("  --stats                     print timing, per-thread busy time and iteration counts afterwards");
+  eprintln!("  --stats-json                also write FILE.stats.json: duration, backend, iteration counts and parameters");
+  eprintln!("  --workers HOST:PORT,...     farm rows out to `worker` processes, rendering locally any they drop");
+  eprintln!("  --checkpoint FILE           save finished rows to FILE as the render goes");
+  eprintln!("  --resume FILE               continue the interrupted render saved in checkpoint FILE");
 }
 
-fn parse_args() -> Arguments {
-  let args: Vec<String> = env::args().collect();
+fn usage_error(program: &str, message: &str) -> ! {
+  log::error(message);
+  report::failure("usage", message);
+  print_usage(program);
+  std::process::exit(1);
+}
 
-  if args.len() != 5 {
-    eprintln!("Usage: {} FILE PIXELS UPPERLEFT LOWERRIGHT", args[0]);
-    eprintln!("Example: {} mandel.png 1000x750 -1.20,0.35 -1,0.20", args[0]);
-    std::process::exit(1);
+// Writes a render's pixels, shaded by `limit`, to its FILE, in the colors of its --map
+// if it has one, under any --overlay and --caption and above any appended --legend, with
+// metadata saying how it was rendered.
+fn write_output(args: &Arguments, pixels: &[u32], limit: usize, bounds: (usize, usize)) -> Result<(), std::io::Error> {
+  let _span = log::span("encode", &[("file", &args.file)]);
+  let shades = shades(pixels, limit);
+  let mut image = match &args.colors {
+    Some(colors) => Cow::Owned(shades.iter().flat_map(|&shade| colors[shade as usize]).collect()),
+    None => Cow::Borrowed(&shades[..]),
+  };
+  if !args.overlays.is_empty() {
+    let corners = parse_complex(&args.upper_left).zip(parse_complex(&args.lower_right));
+    let (upper_left, lower_right) = corners.expect("corners checked by parse_arguments");
+    overlay::draw(&args.overlays, &mut overlay::Canvas::new(image.to_mut(), bounds), upper_left, lower_right, view_width(args), args.overlay_style);
   }
+  if let Some(format) = &args.caption {
+    let text = overlay::expand(format, &caption_values(args, bounds)).map_err(std::io::Error::other)?;
+    overlay::caption(&mut overlay::Canvas::new(image.to_mut(), bounds), &text, args.caption_style);
+  }
+  let mut height = bounds.1;
+  if args.legend == Some(Legend::Append) {
+    let (legend, legend_height) = overlay::legend(bounds.0, render_limit(args), args.colors.as_deref(), args.overlay_style.scale);
+    image.to_mut().extend(legend);
+    height += legend_height;
+  }
+
+  // Encoded as render_streamed does, so the same pixels make the same file either way.
+  let mut writer = stream_writer(args, bounds, height)?;
+  writer.write_all(&image)?;
+  Ok(writer.finish()?)
+}
 
-  Arguments {
-    file: args[1].clone(),
-    pixels: args[2].clone(),
-    upper_left: args[3].clone(),
-    lower_right: args[4].clone(),
+// What each field a --caption format can name stands for in the render `args`
+// describe.
+fn caption_values(args: &Arguments, bounds: (usize, usize)) -> Vec<(&'static str, String)> {
+  let width = view_width(args);
+  let center = location::center(&args.upper_left, &args.lower_right).map_or_else(|_| "?".to_string(), |(re, im)| format!("{},{}", re, im));
+  vec![
+    ("center", center),
+    ("zoom", overlay::format_zoom(4.0 / width)),
+    ("width", format!("{:e}", width)),
+    ("max_iter", render_limit(args).to_string()),
+    ("size", format!("{}x{}", bounds.0, bounds.1)),
+  ]
+}
+
+// What FILE's metadata records. FILE itself is left out: it's no help in rendering the
+// image again, and names change as images are copied about.
+fn provenance(args: &Arguments, bounds: (usize, usize)) -> Provenance<'_> {
+  let mut arguments = args.command_line.clone();
+  if let Some(at) = arguments.iter().position(|arg| *arg == args.file) {
+    arguments.remove(at);
+  }
+  Provenance {
+    title: args.title.as_deref(),
+    author: args.author.as_deref(),
+    size: bounds,
+    upper_left: &args.upper_left,
+    lower_right: &args.lower_right,
+    arguments,
   }
 }
 
 fn write_image(filename: &str, pixels: &[u8], bounds: (usize, usize)) -> Result<(), std::io::Error> {
+  let _span = log::span("encode", &[("file", &filename)]);
   let output = File::create(filename)?;
 
   //
@@ -87,55 +2343,259 @@ fn write_image(filename: &str, pixels: &[u8], bounds: (usize, usize)) -> Result<
   Ok(())
 }
 
-fn render(pixels: &mut [u8], bounds: (usize, usize), upper_left: Complex<f64>, lower_right: Complex<f64>) {
+// A PNG read back as 8-bit gray or RGB.
+struct Image {
+  pixels: Vec<u8>,
+  bounds: (usize, usize),
+  // 1 for gray, 3 for RGB.
+  channels: usize,
+}
+
+// The PNG at `path`, dropping any alpha.
+fn read_image(path: &str) -> Result<Image, String> {
+  let failed = |e: png::DecodingError| format!("error reading '{}': {}", path, e);
+  let mut decoder = png::Decoder::new(BufReader::new(File::open(path).map_err(|e| format!("error opening '{}': {}", path, e))?));
+  decoder.set_transformations(png::Transformations::normalize_to_color8());
+  let mut reader = decoder.read_info().map_err(failed)?;
+  let mut pixels = vec![0; reader.output_buffer_size()];
+  let frame = reader.next_frame(&mut pixels).map_err(failed)?;
+  pixels.truncate(frame.buffer_size());
+  let (channels, kept) = match frame.color_type {
+    png::ColorType::Grayscale => (1, 1),
+    png::ColorType::GrayscaleAlpha => (2, 1),
+    png::ColorType::Rgb => (3, 3),
+    png::ColorType::Rgba => (4, 3),
+    png::ColorType::Indexed => return Err(format!("'{}' has colors png couldn't expand", path)),
+  };
+  if kept < channels {
+    pixels = pixels.chunks(channels).flat_map(|pixel| pixel[..kept].to_vec()).collect();
+  }
+  Ok(Image { pixels, bounds: (frame.width as usize, frame.height as usize), channels: kept })
+}
+
+// Writes a copy of the image at most PROGRESS_WIDTH wide for watching a render's
+// progress. It goes to a temporary file first and is renamed into place, so a viewer
+// reloading `path` never catches it half written.
+fn write_progress_image(path: &str, pixels: &[u8], bounds: (usize, usize)) -> Result<(), std::io::Error> {
+  let (small, small_bounds) = shrink(pixels, bounds, PROGRESS_WIDTH);
+  let partial = format!("{}.partial", path);
+  write_image(&partial, &small, small_bounds)?;
+  std::fs::rename(&partial, path)
+}
+
+// Scales `pixels` down to at most `width` columns, keeping the aspect ratio, by taking
+// the nearest pixel. Images already that narrow are copied unchanged.
+fn shrink(pixels: &[u8], bounds: (usize, usize), width: usize) -> (Vec<u8>, (usize, usize)) {
+  if bounds.0 <= width {
+    return (pixels.to_vec(), bounds);
+  }
+  let small = (width, (bounds.1 * width / bounds.0).max(1));
+  let shrunk = (0..small.0 * small.1)
+    .map(|i| {
+      let (x, y) = (i % small.0 * bounds.0 / small.0, i / small.0 * bounds.1 / small.1);
+      pixels[y * bounds.0 + x]
+    })
+    .collect();
+  (shrunk, small)
+}
+
+// Writes gray images as such and others as RGB in the palette's colors.
+fn write_color_image(filename: &str, pixels: &[u8], bounds: (usize, usize), palette: Palette) -> Result<(), std::io::Error> {
+  if palette == Palette::Gray {
+    return write_image(filename, pixels, bounds);
+  }
+  let colors: Vec<u8> = pixels.iter().flat_map(|&shade| palette.color(shade)).collect();
+  write_rgb_image(filename, &colors, bounds)
+}
+
+fn write_rgb_image(filename: &str, colors: &[u8], bounds: (usize, usize)) -> Result<(), std::io::Error> {
+  let _span = log::span("encode", &[("file", &filename)]);
+  let output = BufWriter::new(File::create(filename)?);
+  PNGEncoder::new(output).encode(colors, bounds.0 as u32, bounds.1 as u32, ColorType::RGB(8))
+}
+
+// Renders one pass of progressive refinement. Every pixel whose image coordinates are
+// multiples of `step` is computed, except those a coarser pass already did, and its
+// value is filled over the step x step block to its lower right, so the buffer always
+// holds a complete, if blocky, image. `top` is the band's first row in the full image,
+// which keeps the sampling grid aligned across bands; `bounds` is the band's own size.
+fn render_pass(pixels: &mut [u32], bounds: (usize, usize), sampler: &dyn Sampler, top: usize, step: usize, first: bool) {
   assert!(pixels.len() == bounds.0 * bounds.1);
 
-  for row in 0..bounds.1 {
-    for column in 0..bounds.0 {
-      let point = pixel_to_point(bounds, (column, row), upper_left, lower_right);
-      pixels[row * bounds.0 + column] = match escape_time(point, 255) {
-        None => 0,
-        Some(count) => 255 - count as u8
+  let first_row = (step - top % step) % step;
+  for row in (first_row..bounds.1).step_by(step) {
+    for column in (0..bounds.0).step_by(step) {
+      if !first && (top + row).is_multiple_of(2 * step) && column.is_multiple_of(2 * step) {
+        continue;
+      }
+
+      let value = sampler.sample(column as f64, (top + row) as f64);
+      for y in row..(row + step).min(bounds.1) {
+        for x in column..(column + step).min(bounds.0) {
+          pixels[y * bounds.0 + x] = value;
+        }
+      }
+    }
+  }
+}
+
+// The pixel of a sample that escaped after `escape` iterations of `limit`, or the limit
+// if it never did. Every sample passes through here, so this is where it's counted.
+fn escape_count(escape: Option<usize>, limit: usize) -> u32 {
+  stats::record(escape, limit);
+  escape.unwrap_or(limit) as u32
+}
+
+// Interior points are black; escaping ones get lighter the sooner they escape, with
+// counts scaled so any iteration limit spans the full gray range. KEYED pixels are
+// shaded already.
+fn shade(pixel: u32, limit: usize) -> u8 {
+  match pixel & KEYED {
+    0 => 255 - (pixel as usize * 255 / limit) as u8,
+    _ => pixel as u8,
+  }
+}
+
+fn shades(pixels: &[u32], limit: usize) -> Vec<u8> {
+  pixels.iter().map(|&pixel| shade(pixel, limit)).collect()
+}
+
+// The pixel standing for several samples of one: their mean escape count, or if any of
+// them is KEYED, their mean shade.
+fn blend(samples: &[u32], limit: usize) -> u32 {
+  let n = samples.len() as u64;
+  if samples.iter().all(|&sample| sample & KEYED == 0) {
+    (samples.iter().map(|&sample| sample as u64).sum::<u64>() / n) as u32
+  } else {
+    KEYED | (samples.iter().map(|&sample| shade(sample, limit) as u64).sum::<u64>() / n) as u32
+  }
+}
+
+// White where a pixel's escape count differs from a neighbor's by more than `threshold`
+// iterations, or where the interior, whose pixels hold `limit`, meets the rest, and black
+// elsewhere.
+fn edge_map(pixels: &[u32], bounds: (usize, usize), threshold: usize, limit: usize) -> Vec<u8> {
+  let interior = |pixel: u32| pixel as usize == limit;
+  (0..pixels.len()).map(|i| {
+    let (column, row) = (i % bounds.0, i / bounds.0);
+    let neighbors = [(column > 0).then(|| i - 1), (column + 1 < bounds.0).then(|| i + 1), (row > 0).then(|| i - bounds.0), (row + 1 < bounds.1).then(|| i + bounds.0)];
+    let edge = neighbors.into_iter().flatten().map(|j| pixels[j]).any(|neighbor| {
+      interior(neighbor) != interior(pixels[i]) || neighbor.abs_diff(pixels[i]) as usize > threshold
+    });
+    if edge { 255 } else { 0 }
+  }).collect()
+}
+
+// Adaptive antialiasing: only pixels whose shade differs noticeably from a neighbor's
+// are re-sampled, so smooth regions cost nothing extra. Returns how many rows are done,
+// as render_parallel does.
+fn antialias(pixels: &mut [u32], bounds: (usize, usize), origin: usize, sampler: &dyn Sampler, threads: usize) -> Result<usize, String> {
+  let original = shades(pixels, sampler.limit());
+
+  for_each_chunk(pixels, bounds.0, 0, tune::current().chunk_rows, threads, |top, chunk| {
+    for (offset, value) in chunk.iter_mut().enumerate() {
+      let pixel = (offset % bounds.0, top + offset / bounds.0);
+      let contrast = neighbor_contrast(&original, bounds, pixel);
+      let grid = if contrast > AA_THRESHOLD.saturating_mul(4) {
+        3
+      } else if contrast > AA_THRESHOLD {
+        2
+      } else {
+        continue;
       };
+      *value = supersample(sampler, (pixel.0, origin + pixel.1), grid);
+    }
+    progress::advance((0, origin + top), (bounds.0, chunk.len() / bounds.0));
+  })
+}
+
+fn neighbor_contrast(pixels: &[u8], bounds: (usize, usize), pixel: (usize, usize)) -> u8 {
+  let (column, row) = pixel;
+  let value = pixels[row * bounds.0 + column];
+  let mut contrast = 0;
+
+  for (dx, dy) in [(-1, 0), (1, 0), (0, -1), (0, 1)] {
+    let (x, y) = (column as isize + dx, row as isize + dy);
+    if x < 0 || y < 0 || x >= bounds.0 as isize || y >= bounds.1 as isize {
+      continue;
+    }
+    let neighbor = pixels[y as usize * bounds.0 + x as usize];
+    contrast = contrast.max(value.abs_diff(neighbor));
+  }
+
+  contrast
+}
+
+// Blends a grid x grid set of samples spread evenly over the pixel, centered on the
+// point `render_pass` would have sampled.
+fn supersample(sampler: &dyn Sampler, pixel: (usize, usize), grid: usize) -> u32 {
+  let mut samples = Vec::with_capacity(grid * grid);
+
+  for sy in 0..grid {
+    for sx in 0..grid {
+      let offset = |s: usize| (s as f64 + 0.5) / grid as f64 - 0.5;
+      samples.push(sampler.sample(pixel.0 as f64 + offset(sx), pixel.1 as f64 + offset(sy)));
     }
   }
+
+  blend(&samples, sampler.limit())
+}
+
+// The corners of a view `width` wide about `center`, its height following from the
+// aspect ratio of `bounds`.
+fn centered_corners(center: Complex<f64>, width: f64, bounds: (usize, usize)) -> (Complex<f64>, Complex<f64>) {
+  let half = Complex { re: width / 2.0, im: width * bounds.1 as f64 / bounds.0 as f64 / 2.0 };
+  (Complex { re: center.re - half.re, im: center.im + half.im }, Complex { re: center.re + half.re, im: center.im - half.im })
 }
 
-fn pixel_to_point(bounds: (usize, usize), pixel: (usize, usize), upper_left: Complex<f64>, lower_right: Complex<f64>) -> Complex<f64> {
+fn pixel_to_point<T: Float>(bounds: (usize, usize), pixel: (T, T), upper_left: Complex<T>, lower_right: Complex<T>) -> Complex<T> {
 
   let (width, height) = (lower_right.re - upper_left.re, upper_left.im - lower_right.im);
 
   Complex {
-    re: upper_left.re + pixel.0 as f64 * width / bounds.0 as f64,
-    im: upper_left.im - pixel.1 as f64 * height / bounds.1 as f64
+    re: upper_left.re + pixel.0 * width / T::from(bounds.0).unwrap(),
+    im: upper_left.im - pixel.1 * height / T::from(bounds.1).unwrap()
   }
 }
 
-fn escape_time(c: Complex<f64>, limit: usize) -> Option<usize> {
-  let mut z = Complex{ re: 0.0, im: 0.0 };
+// Iterates z = z² + c on plain locals, reusing the squares from the bailout test in the
+// update. The imaginary part is a fused multiply-add when the target has one (build
+// with RUSTFLAGS="-C target-cpu=native"); otherwise a software fma would be far slower
+// than the separate multiply and add.
+fn escape_time<T: Float>(c: Complex<T>, limit: usize) -> Option<usize> {
+  let four = T::from(4.0).unwrap();
+  let (mut re, mut im) = (T::zero(), T::zero());
+  let (mut re2, mut im2) = (T::zero(), T::zero());
 
   for i in 0..limit {
-    if z.norm_sqr() > 4.0 {
+    if re2 + im2 > four {
       return Some(i);
     }
-    z = z * z + c;
+    im = if cfg!(target_feature = "fma") { (re + re).mul_add(im, c.im) } else { (re + re) * im + c.im };
+    re = re2 - im2 + c.re;
+    re2 = re * re;
+    im2 = im * im;
   }
 
   None
 }
 
 fn parse_complex(string: &str) -> Option<Complex<f64>> {
-  match parse_pair(string, ',') {
-    Some((re, im)) => Some(Complex { re, im }),
-    None => None
-  }
+  parse_pair(string, ',').map(|(re, im)| Complex { re, im })
 }
 
+fn parse_big_complex(string: &str, bits: usize) -> Option<(BigFloat, BigFloat)> {
+  let (re, im) = string.split_once(',')?;
+  Some((BigFloat::parse(re.trim(), bits).ok()?, BigFloat::parse(im.trim(), bits).ok()?))
+}
+
+// Parses two values around the first `separator`, ignoring whitespace around each, since
+// sizes and coordinates are often pasted in as "1000 x 750" or "-1.0, 0.25".
 fn parse_pair<T: FromStr>(string: &str, separator: char) -> Option<(T, T)> {
   match string.find(separator) {
     None => None,
     Some(index) => {
-      match (T::from_str(&string[..index]), T::from_str(&string[index + 1..])) {
+      match (T::from_str(string[..index].trim()), T::from_str(string[index + 1..].trim())) {
         (Ok(l), Ok(r)) => Some((l, r)),
         _ => None
       }
@@ -151,10 +2611,582 @@ fn test_parse_pair() {
   assert_eq!(parse_pair::<u32>("10,20", ','), Some((10, 20)));
   assert_eq!(parse_pair::<u32>("200x400", 'x'), Some((200, 400)));
   assert_eq!(parse_pair::<f64>("0.5x1.5", 'x'), Some((0.5, 1.5)));
+  assert_eq!(parse_pair::<u32>("1000 x 750", 'x'), Some((1000, 750)));
+  assert_eq!(parse_pair::<f64>(" -1.0,\t0.25 ", ','), Some((-1.0, 0.25)));
+  assert_eq!(parse_pair::<u32>("10 20", ' '), Some((10, 20)));
+  assert_eq!(parse_pair::<u32>("1 0,20", ','), None);
+}
+
+// Parses a command line written as string literals.
+#[cfg(test)]
+fn test_arguments(arguments: &[&str]) -> Result<Arguments, String> {
+  parse_arguments(&arguments.iter().map(|s| s.to_string()).collect::<Vec<_>>())
+}
+
+// A small xorshift generator, so the property tests below are reproducible.
+#[cfg(test)]
+fn test_random(seed: u64) -> impl FnMut() -> u64 {
+  let mut state = seed;
+  move || {
+    state ^= state << 13;
+    state ^= state >> 7;
+    state ^= state << 17;
+    state
+  }
+}
+
+#[test]
+fn test_parse_pair_properties() {
+  let mut pick = test_random(0x9e3779b97f4a7c15);
+  let mut space = || [" ", "", "\t", "  "][(pick() % 4) as usize];
+  let mut next = test_random(0x2545f4914f6cdd1d);
+
+  for _ in 0..2000 {
+    // Any finite f64 pair, written the way Display does, survives any padding.
+    let (re, im) = (f64::from_bits(next()), f64::from_bits(next()));
+    if !re.is_finite() || !im.is_finite() {
+      continue;
+    }
+    let text = format!("{}{}{},{}{}{}", space(), re, space(), space(), im, space());
+    assert_eq!(parse_pair::<f64>(&text, ','), Some((re, im)), "{:?}", text);
+    assert_eq!(parse_complex(&text), Some(Complex { re, im }), "{:?}", text);
+
+    let (width, height) = (next() as u32, next() as u32);
+    let text = format!("{}{}{}x{}{}", space(), width, space(), space(), height);
+    assert_eq!(parse_pair::<u32>(&text, 'x'), Some((width, height)), "{:?}", text);
+
+    // Whitespace inside a number is still an error.
+    let text = format!("{} {},{}", width / 10 + 1, width % 10, height);
+    assert_eq!(parse_pair::<u32>(&text, ','), None, "{:?}", text);
+  }
 }
 
 #[test]
 fn test_parse_complex() {
   assert_eq!(parse_complex("1.25,-0.0625"), Some(Complex { re: 1.25, im: -0.0625 }));
   assert_eq!(parse_complex(",-0.0625"), None);
+  assert_eq!(parse_complex("-1.0, 0.25"), Some(Complex { re: -1.0, im: 0.25 }));
+  assert_eq!(parse_big_complex(" -1.5 , 0.25", 64).map(|(re, im)| (re.to_f64(), im.to_f64())), Some((-1.5, 0.25)));
+}
+
+#[test]
+fn test_render_failures_name_their_cause() {
+  let missing = env::temp_dir().join(format!("mandel-missing-{}", std::process::id())).join("out.png");
+  let error = render(test_arguments(&[missing.to_str().unwrap(), "4x4", "-1,1", "1,-1"]).unwrap()).unwrap_err();
+  assert!(error.starts_with(&format!("error writing '{}': ", missing.display())), "{}", error);
+  assert_eq!(test_arguments(&["out.png", "4x4", "-1;1", "1,-1"]).err(), Some("error parsing upper left corner '-1;1'".to_string()));
+}
+
+#[test]
+fn test_existing_files_are_kept() {
+  let dir = env::temp_dir().join(format!("mandel-existing-{}", std::process::id()));
+  std::fs::create_dir_all(&dir).unwrap();
+  let file = dir.join("mandel.png");
+  let name = file.to_str().unwrap();
+  std::fs::write(&file, "keep me").unwrap();
+
+  let error = render(test_arguments(&[name, "4x4", "-1,1", "1,-1"]).unwrap()).unwrap_err();
+  assert!(error.contains("already exists"), "{}", error);
+  assert_eq!(std::fs::read(&file).unwrap(), b"keep me");
+
+  std::fs::write(dir.join("mandel-2.png"), "").unwrap();
+  assert_eq!(free_file_name(name), dir.join("mandel-3.png").to_str().unwrap());
+  render(test_arguments(&[name, "4x4", "-1,1", "1,-1", "--auto-suffix"]).unwrap()).unwrap();
+  assert!(std::fs::read(dir.join("mandel-3.png")).unwrap().starts_with(b"\x89PNG"));
+
+  render(test_arguments(&[name, "4x4", "-1,1", "1,-1", "--force"]).unwrap()).unwrap();
+  assert!(std::fs::read(&file).unwrap().starts_with(b"\x89PNG"));
+  std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn test_interrupted_renders_resume_to_the_same_image() {
+  let dir = env::temp_dir().join(format!("mandel-interrupted-{}", std::process::id()));
+  std::fs::create_dir_all(&dir).unwrap();
+  let (whole, cut) = (dir.join("whole.png"), dir.join("cut.png"));
+  render(test_arguments(&[whole.to_str().unwrap(), "40x100", "-1.2,0.35", "-1,0.2"]).unwrap()).unwrap();
+
+  // What the render loop saves when Ctrl-C stops it after 37 rows.
+  let args = test_arguments(&[cut.to_str().unwrap(), "40x100", "-1.2,0.35", "-1,0.2"]).unwrap();
+  let (sampler, mut pixels) = (build_sampler(&args, (40, 100)).unwrap(), vec![0; 40 * 100]);
+  render_parallel(&mut pixels[..40 * 37], (40, 37), sampler.as_ref(), 2, 0, 1, true).unwrap();
+  let message = checkpoint_interrupted(&args, &pixels, sampler.limit(), (40, 100), 37).unwrap();
+  let checkpoint = format!("{}.checkpoint", cut.display());
+  assert!(message.contains(&format!("--resume {}", checkpoint)), "{}", message);
+  assert!(cut.exists());
+
+  render(test_arguments(&["--resume", &checkpoint]).unwrap()).unwrap();
+  assert_eq!(std::fs::read(&cut).unwrap(), std::fs::read(&whole).unwrap());
+  assert!(!std::path::Path::new(&checkpoint).exists());
+  std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn test_unrecorded_options_are_taken_only_as_options() {
+  // As another option's value, --workers or --checkpoint is just text.
+  let args = test_arguments(&["t.png", "40x30", "-1,1", "1,-1", "--title", "--workers", "--author", "--checkpoint"]).unwrap();
+  assert_eq!((args.title.as_deref(), args.author.as_deref()), (Some("--workers"), Some("--checkpoint")));
+  assert!(args.workers.is_empty() && args.checkpoint.is_none());
+  assert_eq!(args.command_line, ["t.png", "40x30", "-1,1", "1,-1", "--title", "--workers", "--author", "--checkpoint"]);
+
+  // Given as options, they're left out of the recorded command line.
+  let args = test_arguments(&["t.png", "--checkpoint", "t.checkpoint", "40x30", "-1,1", "1,-1", "--title", "x"]).unwrap();
+  assert_eq!(args.checkpoint.as_deref(), Some("t.checkpoint"));
+  assert_eq!(args.command_line, ["t.png", "40x30", "-1,1", "1,-1", "--title", "x"]);
+  let args = test_arguments(&["t.png", "40x30", "--workers", "a:1,b:2", "-1,1", "1,-1"]).unwrap();
+  assert_eq!((args.workers.len(), args.command_line.len()), (2, 4));
+  assert!(test_arguments(&["--resume", "t.checkpoint", "t.png"]).is_err());
+}
+
+#[test]
+fn test_expected_hashes() {
+  let dir = env::temp_dir().join(format!("mandel-hash-{}", std::process::id()));
+  std::fs::create_dir_all(&dir).unwrap();
+  let file = dir.join("hashed.png");
+  let name = file.to_str().unwrap();
+
+  let plane = Plane::mandelbrot((30, 20), Complex { re: -1.2, im: 0.35 }, Complex { re: -1.0, im: 0.2 }, 255);
+  let mut pixels = vec![0; 30 * 20];
+  render_parallel(&mut pixels, (30, 20), &plane, 2, 0, 1, true).unwrap();
+  let mut sha = Sha256::new();
+  sha.update(&shades(&pixels, 255));
+  let hash = sha256::hex(&sha.finish());
+
+  // Whole images and strips hash the same pixels the same way.
+  render(test_arguments(&[name, "30x20", "-1.2,0.35", "-1,0.2", "--expect-hash", &hash.to_ascii_uppercase()]).unwrap()).unwrap();
+  render(test_arguments(&[name, "30x20", "-1.2,0.35", "-1,0.2", "--force", "--strip-rows", "7", "--expect-hash", &hash]).unwrap()).unwrap();
+  let error = render(test_arguments(&[name, "30x20", "-1.2,0.35", "-1,0.21", "--force", "--expect-hash", &hash]).unwrap()).unwrap_err();
+  assert!(error.ends_with(&format!("not the expected {}", hash)), "{}", error);
+
+  assert!(test_arguments(&[name, "30x20", "-1.2,0.35", "-1,0.2", "--expect-hash", "abc"]).is_err());
+  std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn test_location_files() {
+  let path = env::temp_dir().join(format!("mandel-location-{}.kfr", std::process::id()));
+  std::fs::write(&path, "Re: -0.74364388703715870475\r\nIm: 0.13182590420531197\r\nZoom: 4E20\r\nIterations: 3000\r\n").unwrap();
+
+  let args = test_arguments(&["deep.png", "40x20", "--location", path.to_str().unwrap()]).unwrap();
+  assert_eq!((args.upper_left.as_str(), args.lower_right.as_str()),
+             ("-0.74364388703715870476,0.131825904205311970005", "-0.74364388703715870474,0.131825904205311969995"));
+  assert_eq!((args.max_iter, args.perturbation, args.precision), (MaxIter::Fixed(3000), true, Precision::Double));
+  assert_eq!(args.command_line, ["deep.png", "40x20", "--max-iter", "3000", "--perturbation", &args.upper_left, &args.lower_right]);
+
+  // What the command line says wins over the file.
+  let args = test_arguments(&["deep.png", "40x20", "--location", path.to_str().unwrap(), "--max-iter", "100", "--precision", "192"]).unwrap();
+  assert_eq!((args.max_iter, args.perturbation, args.precision), (MaxIter::Fixed(100), false, Precision::Bits(192)));
+  assert!(test_arguments(&["deep.png", "40x20", "-1,1", "--location", path.to_str().unwrap()]).is_err());
+  std::fs::remove_file(&path).unwrap();
+
+  // A Fractint entry brings its color map along, which checkpoints and workers get too.
+  let dir = env::temp_dir().join(format!("mandel-par-{}", std::process::id()));
+  std::fs::create_dir_all(&dir).unwrap();
+  let (par, map) = (dir.join("classic.par"), dir.join("blues.map"));
+  std::fs::write(&par, "first { corners=-2/1/-1/1 }\nsecond { corners=0.25/0.35/-0.05/0.05 maxiter=500 map=blues.map }\n").unwrap();
+  std::fs::write(&map, "0 0 0\n0 0 255\n").unwrap();
+  let args = test_arguments(&["blue.png", "40x40", "--location", par.to_str().unwrap(), "--entry", "second"]).unwrap();
+  assert_eq!((args.upper_left.as_str(), args.lower_right.as_str(), args.max_iter), ("0.25,0.05", "0.35,-0.05", MaxIter::Fixed(500)));
+  assert_eq!(args.colors.as_ref().map(|colors| colors[1]), Some([0, 0, 255]));
+  assert_eq!(args.command_line, ["blue.png", "40x40", "--max-iter", "500", "--map", map.to_str().unwrap(), "0.25,0.05", "0.35,-0.05"]);
+  assert_eq!(test_arguments(&["blue.png", "40x40", "--entry", "second"]).err().unwrap(), "--entry picks an entry of the --location file, so it needs one");
+  std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn test_normalize_corners() {
+  assert_eq!(normalize_corners("-1.20,0.35", "-1,0.20"), None);
+  let upright = Some(("-1.20,0.35".to_string(), "-1,0.20".to_string()));
+  // Swapped, and the other diagonal both ways.
+  assert_eq!(normalize_corners("-1,0.20", "-1.20,0.35"), upright);
+  assert_eq!(normalize_corners("-1, 0.20", "-1.20 ,0.35"), upright);
+  assert_eq!(normalize_corners("-1.20,0.20", "-1,0.35"), upright);
+  assert_eq!(normalize_corners("-1,0.35", "-1.20,0.20"), upright);
+  // Digits beyond f64 come through untouched.
+  assert_eq!(normalize_corners("-0.74364388703715870475,0.1", "-0.74364388703715870476,0.2").unwrap().0, "-0.74364388703715870475,0.2");
+}
+
+#[test]
+fn test_check_view() {
+  let check = |pixels, upper_left, lower_right| check_view(pixels, upper_left, lower_right, Precision::Double, false);
+  assert_eq!(check("1000x750", "-1.20,0.35", "-1,0.20"), Ok(None));
+  assert_eq!(check("1000x0", "-1,1", "1,-1"), Err("image dimensions must be at least 1x1, got 1000x0".to_string()));
+  assert!(check("-5x10", "-1,1", "1,-1").unwrap_err().starts_with("error parsing image dimensions '-5x10'"));
+  assert!(check("4294967296x1", "-1,1", "1,-1").unwrap_err().starts_with("PNG images are at most"));
+  assert_eq!(check("10x10", "-1,1", "-1,-1"), Err("the region from -1,1 to -1,-1 has no width".to_string()));
+  assert_eq!(check("10x10", "-1,inf", "1,-1"), Err("the upper left corner '-1,inf' is not a finite number".to_string()));
+  assert_eq!(check("10x10", "-1,1", "nan,-1"), Err("the lower right corner 'nan,-1' is not a finite number".to_string()));
+  assert!(check("10x10", "-1e11,1", "1,-1").unwrap_err().contains("more than 1e10 from the origin"));
+
+  // Too deep for f32 or f64 alone, but fine with a reference orbit.
+  let deep = ("-0.7436438870371587,0.1318259042053119", "-0.7436438870371586,0.1318259042053118");
+  assert!(check("1000x1000", deep.0, deep.1).unwrap_err().ends_with("try --perturbation"));
+  assert_eq!(check_view("1000x1000", deep.0, deep.1, Precision::Double, true), Ok(None));
+  // Narrower than f64 can tell the corners apart by.
+  let deeper = ("-0.74364388703715870475,0.13182590420531197", "-0.74364388703715870470,0.13182590420531190");
+  assert_eq!(check_view("40x30", deeper.0, deeper.1, Precision::Bits(128), true), Ok(None));
+  assert!(check_view("40x30", deeper.0, "-0.74364388703715870475,0.13182590420531190", Precision::Bits(128), true).unwrap_err().ends_with("has no width"));
+  // A few ulps per pixel renders, but rounding shows.
+  let shallow = ("-0.743643887037,0.131825904205", "-0.743643887036,0.131825904204");
+  assert!(check("1000x1000", shallow.0, shallow.1).unwrap().unwrap().contains("about 6 distinct double precision values"));
+  assert_eq!(check("10x10", shallow.0, shallow.1).unwrap(), None);
+  assert!(check_view("1000x750", "-1.2000001,0.35", "-1.2,0.3499999", Precision::Single, false).is_err());
+}
+
+#[test]
+fn test_neighbor_contrast() {
+  let pixels = [10, 10, 10,
+                10, 10, 90,
+                10, 10, 10];
+  assert_eq!(neighbor_contrast(&pixels, (3, 3), (0, 0)), 0);
+  assert_eq!(neighbor_contrast(&pixels, (3, 3), (1, 1)), 80);
+  assert_eq!(neighbor_contrast(&pixels, (3, 3), (2, 0)), 80);
+}
+
+#[test]
+fn test_jittered_samples() {
+  // Escaping at once right of x = 3, where pixel 3's samples straddle the edge.
+  struct Edge;
+  impl Sampler for Edge {
+    fn sample(&self, x: f64, _y: f64) -> u32 {
+      if x >= 3.0 { 0 } else { 255 }
+    }
+
+    fn limit(&self) -> usize {
+      255
+    }
+  }
+  let jittered = Jittered { sampler: Box::new(Edge), samples: 16, seed: 5 };
+  // Each sample has a column of its own, so exactly half land to the right.
+  assert_eq!(jittered.sample(3.0, 7.0), 127);
+  assert_eq!((jittered.sample(1.0, 7.0), jittered.sample(5.0, 7.0)), (255, 0));
+
+  let args = test_arguments(&["j.png", "40x30", "-1.2,0.35", "-1,0.2", "--samples", "9", "--seed", "4"]).unwrap();
+  let sampler = build_sampler(&args, (40, 30)).unwrap();
+  let render = || (0..40 * 30).map(|i| sampler.sample((i % 40) as f64, (i / 40) as f64)).collect::<Vec<u32>>();
+  let first = render();
+  assert_eq!(render(), first);
+  let reseeded = test_arguments(&["j.png", "40x30", "-1.2,0.35", "-1,0.2", "--samples", "9", "--seed", "5"]).unwrap();
+  let other = build_sampler(&reseeded, (40, 30)).unwrap();
+  assert!((0..40 * 30).any(|i| other.sample((i % 40) as f64, (i / 40) as f64) != first[i]));
+  assert!(test_arguments(&["j.png", "40x30", "-1.2,0.35", "-1,0.2", "--seed", "4"]).is_err());
+}
+
+#[test]
+fn test_edge_map() {
+  // The interior's boundary is an edge however small the step; a large step is one too.
+  let pixels = [50,  50,  50,   50,
+                50, 100, 100,  100,
+                50, 100, 999, 1000];
+  assert_eq!(edge_map(&pixels, (4, 3), 10, 1000), [0, 255, 255, 255,
+                                                   255, 255, 255, 255,
+                                                   255, 255, 255, 255]);
+  assert_eq!(edge_map(&pixels, (4, 3), 60, 1000), [0, 0, 0, 0,
+                                                   0, 0, 255, 255,
+                                                   0, 255, 255, 255]);
+  assert_eq!(edge_map(&[7; 6], (3, 2), 0, 1000), [0; 6]);
+}
+
+#[test]
+fn test_edges_at_deep_limits() {
+  // With a limit well past 255 iterations the threshold still counts iterations: steps
+  // within it are not edges, though they would be if it were scaled down to shades.
+  let dir = env::temp_dir().join(format!("mandel-edges-{}", std::process::id()));
+  std::fs::create_dir_all(&dir).unwrap();
+  let (file, edges) = (dir.join("deep.png"), dir.join("edges.png"));
+  let bounds = (40, 30);
+  let plane = Plane::mandelbrot(bounds, Complex { re: -0.8, im: 0.2 }, Complex { re: -0.7, im: 0.125 }, 5000);
+  let mut counts = vec![0; bounds.0 * bounds.1];
+  render_parallel(&mut counts, bounds, &plane, 2, 0, 1, true).unwrap();
+  let expected = edge_map(&counts, bounds, 10, 5000);
+  assert_ne!(expected, edge_map(&counts, bounds, 0, 5000));
+
+  render(test_arguments(&[file.to_str().unwrap(), "40x30", "-0.8,0.2", "-0.7,0.125", "--max-iter", "5000", "--edges", edges.to_str().unwrap(),
+                          "--edge-threshold", "10"]).unwrap()).unwrap();
+  assert_eq!(read_image(edges.to_str().unwrap()).unwrap().pixels, expected);
+  std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn test_supersample_uniform_region() {
+  // Deep inside the main cardioid every sample is interior.
+  let upper_left = Complex { re: -0.3, im: 0.1 };
+  let lower_right = Complex { re: -0.1, im: -0.1 };
+  let plane = Plane::mandelbrot((10, 10), upper_left, lower_right, 255);
+  assert_eq!(supersample(&plane, (5, 5), 3), 255);
+}
+
+#[test]
+fn test_shrink_takes_nearest_pixels() {
+  let pixels: Vec<u8> = (0..24).collect();
+  assert_eq!(shrink(&pixels, (6, 4), 3), (vec![0, 2, 4, 12, 14, 16], (3, 2)));
+  assert_eq!(shrink(&pixels, (6, 4), 8), (pixels.clone(), (6, 4)));
+}
+
+#[test]
+fn test_progressive_passes_match_full_render() {
+  let bounds = (37, 23);
+  let upper_left = Complex { re: -1.20, im: 0.35 };
+  let lower_right = Complex { re: -1.0, im: 0.20 };
+
+  let plane = Plane::mandelbrot(bounds, upper_left, lower_right, 255);
+
+  let mut full = vec![0; bounds.0 * bounds.1];
+  render_pass(&mut full, bounds, &plane, 0, 1, true);
+
+  let mut progressive = vec![0; bounds.0 * bounds.1];
+  for (pass, &step) in PASSES.iter().enumerate() {
+    render_parallel(&mut progressive, bounds, &plane, 3, 0, step, pass == 0).unwrap();
+  }
+
+  assert!(full == progressive);
+}
+
+#[test]
+fn test_chunks_are_aligned_and_cover_every_row() {
+  let mut pixels = vec![0u8; 2 * 11];
+  let seen = std::sync::Mutex::new(Vec::new());
+  for_each_chunk(&mut pixels, 2, 3, 4, 2, |top, chunk| {
+    chunk.fill(top as u8);
+    seen.lock().unwrap().push((top, chunk.len() / 2));
+  }).unwrap();
+
+  let mut seen = seen.into_inner().unwrap();
+  seen.sort();
+  // Row 3 of the image ends the chunk starting at row 0, so it stands alone.
+  assert_eq!(seen, [(3, 1), (4, 4), (8, 4), (12, 2)]);
+  assert_eq!(pixels[..4], [3, 3, 4, 4]);
+}
+
+#[test]
+fn test_finished_chunks_arrive_in_order() {
+  let mut pixels = vec![0u8; 3 * 40];
+  let mut done = Vec::new();
+  let rows = for_each_chunk_in_order(&mut pixels, 3, 0, 4, 4, |top, chunk| {
+    // Later chunks finish first.
+    std::thread::sleep(Duration::from_millis(40 - top as u64));
+    chunk.fill(top as u8);
+  }, |top, chunk| {
+    done.push((top, chunk.to_vec()));
+    Ok(())
+  }).unwrap();
+  assert_eq!(rows, 40);
+  assert_eq!(done.iter().map(|(top, _)| *top).collect::<Vec<_>>(), (0..40).step_by(4).collect::<Vec<_>>());
+  assert!(done.iter().all(|(top, chunk)| chunk.len() == 12 && chunk.iter().all(|&value| value as usize == *top)));
+
+  let mut calls = 0;
+  let failed = for_each_chunk_in_order(&mut pixels, 3, 0, 4, 1, |_, _| {}, |_, _| {
+    calls += 1;
+    Err("disk full".to_string())
+  });
+  assert_eq!((failed, calls), (Err("disk full".to_string()), 1));
+}
+
+#[test]
+fn test_single_precision_matches_double_at_low_zoom() {
+  let bounds = (64, 48);
+  let double = Plane::mandelbrot(bounds, Complex { re: -2.0, im: 1.2 }, Complex { re: 1.0, im: -1.2 }, 255);
+  let single = Plane::mandelbrot(bounds, Complex { re: -2.0f32, im: 1.2 }, Complex { re: 1.0f32, im: -1.2 }, 255);
+
+  let mut mismatches = 0;
+  for y in 0..bounds.1 {
+    for x in 0..bounds.0 {
+      if double.sample(x as f64, y as f64) != single.sample(x as f64, y as f64) {
+        mismatches += 1;
+      }
+    }
+  }
+  assert!(mismatches * 50 < bounds.0 * bounds.1, "{} mismatching pixels", mismatches);
+}
+
+#[test]
+fn test_half_turn_mirrors_the_view() {
+  let bounds = (40, 30);
+  let plane = Plane::mandelbrot(bounds, Complex { re: -1.20, im: 0.35 }, Complex { re: -1.0, im: 0.20 }, 255);
+  let turned = Plane { turn: turn(180.0), ..plane };
+  assert!(plane.tile_key(0, 0).is_some() && turned.tile_key(0, 0).is_none());
+
+  // Rounding in the turn may move a boundary point or two across.
+  let mut mismatches = 0;
+  for y in 0..bounds.1 {
+    for x in 0..bounds.0 {
+      if turned.sample(x as f64, y as f64) != plane.sample((bounds.0 - x) as f64, (bounds.1 - y) as f64) {
+        mismatches += 1;
+      }
+    }
+  }
+  assert!(mismatches * 100 < bounds.0 * bounds.1, "{} mismatching pixels", mismatches);
+}
+
+#[test]
+fn test_strips_match_full_render() {
+  let bounds = (50, 37);
+  let plane = Plane::mandelbrot(bounds, Complex { re: -1.20, im: 0.35 }, Complex { re: -1.0, im: 0.20 }, 255);
+
+  let mut full = vec![0; bounds.0 * bounds.1];
+  render_parallel(&mut full, bounds, &plane, 3, 0, 1, true).unwrap();
+  antialias(&mut full, bounds, 0, &plane, 3).unwrap();
+
+  let path = env::temp_dir().join(format!("mandel-strips-{}.png", std::process::id()));
+  let args = test_arguments(&[path.to_str().unwrap(), "50x37", "-1.20,0.35", "-1,0.20", "--antialias", "adaptive", "--threads", "3", "--title", "strips"]).unwrap();
+  write_strips(&args, bounds, &plane, 8).unwrap();
+
+  let decoder = png::Decoder::new(File::open(&path).unwrap());
+  let mut reader = decoder.read_info().unwrap();
+  assert!(reader.info().uncompressed_latin1_text.iter().any(|chunk| chunk.keyword == "Title" && chunk.text == "strips"));
+  let mut strips = vec![0; reader.output_buffer_size()];
+  reader.next_frame(&mut strips).unwrap();
+  std::fs::remove_file(&path).unwrap();
+
+  assert!(shades(&full, 255) == strips);
+}
+
+#[test]
+fn test_supersampling_averages_colors_of_a_larger_render() {
+  let samples = [0, 255, 10, 20, 255, 255, 30, 41];
+  assert_eq!(downsample(&samples, 4, 2, None), [191, 25]);
+  let mut colors = vec![[0, 0, 0]; 256];
+  colors[255] = [255, 0, 4];
+  colors[10] = [0, 100, 0];
+  assert_eq!(downsample(&samples, 4, 2, Some(&colors))[..3], [191, 0, 3]);
+
+  let large = (60, 45);
+  let plane = Plane::mandelbrot(large, Complex { re: -1.20, im: 0.35 }, Complex { re: -1.0, im: 0.20 }, 255);
+  let mut full = vec![0; large.0 * large.1];
+  render_parallel(&mut full, large, &plane, 3, 0, 1, true).unwrap();
+
+  let dir = tempfile::tempdir().unwrap();
+  let path = dir.path().join("supersampled.png");
+  let args = test_arguments(&[path.to_str().unwrap(), "20x15", "-1.20,0.35", "-1,0.20", "--supersample", "3", "--strip-rows", "4", "--no-progress"]).unwrap();
+  render(args).unwrap();
+  let mut reader = png::Decoder::new(File::open(&path).unwrap()).read_info().unwrap();
+  assert_eq!((reader.info().width, reader.info().height), (20, 15));
+  let mut pixels = vec![0; reader.output_buffer_size()];
+  reader.next_frame(&mut pixels).unwrap();
+  assert!(pixels == downsample(&shades(&full, 255), large.0, 3, None));
+
+  assert!(test_arguments(&["s.png", "20x15", "-1,1", "1,-1", "--supersample", "2", "--antialias", "adaptive"]).is_err());
+}
+
+#[test]
+fn test_rerender_reads_the_recorded_view() {
+  let dir = tempfile::tempdir().unwrap();
+  let old = dir.path().join("old.png");
+  let old = old.to_str().unwrap();
+  let args = test_arguments(&[old, "--max-iter", "100", "40x30", "-1.20,0.35", "-1,0.20", "--title", "two words", "--no-progress"]).unwrap();
+  render(args).unwrap();
+
+  let options = ["--size", "80x60", "--max-iter", "300"].map(String::from);
+  let args = parse_arguments(&rerender_arguments(old, &options).unwrap()).unwrap();
+  assert_eq!(args.file, dir.path().join("old-80x60.png").to_str().unwrap());
+  assert_eq!((args.pixels.as_str(), args.upper_left.as_str(), args.lower_right.as_str()), ("80x60", "-1.20,0.35", "-1,0.20"));
+  assert_eq!((args.max_iter, args.title.as_deref()), (MaxIter::Fixed(300), Some("two words")));
+
+  let options = ["--output", "new.png"].map(String::from);
+  assert_eq!(rerender_arguments(old, &options).unwrap()[..2], ["new.png", "--max-iter"]);
+  let plain = dir.path().join("plain.png");
+  write_image(plain.to_str().unwrap(), &[0; 4], (2, 2)).unwrap();
+  assert!(rerender_arguments(plain.to_str().unwrap(), &[]).unwrap_err().contains("doesn't record the arguments"));
+}
+
+#[test]
+fn test_auto_max_iter_grows_with_zoom() {
+  assert_eq!(auto_max_iter(4.0), 255);
+  assert_eq!(auto_max_iter(8.0), 255);
+  assert_eq!(auto_max_iter(4e-6), 255 * 7);
+  assert!(auto_max_iter(4e-30) > auto_max_iter(4e-14));
+  assert!(auto_max_iter(0.0) > 0);
+}
+
+#[test]
+fn test_shade_spans_gray_range() {
+  assert_eq!(escape_count(None, 1000), 1000);
+  assert_eq!(escape_count(Some(17), 1000), 17);
+  assert_eq!(shade(1000, 1000), 0);
+  assert_eq!(shade(0, 1000), 255);
+  assert_eq!(shade(500, 1000), 128);
+  assert_eq!(shade(17, 255), 255 - 17);
+  assert_eq!(shade(KEYED | 7, 1000), 7);
+
+  // Counts blend as counts, and only keyed pixels as shades.
+  assert_eq!(blend(&[0, 255, 255, 255], 255), 191);
+  assert_eq!(blend(&[KEYED | 200, 1000], 1000), KEYED | 100);
+}
+
+#[test]
+fn test_binary_decomposition() {
+  let bounds = (40, 30);
+  let render = |exterior: &str| {
+    let args = test_arguments(&["b.png", "40x30", "-2,1.5", "1,-1.5", "--max-iter", "50", "--exterior", exterior]).unwrap();
+    let mut pixels = vec![0; bounds.0 * bounds.1];
+    render_parallel(&mut pixels, bounds, build_sampler(&args, bounds).unwrap().as_ref(), 2, 0, 1, true).unwrap();
+    shades(&pixels, 50)
+  };
+  let (escape, binary, levels) = (render("escape"), render("binary"), render("binary-levels"));
+  assert!(binary.iter().all(|&shade| [0, 1, 255].contains(&shade)) && binary.contains(&1) && binary.contains(&255));
+  for (i, ((&escape, &binary), &level)) in escape.iter().zip(&binary).zip(&levels).enumerate() {
+    assert_eq!(escape == 0, binary == 0);
+    assert_eq!(level, match binary { 255 => escape, 1 => (escape / 2).max(1), _ => 0 }, "pixel {}", i);
+  }
+  // Pixels are sampled at their corners, so rows 5 and 25 mirror each other across the
+  // real axis, and the sign of every z flips.
+  let row = |y: usize| &binary[y * bounds.0..(y + 1) * bounds.0];
+  let flipped = row(5).iter().zip(row(bounds.1 - 5)).filter(|&(&a, &b)| a != 0 && a == b).count();
+  assert_eq!(flipped, 0);
+
+  assert!(test_arguments(&["b.png", "40x30", "-2,1.5", "1,-1.5", "--exterior", "binary", "--perturbation"]).is_err_and(|message| message.starts_with("--exterior binary needs")));
+}
+
+#[test]
+fn test_contour_bands() {
+  // At the bailout radius the smoothed count is one more than the escape count, and at
+  // its square the same.
+  let radius: f64 = 2.0;
+  assert_eq!(smooth_escape(7, Complex { re: 0.0, im: radius }, radius), 8.0);
+  assert!((smooth_escape(7, Complex { re: -radius * radius, im: 0.0 }, radius) - 7.0).abs() < 1e-12);
+  assert_eq!(band_pixel(0.0, 100, false), 0);
+  assert_eq!(band_pixel(50.5, 100, false), 50);
+  assert_eq!((band_pixel(0.0, 100, true), band_pixel(60.0, 100, true)), (KEYED | 1, KEYED | 255));
+  assert_eq!(band_pixel(500.0, 100, false), 99);
+
+  let bounds = (40, 30);
+  let render = |options: &[&str]| {
+    let args = test_arguments(&[&["b.png", "40x30", "-2,1.5", "1,-1.5", "--max-iter", "60"], options].concat()).unwrap();
+    let mut pixels = vec![0; bounds.0 * bounds.1];
+    render_parallel(&mut pixels, bounds, build_sampler(&args, bounds).unwrap().as_ref(), 2, 0, 1, true).unwrap();
+    shades(&pixels, 60)
+  };
+  let (escape, bands, borders) = (render(&[]), render(&["--coloring", "bands", "--band-width", "3"]), render(&["--coloring", "bands", "--band-width", "3", "--band-borders"]));
+  let shades = |pixels: &[u8]| pixels.iter().collect::<std::collections::BTreeSet<_>>().len();
+  assert!(shades(&bands) < shades(&escape) && shades(&bands) > 3);
+  assert!(escape.iter().zip(&bands).all(|(&a, &b)| (a == 0) == (b == 0)));
+  // Borders only replace pixels at the edge of a band, and some are.
+  let changed = bands.iter().zip(&borders).filter(|(a, b)| a != b).count();
+  assert!(changed > 0 && changed < bands.iter().filter(|&&shade| shade != 0).count());
+
+  let arguments = |options: &[&str]| test_arguments(&[&["b.png", "40x30", "-2,1.5", "1,-1.5"], options].concat());
+  assert_eq!(arguments(&["--band-width", "3"]).err().as_deref(), Some("--band-width only applies with --coloring bands"));
+  assert!(arguments(&["--coloring", "bands", "--exterior", "atom"]).is_err());
+  assert!(arguments(&["--coloring", "bands", "--perturbation"]).is_err());
+}
+
+#[test]
+fn test_cached_render_matches_direct_render() {
+  let bounds = (300, 270);
+  let plane = Plane::mandelbrot(bounds, Complex { re: -1.20, im: 0.35 }, Complex { re: -1.0, im: 0.20 }, 255);
+  let dir = env::temp_dir().join(format!("mandel-render-cache-{}", std::process::id()));
+  let cache = TileCache::open(dir.to_str().unwrap()).unwrap();
+
+  let mut direct = vec![0; bounds.0 * bounds.1];
+  render_parallel(&mut direct, bounds, &plane, 2, 0, 1, true).unwrap();
+
+  // Once to fill the cache, once to read it back.
+  for _ in 0..2 {
+    let mut cached = vec![0; bounds.0 * bounds.1];
+    render_cached(&mut cached, bounds, &plane, 3, &cache).unwrap();
+    assert!(direct == cached);
+  }
+  assert_eq!(std::fs::read_dir(&dir).unwrap().count(), 4);
+
+  std::fs::remove_dir_all(dir).unwrap();
 }
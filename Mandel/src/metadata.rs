@@ -0,0 +1,191 @@
+// Image metadata
+// Rendered PNGs say where they came from, so copies uploaded to galleries keep their
+// provenance. Plain text chunks carry the basics, which most viewers show:
+//
+//   Software      Mandel 0.1.0
+//   Title         --title, if given
+//   Author        --author, if given
+//   Description   the render's arguments but FILE, quoted as a shell would need them,
+//                 enough to render it again
+//
+// and an XMP packet (an iTXt chunk keyed XML:com.adobe.xmp) carries the same along with
+// the view's corners and size, for photo tools that read XMP rather than PNG text.
+// Text that isn't Latin-1 goes in iTXt chunks too, as tEXt can't hold it.
+// `mandel rerender` reads the Description back to render the same view again.
+
+use std::fs::File;
+use std::io::{BufReader, Write};
+
+pub const SOFTWARE: &str = concat!("Mandel ", env!("CARGO_PKG_VERSION"));
+
+// Namespace of the render parameters in the XMP packet.
+const NAMESPACE: &str = "urn:x-mandel:render:1";
+
+pub struct Provenance<'a> {
+  pub title: Option<&'a str>,
+  pub author: Option<&'a str>,
+  pub size: (usize, usize),
+  pub upper_left: &'a str,
+  pub lower_right: &'a str,
+  pub arguments: Vec<String>,
+}
+
+impl Provenance<'_> {
+  // The keyword and text of each chunk, and whether it needs iTXt.
+  fn chunks(&self) -> Vec<(&'static str, String, bool)> {
+    let description = command(&self.arguments);
+    let mut chunks = vec![("Software", SOFTWARE.to_string())];
+    chunks.extend(self.title.map(|title| ("Title", title.to_string())));
+    chunks.extend(self.author.map(|author| ("Author", author.to_string())));
+    chunks.push(("Description", description));
+    let mut chunks: Vec<_> = chunks.into_iter().map(|(keyword, text)| {
+      let latin1 = text.chars().all(|c| (c as u32) < 0x100);
+      (keyword, text, !latin1)
+    }).collect();
+    chunks.push(("XML:com.adobe.xmp", self.xmp(), true));
+    chunks
+  }
+
+  pub fn add_to<W: Write>(&self, encoder: &mut png::Encoder<W>) -> Result<(), png::EncodingError> {
+    for (keyword, text, utf8) in self.chunks() {
+      if utf8 {
+        encoder.add_itxt_chunk(keyword.to_string(), text)?;
+      } else {
+        encoder.add_text_chunk(keyword.to_string(), text)?;
+      }
+    }
+    Ok(())
+  }
+
+  fn xmp(&self) -> String {
+    let mut elements = String::new();
+    if let Some(title) = self.title {
+      elements += &format!("   <dc:title><rdf:Alt><rdf:li xml:lang=\"x-default\">{}</rdf:li></rdf:Alt></dc:title>\n", escape(title));
+    }
+    if let Some(author) = self.author {
+      elements += &format!("   <dc:creator><rdf:Seq><rdf:li>{}</rdf:li></rdf:Seq></dc:creator>\n", escape(author));
+    }
+    format!(concat!(
+      "<?xpacket begin=\"\u{feff}\" id=\"W5M0MpCehiHzreSzNTczkc9d\"?>\n",
+      "<x:xmpmeta xmlns:x=\"adobe:ns:meta/\">\n",
+      " <rdf:RDF xmlns:rdf=\"http://www.w3.org/1999/02/22-rdf-syntax-ns#\">\n",
+      "  <rdf:Description rdf:about=\"\"\n",
+      "    xmlns:dc=\"http://purl.org/dc/elements/1.1/\"\n",
+      "    xmlns:xmp=\"http://ns.adobe.com/xap/1.0/\"\n",
+      "    xmlns:mandel=\"{}\"\n",
+      "    xmp:CreatorTool=\"{}\"\n",
+      "    mandel:Size=\"{}x{}\"\n",
+      "    mandel:UpperLeft=\"{}\"\n",
+      "    mandel:LowerRight=\"{}\"\n",
+      "    mandel:Arguments=\"{}\">\n",
+      "{}",
+      "  </rdf:Description>\n",
+      " </rdf:RDF>\n",
+      "</x:xmpmeta>\n",
+      "<?xpacket end=\"r\"?>"),
+      NAMESPACE, SOFTWARE, self.size.0, self.size.1, escape(self.upper_left), escape(self.lower_right),
+      escape(&command(&self.arguments)), elements)
+  }
+}
+
+// The render arguments recorded in a PNG this program wrote, and the width and height it
+// was rendered at, which leave out any legend appended below.
+pub fn arguments(path: &str) -> Result<(Vec<String>, (usize, usize)), String> {
+  let file = File::open(path).map_err(|e| format!("error opening '{}': {}", path, e))?;
+  let reader = png::Decoder::new(BufReader::new(file)).read_info().map_err(|e| format!("error reading '{}': {}", path, e))?;
+  let info = reader.info();
+  let mut texts = info.uncompressed_latin1_text.iter().map(|chunk| (chunk.keyword.clone(), chunk.text.clone()))
+    .chain(info.utf8_text.iter().filter_map(|chunk| Some((chunk.keyword.clone(), chunk.get_text().ok()?))));
+  let mut software = None;
+  let mut description = None;
+  let mut size = (info.width as usize, info.height as usize);
+  for (keyword, text) in &mut texts {
+    match keyword.as_str() {
+      "Software" => software = Some(text),
+      "Description" => description = Some(text),
+      "XML:com.adobe.xmp" => size = recorded_size(&text).unwrap_or(size),
+      _ => {}
+    }
+  }
+  match (software, description) {
+    (Some(software), Some(description)) if software.starts_with("Mandel ") => Ok((split(&description)?, size)),
+    _ => Err(format!("'{}' doesn't record the arguments of a Mandel render", path)),
+  }
+}
+
+// The mandel:Size an XMP packet records.
+fn recorded_size(xmp: &str) -> Option<(usize, usize)> {
+  let start = xmp.find("mandel:Size=\"")? + "mandel:Size=\"".len();
+  let (width, height) = xmp[start..].split('"').next()?.split_once('x')?;
+  Some((width.parse().ok()?, height.parse().ok()?))
+}
+
+// Joins arguments into a command line, quoting those the shell would split or expand.
+fn command(arguments: &[String]) -> String {
+  let quote = |argument: &String| {
+    if !argument.is_empty() && argument.chars().all(|c| c.is_alphanumeric() || "-_.,/:=+@%".contains(c)) {
+      argument.clone()
+    } else {
+      format!("'{}'", argument.replace('\'', "'\\''"))
+    }
+  };
+  arguments.iter().map(quote).collect::<Vec<_>>().join(" ")
+}
+
+// Splits a command line as `command` writes them: words are separated by spaces, quoted
+// with single quotes, and may escape a character with a backslash.
+fn split(line: &str) -> Result<Vec<String>, String> {
+  let mut arguments = Vec::new();
+  let mut word: Option<String> = None;
+  let mut chars = line.chars();
+  while let Some(c) = chars.next() {
+    match c {
+      ' ' => arguments.extend(word.take()),
+      '\'' => {
+        let word = word.get_or_insert_with(String::new);
+        loop {
+          match chars.next() {
+            Some('\'') => break,
+            Some(c) => word.push(c),
+            None => return Err("unterminated quote in the recorded arguments".to_string()),
+          }
+        }
+      }
+      '\\' => word.get_or_insert_with(String::new).push(chars.next().ok_or("stray backslash in the recorded arguments")?),
+      c => word.get_or_insert_with(String::new).push(c),
+    }
+  }
+  arguments.extend(word);
+  Ok(arguments)
+}
+
+fn escape(text: &str) -> String {
+  text.replace('&', "&amp;").replace('<', "&lt;").replace('>', "&gt;").replace('"', "&quot;")
+}
+
+#[test]
+fn test_provenance_chunks() {
+  let arguments = ["40x30", "-2,1", "1,-1", "--title", "Été & <hiver>"].map(String::from);
+  let provenance = Provenance { title: Some("Été & <hiver>"), author: Some("Ōkubo"), size: (40, 30), upper_left: "-2,1", lower_right: "1,-1", arguments: arguments.to_vec() };
+  let chunks = provenance.chunks();
+  let keywords: Vec<_> = chunks.iter().map(|(keyword, _, utf8)| (*keyword, *utf8)).collect();
+  assert_eq!(keywords, [("Software", false), ("Title", false), ("Author", true), ("Description", false), ("XML:com.adobe.xmp", true)]);
+  assert_eq!(chunks[3].1, "40x30 -2,1 1,-1 --title 'Été & <hiver>'");
+  assert_eq!(split(&chunks[3].1).unwrap(), arguments);
+
+  let xmp = &chunks[4].1;
+  assert_eq!(recorded_size(xmp), Some((40, 30)));
+  assert!(xmp.contains("<dc:title><rdf:Alt><rdf:li xml:lang=\"x-default\">Été &amp; &lt;hiver&gt;</rdf:li></rdf:Alt></dc:title>"), "{}", xmp);
+  assert!(xmp.contains("<dc:creator><rdf:Seq><rdf:li>Ōkubo</rdf:li></rdf:Seq></dc:creator>"));
+  assert!(xmp.contains("mandel:UpperLeft=\"-2,1\"") && xmp.contains("mandel:Size=\"40x30\""));
+  assert!(xmp.contains(&format!("xmp:CreatorTool=\"{}\"", SOFTWARE)));
+
+  let awkward = ["it's", "", "a\\b", "x y"].map(String::from);
+  assert_eq!(command(&awkward), "'it'\\''s' '' 'a\\b' 'x y'");
+  assert_eq!(split(&command(&awkward)).unwrap(), awkward);
+  assert!(split("--title 'open").is_err());
+
+  let bare = Provenance { title: None, author: None, ..provenance };
+  assert_eq!(bare.chunks().len(), 3);
+  assert!(!bare.xmp().contains("<dc:"));
+}
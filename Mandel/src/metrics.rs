@@ -0,0 +1,141 @@
+// Metrics
+// The long-running modes count what they render and serve the totals at /metrics in the
+// Prometheus text format: `serve` and `serve-api` on their own port, and the workers on
+// the one given with --metrics ADDRESS. Pixel and iteration rates are for Prometheus
+// to work out from the counters, as rate(mandel_pixels_total[1m]) and the like; the
+// pixels/s of the last render is also kept, for a glance.
+
+use std::io::{self, BufRead, BufReader, Write};
+use std::net::{TcpListener, TcpStream};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Mutex;
+
+use crate::server::reason;
+use crate::{log, stats};
+
+pub const CONTENT_TYPE: &str = "text/plain; version=0.0.4";
+
+// Upper bounds of the render duration histogram's buckets, in seconds.
+const BUCKETS: [f64; 10] = [0.01, 0.05, 0.1, 0.5, 1.0, 5.0, 10.0, 60.0, 300.0, 1800.0];
+
+struct Renders {
+  succeeded: u64,
+  failed: u64,
+  pixels: u64,
+  pixels_per_second: f64,
+  // Renders that took up to each bucket's bound, not counting the shorter buckets.
+  durations: [u64; BUCKETS.len() + 1],
+  seconds: f64,
+}
+
+static RENDERS: Mutex<Renders> = Mutex::new(Renders { succeeded: 0, failed: 0, pixels: 0, pixels_per_second: 0.0, durations: [0; BUCKETS.len() + 1], seconds: 0.0 });
+static QUEUED: AtomicU64 = AtomicU64::new(0);
+
+// Counts a render of `pixels` pixels that took `seconds`, and failed unless `ok`.
+pub fn rendered(pixels: u64, seconds: f64, ok: bool) {
+  let mut renders = RENDERS.lock().unwrap();
+  if ok {
+    renders.succeeded += 1;
+  } else {
+    renders.failed += 1;
+  }
+  renders.pixels += pixels;
+  if ok && seconds > 0.0 {
+    renders.pixels_per_second = pixels as f64 / seconds;
+  }
+  let bucket = BUCKETS.iter().position(|&bound| seconds <= bound).unwrap_or(BUCKETS.len());
+  renders.durations[bucket] += 1;
+  renders.seconds += seconds;
+}
+
+// How many jobs wait to be rendered.
+pub fn queued(jobs: usize) {
+  QUEUED.store(jobs as u64, Ordering::Relaxed);
+}
+
+// The metrics, as GET /metrics answers.
+pub fn text() -> String {
+  let renders = RENDERS.lock().unwrap();
+  let mut text = String::new();
+  let mut metric = |name: &str, kind: &str, help: &str, samples: &[(&str, String)]| {
+    text += &format!("# HELP {} {}\n# TYPE {} {}\n", name, help, name, kind);
+    for (labels, value) in samples {
+      text += &format!("{}{} {}\n", name, labels, value);
+    }
+  };
+  metric("mandel_renders_total", "counter", "Renders finished, by outcome.",
+         &[("{outcome=\"ok\"}", renders.succeeded.to_string()), ("{outcome=\"failed\"}", renders.failed.to_string())]);
+  metric("mandel_pixels_total", "counter", "Pixels in finished renders.", &[("", renders.pixels.to_string())]);
+  metric("mandel_pixels_per_second", "gauge", "Pixels per second of the last successful render.", &[("", renders.pixels_per_second.to_string())]);
+  metric("mandel_iterations_total", "counter", "Iterations executed by render threads.", &[("", stats::iterations().to_string())]);
+  metric("mandel_queue_depth", "gauge", "Jobs waiting to be rendered.", &[("", QUEUED.load(Ordering::Relaxed).to_string())]);
+
+  let mut cumulative = 0;
+  let mut histogram = Vec::new();
+  for (i, count) in renders.durations.iter().enumerate() {
+    cumulative += count;
+    let bound = BUCKETS.get(i).map_or("+Inf".to_string(), f64::to_string);
+    histogram.push((format!("_bucket{{le=\"{}\"}}", bound), cumulative.to_string()));
+  }
+  histogram.push(("_sum".to_string(), renders.seconds.to_string()));
+  histogram.push(("_count".to_string(), cumulative.to_string()));
+  let histogram: Vec<(&str, String)> = histogram.iter().map(|(suffix, value)| (suffix.as_str(), value.clone())).collect();
+  metric("mandel_render_duration_seconds", "histogram", "How long renders took.", &histogram);
+  text
+}
+
+// Serves /metrics on `address` from a thread of its own, for the modes without HTTP.
+pub fn serve(address: &str) -> Result<(), io::Error> {
+  let listener = TcpListener::bind(address)?;
+  log::info(&format!("serving metrics on http://{}/metrics", listener.local_addr()?));
+  std::thread::spawn(move || {
+    for stream in listener.incoming().flatten() {
+      if let Err(e) = handle_connection(stream) {
+        log::warn(&format!("metrics request failed: {}", e));
+      }
+    }
+  });
+  Ok(())
+}
+
+fn handle_connection(stream: TcpStream) -> Result<(), io::Error> {
+  let mut reader = BufReader::new(stream.try_clone()?);
+  let mut request_line = String::new();
+  reader.read_line(&mut request_line)?;
+  let mut header = String::new();
+  while reader.read_line(&mut header)? > 2 {
+    header.clear();
+  }
+
+  let (status, content_type, body) = match request_line.split(' ').collect::<Vec<_>>().as_slice() {
+    ["GET", "/metrics", _version] => (200, CONTENT_TYPE, text()),
+    _ => (404, "text/plain", "only GET /metrics is served here\n".to_string()),
+  };
+  let mut writer = io::BufWriter::new(stream);
+  write!(writer, "HTTP/1.1 {} {}\r\nContent-Type: {}\r\nContent-Length: {}\r\n", status, reason(status), content_type, body.len())?;
+  write!(writer, "Connection: close\r\n\r\n{}", body)?;
+  writer.flush()
+}
+
+#[test]
+fn test_metrics_text() {
+  rendered(1000, 0.5, true);
+  rendered(0, 3600.0, false);
+  let text = text();
+  for line in [
+    "# TYPE mandel_renders_total counter",
+    "# TYPE mandel_render_duration_seconds histogram",
+    "# TYPE mandel_queue_depth gauge",
+    "mandel_render_duration_seconds_bucket{le=\"1800\"} ",
+  ] {
+    assert!(text.contains(line), "no '{}' in\n{}", line, text);
+  }
+  // Buckets count cumulatively, ending with every render.
+  let count = |name: &str| -> u64 {
+    let line = text.lines().find(|line| line.starts_with(name)).unwrap();
+    line.rsplit(' ').next().unwrap().parse().unwrap()
+  };
+  assert!(count("mandel_render_duration_seconds_bucket{le=\"0.5\"}") >= 1);
+  assert_eq!(count("mandel_render_duration_seconds_bucket{le=\"+Inf\"}"), count("mandel_render_duration_seconds_count"));
+  assert!(count("mandel_render_duration_seconds_bucket{le=\"+Inf\"}") > count("mandel_render_duration_seconds_bucket{le=\"1800\"}"));
+}
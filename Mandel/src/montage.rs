@@ -0,0 +1,175 @@
+// Montages
+// `mandel montage JOBS --out SHEET.png` renders each job of a batch file and lays the
+// images out on one sheet, for comparing variations of a view side by side: color maps,
+// iteration limits, shadings. Jobs are as `batch` takes them, except that they may leave
+// out the output, since each renders to a scratch file, and the size, which defaults to
+// --cell. The sheet holds --grid COLUMNSxROWS cells, by default as square a grid as fits
+// every job, each an image with a label beneath giving its job's values of the fields
+// that vary from job to job. Other options apply to every render, as with batch.
+
+use crate::json::Value;
+use crate::overlay::{self, Canvas};
+use crate::{interrupt, job, log, parse_arguments, parse_pair, read_image, render, write_rgb_image, Image};
+
+const DEFAULT_CELL: &str = "320x240";
+
+// Pixels between cells and around the sheet, and around each label.
+const GAP: usize = 4;
+const PAD: usize = 3;
+
+pub fn main(arguments: &[String]) -> Result<Vec<(String, Value)>, String> {
+  let (mut path, mut out, mut grid, mut cell, mut force) = (None, None, None, DEFAULT_CELL.to_string(), false);
+  let mut passed = Vec::new();
+  let mut options = arguments.iter();
+  while let Some(option) = options.next() {
+    match option.as_str() {
+      "--out" => out = Some(options.next().ok_or("--out expects a PNG file name")?.as_str()),
+      "--grid" => grid = Some(options.next().and_then(|grid| parse_pair::<usize>(grid, 'x')).filter(|&(columns, rows)| columns > 0 && rows > 0)
+        .ok_or("--grid expects COLUMNSxROWS, like 3x3")?),
+      "--cell" => cell = options.next().filter(|cell| parse_pair::<usize>(cell, 'x').is_some()).ok_or("--cell expects a size WIDTHxHEIGHT")?.clone(),
+      "--force" => force = true,
+      jobs if path.is_none() && !jobs.starts_with("--") => path = Some(jobs),
+      _ => passed.push(option.clone()),
+    }
+  }
+  let path = path.ok_or("montage expects a file of jobs, or - for standard input")?;
+  let out = out.ok_or("montage needs --out SHEET.png")?;
+  if !force && std::path::Path::new(out).exists() {
+    return Err(format!("'{}' already exists; pass --force to overwrite it", out));
+  }
+  let text = if path == "-" {
+    std::io::read_to_string(std::io::stdin()).map_err(|e| format!("error reading jobs from standard input: {}", e))?
+  } else {
+    std::fs::read_to_string(path).map_err(|e| format!("error reading jobs '{}': {}", path, e))?
+  };
+  let jobs = job::batch(&text).map_err(|e| format!("jobs '{}': {}", path, e))?;
+  if jobs.is_empty() {
+    return Err(format!("jobs '{}': no jobs to lay out", path));
+  }
+  let (columns, rows) = grid.unwrap_or_else(|| {
+    let columns = (1..).find(|columns| columns * columns >= jobs.len()).unwrap();
+    (columns, jobs.len().div_ceil(columns))
+  });
+  if columns * rows < jobs.len() {
+    return Err(format!("--grid {}x{} has room for {} images, not the {} jobs", columns, rows, columns * rows, jobs.len()));
+  }
+
+  let scratch = tempfile::tempdir().map_err(|e| format!("error creating a scratch directory: {}", e))?;
+  let mut images = Vec::new();
+  for (i, job) in jobs.iter().enumerate() {
+    if interrupt::requested() {
+      return Err(format!("interrupted after {} of {} jobs", i, jobs.len()));
+    }
+    let name = format!("jobs[{}]", i);
+    log::info(&format!("{} of {}: rendering {}", i + 1, jobs.len(), name));
+    let file = scratch.path().join(format!("{}.png", i)).to_string_lossy().into_owned();
+    let mut arguments = job::arguments(&with_defaults(job, &file, &cell), &name)?;
+    arguments.extend(passed.iter().cloned().chain(["--force".to_string()]));
+    render(parse_arguments(&arguments)?).map_err(|e| format!("{}: {}", name, e))?;
+    images.push(read_image(&file)?);
+  }
+
+  let (sheet, bounds) = lay_out(&images, &labels(&jobs), columns);
+  write_rgb_image(out, &sheet, bounds).map_err(|e| format!("error writing '{}': {}", out, e))?;
+  log::info(&format!("laid out {} images on {}x{} '{}'", images.len(), bounds.0, bounds.1, out));
+  Ok(vec![("file".to_string(), out.into()), ("size".to_string(), Value::Array(vec![bounds.0.into(), bounds.1.into()])), ("images".to_string(), images.len().into())])
+}
+
+// `job` rendering to `file`, at `cell` unless it gives a size of its own.
+fn with_defaults(job: &Value, file: &str, cell: &str) -> Value {
+  let Value::Object(members) = job else {
+    return job.clone();
+  };
+  let mut members: Vec<(String, Value)> = members.iter().filter(|(name, _)| name != "output").cloned().collect();
+  members.push(("output".to_string(), file.into()));
+  if !members.iter().any(|(name, _)| name == "size") {
+    let (width, height) = parse_pair::<usize>(cell, 'x').unwrap();
+    members.push(("size".to_string(), Value::Array(vec![width.into(), height.into()])));
+  }
+  Value::Object(members)
+}
+
+// A label for each job naming the fields that vary from job to job, or its place in the
+// file if none do.
+fn labels(jobs: &[Value]) -> Vec<String> {
+  let members = |job: &Value| match job {
+    Value::Object(members) => members.iter().filter(|(name, _)| name != "output" && name != "schema_version").cloned().collect(),
+    _ => Vec::new(),
+  };
+  let all: Vec<Vec<(String, Value)>> = jobs.iter().map(members).collect();
+  all.iter().enumerate().map(|(i, job)| {
+    let differing: Vec<String> = job.iter().filter(|member| !all.iter().all(|other| other.contains(member))).map(|(name, value)| match value {
+      Value::String(text) => format!("{} {}", name, text),
+      _ => format!("{} {}", name, value),
+    }).collect();
+    if differing.is_empty() { format!("jobs[{}]", i) } else { differing.join(", ") }
+  }).collect()
+}
+
+// The images laid out row by row, `columns` to a row, each with its label beneath, as
+// RGB pixels and the sheet's size.
+fn lay_out(images: &[Image], labels: &[String], columns: usize) -> (Vec<u8>, (usize, usize)) {
+  let cell = images.iter().fold((1, 1), |cell, image| (cell.0.max(image.bounds.0), cell.1.max(image.bounds.1)));
+  let scale = overlay::default_scale(cell);
+  let label_height = overlay::text_size("", scale).1 + 2 * PAD * scale;
+  let rows = images.len().div_ceil(columns);
+  let pitch = (cell.0 + GAP, cell.1 + label_height + GAP);
+  let bounds = (columns * pitch.0 + GAP, rows * pitch.1 + GAP);
+  let mut sheet = vec![0; bounds.0 * bounds.1 * 3];
+
+  for (i, (image, label)) in images.iter().zip(labels).enumerate() {
+    // Smaller images sit centered in their cells.
+    let left = GAP + i % columns * pitch.0 + (cell.0 - image.bounds.0) / 2;
+    let top = GAP + i / columns * pitch.1 + (cell.1 - image.bounds.1) / 2;
+    for (y, row) in image.pixels.chunks(image.bounds.0 * image.channels).enumerate() {
+      for (x, pixel) in row.chunks(image.channels).enumerate() {
+        let at = ((top + y) * bounds.0 + left + x) * 3;
+        sheet[at..at + 3].copy_from_slice(&[pixel[0], pixel[pixel.len() / 2], pixel[pixel.len() - 1]]);
+      }
+    }
+    let room = (cell.0 - 2 * PAD * scale) / (overlay::text_size("XX", scale).0 - overlay::text_size("X", scale).0);
+    let text = match label.chars().count() <= room {
+      true => label.clone(),
+      false => label.chars().take(room.saturating_sub(3)).chain("...".chars()).collect(),
+    };
+    let (x, y) = (GAP + i % columns * pitch.0 + PAD * scale, GAP + i / columns * pitch.1 + cell.1 + PAD * scale);
+    Canvas::new(&mut sheet, bounds).text(x as isize, y as isize, &text, scale);
+  }
+  (sheet, bounds)
+}
+
+#[test]
+fn test_montages() {
+  let jobs = job::batch(r#"{"schema_version": 1, "center": "-0.5,0", "width": 3, "exterior": "escape", "max_iter": 20}
+{"schema_version": 1, "center": "-0.5,0", "width": 3, "exterior": "atom", "max_iter": 20}
+{"schema_version": 1, "center": "-0.5,0", "width": 3, "exterior": "atom", "max_iter": 50, "size": [40, 20]}"#).unwrap();
+  assert_eq!(labels(&jobs), ["exterior escape, max_iter 20", "exterior atom, max_iter 20", "exterior atom, max_iter 50, size [40,20]"]);
+  assert_eq!(labels(&jobs[..1]), ["jobs[0]"]);
+  let arguments = job::arguments(&with_defaults(&jobs[0], "cell.png", "60x45"), "jobs[0]").unwrap();
+  assert_eq!(arguments[..2], ["cell.png", "60x45"]);
+
+  let gray = Image { pixels: vec![200; 6 * 4], bounds: (6, 4), channels: 1 };
+  let rgb = Image { pixels: [10, 20, 30].repeat(2 * 2), bounds: (2, 2), channels: 3 };
+  let (sheet, bounds) = lay_out(&[gray, rgb], &["a".to_string(), "b".to_string()], 2);
+  let height = 4 + 7 + 2 * PAD;
+  assert_eq!(bounds, (2 * (6 + GAP) + GAP, height + 2 * GAP));
+  let at = |x: usize, y: usize| &sheet[(y * bounds.0 + x) * 3..][..3];
+  assert_eq!((at(GAP, GAP), at(GAP - 1, GAP)), (&[200, 200, 200][..], &[0, 0, 0][..]));
+  // The smaller image is centered in its cell.
+  assert_eq!((at(2 * GAP + 6 + 2, GAP + 1), at(2 * GAP + 6 + 1, GAP + 1)), (&[10, 20, 30][..], &[0, 0, 0][..]));
+  // The label is drawn in the strip under the image.
+  assert!((GAP + 4..GAP + height).any(|y| at(GAP + PAD + 1, y) == [255, 255, 255]));
+
+  let dir = tempfile::tempdir().unwrap();
+  let path = |name: &str| dir.path().join(name).to_str().unwrap().to_string();
+  std::fs::write(path("jobs.jsonl"), r#"{"schema_version": 1, "center": "-0.5,0", "width": 3, "max_iter": 10}
+{"schema_version": 1, "center": "-0.5,0", "width": 3, "max_iter": 40}"#).unwrap();
+  let arguments = |extra: &[&str]| [&[path("jobs.jsonl").as_str(), "--out", path("sheet.png").as_str(), "--cell", "30x20", "--no-progress"][..], extra].concat()
+    .iter().map(|arg| arg.to_string()).collect::<Vec<String>>();
+  main(&arguments(&[])).unwrap();
+  assert_eq!(read_image(&path("sheet.png")).unwrap().bounds, (2 * (30 + GAP) + GAP, 20 + 7 + 2 * PAD + 2 * GAP));
+  assert!(main(&arguments(&[])).unwrap_err().contains("already exists"));
+  assert!(main(&arguments(&["--force", "--grid", "1x1"])).unwrap_err().contains("room for 1"));
+  main(&arguments(&["--force", "--grid", "1x2"])).unwrap();
+  assert_eq!(read_image(&path("sheet.png")).unwrap().bounds, (30 + 2 * GAP, 2 * (20 + 7 + 2 * PAD + GAP) + GAP));
+}
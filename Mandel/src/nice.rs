@@ -0,0 +1,65 @@
+// Background rendering
+// With --nice a render keeps out of the way of whoever is using the machine: it drops
+// to the lowest scheduling priority, which the render threads inherit, --spare-cores N
+// leaves N of the cores free by starting that many fewer threads, and --cpu-share P has
+// each render thread rest after every piece of work for long enough that it works only
+// P percent of the time.
+
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::time::Duration;
+
+// The percentage of the time render threads work; 100 until enable says otherwise.
+static SHARE: AtomicUsize = AtomicUsize::new(100);
+
+// Lowers the priority of the calling thread, and of the threads it starts from now on,
+// and has throttle keep render threads to `share` percent of the time.
+pub fn enable(share: Option<usize>) {
+  lower_priority();
+  SHARE.store(share.unwrap_or(100), Ordering::Relaxed);
+}
+
+// The threads of a render that would otherwise start `threads`, on a machine with
+// `cores`, leaving `spare` of them free; at least one.
+pub fn threads(threads: usize, cores: usize, spare: usize) -> usize {
+  threads.min(cores.saturating_sub(spare)).max(1)
+}
+
+// How long a render thread should rest after working for `busy`.
+fn rest(busy: Duration, share: usize) -> Duration {
+  busy.mul_f64(100.0 / share as f64 - 1.0)
+}
+
+// Rests after a piece of work that took `busy`, if --cpu-share asked to.
+pub fn throttle(busy: Duration) {
+  let share = SHARE.load(Ordering::Relaxed);
+  if share < 100 {
+    std::thread::sleep(rest(busy, share));
+  }
+}
+
+#[cfg(unix)]
+fn lower_priority() {
+  // SAFETY: setpriority only reads its arguments; on Linux, who 0 is the calling thread.
+  if unsafe { libc::setpriority(libc::PRIO_PROCESS, 0, 19) } != 0 {
+    crate::log::warn(&format!("couldn't lower the render's priority: {}", std::io::Error::last_os_error()));
+  }
+}
+
+#[cfg(not(unix))]
+fn lower_priority() {
+  crate::log::warn("--nice can only lower the priority on Unix; the render keeps its priority");
+}
+
+#[test]
+fn test_nice_threads_and_rests() {
+  assert_eq!(threads(8, 8, 2), 6);
+  assert_eq!(threads(4, 8, 2), 4);
+  assert_eq!(threads(8, 2, 4), 1);
+  assert_eq!(rest(Duration::from_millis(30), 25), Duration::from_millis(90));
+  assert_eq!(rest(Duration::from_millis(30), 100), Duration::ZERO);
+
+  let parse = |extra: &[&str]| crate::test_arguments(&[&["a.png", "40x30", "-2,1.5", "2,-1.5"][..], extra].concat());
+  assert_eq!(parse(&["--nice", "--spare-cores", "2", "--cpu-share", "50"]).map(|args| (args.nice, args.spare_cores, args.cpu_share)).unwrap(), (true, Some(2), Some(50)));
+  assert_eq!(parse(&["--cpu-share", "50"]).err().unwrap(), "--cpu-share only applies with --nice");
+  assert!(parse(&["--nice", "--cpu-share", "0"]).is_err());
+}
@@ -0,0 +1,202 @@
+// Orbits
+// `mandel orbit --point RE,IM` follows one point's orbit, z -> z² + c from z = 0, for
+// --iters iterations or until it escapes. --csv FILE writes the orbit's points, to
+// standard output for -. Given a render's FILE PIXELS UPPERLEFT LOWERRIGHT and options
+// as well, it renders that view and draws the orbit over it: a line from each point to
+// the next, the points themselves, and c boxed.
+
+use std::fs::File;
+use std::io::{self, BufWriter, Write};
+use std::str::FromStr;
+
+use num::Complex;
+
+use crate::{antialias, build_sampler, claim_file, log, parse_arguments, parse_complex, parse_pair, render_parallel, rotate_about, shades, turn, write_rgb_image, Antialias};
+
+const DEFAULT_ITERS: usize = 500;
+
+const LINE: [u8; 3] = [255, 48, 48];
+const POINT: [u8; 3] = [255, 255, 255];
+const START: [u8; 3] = [64, 255, 64];
+
+pub fn main(arguments: &[String]) -> Result<(), String> {
+  let mut point = None;
+  let mut iters = DEFAULT_ITERS;
+  let mut csv = None;
+  let mut render = Vec::new();
+  let mut options = arguments.iter();
+  while let Some(option) = options.next() {
+    match option.as_str() {
+      "--point" => point = Some(options.next().and_then(|value| parse_complex(value)).ok_or("--point expects a point RE,IM")?),
+      "--iters" => match options.next().map(|value| usize::from_str(value)) {
+        Some(Ok(count)) if count > 0 => iters = count,
+        _ => return Err("--iters expects a positive number of iterations".to_string()),
+      },
+      "--csv" => csv = Some(options.next().ok_or("--csv expects a file name, or - for standard output")?),
+      _ => render.push(option.clone()),
+    }
+  }
+  let c = point.ok_or("orbit expects --point RE,IM")?;
+  if csv.is_none() && render.is_empty() {
+    return Err("orbit expects --csv FILE, a view to draw the orbit over, or both".to_string());
+  }
+
+  let points = orbit(c, iters);
+  let steps = points.len() - 1;
+  if points[steps].norm_sqr() > 4.0 {
+    log::info(&format!("{},{} escapes after {} iterations", c.re, c.im, steps));
+  } else {
+    log::info(&format!("{},{} stays within radius 2 for all {} iterations", c.re, c.im, steps));
+  }
+
+  if let Some(path) = csv {
+    write_csv(path, &points).map_err(|e| format!("error writing orbit '{}': {}", path, e))?;
+  }
+  if !render.is_empty() {
+    draw_over_view(&render, &points)?;
+  }
+  Ok(())
+}
+
+// z_0 = 0, z_1 = c, ... up to `iters` iterations or the first point past radius 2.
+fn orbit(c: Complex<f64>, iters: usize) -> Vec<Complex<f64>> {
+  let mut points = vec![Complex { re: 0.0, im: 0.0 }];
+  let mut z = points[0];
+  for _ in 0..iters {
+    if z.norm_sqr() > 4.0 {
+      break;
+    }
+    z = z * z + c;
+    points.push(z);
+  }
+  points
+}
+
+fn write_csv(path: &str, points: &[Complex<f64>]) -> Result<(), io::Error> {
+  let mut out: BufWriter<Box<dyn Write>> = BufWriter::new(if path == "-" { Box::new(io::stdout()) } else { Box::new(File::create(path)?) });
+  writeln!(out, "n,re,im,abs")?;
+  for (n, z) in points.iter().enumerate() {
+    writeln!(out, "{},{},{},{}", n, z.re, z.im, z.norm())?;
+  }
+  out.flush()
+}
+
+// Renders the view the render arguments `arguments` give, draws the orbit over it and
+// writes it to their FILE.
+fn draw_over_view(arguments: &[String], points: &[Complex<f64>]) -> Result<(), String> {
+  let mut args = parse_arguments(arguments)?;
+  claim_file(&mut args)?;
+  let bounds = parse_pair(&args.pixels, 'x').ok_or(format!("error parsing image dimensions '{}'", args.pixels))?;
+  let sampler = build_sampler(&args, bounds)?;
+  let mut pixels = vec![0; bounds.0 * bounds.1];
+  crate::interrupt::install();
+  let rows = render_parallel(&mut pixels, bounds, sampler.as_ref(), args.threads, 0, 1, true)?;
+  if rows < bounds.1 || (args.antialias == Antialias::Adaptive && antialias(&mut pixels, bounds, 0, sampler.as_ref(), args.threads)? < bounds.1) {
+    return Err("interrupted".to_string());
+  }
+
+  let mut image: Vec<u8> = shades(&pixels, sampler.limit()).iter().flat_map(|&shade| args.colors.as_ref().map_or([shade; 3], |colors| colors[shade as usize])).collect();
+  let corner = |text: &str| parse_complex(text).ok_or(format!("error parsing corner '{}'", text));
+  let view = View { bounds, upper_left: corner(&args.upper_left)?, lower_right: corner(&args.lower_right)?, turn: turn(-args.rotation) };
+  draw(&mut image, &view, points);
+  write_rgb_image(&args.file, &image, bounds).map_err(|e| format!("error writing '{}': {}", args.file, e))
+}
+
+struct View {
+  bounds: (usize, usize),
+  upper_left: Complex<f64>,
+  lower_right: Complex<f64>,
+  // The turn undoing the view's rotation.
+  turn: Option<Complex<f64>>,
+}
+
+impl View {
+  // Image coordinates of the point `z`, the inverse of pixel_to_point.
+  fn pixel(&self, z: Complex<f64>) -> (f64, f64) {
+    let center = (self.upper_left + self.lower_right) / 2.0;
+    let z = self.turn.map_or(z, |turn| rotate_about(z, center, turn));
+    let x = (z.re - self.upper_left.re) / (self.lower_right.re - self.upper_left.re) * self.bounds.0 as f64;
+    let y = (self.upper_left.im - z.im) / (self.upper_left.im - self.lower_right.im) * self.bounds.1 as f64;
+    (x, y)
+  }
+}
+
+fn draw(image: &mut [u8], view: &View, points: &[Complex<f64>]) {
+  let bounds = view.bounds;
+  let mut plot = |x: f64, y: f64, color: [u8; 3]| {
+    if x >= 0.0 && y >= 0.0 && x < bounds.0 as f64 && y < bounds.1 as f64 {
+      let at = 3 * (y as usize * bounds.0 + x as usize);
+      image[at..at + 3].copy_from_slice(&color);
+    }
+  };
+  let pixels: Vec<(f64, f64)> = points.iter().map(|&z| view.pixel(z)).collect();
+  for pair in pixels.windows(2) {
+    if let Some(((x0, y0), (x1, y1))) = clip(pair[0], pair[1], bounds) {
+      let steps = (x1 - x0).abs().max((y1 - y0).abs()).ceil().max(1.0);
+      for i in 0..=steps as usize {
+        let t = i as f64 / steps;
+        plot(x0 + t * (x1 - x0), y0 + t * (y1 - y0), LINE);
+      }
+    }
+  }
+  for &(x, y) in &pixels[1..] {
+    for (dx, dy) in [(-1.0, -1.0), (0.0, -1.0), (1.0, -1.0), (-1.0, 0.0), (0.0, 0.0), (1.0, 0.0), (-1.0, 1.0), (0.0, 1.0), (1.0, 1.0)] {
+      plot(x + dx, y + dy, POINT);
+    }
+  }
+  // A box around c, the orbit's first step.
+  if let Some(&(x, y)) = pixels.get(1) {
+    for d in -3..=3 {
+      let d = d as f64;
+      for (dx, dy) in [(d, -3.0), (d, 3.0), (-3.0, d), (3.0, d)] {
+        plot(x + dx, y + dy, START);
+      }
+    }
+  }
+}
+
+// The part of the segment from `a` to `b` within the image, if any (Liang-Barsky), so
+// orbits far outside a deep view don't cost a step per pixel of their length.
+fn clip(a: (f64, f64), b: (f64, f64), bounds: (usize, usize)) -> Option<((f64, f64), (f64, f64))> {
+  let (dx, dy) = (b.0 - a.0, b.1 - a.1);
+  let (mut low, mut high) = (0.0f64, 1.0f64);
+  for (p, q) in [(-dx, a.0), (dx, bounds.0 as f64 - a.0), (-dy, a.1), (dy, bounds.1 as f64 - a.1)] {
+    if p == 0.0 {
+      if q < 0.0 {
+        return None;
+      }
+    } else if p < 0.0 {
+      low = low.max(q / p);
+    } else {
+      high = high.min(q / p);
+    }
+  }
+  (low <= high).then_some(((a.0 + low * dx, a.1 + low * dy), (a.0 + high * dx, a.1 + high * dy)))
+}
+
+#[test]
+fn test_orbits() {
+  let cycle = orbit(Complex { re: -1.0, im: 0.0 }, 4);
+  assert_eq!(cycle.iter().map(|z| z.re).collect::<Vec<_>>(), [0.0, -1.0, 0.0, -1.0, 0.0]);
+  // 0, 1, 2, 5: the orbit stops at the first point past radius 2.
+  assert_eq!(orbit(Complex { re: 1.0, im: 0.0 }, 100).len(), 4);
+
+  let view = View { bounds: (40, 20), upper_left: Complex { re: -2.0, im: 1.0 }, lower_right: Complex { re: 2.0, im: -1.0 }, turn: None };
+  assert_eq!(view.pixel(Complex { re: 0.0, im: 0.0 }), (20.0, 10.0));
+  assert_eq!(view.pixel(Complex { re: -2.0, im: 1.0 }), (0.0, 0.0));
+  let turned = View { turn: crate::turn(-90.0), ..view };
+  let (x, y) = turned.pixel(Complex { re: 0.0, im: 0.5 });
+  assert!((x - 25.0).abs() < 1e-9 && (y - 10.0).abs() < 1e-9);
+
+  assert_eq!(clip((-10.0, 5.0), (50.0, 5.0), (40, 20)), Some(((0.0, 5.0), (40.0, 5.0))));
+  assert_eq!(clip((-10.0, -5.0), (-1.0, 30.0), (40, 20)), None);
+
+  let mut image = vec![0; 40 * 20 * 3];
+  draw(&mut image, &View { turn: None, ..turned }, &cycle);
+  let color = |x: usize, y: usize| &image[3 * (y * 40 + x)..3 * (y * 40 + x) + 3];
+  // The line from 0 to -1 and back, the points, and the box around c = -1.
+  assert_eq!(color(15, 10), LINE);
+  assert_eq!(color(20, 10), POINT);
+  assert_eq!(color(13, 10), START);
+  assert_eq!(color(5, 5), [0, 0, 0]);
+}
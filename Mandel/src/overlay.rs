@@ -0,0 +1,511 @@
+// Overlays
+// --overlay draws on the finished image, after any --map has colored it, in white and
+// black so it reads over any palette. `axes` marks the real and imaginary axes where
+// they cross the view, naming them Re and Im, and labels ticks along the bottom and left
+// edges in complex coordinates; `grid` does the same and draws faint lines across the
+// image at the ticks. Ticks fall on round numbers, 1, 2 or 5 times a power of ten, as
+// close together as their labels allow. `scalebar` puts a bar of such a round length,
+// about a fifth of the image across, in one corner, with the length and the view's zoom
+// (4 over its width, as for --zoom elsewhere) above it. Text is drawn in the 5 by 7 pixel
+// font below, each of its pixels a `scale` pixel square.
+//
+// --caption writes a line of text in a corner over a darkened box: by default the view's
+// center, zoom and iteration limit, or a format naming those and other CAPTION_FIELDS in
+// braces, so an image carries the coordinates to render it again.
+//
+// --legend draws a strip showing which color stands for which escape count: a bar of
+// every shade from escaping at once to escaping at the iteration limit, labeled with
+// round counts, and a swatch of the interior's color. Shades are whole escape counts
+// scaled to the limit, so the counts are too.
+
+use num::Complex;
+
+use crate::shade;
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum Overlay {
+  Axes,
+  Grid,
+  ScaleBar,
+}
+
+const OVERLAYS: [(&str, Overlay); 3] = [("axes", Overlay::Axes), ("grid", Overlay::Grid), ("scalebar", Overlay::ScaleBar)];
+
+// Where overlays that sit in a corner go.
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum Corner {
+  TopLeft,
+  TopRight,
+  BottomLeft,
+  BottomRight,
+}
+
+pub const CORNERS: [(&str, Corner); 4] =
+  [("top-left", Corner::TopLeft), ("top-right", Corner::TopRight), ("bottom-left", Corner::BottomLeft), ("bottom-right", Corner::BottomRight)];
+
+// How overlays are drawn: text `scale` pixels to a font pixel, and corner overlays in
+// `corner`.
+#[derive(Clone, Copy, Debug)]
+pub struct Style {
+  pub scale: usize,
+  pub corner: Corner,
+}
+
+// The overlays named in the comma-separated `list`, or None if it names one that isn't.
+pub fn parse(list: &str) -> Option<Vec<Overlay>> {
+  list.split(',').map(|name| OVERLAYS.iter().find(|(known, _)| *known == name).map(|&(_, overlay)| overlay)).collect()
+}
+
+// Glyphs for the printable ASCII characters, space first: seven rows top to bottom, the
+// leftmost of each row's five pixels in bit 4.
+const FONT: [[u8; 7]; 95] = [
+  [0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00], [0x04, 0x04, 0x04, 0x04, 0x04, 0x00, 0x04], [0x0a, 0x0a, 0x0a, 0x00, 0x00, 0x00, 0x00], [0x0a, 0x0a, 0x1f, 0x0a, 0x1f, 0x0a, 0x0a],
+  [0x04, 0x0f, 0x14, 0x0e, 0x05, 0x1e, 0x04], [0x18, 0x19, 0x02, 0x04, 0x08, 0x13, 0x03], [0x0c, 0x12, 0x14, 0x08, 0x15, 0x12, 0x0d], [0x04, 0x04, 0x04, 0x00, 0x00, 0x00, 0x00],
+  [0x02, 0x04, 0x08, 0x08, 0x08, 0x04, 0x02], [0x08, 0x04, 0x02, 0x02, 0x02, 0x04, 0x08], [0x00, 0x04, 0x15, 0x0e, 0x15, 0x04, 0x00], [0x00, 0x04, 0x04, 0x1f, 0x04, 0x04, 0x00],
+  [0x00, 0x00, 0x00, 0x00, 0x0c, 0x04, 0x08], [0x00, 0x00, 0x00, 0x1f, 0x00, 0x00, 0x00], [0x00, 0x00, 0x00, 0x00, 0x00, 0x0c, 0x0c], [0x00, 0x01, 0x02, 0x04, 0x08, 0x10, 0x00],
+  [0x0e, 0x11, 0x13, 0x15, 0x19, 0x11, 0x0e], [0x04, 0x0c, 0x04, 0x04, 0x04, 0x04, 0x0e], [0x0e, 0x11, 0x01, 0x02, 0x04, 0x08, 0x1f], [0x1f, 0x02, 0x04, 0x02, 0x01, 0x11, 0x0e],
+  [0x02, 0x06, 0x0a, 0x12, 0x1f, 0x02, 0x02], [0x1f, 0x10, 0x1e, 0x01, 0x01, 0x11, 0x0e], [0x06, 0x08, 0x10, 0x1e, 0x11, 0x11, 0x0e], [0x1f, 0x01, 0x02, 0x04, 0x08, 0x08, 0x08],
+  [0x0e, 0x11, 0x11, 0x0e, 0x11, 0x11, 0x0e], [0x0e, 0x11, 0x11, 0x0f, 0x01, 0x02, 0x0c], [0x00, 0x0c, 0x0c, 0x00, 0x0c, 0x0c, 0x00], [0x00, 0x0c, 0x0c, 0x00, 0x0c, 0x04, 0x08],
+  [0x02, 0x04, 0x08, 0x10, 0x08, 0x04, 0x02], [0x00, 0x00, 0x1f, 0x00, 0x1f, 0x00, 0x00], [0x08, 0x04, 0x02, 0x01, 0x02, 0x04, 0x08], [0x0e, 0x11, 0x01, 0x02, 0x04, 0x00, 0x04],
+  [0x0e, 0x11, 0x01, 0x0d, 0x15, 0x15, 0x0e], [0x0e, 0x11, 0x11, 0x1f, 0x11, 0x11, 0x11], [0x1e, 0x11, 0x11, 0x1e, 0x11, 0x11, 0x1e], [0x0e, 0x11, 0x10, 0x10, 0x10, 0x11, 0x0e],
+  [0x1c, 0x12, 0x11, 0x11, 0x11, 0x12, 0x1c], [0x1f, 0x10, 0x10, 0x1e, 0x10, 0x10, 0x1f], [0x1f, 0x10, 0x10, 0x1e, 0x10, 0x10, 0x10], [0x0e, 0x11, 0x10, 0x17, 0x11, 0x11, 0x0f],
+  [0x11, 0x11, 0x11, 0x1f, 0x11, 0x11, 0x11], [0x0e, 0x04, 0x04, 0x04, 0x04, 0x04, 0x0e], [0x07, 0x02, 0x02, 0x02, 0x02, 0x12, 0x0c], [0x11, 0x12, 0x14, 0x18, 0x14, 0x12, 0x11],
+  [0x10, 0x10, 0x10, 0x10, 0x10, 0x10, 0x1f], [0x11, 0x1b, 0x15, 0x15, 0x11, 0x11, 0x11], [0x11, 0x11, 0x19, 0x15, 0x13, 0x11, 0x11], [0x0e, 0x11, 0x11, 0x11, 0x11, 0x11, 0x0e],
+  [0x1e, 0x11, 0x11, 0x1e, 0x10, 0x10, 0x10], [0x0e, 0x11, 0x11, 0x11, 0x15, 0x12, 0x0d], [0x1e, 0x11, 0x11, 0x1e, 0x14, 0x12, 0x11], [0x0f, 0x10, 0x10, 0x0e, 0x01, 0x01, 0x1e],
+  [0x1f, 0x04, 0x04, 0x04, 0x04, 0x04, 0x04], [0x11, 0x11, 0x11, 0x11, 0x11, 0x11, 0x0e], [0x11, 0x11, 0x11, 0x11, 0x11, 0x0a, 0x04], [0x11, 0x11, 0x11, 0x15, 0x15, 0x15, 0x0a],
+  [0x11, 0x11, 0x0a, 0x04, 0x0a, 0x11, 0x11], [0x11, 0x11, 0x11, 0x0a, 0x04, 0x04, 0x04], [0x1f, 0x01, 0x02, 0x04, 0x08, 0x10, 0x1f], [0x0e, 0x08, 0x08, 0x08, 0x08, 0x08, 0x0e],
+  [0x00, 0x10, 0x08, 0x04, 0x02, 0x01, 0x00], [0x0e, 0x02, 0x02, 0x02, 0x02, 0x02, 0x0e], [0x04, 0x0a, 0x11, 0x00, 0x00, 0x00, 0x00], [0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x1f],
+  [0x08, 0x04, 0x02, 0x00, 0x00, 0x00, 0x00], [0x00, 0x00, 0x0e, 0x01, 0x0f, 0x11, 0x0f], [0x10, 0x10, 0x16, 0x19, 0x11, 0x11, 0x1e], [0x00, 0x00, 0x0e, 0x10, 0x10, 0x11, 0x0e],
+  [0x01, 0x01, 0x0d, 0x13, 0x11, 0x11, 0x0f], [0x00, 0x00, 0x0e, 0x11, 0x1f, 0x10, 0x0e], [0x06, 0x09, 0x08, 0x1c, 0x08, 0x08, 0x08], [0x00, 0x0f, 0x11, 0x11, 0x0f, 0x01, 0x0e],
+  [0x10, 0x10, 0x16, 0x19, 0x11, 0x11, 0x11], [0x04, 0x00, 0x0c, 0x04, 0x04, 0x04, 0x0e], [0x02, 0x00, 0x06, 0x02, 0x02, 0x12, 0x0c], [0x10, 0x10, 0x12, 0x14, 0x18, 0x14, 0x12],
+  [0x0c, 0x04, 0x04, 0x04, 0x04, 0x04, 0x0e], [0x00, 0x00, 0x1a, 0x15, 0x15, 0x11, 0x11], [0x00, 0x00, 0x16, 0x19, 0x11, 0x11, 0x11], [0x00, 0x00, 0x0e, 0x11, 0x11, 0x11, 0x0e],
+  [0x00, 0x00, 0x1e, 0x11, 0x1e, 0x10, 0x10], [0x00, 0x00, 0x0d, 0x13, 0x0f, 0x01, 0x01], [0x00, 0x00, 0x16, 0x19, 0x10, 0x10, 0x10], [0x00, 0x00, 0x0e, 0x10, 0x0e, 0x01, 0x1e],
+  [0x08, 0x08, 0x1c, 0x08, 0x08, 0x09, 0x06], [0x00, 0x00, 0x11, 0x11, 0x11, 0x13, 0x0d], [0x00, 0x00, 0x11, 0x11, 0x11, 0x0a, 0x04], [0x00, 0x00, 0x11, 0x11, 0x15, 0x15, 0x0a],
+  [0x00, 0x00, 0x11, 0x0a, 0x04, 0x0a, 0x11], [0x00, 0x00, 0x11, 0x11, 0x0f, 0x01, 0x0e], [0x00, 0x00, 0x1f, 0x02, 0x04, 0x08, 0x1f], [0x02, 0x04, 0x04, 0x08, 0x04, 0x04, 0x02],
+  [0x04, 0x04, 0x04, 0x04, 0x04, 0x04, 0x04], [0x08, 0x04, 0x04, 0x02, 0x04, 0x04, 0x08], [0x00, 0x00, 0x08, 0x15, 0x02, 0x00, 0x00],
+];
+
+const GLYPH: (usize, usize) = (5, 7);
+
+// The image overlays are drawn on: gray levels or RGB triples, row by row.
+pub struct Canvas<'a> {
+  pixels: &'a mut [u8],
+  bounds: (usize, usize),
+  channels: usize,
+}
+
+impl<'a> Canvas<'a> {
+  pub fn new(pixels: &'a mut [u8], bounds: (usize, usize)) -> Canvas<'a> {
+    let channels = pixels.len() / (bounds.0 * bounds.1);
+    Canvas { pixels, bounds, channels }
+  }
+
+  // Moves the pixel at (x, y), if it is on the canvas, `opacity` of the way toward gray
+  // `level`.
+  fn paint(&mut self, x: isize, y: isize, level: u8, opacity: f64) {
+    if x < 0 || y < 0 || x as usize >= self.bounds.0 || y as usize >= self.bounds.1 {
+      return;
+    }
+    let at = (y as usize * self.bounds.0 + x as usize) * self.channels;
+    for value in &mut self.pixels[at..at + self.channels] {
+      *value = (*value as f64 + (level as f64 - *value as f64) * opacity).round() as u8;
+    }
+  }
+
+  // Sets the pixels from (x, y) across `width` and down `height` to `color`, a level or
+  // an RGB triple as the canvas holds.
+  fn swatch(&mut self, x: usize, y: usize, width: usize, height: usize, color: &[u8]) {
+    for y in y..(y + height).min(self.bounds.1) {
+      for x in x..(x + width).min(self.bounds.0) {
+        let at = (y * self.bounds.0 + x) * self.channels;
+        self.pixels[at..at + self.channels].copy_from_slice(color);
+      }
+    }
+  }
+
+  fn fill(&mut self, x: isize, y: isize, width: isize, height: isize, level: u8, opacity: f64) {
+    for y in y..y + height {
+      for x in x..x + width {
+        self.paint(x, y, level, opacity);
+      }
+    }
+  }
+
+  // The mean level of the pixels from (x, y) across `width` and down `height` that are
+  // on the canvas, over all channels.
+  fn mean(&self, x: isize, y: isize, width: usize, height: usize) -> f64 {
+    let (mut total, mut count) = (0.0, 0);
+    for y in (y.max(0) as usize..(y + height as isize).max(0) as usize).filter(|&y| y < self.bounds.1) {
+      for x in (x.max(0) as usize..(x + width as isize).max(0) as usize).filter(|&x| x < self.bounds.0) {
+        let at = (y * self.bounds.0 + x) * self.channels;
+        total += self.pixels[at..at + self.channels].iter().map(|&value| value as f64).sum::<f64>();
+        count += self.channels;
+      }
+    }
+    total / count.max(1) as f64
+  }
+
+  // Draws `text` with its top left corner at (x, y), each glyph pixel a `scale` square,
+  // outlined `scale` wide: white on black over dark pixels, black on white over light.
+  pub fn text(&mut self, x: isize, y: isize, text: &str, scale: usize) {
+    let s = scale as isize;
+    let (width, height) = text_size(text, scale);
+    let light = self.mean(x, y, width, height) > 127.0;
+    for (level, outline) in [(0, 1), (255, 0)] {
+      let level = if light { 255 - level } else { level };
+      for (i, c) in text.chars().enumerate() {
+        for (row, bits) in glyph(c).iter().enumerate() {
+          for column in (0..GLYPH.0).filter(|column| bits >> (GLYPH.0 - 1 - column) & 1 == 1) {
+            let (left, top) = (x + ((i * (GLYPH.0 + 1) + column) as isize) * s, y + row as isize * s);
+            self.fill(left - outline * s, top - outline * s, (1 + 2 * outline) * s, (1 + 2 * outline) * s, level, 1.0);
+          }
+        }
+      }
+    }
+  }
+}
+
+// The glyph of `c`, or of '?' for characters the font lacks.
+fn glyph(c: char) -> &'static [u8; 7] {
+  &FONT[if c == ' ' || c.is_ascii_graphic() { c as usize - 32 } else { '?' as usize - 32 }]
+}
+
+// The width and height of `text` drawn at `scale`.
+pub fn text_size(text: &str, scale: usize) -> (usize, usize) {
+  let length = text.chars().count();
+  ((length * (GLYPH.0 + 1)).saturating_sub(1) * scale, GLYPH.1 * scale)
+}
+
+// A font scale that keeps text legible without crowding an image of size `bounds`.
+pub fn default_scale(bounds: (usize, usize)) -> usize {
+  (bounds.0.min(bounds.1) / 600).max(1)
+}
+
+// Draws `overlays` on `canvas`, a view from `upper_left` to `lower_right`. `width` is the
+// view's width across the real axis, worked out in more digits than the corners hold.
+pub fn draw(overlays: &[Overlay], canvas: &mut Canvas, upper_left: Complex<f64>, lower_right: Complex<f64>, width: f64, style: Style) {
+  if overlays.iter().any(|&overlay| overlay == Overlay::Axes || overlay == Overlay::Grid) {
+    axes(canvas, upper_left, lower_right, style.scale, overlays.contains(&Overlay::Grid));
+  }
+  if overlays.contains(&Overlay::ScaleBar) {
+    scale_bar(canvas, width, style);
+  }
+}
+
+// Pixels from the edge a tick mark reaches, a gap between marks and labels, the least
+// room between neighboring labels, and the margin around corner overlays, in font
+// pixels.
+const TICK: usize = 4;
+const GAP: usize = 3;
+const SPACING: usize = 12;
+const MARGIN: usize = 6;
+
+fn axes(canvas: &mut Canvas, upper_left: Complex<f64>, lower_right: Complex<f64>, scale: usize, grid: bool) {
+  let (width, height) = (canvas.bounds.0 as f64, canvas.bounds.1 as f64);
+  let column = |re: f64| ((re - upper_left.re) / (lower_right.re - upper_left.re) * width).round() as isize;
+  let row = |im: f64| ((upper_left.im - im) / (upper_left.im - lower_right.im) * height).round() as isize;
+  let (s, text_height) = (scale as isize, (GLYPH.1 * scale) as isize);
+  let (w, h) = (canvas.bounds.0 as isize, canvas.bounds.1 as isize);
+
+  // Real labels sit side by side, so their width sets how close they come; imaginary
+  // labels are stacked, so only their height does.
+  let across = ticks(upper_left.re, lower_right.re, canvas.bounds.0, |label| text_size(label, scale).0 + SPACING * scale, "");
+  let down = ticks(lower_right.im, upper_left.im, canvas.bounds.1, |_| (GLYPH.1 + SPACING) * 2 * scale, "i");
+
+  if grid {
+    for &(re, _) in &across {
+      canvas.fill(column(re), 0, 1, h, 255, 0.35);
+    }
+    for &(im, _) in &down {
+      canvas.fill(0, row(im), w, 1, 255, 0.35);
+    }
+  }
+
+  // Each axis is a white line between black ones, named at its far end.
+  if upper_left.re <= 0.0 && 0.0 <= lower_right.re {
+    let x = column(0.0);
+    canvas.fill(x - s, 0, 3 * s, h, 0, 1.0);
+    canvas.fill(x, 0, s, h, 255, 1.0);
+    canvas.text(x + (TICK + GAP) as isize * s, (GAP + 1) as isize * s, "Im", scale);
+  }
+  if lower_right.im <= 0.0 && 0.0 <= upper_left.im {
+    let y = row(0.0);
+    canvas.fill(0, y - s, w, 3 * s, 0, 1.0);
+    canvas.fill(0, y, w, s, 255, 1.0);
+    let name = text_size("Re", scale).0 as isize;
+    canvas.text(w - name - (GAP + 1) as isize * s, y - text_height - (GAP + 1) as isize * s, "Re", scale);
+  }
+
+  // Labels of the real axis along the bottom edge, centered over their ticks.
+  let band = (TICK + GAP) as isize * s + text_height;
+  for (re, label) in &across {
+    let x = column(*re);
+    canvas.fill(x - s, h - TICK as isize * s - s, 3 * s, TICK as isize * s + s, 0, 1.0);
+    canvas.fill(x, h - TICK as isize * s, s, TICK as isize * s, 255, 1.0);
+    let label_width = text_size(label, scale).0 as isize;
+    canvas.text((x - label_width / 2).clamp(s, (w - label_width - s).max(s)), h - band, label, scale);
+  }
+  // Those of the imaginary axis up the left edge, clear of the bottom labels.
+  for (im, label) in &down {
+    let y = row(*im);
+    if y > h - band - text_height {
+      continue;
+    }
+    canvas.fill(0, y - s, TICK as isize * s + s, 3 * s, 0, 1.0);
+    canvas.fill(0, y, TICK as isize * s, s, 255, 1.0);
+    canvas.text((TICK + GAP) as isize * s, (y - text_height / 2).max(s), label, scale);
+  }
+}
+
+// A bar the length of a round number, with the length and `width`'s zoom above it.
+fn scale_bar(canvas: &mut Canvas, width: f64, style: Style) {
+  let (s, scale) = (style.scale as isize, style.scale);
+  let (length, label) = round_length(width / 5.0);
+  let bar = (length / width * canvas.bounds.0 as f64).round().max(1.0) as isize;
+  let zoom = format!("zoom {}", format_zoom(4.0 / width));
+  let line = (GLYPH.1 + GAP) as isize * s;
+  let box_width = bar.max(text_size(&zoom, scale).0 as isize).max(text_size(&label, scale).0 as isize);
+  let box_height = 2 * line + TICK as isize * s;
+  let margin = MARGIN as isize * s;
+  let (w, h) = (canvas.bounds.0 as isize, canvas.bounds.1 as isize);
+  let left = match style.corner {
+    Corner::TopLeft | Corner::BottomLeft => margin,
+    Corner::TopRight | Corner::BottomRight => w - margin - box_width,
+  };
+  let top = match style.corner {
+    Corner::TopLeft | Corner::TopRight => margin,
+    Corner::BottomLeft | Corner::BottomRight => h - margin - box_height,
+  };
+
+  canvas.text(left, top, &zoom, scale);
+  let label_width = text_size(&label, scale).0 as isize;
+  canvas.text(left + (bar - label_width) / 2, top + line, &label, scale);
+  // The bar, with end caps reaching up, as white over black.
+  let (bar_top, cap) = (top + 2 * line, TICK as isize * s);
+  for (level, outline) in [(0, s), (255, 0)] {
+    canvas.fill(left - outline, bar_top + cap - s - outline, bar + 2 * outline, s + 2 * outline, level, 1.0);
+    for x in [left, left + bar - s] {
+      canvas.fill(x - outline, bar_top - outline, s + 2 * outline, cap + 2 * outline, level, 1.0);
+    }
+  }
+}
+
+// The largest of 1, 2 and 5 times a power of ten that is at most `most`, with its digits
+// written out exactly.
+fn round_length(most: f64) -> (f64, String) {
+  let power = most.log10().floor() as i32;
+  let mantissa = [5, 2, 1].into_iter().find(|&m| m as f64 * 10f64.powi(power) <= most).unwrap_or(1);
+  let label = match power {
+    0..=3 => (mantissa * 10i64.pow(power as u32)).to_string(),
+    -3..0 => format!("{:.*}", -power as usize, mantissa as f64 * 10f64.powi(power)),
+    _ => format!("{}e{}", mantissa, power),
+  };
+  (mantissa as f64 * 10f64.powi(power), label)
+}
+
+// A zoom to three figures, in exponent form once it's large.
+pub fn format_zoom(zoom: f64) -> String {
+  if zoom < 1e4 {
+    format!("{:.3}", zoom).trim_end_matches('0').trim_end_matches('.').to_string() + "x"
+  } else {
+    format!("{:.2e}x", zoom)
+  }
+}
+
+// The legend for an image `width` pixels wide rendered to iteration `limit`, in `colors`
+// or gray, as pixels of that width and the height it returns.
+pub fn legend(width: usize, limit: usize, colors: Option<&[[u8; 3]]>, scale: usize) -> (Vec<u8>, usize) {
+  let text_height = GLYPH.1 * scale;
+  let (margin, bar_height) = (MARGIN * scale, 3 * text_height);
+  let height = 2 * margin + bar_height + (TICK + GAP) * scale + text_height;
+  let channels = if colors.is_some() { 3 } else { 1 };
+  let mut pixels = vec![0; width * height * channels];
+  let mut canvas = Canvas::new(&mut pixels, (width, height));
+
+  let swatch = text_size("interior", scale).0;
+  let bar = width.saturating_sub(3 * margin + swatch).max(1);
+  let color = |shade: u8| colors.map_or(vec![shade], |colors| colors[shade as usize].to_vec());
+  for x in 0..bar {
+    let count = x * limit / bar;
+    canvas.swatch(margin + x, margin, 1, bar_height, &color(shade(count as u32, limit)));
+  }
+  // Framed, since the interior is often as black as the background.
+  canvas.fill((2 * margin + bar - scale) as isize, (margin - scale) as isize, (swatch + 2 * scale) as isize, (bar_height + 2 * scale) as isize, 255, 1.0);
+  canvas.swatch(2 * margin + bar, margin, swatch, bar_height, &color(shade(limit as u32, limit)));
+
+  // Round counts as close together as the label of the limit allows.
+  let room = (text_size(&limit.to_string(), scale).0 + SPACING * scale) as f64;
+  let step = round_step((room * limit as f64 / bar as f64).max(1.0)) as usize;
+  let (s, top) = (scale as isize, (margin + bar_height) as isize);
+  for count in (0..=limit).step_by(step.max(1)) {
+    let x = (margin + count * bar / limit) as isize;
+    canvas.fill(x, top, s, TICK as isize * s, 255, 1.0);
+    let label = count.to_string();
+    let label_width = text_size(&label, scale).0 as isize;
+    canvas.text((x - label_width / 2).min((margin + bar) as isize - label_width).max(0), top + ((TICK + GAP) * scale) as isize, &label, scale);
+  }
+  canvas.text((2 * margin + bar) as isize, top + ((TICK + GAP) * scale) as isize, "interior", scale);
+  (pixels, height)
+}
+
+// The fields a --caption format can name, and the format `auto` stands for.
+pub const CAPTION_FIELDS: [&str; 5] = ["center", "zoom", "width", "max_iter", "size"];
+pub const AUTO_CAPTION: &str = "center {center}  zoom {zoom}  max-iter {max_iter}";
+
+// `format` with each field in braces replaced by its value in `values`.
+pub fn expand(format: &str, values: &[(&str, String)]) -> Result<String, String> {
+  let mut text = String::new();
+  let mut rest = format;
+  while let Some(open) = rest.find('{') {
+    let close = rest[open..].find('}').ok_or_else(|| format!("caption format '{}' has a '{{' without a '}}'", format))? + open;
+    let name = &rest[open + 1..close];
+    let value = values.iter().find(|(field, _)| *field == name)
+      .ok_or_else(|| format!("caption format '{}' names '{}', not one of {}", format, name, CAPTION_FIELDS.join(", ")))?;
+    text += &rest[..open];
+    text += &value.1;
+    rest = &rest[close + 1..];
+  }
+  Ok(text + rest)
+}
+
+// Writes `text` in `style`'s corner, over a box that darkens what's behind it.
+pub fn caption(canvas: &mut Canvas, text: &str, style: Style) {
+  let s = style.scale as isize;
+  let (width, height) = text_size(text, style.scale);
+  let (width, height, margin, pad) = (width as isize, height as isize, (MARGIN * style.scale) as isize, (GAP * style.scale) as isize);
+  let (w, h) = (canvas.bounds.0 as isize, canvas.bounds.1 as isize);
+  let left = match style.corner {
+    Corner::TopLeft | Corner::BottomLeft => margin,
+    Corner::TopRight | Corner::BottomRight => w - margin - width,
+  };
+  let top = match style.corner {
+    Corner::TopLeft | Corner::TopRight => margin,
+    Corner::BottomLeft | Corner::BottomRight => h - margin - height,
+  };
+  canvas.fill(left - pad, top - pad, width + 2 * pad, height + 2 * pad, 0, 0.6);
+  canvas.text(left.max(s), top, text, style.scale);
+}
+
+// Round-numbered ticks from `low` to `high` across `pixels` pixels, each with its label
+// ending in `suffix`, spaced so that each has at least the `room` its label needs.
+fn ticks(low: f64, high: f64, pixels: usize, room: impl Fn(&str) -> usize, suffix: &str) -> Vec<(f64, String)> {
+  let per_pixel = (high - low) / pixels as f64;
+  let mut least = room("-0.0") as f64 * per_pixel;
+  loop {
+    let step = round_step(least);
+    let decimals = (-step.log10().floor()).max(0.0) as usize;
+    let ticks: Vec<(f64, String)> = ((low / step).ceil() as i64..=(high / step).floor() as i64)
+      .map(|k| (k as f64 * step, format!("{:.*}{}", decimals, k as f64 * step, suffix)))
+      .collect();
+    // Fewer decimals never need more room, so this settles.
+    let needed = ticks.iter().map(|(_, label)| room(label)).max().unwrap_or(0) as f64 * per_pixel;
+    if needed <= step {
+      return ticks;
+    }
+    least = needed;
+  }
+}
+
+// The least of 1, 2 and 5 times a power of ten that is at least `least`.
+fn round_step(least: f64) -> f64 {
+  let power = 10f64.powf(least.log10().floor());
+  [1.0, 2.0, 5.0, 10.0].into_iter().map(|m| m * power).find(|&step| step >= least).unwrap_or(10.0 * power)
+}
+
+#[test]
+fn test_axes_and_grid() {
+  assert_eq!(parse("axes"), Some(vec![Overlay::Axes]));
+  assert_eq!(parse("grid,axes"), Some(vec![Overlay::Grid, Overlay::Axes]));
+  assert_eq!(parse("axes,legend"), None);
+
+  assert_eq!(round_step(0.3), 0.5);
+  assert_eq!(round_step(2.0), 2.0);
+  assert_eq!(round_step(0.0011), 0.002);
+  let labels = |pixels: usize| ticks(-2.5, 1.5, pixels, |label| label.len() * 10, "").into_iter().map(|(_, label)| label).collect::<Vec<_>>();
+  assert_eq!(labels(300), ["-2", "-1", "0", "1"]);
+  assert_eq!(labels(800)[..3], ["-2.4", "-2.2", "-2.0"]);
+  assert_eq!(text_size("0.5i", 2), (46, 14));
+
+  // The real axis crosses this view in the middle; the imaginary axis a quarter in.
+  let bounds = (200, 100);
+  let mut gray = vec![100; bounds.0 * bounds.1];
+  let style = Style { scale: 1, corner: Corner::BottomRight };
+  draw(&[Overlay::Grid], &mut Canvas::new(&mut gray, bounds), Complex { re: -1.0, im: 1.0 }, Complex { re: 3.0, im: -1.0 }, 4.0, style);
+  assert_eq!(gray[50 * bounds.0 + 120], 255);
+  assert_eq!((gray[49 * bounds.0 + 120], gray[51 * bounds.0 + 120]), (0, 0));
+  assert_eq!(gray[70 * bounds.0 + 50], 255);
+  // Grid lines lighten what they cross.
+  assert!(gray.iter().any(|&level| level > 100 && level < 255));
+
+  // RGB pixels take the same levels in every channel, and over dark pixels text is white
+  // on black.
+  let mut rgb = vec![[10, 200, 30]; 40 * 20].concat();
+  Canvas::new(&mut rgb, (40, 20)).text(2, 2, "1", 1);
+  let pixel = |x: usize, y: usize| &rgb[(y * 40 + x) * 3..][..3];
+  assert_eq!(pixel(4, 2), [255, 255, 255]);
+  assert_eq!(pixel(3, 2), [0, 0, 0]);
+  assert_eq!(pixel(30, 15), [10, 200, 30]);
+  let mut light = vec![200; 40 * 20];
+  Canvas::new(&mut light, (40, 20)).text(2, 2, "1", 1);
+  assert_eq!((light[2 * 40 + 4], light[2 * 40 + 3]), (0, 255));
+}
+
+#[test]
+fn test_scale_bars() {
+  assert_eq!(round_length(0.8), (0.5, "0.5".to_string()));
+  assert_eq!(round_length(2.0), (2.0, "2".to_string()));
+  assert_eq!(round_length(1234.0), (1000.0, "1000".to_string()));
+  assert_eq!(round_length(3e-7).1, "2e-7");
+  assert_eq!(round_length(0.0011).1, "0.001");
+  assert_eq!(format_zoom(1.0), "1x");
+  assert_eq!(format_zoom(133.33333), "133.333x");
+  assert_eq!(format_zoom(2.5e9), "2.50e9x");
+
+  // A fifth of this 4-wide view rounds down to 0.5, an eighth of the width.
+  let bounds = (400, 200);
+  let mut gray = vec![100; bounds.0 * bounds.1];
+  draw(&[Overlay::ScaleBar], &mut Canvas::new(&mut gray, bounds), Complex { re: -2.0, im: 1.0 }, Complex { re: 2.0, im: -1.0 }, 4.0, Style { scale: 1, corner: Corner::TopLeft });
+  let bar = (0..bounds.1).find(|&y| (6..56).all(|x| gray[y * bounds.0 + x] == 255)).unwrap();
+  assert_eq!(gray[bar * bounds.0 + 56], 0);
+  assert!(gray[bar * bounds.0 + 57..].iter().take(100).all(|&level| level == 100));
+  // Nothing lands in the other corners.
+  assert!(gray[bounds.0 * bounds.1 / 2..].iter().all(|&level| level == 100));
+}
+
+#[test]
+fn test_legends() {
+  let (gray, height) = legend(300, 100, None, 1);
+  assert_eq!(gray.len(), 300 * height);
+  // The bar runs from the first shade to the last escaping one, then the interior.
+  let row = &gray[(MARGIN + 1) * 300..][..300];
+  assert_eq!(row[MARGIN], 255);
+  let swatch = text_size("interior", 1).0;
+  let bar = 300 - 3 * MARGIN - swatch;
+  assert_eq!(row[MARGIN + bar - 1], 255 - (99 * 255 / 100) as u8);
+  assert_eq!(row[2 * MARGIN + bar + 1], 0);
+  assert!(row[MARGIN..MARGIN + bar].windows(2).all(|pair| pair[0] >= pair[1]));
+
+  let colors: Vec<[u8; 3]> = (0..=255).map(|shade| [shade as u8, 0, 255 - shade as u8]).collect();
+  let (rgb, colored_height) = legend(300, 100, Some(&colors), 2);
+  assert_eq!(rgb.len(), 300 * colored_height * 3);
+  assert!(colored_height > height);
+  assert_eq!(rgb[(2 * MARGIN * 300 + 2 * MARGIN) * 3..][..3], [255, 0, 0]);
+}
+
+#[test]
+fn test_captions() {
+  let values = [("center", "-0.5,0".to_string()), ("zoom", "1x".to_string()), ("max_iter", "255".to_string())];
+  assert_eq!(expand(AUTO_CAPTION, &values).as_deref(), Ok("center -0.5,0  zoom 1x  max-iter 255"));
+  assert_eq!(expand("plain", &values).as_deref(), Ok("plain"));
+  assert!(expand("{center", &values).is_err());
+  assert!(expand("{depth}", &values).unwrap_err().contains("'depth'"));
+
+  // In the bottom left corner, over a darkened box, and nowhere else.
+  let bounds = (200, 50);
+  let mut gray = vec![200; bounds.0 * bounds.1];
+  caption(&mut Canvas::new(&mut gray, bounds), "X", Style { scale: 1, corner: Corner::BottomLeft });
+  let (left, top) = (MARGIN, bounds.1 - MARGIN - GLYPH.1);
+  assert_eq!(gray[(top - GAP) * bounds.0 + left], 80);
+  assert_eq!(gray[top * bounds.0 + left], 255);
+  assert!(gray[..bounds.0 * (top - GAP)].iter().all(|&level| level == 200));
+}
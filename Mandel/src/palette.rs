@@ -0,0 +1,198 @@
+// Palettes
+// Map the 0-255 shades the renderers produce to colors. Shade 0 is the set's interior
+// and stays black in every palette; higher shades escaped sooner.
+
+use std::str::FromStr;
+
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub enum Palette {
+  #[default]
+  Gray,
+  // Black through red and yellow to white.
+  Fire,
+  // Black through deep blue to cyan.
+  Ocean,
+  // Hue around the color wheel with escape time.
+  Rainbow,
+}
+
+pub const PALETTES: [Palette; 4] = [Palette::Gray, Palette::Fire, Palette::Ocean, Palette::Rainbow];
+
+impl Palette {
+  pub fn name(self) -> &'static str {
+    match self {
+      Palette::Gray => "gray",
+      Palette::Fire => "fire",
+      Palette::Ocean => "ocean",
+      Palette::Rainbow => "rainbow",
+    }
+  }
+
+  pub fn color(self, shade: u8) -> [u8; 3] {
+    let v = shade as u32;
+    match self {
+      Palette::Gray => [shade; 3],
+      Palette::Fire => [ramp(v, 0), ramp(v, 85), ramp(v, 170)],
+      Palette::Ocean => [(v * v / 255) as u8, (v * 3 / 4) as u8, ramp(v, 0)],
+      Palette::Rainbow if shade == 0 => [0; 3],
+      Palette::Rainbow => hue(v * 6 * 255 / 256),
+    }
+  }
+}
+
+// A palette part way through fading into another: `t` runs from 0, all `from`, to 1,
+// all `to`. Colors mix in the OKLab color space, where equal steps look like equal
+// changes, so a fade neither dims through a muddy middle nor lurches at one end.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Blend {
+  pub from: Palette,
+  pub to: Palette,
+  pub t: f64,
+}
+
+impl Blend {
+  pub const fn solid(palette: Palette) -> Blend {
+    Blend { from: palette, to: palette, t: 0.0 }
+  }
+
+  // The palette throughout, if it isn't fading.
+  pub fn single(self) -> Option<Palette> {
+    match self.t {
+      t if t <= 0.0 || self.from == self.to => Some(self.from),
+      t if t >= 1.0 => Some(self.to),
+      _ => None,
+    }
+  }
+
+  pub fn color(self, shade: u8) -> [u8; 3] {
+    if let Some(palette) = self.single() {
+      return palette.color(shade);
+    }
+    let (from, to) = (oklab(self.from.color(shade)), oklab(self.to.color(shade)));
+    let mixed: [f64; 3] = std::array::from_fn(|i| from[i] + (to[i] - from[i]) * self.t);
+    srgb(mixed)
+  }
+}
+
+// The 256 colors of a Fractint .map file, one "R G B" line per color index, with
+// anything after the three numbers a comment. Missing colors are black.
+pub fn load_map(path: &str) -> Result<Vec<[u8; 3]>, String> {
+  let text = std::fs::read_to_string(path).map_err(|e| format!("error reading color map '{}': {}", path, e))?;
+  let mut colors = text.lines().filter(|line| !line.trim().is_empty()).enumerate().take(256).map(|(i, line)| {
+    let mut numbers = line.split_whitespace().map(u8::from_str);
+    match (numbers.next(), numbers.next(), numbers.next()) {
+      (Some(Ok(r)), Some(Ok(g)), Some(Ok(b))) => Ok([r, g, b]),
+      _ => Err(format!("{}: color {} isn't three numbers from 0 to 255: '{}'", path, i, line)),
+    }
+  }).collect::<Result<Vec<_>, _>>()?;
+  colors.resize(256, [0; 3]);
+  Ok(colors)
+}
+
+// sRGB to OKLab, per Björn Ottosson's definition.
+fn oklab(color: [u8; 3]) -> [f64; 3] {
+  let [r, g, b] = color.map(|c| {
+    let c = c as f64 / 255.0;
+    if c <= 0.04045 { c / 12.92 } else { ((c + 0.055) / 1.055).powf(2.4) }
+  });
+  let l = (0.4122214708 * r + 0.5363325363 * g + 0.0514459929 * b).cbrt();
+  let m = (0.2119034982 * r + 0.6806995451 * g + 0.1073969566 * b).cbrt();
+  let s = (0.0883024619 * r + 0.2817188376 * g + 0.6299787005 * b).cbrt();
+  [
+    0.2104542553 * l + 0.7936177850 * m - 0.0040720468 * s,
+    1.9779984951 * l - 2.4285922050 * m + 0.4505937099 * s,
+    0.0259040371 * l + 0.7827717662 * m - 0.8086757660 * s,
+  ]
+}
+
+// OKLab back to sRGB, rounded and clamped to the displayable range.
+fn srgb(lab: [f64; 3]) -> [u8; 3] {
+  let [lightness, a, b] = lab;
+  let l = (lightness + 0.3963377774 * a + 0.2158037573 * b).powi(3);
+  let m = (lightness - 0.1055613458 * a - 0.0638541728 * b).powi(3);
+  let s = (lightness - 0.0894841775 * a - 1.2914855480 * b).powi(3);
+  let linear = [
+    4.0767416621 * l - 3.3077115913 * m + 0.2309699292 * s,
+    -1.2684380046 * l + 2.6097574011 * m - 0.3413193965 * s,
+    -0.0041960863 * l - 0.7034186147 * m + 1.7076147010 * s,
+  ];
+  linear.map(|c| {
+    let c = if c <= 0.0031308 { 12.92 * c } else { 1.055 * c.powf(1.0 / 2.4) - 0.055 };
+    (c * 255.0).round().clamp(0.0, 255.0) as u8
+  })
+}
+
+// Moves an escaped shade `offset` steps around the 255 escaped shades, which cycles a
+// palette's colors through the image. The interior stays put.
+pub fn cycle(shade: u8, offset: u8) -> u8 {
+  match shade {
+    0 => 0,
+    _ => ((shade as u32 - 1 + offset as u32) % 255 + 1) as u8,
+  }
+}
+
+// Rises from 0 to 255 over the 85 shades starting at `start`.
+fn ramp(v: u32, start: u32) -> u8 {
+  (v.saturating_sub(start) * 3).min(255) as u8
+}
+
+// Fully saturated color at `position` along the six 255-step edges of the color cube.
+fn hue(position: u32) -> [u8; 3] {
+  let (edge, t) = (position / 255, (position % 255) as u8);
+  match edge {
+    0 => [255, t, 0],
+    1 => [255 - t, 255, 0],
+    2 => [0, 255, t],
+    3 => [0, 255 - t, 255],
+    4 => [t, 0, 255],
+    _ => [255, 0, 255 - t],
+  }
+}
+
+#[test]
+fn test_palettes_keep_interior_black() {
+  for palette in PALETTES {
+    assert_eq!(palette.color(0), [0, 0, 0], "{}", palette.name());
+  }
+  assert_eq!(Palette::Gray.color(77), [77, 77, 77]);
+  assert_eq!(Palette::Fire.color(255), [255, 255, 255]);
+  assert_eq!(Palette::Rainbow.color(1), [255, 5, 0]);
+}
+
+#[test]
+fn test_load_map() {
+  let path = std::env::temp_dir().join(format!("mandel-map-{}.map", std::process::id()));
+  std::fs::write(&path, "0 0 0   black\n255 128 7\n\n1 2 3 the rest are black\n").unwrap();
+  let colors = load_map(path.to_str().unwrap()).unwrap();
+  assert_eq!((colors.len(), colors[1], colors[2], colors[3]), (256, [255, 128, 7], [1, 2, 3], [0, 0, 0]));
+  std::fs::write(&path, "0 0 0\n256 0 0\n").unwrap();
+  assert!(load_map(path.to_str().unwrap()).unwrap_err().ends_with("color 1 isn't three numbers from 0 to 255: '256 0 0'"));
+  std::fs::remove_file(&path).unwrap();
+}
+
+#[test]
+fn test_cycle_wraps_escaped_shades() {
+  assert_eq!(cycle(0, 100), 0);
+  assert_eq!(cycle(10, 5), 15);
+  assert_eq!(cycle(255, 1), 1);
+  assert_eq!(cycle(200, 255), 200);
+}
+
+#[test]
+fn test_blends_fade_in_oklab() {
+  let fade = |t| Blend { from: Palette::Ocean, to: Palette::Fire, t };
+  for shade in [0, 1, 90, 200, 255] {
+    assert_eq!(fade(0.0).color(shade), Palette::Ocean.color(shade));
+    assert_eq!(fade(1.0).color(shade), Palette::Fire.color(shade));
+  }
+  assert_eq!(fade(0.5).color(0), [0, 0, 0]);
+  // Colors survive the trip through OKLab.
+  for color in [[255, 5, 0], [12, 200, 99], [255, 255, 255]] {
+    assert_eq!(srgb(oklab(color)), color);
+  }
+  // A palette fading into itself isn't fading.
+  let gray = Blend { from: Palette::Gray, to: Palette::Fire, t: 0.5 };
+  assert_eq!(gray.color(0), [0, 0, 0]);
+  assert_eq!(Blend { from: Palette::Gray, to: Palette::Gray, t: 0.5 }.single(), Some(Palette::Gray));
+  assert_eq!(gray.single(), None);
+}
@@ -0,0 +1,179 @@
+// Periods
+// The orbit of a point inside the set settles into a cycle, and the cycle's period says
+// which hyperbolic component the point lies in: 1 for the main cardioid, 2 for the disk
+// left of it, and so on. `--interior period` shades interior pixels by it instead of
+// black, and `mandel inspect --point RE,IM` reports it, with the multiplier of the cycle
+// and the component's nucleus: the center, where the cycle passes through 0, found by
+// Newton's method from the point.
+//
+// Outside the set, the iteration at which an orbit comes nearest 0 marks its atom domain,
+// the region around the component of that period; `--exterior atom` shades by it, in the
+// same shades as the periods, so each domain matches its component.
+
+use std::str::FromStr;
+
+use num::{Complex, Float};
+
+use crate::{escape_time, parse_complex};
+
+const DEFAULT_MAX_ITER: usize = 10_000;
+
+// Longest cycle looked for.
+const MAX_PERIOD: usize = 4096;
+
+// Newton steps tried before giving up on a nucleus.
+const NEWTON_STEPS: usize = 64;
+
+// The period of the cycle `c`'s orbit settles into within `limit` iterations, or None if it
+// escapes or hasn't settled by then.
+pub fn period<T: Float>(c: Complex<T>, limit: usize) -> Option<usize> {
+  let z = settle(c, limit)?;
+  // Points closer than this are taken for the same point of the cycle.
+  let tolerance = T::epsilon().sqrt() * z.norm().max(T::one());
+  let mut w = z;
+  for period in 1..=MAX_PERIOD {
+    w = w * w + c;
+    if (w - z).norm() < tolerance {
+      return Some(period);
+    }
+  }
+  None
+}
+
+// z after `limit` iterations from 0, or None if it escaped.
+fn settle<T: Float>(c: Complex<T>, limit: usize) -> Option<Complex<T>> {
+  let four = T::from(4.0).unwrap();
+  let mut z = Complex::new(T::zero(), T::zero());
+  for _ in 0..limit {
+    if z.norm_sqr() > four {
+      return None;
+    }
+    z = z * z + c;
+  }
+  Some(z)
+}
+
+// The escape time of `c` and its atom domain: the n >= 1 at which |z_n| was least before
+// it escaped. None if it didn't within `limit`.
+pub fn atom_domain<T: Float>(c: Complex<T>, limit: usize) -> Option<(usize, usize)> {
+  let four = T::from(4.0).unwrap();
+  let mut z = c;
+  let (mut domain, mut least) = (1, z.norm_sqr());
+  for i in 1..limit {
+    let size = z.norm_sqr();
+    if size > four {
+      return Some((i, domain));
+    }
+    if size < least {
+      (domain, least) = (i, size);
+    }
+    z = z * z + c;
+  }
+  None
+}
+
+// The shade of an interior pixel of period `period`: sixteen levels down from white,
+// repeating, so neighboring components stand apart.
+pub fn shade(period: usize) -> u8 {
+  255 - ((period - 1) % 16 * 16) as u8
+}
+
+// The multiplier of the cycle of `period` that `c`'s orbit settles into: the product of
+// 2z over the cycle. Its magnitude is below 1 for attracting cycles, and 0 at the nucleus.
+fn multiplier(c: Complex<f64>, limit: usize, period: usize) -> Option<Complex<f64>> {
+  let mut z = settle(c, limit)?;
+  let mut product = Complex::new(1.0, 0.0);
+  for _ in 0..period {
+    product = product * z * 2.0;
+    z = z * z + c;
+  }
+  Some(product)
+}
+
+// The nucleus of period `period` nearest `c` as Newton's method finds it: the root of
+// z_period(c) = 0, with dz/dc carried along the orbit.
+fn nucleus(c: Complex<f64>, period: usize) -> Option<Complex<f64>> {
+  let mut c = c;
+  for _ in 0..NEWTON_STEPS {
+    let (mut z, mut dz) = (Complex::new(0.0, 0.0), Complex::new(0.0, 0.0));
+    for _ in 0..period {
+      dz = z * dz * 2.0 + 1.0;
+      z = z * z + c;
+    }
+    let step = z / dz;
+    if !step.re.is_finite() || !step.im.is_finite() {
+      return None;
+    }
+    c -= step;
+    if step.norm() <= 1e-15 * c.norm().max(1.0) {
+      return Some(c);
+    }
+  }
+  None
+}
+
+// `mandel inspect --point RE,IM [--max-iter N]`
+pub fn inspect(arguments: &[String]) -> Result<(), String> {
+  let mut point = None;
+  let mut limit = DEFAULT_MAX_ITER;
+  let mut options = arguments.iter().map(String::as_str);
+  while let Some(option) = options.next() {
+    match option {
+      "--point" => point = Some(options.next().and_then(parse_complex).ok_or("--point expects a point RE,IM")?),
+      "--max-iter" => match options.next().map(usize::from_str) {
+        Some(Ok(value)) if value > 0 => limit = value,
+        _ => return Err("--max-iter expects a positive number".to_string()),
+      },
+      _ => return Err("inspect accepts --point RE,IM and --max-iter N".to_string()),
+    }
+  }
+  let c = point.ok_or("inspect expects --point RE,IM")?;
+
+  println!("point        {},{}", c.re, c.im);
+  if let Some(count) = escape_time(c, limit) {
+    println!("escapes      after {} iterations", count);
+    return Ok(());
+  }
+  println!("escapes      not within {} iterations", limit);
+  let Some(period) = period(c, limit) else {
+    println!("period       none settled on; try a higher --max-iter");
+    return Ok(());
+  };
+  println!("period       {}", period);
+  if let Some(multiplier) = multiplier(c, limit, period) {
+    println!("multiplier   {:.6} (magnitude {:.6})", multiplier, multiplier.norm());
+  }
+  match nucleus(c, period) {
+    Some(nucleus) => println!("nucleus      {},{}", nucleus.re, nucleus.im),
+    None => println!("nucleus      Newton's method didn't converge"),
+  }
+  Ok(())
+}
+
+#[test]
+fn test_periods_and_nuclei() {
+  let at = |re, im| Complex { re, im };
+  assert_eq!(period(at(0.0, 0.0), 1000), Some(1));
+  assert_eq!(period(at(-0.1, 0.1), 1000), Some(1));
+  assert_eq!(period(at(-1.0, 0.0), 1000), Some(2));
+  assert_eq!(period(Complex { re: -1.05f32, im: 0.05 }, 1000), Some(2));
+  assert_eq!(period(at(-0.12, 0.74), 1000), Some(3));
+  assert_eq!(period(at(-1.76, 0.0), 1000), Some(3));
+  assert_eq!(period(at(1.0, 0.0), 1000), None);
+
+  for (c, domain) in [(at(0.3, 0.0), 1), (at(-0.75, 0.1), 2), (at(-1.3, 0.1), 4), (at(-1.76, 0.02), 15)] {
+    let (escape, found) = atom_domain(c, 1000).unwrap();
+    assert_eq!((Some(escape), found), (escape_time(c, 1000), domain));
+  }
+  assert_eq!(atom_domain(at(-0.12, 0.8), 1000), None);
+
+  assert_eq!(shade(1), 255);
+  assert_eq!(shade(2), 239);
+  assert_eq!(shade(17), 255);
+
+  // The period 3 bulb's nucleus, and the cycle through 0 there.
+  let nucleus = nucleus(at(-0.12, 0.74), 3).unwrap();
+  assert!((nucleus - at(-0.12256116687665362, 0.7448617666197442)).norm() < 1e-12, "{}", nucleus);
+  assert!(multiplier(nucleus, 1000, 3).unwrap().norm() < 1e-9);
+  assert!(multiplier(at(-0.12, 0.74), 1000, 3).unwrap().norm() < 1.0);
+}
@@ -0,0 +1,232 @@
+// Perturbation rendering
+// Iterates a single high-precision reference orbit at the view center, then renders
+// every pixel as a cheap f64 offset from it. Plain f64 runs out of digits to tell
+// neighboring pixels apart around a 1e-14 pixel pitch; the offsets never do.
+
+use std::ops::{Add, Div, Mul, Sub};
+
+use num::Complex;
+
+use crate::{escape_count, Sampler};
+#[cfg(test)]
+use crate::double_double::DoubleDouble;
+
+// The arithmetic a reference orbit needs, implemented by each high-precision backend.
+pub trait Real: Clone + Add<Output = Self> + Sub<Output = Self> + Mul<Output = Self> + Div<Output = Self> {
+  fn from_f64(value: f64) -> Self;
+  fn to_f64(&self) -> f64;
+}
+
+// The series approximation is trusted while its cubic term stays this small relative to
+// the quadratic one across the whole view.
+const SERIES_TOLERANCE: f64 = 1e-4;
+
+pub struct ReferenceOrbit {
+  orbit: Vec<Complex<f64>>,
+  series: Option<Series>,
+}
+
+// Coefficients of dz_n ≈ A·dc + B·dc² + C·dc³ at iteration `skip`, letting pixels start
+// there instead of at zero.
+struct Series {
+  skip: usize,
+  a: Complex<f64>,
+  b: Complex<f64>,
+  c: Complex<f64>,
+}
+
+impl ReferenceOrbit {
+  // z₁ = c starts the orbit, so every value carries the coordinates' precision.
+  pub fn new<T: Real>(center: (T, T), limit: usize) -> ReferenceOrbit {
+    let (c_re, c_im) = center;
+    let (mut re, mut im) = (c_re.clone(), c_im.clone());
+    let mut orbit = vec![Complex { re: 0.0, im: 0.0 }];
+
+    for _ in 0..limit {
+      let z = Complex { re: re.to_f64(), im: im.to_f64() };
+      orbit.push(z);
+      if z.norm_sqr() > 4.0 {
+        break;
+      }
+
+      let two = T::from_f64(2.0);
+      (re, im) = (
+        re.clone() * re.clone() - im.clone() * im.clone() + c_re.clone(),
+        two * re * im + c_im.clone(),
+      );
+    }
+
+    ReferenceOrbit { orbit, series: None }
+  }
+
+  // Advances the series coefficients alongside the reference orbit for as long as the
+  // approximation holds for every offset up to `radius`, and records where to resume.
+  pub fn approximate(&mut self, radius: f64) {
+    let zero = Complex { re: 0.0, im: 0.0 };
+    let mut series = Series { skip: 0, a: zero, b: zero, c: zero };
+
+    for (n, &z) in self.orbit.iter().enumerate().take(self.orbit.len() - 1) {
+      let a = 2.0 * z * series.a + 1.0;
+      let b = 2.0 * z * series.b + series.a * series.a;
+      let c = 2.0 * z * series.c + 2.0 * series.a * series.b;
+
+      if c.norm() * radius > SERIES_TOLERANCE * b.norm() {
+        break;
+      }
+      series = Series { skip: n + 1, a, b, c };
+    }
+
+    self.series = if series.skip > 0 { Some(series) } else { None };
+  }
+
+  pub fn skipped_iterations(&self) -> usize {
+    self.series.as_ref().map_or(0, |series| series.skip)
+  }
+
+  // Escape time of the point `delta` away from the reference center. Iterates
+  // dz' = 2·Z·dz + dz² + dc, rebasing onto the start of the reference whenever the
+  // offset outgrows the full value (or the reference itself escaped), which keeps the
+  // offset small and avoids the classic perturbation glitches.
+  pub fn escape_time(&self, delta: Complex<f64>, limit: usize) -> Option<usize> {
+    let (mut dz, mut m) = match &self.series {
+      Some(series) => (series.a * delta + series.b * delta * delta + series.c * delta * delta * delta, series.skip),
+      None => (Complex { re: 0.0, im: 0.0 }, 0),
+    };
+
+    for i in m.min(limit)..limit {
+      let z = self.orbit[m] + dz;
+      if z.norm_sqr() > 4.0 {
+        return Some(i);
+      }
+      if m == self.orbit.len() - 1 || z.norm_sqr() < dz.norm_sqr() {
+        dz = z;
+        m = 0;
+      }
+      dz = 2.0 * self.orbit[m] * dz + dz * dz + delta;
+      m += 1;
+    }
+
+    None
+  }
+}
+
+pub struct Perturbation {
+  orbit: ReferenceOrbit,
+  bounds: (usize, usize),
+  pitch: (f64, f64),
+  limit: usize,
+  // Rotation of the grid about the reference point, as a unit complex number.
+  turn: Complex<f64>,
+}
+
+impl Perturbation {
+  pub fn new<T: Real>(bounds: (usize, usize), upper_left: (T, T), lower_right: (T, T), limit: usize, series: bool) -> Perturbation {
+    let pitch = (
+      ((lower_right.0.clone() - upper_left.0.clone()) / T::from_f64(bounds.0 as f64)).to_f64(),
+      ((upper_left.1.clone() - lower_right.1.clone()) / T::from_f64(bounds.1 as f64)).to_f64(),
+    );
+    let half = T::from_f64(0.5);
+    let center = ((upper_left.0 + lower_right.0) * half.clone(), (upper_left.1 + lower_right.1) * half);
+
+    let mut orbit = ReferenceOrbit::new(center, limit);
+    if series {
+      // The farthest any pixel gets from the center is the half-diagonal.
+      let radius = (pitch.0 * bounds.0 as f64).hypot(pitch.1 * bounds.1 as f64) / 2.0;
+      orbit.approximate(radius);
+    }
+
+    Perturbation { orbit, bounds, pitch, limit, turn: Complex { re: 1.0, im: 0.0 } }
+  }
+
+  // The same view turned `degrees` counterclockwise about its center.
+  pub fn rotated(self, degrees: f64) -> Perturbation {
+    Perturbation { turn: Complex::from_polar(1.0, degrees.to_radians()), ..self }
+  }
+
+  pub fn skipped_iterations(&self) -> usize {
+    self.orbit.skipped_iterations()
+  }
+}
+
+impl Sampler for Perturbation {
+  fn sample(&self, x: f64, y: f64) -> u32 {
+    let delta = Complex {
+      re: (x - self.bounds.0 as f64 / 2.0) * self.pitch.0,
+      im: -(y - self.bounds.1 as f64 / 2.0) * self.pitch.1
+    } * self.turn;
+    escape_count(self.orbit.escape_time(delta, self.limit), self.limit)
+  }
+
+  fn limit(&self) -> usize {
+    self.limit
+  }
+}
+
+#[test]
+fn test_perturbation_matches_direct_iteration() {
+  use crate::{escape_time, pixel_to_point};
+
+  let bounds = (64, 48);
+  let upper_left = Complex { re: -1.20, im: 0.35 };
+  let lower_right = Complex { re: -1.0, im: 0.20 };
+  let dd = |c: Complex<f64>| (DoubleDouble::new(c.re), DoubleDouble::new(c.im));
+  let perturbation = Perturbation::new(bounds, dd(upper_left), dd(lower_right), 255, false);
+
+  // Rounding differs between the two methods, so a few boundary pixels may disagree.
+  let mut mismatches = 0;
+  for y in 0..bounds.1 {
+    for x in 0..bounds.0 {
+      let direct = escape_time(pixel_to_point(bounds, (x as f64, y as f64), upper_left, lower_right), 255);
+      if escape_count(direct, 255) != perturbation.sample(x as f64, y as f64) {
+        mismatches += 1;
+      }
+    }
+  }
+  assert!(mismatches * 100 < bounds.0 * bounds.1, "{} mismatching pixels", mismatches);
+}
+
+#[test]
+fn test_series_approximation_skips_iterations_without_changing_result() {
+  // A small view near a minibrot, where the reference survives long enough to skip.
+  let center = (-1.7687788, 0.0017389);
+  let half_width = 1e-7;
+  let corner = |re: f64, im: f64| (DoubleDouble::new(center.0 + re), DoubleDouble::new(center.1 + im));
+  let (upper_left, lower_right) = (corner(-half_width, half_width), corner(half_width, -half_width));
+
+  let bounds = (32, 32);
+  let plain = Perturbation::new(bounds, upper_left, lower_right, 255, false);
+  let series = Perturbation::new(bounds, upper_left, lower_right, 255, true);
+  assert!(series.skipped_iterations() > 0);
+
+  let mut mismatches = 0;
+  for y in 0..bounds.1 {
+    for x in 0..bounds.0 {
+      if plain.sample(x as f64, y as f64) != series.sample(x as f64, y as f64) {
+        mismatches += 1;
+      }
+    }
+  }
+  assert!(mismatches * 100 < bounds.0 * bounds.1, "{} mismatching pixels", mismatches);
+}
+
+#[test]
+fn test_big_float_reference_matches_double_double() {
+  use crate::big_float::BigFloat;
+  use std::str::FromStr;
+
+  // Deep enough that f64 corners would collapse, shallow enough for double-double.
+  let (upper_left, lower_right) = (("-0.74364388703715870475", "0.13182590420531197050"), ("-0.74364388703715870465", "0.13182590420531197040"));
+  let dd = |(re, im): (&str, &str)| (DoubleDouble::from_str(re).unwrap(), DoubleDouble::from_str(im).unwrap());
+  let big = |(re, im): (&str, &str)| (BigFloat::parse(re, 192).unwrap(), BigFloat::parse(im, 192).unwrap());
+
+  let bounds = (16, 16);
+  let double = Perturbation::new(bounds, dd(upper_left), dd(lower_right), 255, false);
+  let arbitrary = Perturbation::new(bounds, big(upper_left), big(lower_right), 255, false);
+  assert!((double.pitch.0 / arbitrary.pitch.0 - 1.0).abs() < 1e-9);
+
+  for y in 0..bounds.1 {
+    for x in 0..bounds.0 {
+      assert_eq!(double.sample(x as f64, y as f64), arbitrary.sample(x as f64, y as f64));
+    }
+  }
+}
@@ -0,0 +1,217 @@
+// Terminal previews
+// Draws images with the upper-half-block character, one pixel in the foreground and one
+// in the background of every cell, so a text terminal shows roughly square pixels in
+// 24-bit color; or, for terminals without color, as characters of increasing density.
+// Terminals with a graphics protocol (Sixel, or Kitty's) show the image itself at full
+// resolution. --preview prints one of these instead of writing the image, to check a
+// view before committing to a big render.
+
+use std::io::Write;
+
+use image::png::PNGEncoder;
+use image::ColorType;
+
+use crate::palette::Palette;
+use crate::{build_sampler, render_parallel, shades, Arguments};
+
+// Preview width when $COLUMNS doesn't say.
+const DEFAULT_COLUMNS: usize = 80;
+
+// Characters from the set's black interior to the fastest-escaping points.
+const DENSITY: &[u8] = b" .:-=+*#%@";
+
+// Base64 bytes per Kitty graphics escape; the protocol's limit.
+const KITTY_CHUNK: usize = 4096;
+
+const BASE64: &[u8] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Preview {
+  // 24-bit color half blocks.
+  Term,
+  // Plain characters.
+  Ascii,
+  // Sixel graphics at full resolution.
+  Sixel,
+  // Kitty graphics protocol at full resolution.
+  Kitty,
+}
+
+// Renders the view `args` describes at terminal size, or full size for the graphics
+// protocols, and prints it to standard output.
+pub fn show(args: &Arguments, image: (usize, usize), preview: Preview) -> Result<(), String> {
+  let columns = std::env::var("COLUMNS").ok().and_then(|columns| columns.parse().ok()).unwrap_or(DEFAULT_COLUMNS);
+  let columns = columns.min(image.0).max(1);
+  // Half blocks give square pixels two to a cell; characters are about twice as tall
+  // as they are wide.
+  let bounds = match preview {
+    Preview::Term => (columns, (columns * image.1 / image.0).max(1)),
+    Preview::Ascii => (columns, (columns * image.1 / image.0 / 2).max(1)),
+    Preview::Sixel | Preview::Kitty => image,
+  };
+
+  let sampler = build_sampler(args, bounds)?;
+  let mut pixels = vec![0; bounds.0 * bounds.1];
+  render_parallel(&mut pixels, bounds, sampler.as_ref(), args.threads, 0, 1, true)?;
+  let pixels = shades(&pixels, sampler.limit());
+
+  let mut out = Vec::new();
+  match preview {
+    Preview::Term => encode_half_blocks(&mut out, &pixels, bounds, Palette::Gray),
+    Preview::Ascii => encode_ascii(&mut out, &pixels, bounds),
+    Preview::Sixel => encode_sixel(&mut out, &pixels, bounds, Palette::Gray),
+    Preview::Kitty => encode_kitty(&mut out, &pixels, bounds),
+  }
+  std::io::stdout().write_all(&out).map_err(|e| format!("error writing preview: {}", e))
+}
+
+// Appends escape sequences drawing `pixels` as half blocks colored with `palette`, one
+// line of cells per pair of rows, each line starting at the cursor's column and ending
+// with a newline.
+pub fn encode_half_blocks(out: &mut Vec<u8>, pixels: &[u8], bounds: (usize, usize), palette: Palette) {
+  for top in (0..bounds.1).step_by(2) {
+    let mut colors = None;
+    for x in 0..bounds.0 {
+      let upper = pixels[top * bounds.0 + x];
+      // An odd last row leaves the lower halves black.
+      let lower = if top + 1 < bounds.1 { pixels[(top + 1) * bounds.0 + x] } else { 0 };
+      if colors != Some((upper, lower)) {
+        let ([r, g, b], [br, bg, bb]) = (palette.color(upper), palette.color(lower));
+        write!(out, "\x1b[38;2;{};{};{}m\x1b[48;2;{};{};{}m", r, g, b, br, bg, bb).unwrap();
+        colors = Some((upper, lower));
+      }
+      out.extend_from_slice("▀".as_bytes());
+    }
+    out.extend_from_slice(b"\x1b[0m\r\n");
+  }
+}
+
+// Appends one line of characters per row of `pixels`, denser for lighter shades.
+fn encode_ascii(out: &mut Vec<u8>, pixels: &[u8], bounds: (usize, usize)) {
+  for row in pixels.chunks(bounds.0) {
+    out.extend(row.iter().map(|&shade| DENSITY[shade as usize * DENSITY.len() / 256]));
+    out.push(b'\n');
+  }
+}
+
+// Appends a Sixel image of `pixels`, one color register per shade. Each band of six rows
+// is drawn once per shade present in it, overprinting with `$` and moving down with `-`.
+fn encode_sixel(out: &mut Vec<u8>, pixels: &[u8], bounds: (usize, usize), palette: Palette) {
+  // 1:1 pixel aspect, then the raster size.
+  write!(out, "\x1bP0;1q\"1;1;{};{}", bounds.0, bounds.1).unwrap();
+  for shade in 0..=255u8 {
+    let [r, g, b] = palette.color(shade).map(|channel| (channel as usize * 100 + 127) / 255);
+    write!(out, "#{};2;{};{};{}", shade, r, g, b).unwrap();
+  }
+
+  for top in (0..bounds.1).step_by(6) {
+    let band = &pixels[top * bounds.0..(top + 6).min(bounds.1) * bounds.0];
+    let mut present = [false; 256];
+    band.iter().for_each(|&shade| present[shade as usize] = true);
+
+    for shade in (0..256).filter(|&shade| present[shade]) {
+      write!(out, "#{}", shade).unwrap();
+      let mut run = (0, 0);
+      for x in 0..bounds.0 {
+        let bits = band.chunks(bounds.0).enumerate()
+          .filter(|(_, row)| row[x] as usize == shade)
+          .fold(0, |bits, (k, _)| bits | 1 << k);
+        let sixel = 63 + bits as u8;
+        if run.1 > 0 && run.0 != sixel {
+          sixel_run(out, run);
+          run.1 = 0;
+        }
+        run = (sixel, run.1 + 1);
+      }
+      sixel_run(out, run);
+      out.push(b'$');
+    }
+    out.push(b'-');
+  }
+  out.extend_from_slice(b"\x1b\\");
+}
+
+// Appends `count` repeats of a sixel character, using `!` repeat introducers for runs.
+fn sixel_run(out: &mut Vec<u8>, (sixel, count): (u8, usize)) {
+  if count > 3 {
+    write!(out, "!{}", count).unwrap();
+    out.push(sixel);
+  } else {
+    out.extend(std::iter::repeat_n(sixel, count));
+  }
+}
+
+// Appends `pixels` as a PNG in Kitty graphics escapes, split into the chunks the protocol
+// requires, then a newline so following output starts below the image.
+fn encode_kitty(out: &mut Vec<u8>, pixels: &[u8], bounds: (usize, usize)) {
+  let mut png = Vec::new();
+  PNGEncoder::new(&mut png).encode(pixels, bounds.0 as u32, bounds.1 as u32, ColorType::Gray(8))
+    .expect("error encoding PNG");
+  let payload = base64(&png);
+
+  let chunks: Vec<&[u8]> = payload.chunks(KITTY_CHUNK).collect();
+  for (i, chunk) in chunks.iter().enumerate() {
+    let more = (i + 1 < chunks.len()) as u8;
+    if i == 0 {
+      write!(out, "\x1b_Ga=T,f=100,q=2,m={};", more).unwrap();
+    } else {
+      write!(out, "\x1b_Gm={};", more).unwrap();
+    }
+    out.extend_from_slice(chunk);
+    out.extend_from_slice(b"\x1b\\");
+  }
+  out.push(b'\n');
+}
+
+fn base64(bytes: &[u8]) -> Vec<u8> {
+  let mut encoded = Vec::with_capacity(bytes.len().div_ceil(3) * 4);
+  for group in bytes.chunks(3) {
+    let word = group.iter().enumerate().fold(0u32, |word, (i, &byte)| word | (byte as u32) << (16 - 8 * i));
+    for i in 0..4 {
+      encoded.push(if i <= group.len() { BASE64[(word >> (18 - 6 * i) & 63) as usize] } else { b'=' });
+    }
+  }
+  encoded
+}
+
+#[test]
+fn test_half_blocks_pair_rows() {
+  let mut out = Vec::new();
+  encode_half_blocks(&mut out, &[10, 10, 20, 30, 40, 40], (2, 3), Palette::Gray);
+  let text = String::from_utf8(out).unwrap();
+  assert_eq!(text.matches('▀').count(), 4);
+  assert!(text.starts_with("\x1b[38;2;10;10;10m\x1b[48;2;20;20;20m▀\x1b[38;2;10;10;10m\x1b[48;2;30;30;30m▀"));
+  // The odd last row is drawn over black, and one color change covers both cells.
+  assert!(text.ends_with("\x1b[38;2;40;40;40m\x1b[48;2;0;0;0m▀▀\x1b[0m\r\n"));
+}
+
+#[test]
+fn test_ascii_density() {
+  let mut out = Vec::new();
+  encode_ascii(&mut out, &[0, 26, 128, 255], (2, 2));
+  assert_eq!(out, b" .\n+@\n");
+}
+
+#[test]
+fn test_sixel_bands() {
+  let mut out = Vec::new();
+  // Two bands: rows 0-5 alternate black and white by column, row 6 is all white.
+  let mut pixels = vec![0u8; 5 * 7];
+  for (i, pixel) in pixels.iter_mut().enumerate() {
+    *pixel = if i >= 30 || i % 5 % 2 == 1 { 255 } else { 0 };
+  }
+  encode_sixel(&mut out, &pixels, (5, 7), Palette::Gray);
+  let text = String::from_utf8(out).unwrap();
+  assert!(text.starts_with("\x1bP0;1q\"1;1;5;7#0;2;0;0;0#1;2;0;0;0"));
+  assert!(text.contains("#255;2;100;100;100"));
+  // Full sixels are '~' and empty ones '?'; the last band has one row, bit 0 ('@').
+  assert!(text.ends_with("#0~?~?~$#255?~?~?$-#255!5@$-\x1b\\"));
+}
+
+#[test]
+fn test_base64() {
+  assert_eq!(base64(b""), b"");
+  assert_eq!(base64(b"M"), b"TQ==");
+  assert_eq!(base64(b"Ma"), b"TWE=");
+  assert_eq!(base64(b"Man is"), b"TWFuIGlz");
+}
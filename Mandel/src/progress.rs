@@ -0,0 +1,255 @@
+// Progress bar
+// Render threads report the regions they finish to process-wide counters; while a
+// render to a file runs, a reporter thread redraws a bar from them on standard error a
+// few times a second:
+//
+//   [##############----------------]  47%  0:12 elapsed, about 0:31 left
+//
+// Pixels count once per pass over them, so a progressive render's total is its size
+// times the number of passes, and antialiasing adds one more.
+//
+// Pixels near the set cost far more than those that escape at once, so the time left
+// isn't extrapolated from the pixel count. Before the render starts, a sparse probe
+// samples one pixel per cell of a coarse grid, and the escape time the probe finds
+// stands in for the cost of every pixel in its cell. The time left is the time taken so
+// far, scaled by the estimated cost still to go over the estimated cost already done.
+//
+// A service running several renders at once can't tell them apart in those counters,
+// so each of its renders also counts into a Tracker of its own, which the thread that
+// starts the render hands on to the render threads it spawns.
+
+use std::cell::RefCell;
+use std::io::Write;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::thread::JoinHandle;
+use std::time::{Duration, Instant};
+
+use crossbeam::channel::{self, RecvTimeoutError, Sender};
+
+use crate::{shade, Sampler};
+
+// Characters of bar between the brackets.
+const BAR_WIDTH: usize = 30;
+
+// Time between redraws.
+const REDRAW: Duration = Duration::from_millis(200);
+
+// Side in pixels of the cells the probe samples one pixel of.
+const PROBE_CELL: usize = 16;
+
+// Time a render has to run before its estimate of the time left is shown.
+const SETTLE: Duration = Duration::from_secs(1);
+
+static DONE: AtomicU64 = AtomicU64::new(0);
+static COST_DONE: AtomicU64 = AtomicU64::new(0);
+static COSTS: Mutex<Option<CostMap>> = Mutex::new(None);
+
+thread_local! {
+  static TRACKER: RefCell<Option<Tracker>> = const { RefCell::new(None) };
+}
+
+// Pixels finished by one render among several.
+#[derive(Clone, Debug, Default)]
+pub struct Tracker(Arc<AtomicU64>);
+
+impl Tracker {
+  pub fn done(&self) -> u64 {
+    self.0.load(Ordering::Relaxed)
+  }
+}
+
+// The tracker the renders on this thread count into, for handing to threads it spawns.
+pub fn tracker() -> Option<Tracker> {
+  TRACKER.with(|tracker| tracker.borrow().clone())
+}
+
+// Has this thread's renders count into `tracker`.
+pub fn track(tracker: Option<Tracker>) {
+  TRACKER.with(|current| *current.borrow_mut() = tracker);
+}
+
+// The estimated cost of each pixel of the image, a cell at a time.
+pub struct CostMap {
+  bounds: (usize, usize),
+  columns: usize,
+  cells: Vec<u64>,
+}
+
+impl CostMap {
+  // Samples the middle pixel of each cell with `sampler`. A pixel's shade says roughly
+  // how many iterations it took: black is the whole limit, and lighter shades escaped
+  // sooner, so 256 minus the shade is proportional to its iterations. Keyed pixels hold
+  // no count, so shades it is.
+  pub fn probe(sampler: &dyn Sampler, bounds: (usize, usize)) -> CostMap {
+    let columns = bounds.0.div_ceil(PROBE_CELL);
+    let mut cells = Vec::with_capacity(columns * bounds.1.div_ceil(PROBE_CELL));
+    for top in (0..bounds.1).step_by(PROBE_CELL) {
+      for left in (0..bounds.0).step_by(PROBE_CELL) {
+        let middle = ((left + bounds.0.min(left + PROBE_CELL)) / 2, (top + bounds.1.min(top + PROBE_CELL)) / 2);
+        cells.push(256 - shade(sampler.sample(middle.0 as f64, middle.1 as f64), sampler.limit()) as u64);
+      }
+    }
+    CostMap { bounds, columns, cells }
+  }
+
+  // The estimated cost of the `size` pixels whose top-left pixel is `origin`.
+  fn cost(&self, origin: (usize, usize), size: (usize, usize)) -> u64 {
+    let (right, bottom) = ((origin.0 + size.0).min(self.bounds.0), (origin.1 + size.1).min(self.bounds.1));
+    let mut cost = 0;
+    for row in origin.1 / PROBE_CELL..bottom.div_ceil(PROBE_CELL) {
+      let rows = bottom.min((row + 1) * PROBE_CELL) - origin.1.max(row * PROBE_CELL);
+      for column in origin.0 / PROBE_CELL..right.div_ceil(PROBE_CELL) {
+        let columns = right.min((column + 1) * PROBE_CELL) - origin.0.max(column * PROBE_CELL);
+        cost += self.cells[row * self.columns + column] * (rows * columns) as u64;
+      }
+    }
+    cost
+  }
+}
+
+// Counts the `size` pixels whose top-left pixel is `origin` as finished.
+pub fn advance(origin: (usize, usize), size: (usize, usize)) {
+  DONE.fetch_add((size.0 * size.1) as u64, Ordering::Relaxed);
+  TRACKER.with(|tracker| {
+    if let Some(tracker) = tracker.borrow().as_ref() {
+      tracker.0.fetch_add((size.0 * size.1) as u64, Ordering::Relaxed);
+    }
+  });
+  if let Some(costs) = COSTS.lock().unwrap().as_ref() {
+    COST_DONE.fetch_add(costs.cost(origin, size), Ordering::Relaxed);
+  }
+}
+
+// The bar itself; dropping it draws the final state and ends the line.
+pub struct Bar {
+  stop: Option<Sender<()>>,
+  reporter: Option<JoinHandle<()>>,
+}
+
+// How far a render has come.
+struct Progress {
+  done: u64,
+  total: u64,
+  cost_done: u64,
+  cost_total: u64,
+  elapsed: Duration,
+}
+
+// Starts drawing a bar for a render that makes `passes` passes over the pixels of
+// `costs`.
+pub fn start(costs: CostMap, passes: usize) -> Bar {
+  let total = (costs.bounds.0 * costs.bounds.1 * passes) as u64;
+  let cost_total = costs.cost((0, 0), costs.bounds) * passes as u64;
+  DONE.store(0, Ordering::Relaxed);
+  COST_DONE.store(0, Ordering::Relaxed);
+  *COSTS.lock().unwrap() = Some(costs);
+
+  let (stop, stopped) = channel::bounded::<()>(0);
+  let started = Instant::now();
+  let reporter = std::thread::spawn(move || loop {
+    let finished = stopped.recv_timeout(REDRAW) == Err(RecvTimeoutError::Disconnected);
+    let progress = Progress {
+      done: DONE.load(Ordering::Relaxed),
+      total,
+      cost_done: COST_DONE.load(Ordering::Relaxed),
+      cost_total,
+      elapsed: started.elapsed(),
+    };
+    let mut stderr = std::io::stderr().lock();
+    let _ = write!(stderr, "\r{}\x1b[K", line(&progress));
+    if finished {
+      let _ = writeln!(stderr);
+      return;
+    }
+    let _ = stderr.flush();
+  });
+  Bar { stop: Some(stop), reporter: Some(reporter) }
+}
+
+impl Drop for Bar {
+  fn drop(&mut self) {
+    self.stop.take();
+    if let Some(reporter) = self.reporter.take() {
+      let _ = reporter.join();
+    }
+    COSTS.lock().unwrap().take();
+  }
+}
+
+fn line(progress: &Progress) -> String {
+  // Margins rendered around strips can count a few pixels twice.
+  let fraction = if progress.total == 0 { 1.0 } else { (progress.done as f64 / progress.total as f64).min(1.0) };
+  let filled = (fraction * BAR_WIDTH as f64) as usize;
+  let mut line = format!("[{}{}] {:>3}% {:>5} elapsed", "#".repeat(filled), "-".repeat(BAR_WIDTH - filled), (fraction * 100.0) as u32, clock(progress.elapsed));
+  if let Some(left) = time_left(progress) {
+    line += &format!(", about {} left", clock(left));
+  }
+  line
+}
+
+// The time left if the remaining work goes at the pace of the work done so far, or
+// None until enough has been done to judge the pace, or once the render is done.
+fn time_left(progress: &Progress) -> Option<Duration> {
+  if progress.elapsed < SETTLE || progress.cost_done == 0 || progress.done >= progress.total {
+    return None;
+  }
+  let remaining = progress.cost_total.saturating_sub(progress.cost_done) as f64 / progress.cost_done as f64;
+  Some(progress.elapsed.mul_f64(remaining))
+}
+
+// `elapsed` as M:SS, or H:MM:SS once it reaches an hour.
+fn clock(elapsed: Duration) -> String {
+  let seconds = elapsed.as_secs();
+  match seconds / 3600 {
+    0 => format!("{}:{:02}", seconds / 60, seconds % 60),
+    hours => format!("{}:{:02}:{:02}", hours, seconds / 60 % 60, seconds % 60),
+  }
+}
+
+#[test]
+fn test_progress_lines() {
+  let progress = |done, elapsed| Progress { done, total: 200, cost_done: done * 3, cost_total: 400, elapsed: Duration::from_secs(elapsed) };
+  assert_eq!(line(&progress(0, 0)), format!("[{}]   0%  0:00 elapsed", "-".repeat(30)));
+  // Half the pixels but three quarters of the cost are done, so a third as long again.
+  assert_eq!(line(&progress(100, 75)), format!("[{}{}]  50%  1:15 elapsed, about 0:25 left", "#".repeat(15), "-".repeat(15)));
+  assert_eq!(line(&progress(250, 3 * 3600 + 61)), format!("[{}] 100% 3:01:01 elapsed", "#".repeat(30)));
+}
+
+#[test]
+fn test_trackers_follow_their_render() {
+  let counter = Tracker::default();
+  track(Some(counter.clone()));
+  advance((0, 0), (10, 3));
+  let handed = tracker();
+  std::thread::spawn(move || {
+    advance((0, 0), (10, 1));
+    track(handed);
+    advance((0, 0), (10, 2));
+  }).join().unwrap();
+  track(None);
+  advance((0, 0), (10, 5));
+  assert_eq!(counter.done(), 50);
+}
+
+#[test]
+fn test_cost_map_weights_regions_by_probed_cost() {
+  struct Halves;
+  impl Sampler for Halves {
+    // Black, the costliest, on the left half of a 64-pixel-wide image; white on the right.
+    fn sample(&self, x: f64, _y: f64) -> u32 {
+      if x < 32.0 { 255 } else { 0 }
+    }
+
+    fn limit(&self) -> usize {
+      255
+    }
+  }
+
+  let costs = CostMap::probe(&Halves, (64, 40));
+  assert_eq!(costs.cells.len(), 4 * 3);
+  assert_eq!(costs.cost((0, 0), (64, 40)), 32 * 40 * 256 + 32 * 40);
+  // Regions that straddle cells take a share of each.
+  assert_eq!(costs.cost((24, 10), (16, 20)), 8 * 20 * 256 + 8 * 20);
+  assert_eq!(costs.cost((60, 38), (10, 10)), 4 * 2);
+}
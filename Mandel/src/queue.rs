@@ -0,0 +1,319 @@
+// Queue worker
+// `mandel worker --queue redis://...` renders jobs, as `mandel schema` describes them,
+// from a Redis list that other systems push onto:
+//
+//   LPUSH mandel:jobs '{"schema_version":1,"output":"a.png","size":[400,300],...}'
+//
+// The worker moves each job onto KEY:processing while it renders, so a crashed worker's
+// jobs can be found and pushed back; then it stores the PNG at KEY:image:OUTPUT, pushes
+// a report onto KEY:done and takes the job off KEY:processing. Failed jobs are reported
+// there too, with their error, rather than retried. Ctrl-C puts the job in progress
+// back at the head of the queue.
+//
+// With --metrics ADDRESS, the queue depth reported there is the length of KEY.
+//
+// Redis speaks RESP: commands go as arrays of bulk strings, and replies come back
+// tagged by their first byte. Only the handful of commands above are used.
+
+use std::io::{self, BufRead, BufReader, BufWriter, Write};
+use std::net::TcpStream;
+use std::time::Instant;
+
+use crate::json::{self, Value};
+use crate::{interrupt, job, log, metrics, parse_arguments, parse_pair, render};
+
+// Seconds to wait for a job before checking for Ctrl-C.
+const POLL_SECONDS: &str = "1";
+
+// Fields the worker decides, since it keeps images in Redis rather than on its disk, or
+// that would have it read its own files for whoever can push onto the queue.
+const WORKER_FIELDS: [&str; 9] = ["existing", "location", "map", "formula", "edges", "histogram", "legend", "stats_json", "threads"];
+
+#[derive(Debug, PartialEq)]
+struct Address {
+  host: String,
+  port: u16,
+  password: Option<String>,
+  database: Option<u32>,
+}
+
+#[derive(Debug, PartialEq)]
+enum Reply {
+  Status(String),
+  Integer(i64),
+  // Bulk strings and arrays can be null: nothing popped before the timeout, say.
+  Bulk(Option<Vec<u8>>),
+  Array(Option<Vec<Reply>>),
+}
+
+struct Connection {
+  reader: BufReader<TcpStream>,
+  writer: BufWriter<TcpStream>,
+}
+
+// redis://[:PASSWORD@]HOST[:PORT][/DATABASE]
+fn parse_url(url: &str) -> Result<Address, String> {
+  let invalid = || format!("bad queue '{}'; expected redis://[:PASSWORD@]HOST[:PORT][/DATABASE]", url);
+  let rest = url.strip_prefix("redis://").ok_or_else(invalid)?;
+  let (credentials, rest) = rest.rsplit_once('@').map_or((None, rest), |(credentials, rest)| (Some(credentials), rest));
+  let password = credentials.map(|credentials| credentials.split_once(':').map_or(credentials, |(_user, password)| password).to_string());
+  let (server, database) = rest.split_once('/').map_or((rest, None), |(server, database)| (server, Some(database)));
+  let database = match database {
+    None | Some("") => None,
+    Some(database) => Some(database.parse().map_err(|_| invalid())?),
+  };
+  let (host, port) = match server.rsplit_once(':') {
+    Some((host, port)) => (host, port.parse().map_err(|_| invalid())?),
+    None => (server, 6379),
+  };
+  if host.is_empty() {
+    return Err(invalid());
+  }
+  Ok(Address { host: host.to_string(), port, password, database })
+}
+
+impl Connection {
+  fn open(address: &Address) -> io::Result<Connection> {
+    let stream = TcpStream::connect((address.host.as_str(), address.port))?;
+    let mut connection = Connection { reader: BufReader::new(stream.try_clone()?), writer: BufWriter::new(stream) };
+    if let Some(password) = &address.password {
+      connection.call(&[b"AUTH", password.as_bytes()])?;
+    }
+    if let Some(database) = address.database {
+      connection.call(&[b"SELECT", database.to_string().as_bytes()])?;
+    }
+    Ok(connection)
+  }
+
+  fn call(&mut self, arguments: &[&[u8]]) -> io::Result<Reply> {
+    self.writer.write_all(&encode(arguments))?;
+    self.writer.flush()?;
+    read_reply(&mut self.reader)
+  }
+}
+
+fn encode(arguments: &[&[u8]]) -> Vec<u8> {
+  let mut command = format!("*{}\r\n", arguments.len()).into_bytes();
+  for argument in arguments {
+    command.extend(format!("${}\r\n", argument.len()).as_bytes());
+    command.extend(*argument);
+    command.extend(b"\r\n");
+  }
+  command
+}
+
+// Error replies come back as errors.
+fn read_reply(reader: &mut impl BufRead) -> io::Result<Reply> {
+  let mut line = String::new();
+  if reader.read_line(&mut line)? == 0 {
+    return Err(io::Error::new(io::ErrorKind::UnexpectedEof, "the server closed the connection"));
+  }
+  let line = line.trim_end_matches("\r\n");
+  let malformed = || io::Error::new(io::ErrorKind::InvalidData, format!("malformed reply '{}'", line));
+  let length = || -> io::Result<Option<usize>> {
+    match line[1..].parse::<i64>().map_err(|_| malformed())? {
+      -1 => Ok(None),
+      length => usize::try_from(length).map(Some).map_err(|_| malformed()),
+    }
+  };
+  match line.as_bytes().first() {
+    Some(b'+') => Ok(Reply::Status(line[1..].to_string())),
+    Some(b'-') => Err(io::Error::other(format!("redis: {}", &line[1..]))),
+    Some(b':') => line[1..].parse().map(Reply::Integer).map_err(|_| malformed()),
+    Some(b'$') => match length()? {
+      None => Ok(Reply::Bulk(None)),
+      Some(length) => {
+        let mut data = vec![0; length + 2];
+        reader.read_exact(&mut data)?;
+        data.truncate(length);
+        Ok(Reply::Bulk(Some(data)))
+      }
+    },
+    Some(b'*') => match length()? {
+      None => Ok(Reply::Array(None)),
+      Some(length) => (0..length).map(|_| read_reply(reader)).collect::<io::Result<_>>().map(|replies| Reply::Array(Some(replies))),
+    },
+    _ => Err(malformed()),
+  }
+}
+
+// Takes jobs off the list `key` at `url` until interrupted.
+pub fn work(url: &str, key: &str, threads: usize) -> Result<(), String> {
+  let address = parse_url(url)?;
+  let mut connection = Connection::open(&address).map_err(|e| format!("error connecting to {}: {}", url, e))?;
+  let scratch = tempfile::tempdir().map_err(|e| format!("error creating a scratch directory: {}", e))?;
+  let output = scratch.path().join("render.png");
+  log::info(&format!("worker taking jobs from {} at {}:{}", key, address.host, address.port));
+  interrupt::install();
+  while !interrupt::requested() {
+    next_job(&mut connection, key, threads, &output.to_string_lossy()).map_err(|e| format!("queue {}: {}", url, e))?;
+  }
+  Err("interrupted".to_string())
+}
+
+// Waits a moment for a job and renders it, if one comes, into the file `scratch`.
+fn next_job(connection: &mut Connection, key: &str, threads: usize, scratch: &str) -> io::Result<()> {
+  let processing = format!("{}:processing", key);
+  let job = match connection.call(&[b"BRPOPLPUSH", key.as_bytes(), processing.as_bytes(), POLL_SECONDS.as_bytes()])? {
+    Reply::Bulk(Some(job)) => job,
+    Reply::Bulk(None) | Reply::Array(None) => return Ok(()),
+    reply => return Err(io::Error::new(io::ErrorKind::InvalidData, format!("unexpected reply {:?}", reply))),
+  };
+  if let Reply::Integer(length) = connection.call(&[b"LLEN", key.as_bytes()])? {
+    metrics::queued(length.max(0) as usize);
+  }
+
+  let start = Instant::now();
+  let result = run_job(&job, threads, scratch);
+  if !interrupt::requested() {
+    let pixels = result.as_ref().map_or(0, |(_, (width, height))| width * height);
+    metrics::rendered(pixels as u64, start.elapsed().as_secs_f64(), result.is_ok());
+  }
+  let report = match result {
+    Ok((output, _)) => {
+      let image = format!("{}:image:{}", key, output);
+      let png = std::fs::read(scratch)?;
+      connection.call(&[b"SET", image.as_bytes(), &png])?;
+      log::info(&format!("rendered {} in {:.1} s", output, start.elapsed().as_secs_f64()));
+      Value::Object(vec![
+        ("status".to_string(), "ok".into()),
+        ("output".to_string(), output.as_str().into()),
+        ("image".to_string(), image.as_str().into()),
+        ("seconds".to_string(), start.elapsed().as_secs_f64().into()),
+      ])
+    }
+    Err(message) if interrupt::requested() => {
+      log::info(&format!("{}; returning the job to {}", message, key));
+      connection.call(&[b"RPUSH", key.as_bytes(), &job])?;
+      connection.call(&[b"LREM", processing.as_bytes(), b"1", &job])?;
+      return Ok(());
+    }
+    Err(message) => {
+      log::warn(&format!("job failed: {}", message));
+      Value::Object(vec![
+        ("status".to_string(), "error".into()),
+        ("message".to_string(), message.as_str().into()),
+        ("job".to_string(), String::from_utf8_lossy(&job).as_ref().into()),
+      ])
+    }
+  };
+  connection.call(&[b"LPUSH", format!("{}:done", key).as_bytes(), report.to_string().as_bytes()])?;
+  connection.call(&[b"LREM", processing.as_bytes(), b"1", &job])?;
+  Ok(())
+}
+
+// Renders `job` into `scratch`, and returns the output it names and the image size.
+fn run_job(job: &[u8], threads: usize, scratch: &str) -> Result<(String, (usize, usize)), String> {
+  let mut job = std::str::from_utf8(job).map_err(|_| "job: not UTF-8 text".to_string()).and_then(|text| json::parse(text).map_err(|e| format!("job: {}", e)))?;
+  let Value::Object(fields) = &mut job else {
+    return Err(format!("job: expected a job object, got {}", job));
+  };
+  if let Some((name, _)) = fields.iter().find(|(name, _)| WORKER_FIELDS.contains(&name.as_str())) {
+    return Err(format!("job.{}: set by the worker", name));
+  }
+  let Some(Value::String(output)) = fields.iter().find(|(name, _)| name == "output").map(|(_, value)| value.clone()) else {
+    return Err("job.output: required, as a string".to_string());
+  };
+  fields.retain(|(name, _)| name != "output");
+  fields.push(("output".to_string(), scratch.into()));
+
+  let mut arguments = job::arguments(&job, "job")?;
+  arguments.extend(["--force", "--no-progress", "--threads"].map(String::from));
+  arguments.push(threads.to_string());
+  let args = parse_arguments(&arguments)?;
+  let size = parse_pair(&args.pixels, 'x').ok_or(format!("error parsing image dimensions '{}'", args.pixels))?;
+  render(args)?;
+  Ok((output, size))
+}
+
+#[test]
+fn test_parse_queue_urls() {
+  assert_eq!(parse_url("redis://localhost"), Ok(Address { host: "localhost".to_string(), port: 6379, password: None, database: None }));
+  assert_eq!(parse_url("redis://:secret@10.0.0.2:7000/3"),
+             Ok(Address { host: "10.0.0.2".to_string(), port: 7000, password: Some("secret".to_string()), database: Some(3) }));
+  assert!(parse_url("amqp://localhost").is_err());
+  assert!(parse_url("redis://localhost:port").is_err());
+  assert!(parse_url("redis:///2").is_err());
+}
+
+#[test]
+fn test_resp_replies() {
+  assert_eq!(encode(&[b"LPUSH", b"jobs", b"{}"]), b"*3\r\n$5\r\nLPUSH\r\n$4\r\njobs\r\n$2\r\n{}\r\n");
+  let mut replies = &b"+OK\r\n:42\r\n$5\r\na\r\nbc\r\n$-1\r\n*2\r\n$1\r\nx\r\n:1\r\n*-1\r\n-ERR wrong\r\n"[..];
+  assert_eq!(read_reply(&mut replies).unwrap(), Reply::Status("OK".to_string()));
+  assert_eq!(read_reply(&mut replies).unwrap(), Reply::Integer(42));
+  assert_eq!(read_reply(&mut replies).unwrap(), Reply::Bulk(Some(b"a\r\nbc".to_vec())));
+  assert_eq!(read_reply(&mut replies).unwrap(), Reply::Bulk(None));
+  assert_eq!(read_reply(&mut replies).unwrap(), Reply::Array(Some(vec![Reply::Bulk(Some(b"x".to_vec())), Reply::Integer(1)])));
+  assert_eq!(read_reply(&mut replies).unwrap(), Reply::Array(None));
+  assert_eq!(read_reply(&mut replies).unwrap_err().to_string(), "redis: ERR wrong");
+}
+
+#[test]
+fn test_works_through_a_queue() {
+  use std::collections::HashMap;
+  use std::net::TcpListener;
+
+  // Just enough of Redis for one worker: lists and strings, and a BRPOPLPUSH that
+  // doesn't wait.
+  let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+  let port = listener.local_addr().unwrap().port();
+  let server = std::thread::spawn(move || {
+    let (stream, _) = listener.accept().unwrap();
+    let (mut reader, mut writer) = (BufReader::new(stream.try_clone().unwrap()), stream);
+    let mut lists: HashMap<Vec<u8>, Vec<Vec<u8>>> = HashMap::new();
+    let mut strings = HashMap::new();
+    let text = |bytes: &[u8]| String::from_utf8_lossy(bytes).into_owned();
+    lists.insert(b"jobs".to_vec(), vec![
+      br#"{"schema_version": 1, "output": "b.png", "size": [4, 3]}"#.to_vec(),
+      br#"{"schema_version": 1, "output": "a.png", "size": [40, 30], "upper_left": "-2,1", "lower_right": "1,-1"}"#.to_vec(),
+    ]);
+    while let Ok(Reply::Array(Some(command))) = read_reply(&mut reader) {
+      let command: Vec<Vec<u8>> = command.into_iter().map(|reply| match reply { Reply::Bulk(Some(bytes)) => bytes, _ => panic!() }).collect();
+      let reply = match text(&command[0]).as_str() {
+        "BRPOPLPUSH" => match lists.get_mut(&command[1]).and_then(Vec::pop) {
+          Some(job) => {
+            lists.entry(command[2].clone()).or_default().insert(0, job.clone());
+            encode(&[&job])[4..].to_vec()
+          }
+          None => b"$-1\r\n".to_vec(),
+        },
+        "LLEN" => format!(":{}\r\n", lists.get(&command[1]).map_or(0, Vec::len)).into_bytes(),
+        "LPUSH" => {
+          lists.entry(command[1].clone()).or_default().insert(0, command[2].clone());
+          b":1\r\n".to_vec()
+        }
+        "LREM" => {
+          lists.get_mut(&command[1]).unwrap().retain(|job| *job != command[3]);
+          b":1\r\n".to_vec()
+        }
+        "SET" => {
+          strings.insert(text(&command[1]), command[2].clone());
+          b"+OK\r\n".to_vec()
+        }
+        other => panic!("unexpected command {}", other),
+      };
+      writer.write_all(&reply).unwrap();
+    }
+    (lists, strings)
+  });
+
+  let scratch = tempfile::tempdir().unwrap();
+  let output = scratch.path().join("render.png");
+  let mut connection = Connection::open(&parse_url(&format!("redis://127.0.0.1:{}", port)).unwrap()).unwrap();
+  for _ in 0..3 {
+    next_job(&mut connection, "jobs", 2, &output.to_string_lossy()).unwrap();
+  }
+  drop(connection);
+
+  let (lists, strings) = server.join().unwrap();
+  assert!(strings["jobs:image:a.png"].starts_with(b"\x89PNG"));
+  assert!(lists[&b"jobs".to_vec()].is_empty() && lists[&b"jobs:processing".to_vec()].is_empty());
+  let done: Vec<String> = lists[&b"jobs:done".to_vec()].iter().map(|report| String::from_utf8_lossy(report).into_owned()).collect();
+  assert_eq!(done.len(), 2);
+  assert!(done[0].starts_with(r#"{"status":"error","message":"job: give the view as upper_left and lower_right, as center and width, or as location","#), "{}", done[0]);
+  assert!(done[1].starts_with(r#"{"status":"ok","output":"a.png","image":"jobs:image:a.png","seconds":"#), "{}", done[1]);
+
+  let reading = br#"{"schema_version": 1, "output": "c.png", "size": [4, 3], "location": "/etc/passwd"}"#;
+  assert_eq!(run_job(reading, 1, &output.to_string_lossy()), Err("job.location: set by the worker".to_string()));
+}
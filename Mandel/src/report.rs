@@ -0,0 +1,90 @@
+// Final report
+// With --output-format json, a command ends by writing one JSON object to standard
+// output saying how it went, for scripts that would otherwise have to scrape messages
+// meant for people:
+//
+//   {"status":"ok","command":"render","file":"mandel.png","size":[1000,750],...}
+//   {"status":"error","code":"usage","message":"--max-iter expects ...","argument":"--max-iter"}
+//
+// The code is "usage" for a bad command line, "interrupted" after Ctrl-C, and "failed"
+// for anything else that went wrong.
+
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::OnceLock;
+
+use crate::json::Value;
+
+static JSON: AtomicBool = AtomicBool::new(false);
+static ARGUMENTS: OnceLock<Vec<String>> = OnceLock::new();
+
+// Takes --output-format, which applies to every command, out of `arguments`, and
+// returns whether it asked for JSON.
+pub fn options(arguments: &mut Vec<String>) -> Result<bool, String> {
+  let mut json = false;
+  while let Some(i) = arguments.iter().position(|arg| arg == "--output-format") {
+    json = match arguments.get(i + 1).map(String::as_str) {
+      Some("text") => false,
+      Some("json") => true,
+      _ => return Err("--output-format expects 'text' or 'json'".to_string()),
+    };
+    arguments.drain(i..i + 2);
+  }
+  Ok(json)
+}
+
+// Turns the report on or off; `arguments` are searched for the one an error is about.
+pub fn configure(json: bool, arguments: &[String]) {
+  JSON.store(json, Ordering::Relaxed);
+  let _ = ARGUMENTS.set(arguments.to_vec());
+}
+
+// Whether standard output is reserved for the report.
+pub fn json() -> bool {
+  JSON.load(Ordering::Relaxed)
+}
+
+pub fn success(command: &str, fields: Vec<(String, Value)>) {
+  if json() {
+    let mut members = vec![("status".to_string(), "ok".into()), ("command".to_string(), command.into())];
+    members.extend(fields);
+    println!("{}", Value::Object(members));
+  }
+}
+
+pub fn failure(code: &str, message: &str) {
+  if json() {
+    let arguments = ARGUMENTS.get().map_or(&[][..], Vec::as_slice);
+    let argument = offending_argument(message, arguments).map_or(Value::Null, |argument| argument.as_str().into());
+    println!("{}", Value::Object(vec![
+      ("status".to_string(), "error".into()),
+      ("code".to_string(), code.into()),
+      ("message".to_string(), message.into()),
+      ("argument".to_string(), argument),
+    ]));
+  }
+}
+
+// The argument an error message is about: the option it starts with, or else the
+// first thing it quotes that was given on the command line as is.
+fn offending_argument(message: &str, arguments: &[String]) -> Option<String> {
+  let given = |word: &str| arguments.iter().any(|arg| arg == word);
+  let first = message.split_whitespace().next().unwrap_or("");
+  if first.starts_with("--") && given(first) {
+    return Some(first.to_string());
+  }
+  message.split('\'').skip(1).step_by(2).find(|quoted| given(quoted)).map(str::to_string)
+}
+
+#[test]
+fn test_report_options_and_offending_arguments() {
+  let mut arguments: Vec<String> = ["a.png", "--output-format", "json", "10x10"].iter().map(|s| s.to_string()).collect();
+  assert_eq!(options(&mut arguments), Ok(true));
+  assert_eq!(arguments, ["a.png", "10x10"]);
+  assert!(options(&mut vec!["--output-format".to_string(), "yaml".to_string()]).is_err());
+
+  let arguments: Vec<String> = ["a.png", "10y10", "--precision", "single", "--perturbation"].iter().map(|s| s.to_string()).collect();
+  assert_eq!(offending_argument("--precision single cannot be combined with perturbation", &arguments).as_deref(), Some("--precision"));
+  assert_eq!(offending_argument("error parsing image dimensions '10y10'", &arguments).as_deref(), Some("10y10"));
+  assert_eq!(offending_argument("--checkpoint expects a file name", &arguments), None);
+  assert_eq!(offending_argument("error writing 'b.png': denied", &arguments), None);
+}
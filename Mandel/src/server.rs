@@ -0,0 +1,352 @@
+// HTTP tile server
+// `mandel serve` renders PNGs on demand for web pages:
+//
+//   /render?cx=..&cy=..&zoom=..&w=..&h=..[&max_iter=..]   a view centered on cx + cy·i,
+//       with zoom 1 showing 4 units across
+//   /tile/{z}/{x}/{y}.png   256-pixel map tiles; zoom level z splits the square from
+//       -2 - 2i to 2 + 2i into 2^z by 2^z tiles, with tile 0/0/0 covering all of it
+//
+//   /metrics   Prometheus metrics
+//
+// Iteration limits grow with zoom as with --max-iter auto unless max_iter is given.
+// At most --jobs images render at once, each with --threads; other requests wait their
+// turn, and beyond MAX_WAITING of them are turned away with 503. With --cache, images go
+// through the same tile cache renders use. Connections that stall for TIMEOUT are
+// dropped, as are new ones once MAX_CONNECTIONS are open.
+
+use std::collections::HashMap;
+use std::io::{self, BufRead, BufReader, Write};
+use std::net::{TcpListener, TcpStream};
+use std::str::FromStr;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+
+use crossbeam::channel::{self, Receiver, Sender};
+use image::png::PNGEncoder;
+use image::ColorType;
+use num::Complex;
+
+use crate::cache::{TileCache, TILE_SIZE};
+use crate::{auto_max_iter, log, metrics, render_parallel, shades, Plane, Sampler};
+
+// Largest image side served, so one request can't tie the machine up for hours.
+pub const MAX_SIDE: usize = 4096;
+
+// Largest max_iter served, for the same reason.
+pub const MAX_ITER: usize = 100_000;
+
+// Deepest tile zoom level; beyond this f64 runs out of precision.
+const MAX_TILE_ZOOM: u32 = 40;
+
+// Requests waiting for a render slot beyond this many are turned away with 503.
+const MAX_WAITING: usize = 256;
+
+// Longest request line and headers read, in bytes; longer ones are answered with 431.
+const MAX_HEAD: u64 = 16 << 10;
+
+// How long a connection may go without sending or taking a byte before it's dropped.
+const TIMEOUT: Duration = Duration::from_secs(30);
+
+// Connections handled at once, each on a thread; new ones beyond this are closed.
+const MAX_CONNECTIONS: usize = 512;
+
+// What a request asks to have rendered.
+#[derive(Debug, PartialEq)]
+struct Request {
+  bounds: (usize, usize),
+  upper_left: Complex<f64>,
+  lower_right: Complex<f64>,
+  limit: usize,
+}
+
+// An HTTP error status and the message sent with it.
+type Failure = (u16, String);
+
+// The render slots every connection shares: a render takes a token from `free` and puts
+// it back when it's done.
+struct Slots {
+  free: Receiver<()>,
+  release: Sender<()>,
+  waiting: AtomicUsize,
+}
+
+impl Slots {
+  fn new(jobs: usize) -> Slots {
+    let (release, free) = channel::bounded(jobs);
+    for _ in 0..jobs {
+      release.send(()).unwrap();
+    }
+    Slots { free, release, waiting: AtomicUsize::new(0) }
+  }
+
+  // Runs `render` once a slot is free, unless too many requests are waiting already.
+  fn run<T>(&self, render: impl FnOnce() -> T) -> Result<T, Failure> {
+    if self.waiting.fetch_add(1, Ordering::SeqCst) >= MAX_WAITING {
+      self.waiting.fetch_sub(1, Ordering::SeqCst);
+      return Err((503, format!("{} requests are already waiting; try again later", MAX_WAITING)));
+    }
+    self.free.recv().unwrap();
+    self.waiting.fetch_sub(1, Ordering::SeqCst);
+    let result = render();
+    self.release.send(()).unwrap();
+    Ok(result)
+  }
+}
+
+pub fn serve(address: &str, threads: usize, jobs: usize, cache: Option<TileCache>) -> Result<(), io::Error> {
+  let listener = TcpListener::bind(address)?;
+  log::info(&format!("serving on http://{}", listener.local_addr()?));
+  serve_listener(listener, threads, jobs, cache.map(Arc::new))
+}
+
+fn serve_listener(listener: TcpListener, threads: usize, jobs: usize, cache: Option<Arc<TileCache>>) -> Result<(), io::Error> {
+  let (slots, open) = (Arc::new(Slots::new(jobs)), Arc::new(AtomicUsize::new(0)));
+  for stream in listener.incoming() {
+    let (cache, slots) = (cache.clone(), slots.clone());
+    spawn_connection(stream?, &open, move |stream| handle_connection(stream, threads, cache.as_deref(), &slots));
+  }
+  Ok(())
+}
+
+// Handles `stream` on a thread of its own, with TIMEOUT on its reads and writes, unless
+// MAX_CONNECTIONS are open already; `open` counts them.
+pub fn spawn_connection(stream: TcpStream, open: &Arc<AtomicUsize>, handle: impl FnOnce(TcpStream) -> Result<(), io::Error> + Send + 'static) {
+  if open.fetch_add(1, Ordering::SeqCst) >= MAX_CONNECTIONS {
+    open.fetch_sub(1, Ordering::SeqCst);
+    log::warn(&format!("{} connections are open already; closing a new one", MAX_CONNECTIONS));
+    return;
+  }
+  let open = open.clone();
+  std::thread::spawn(move || {
+    let result = stream.set_read_timeout(Some(TIMEOUT)).and_then(|()| stream.set_write_timeout(Some(TIMEOUT))).and_then(|()| handle(stream));
+    open.fetch_sub(1, Ordering::SeqCst);
+    if let Err(e) = result {
+      log::warn(&format!("request failed: {}", e));
+    }
+  });
+}
+
+// Reads a request line and the headers after it, or the 431 to answer if they run past
+// MAX_HEAD bytes.
+pub fn read_head(reader: impl BufRead) -> Result<Result<(String, Vec<String>), Failure>, io::Error> {
+  let mut limited = reader.take(MAX_HEAD);
+  let mut request_line = String::new();
+  limited.read_line(&mut request_line)?;
+  let mut headers = Vec::new();
+  loop {
+    let mut header = String::new();
+    if limited.read_line(&mut header)? <= 2 {
+      break;
+    }
+    headers.push(header);
+  }
+  if limited.limit() == 0 {
+    return Ok(Err((431, format!("request lines and headers are limited to {} bytes", MAX_HEAD))));
+  }
+  Ok(Ok((request_line, headers)))
+}
+
+fn handle_connection(stream: TcpStream, threads: usize, cache: Option<&TileCache>, slots: &Slots) -> Result<(), io::Error> {
+  let mut reader = BufReader::new(stream.try_clone()?);
+  // Headers carry nothing we need, but have to be read past.
+  let head = read_head(&mut reader)?;
+
+  let response = head.and_then(|(request_line, _headers)| match request_line.split(' ').collect::<Vec<_>>().as_slice() {
+    ["GET", "/metrics", _version] => Ok((metrics::CONTENT_TYPE, metrics::text().into_bytes())),
+    ["GET", target, _version] => parse_target(target).and_then(|request| render_png(&request, threads, cache, slots)).map(|png| ("image/png", png)),
+    [_, _, _] => Err((405, "only GET is supported".to_string())),
+    _ => Err((400, "malformed request line".to_string())),
+  });
+
+  let mut writer = io::BufWriter::new(stream);
+  match response {
+    Ok((content_type, body)) => {
+      write!(writer, "HTTP/1.1 200 OK\r\nContent-Type: {}\r\nContent-Length: {}\r\n", content_type, body.len())?;
+      write!(writer, "Access-Control-Allow-Origin: *\r\nConnection: close\r\n\r\n")?;
+      writer.write_all(&body)?;
+    }
+    Err((status, message)) => {
+      write!(writer, "HTTP/1.1 {} {}\r\nContent-Type: text/plain\r\nContent-Length: {}\r\n", status, reason(status), message.len() + 1)?;
+      write!(writer, "Connection: close\r\n\r\n{}\n", message)?;
+    }
+  }
+  writer.flush()
+}
+
+// The reason phrase for the statuses the servers answer with.
+pub fn reason(status: u16) -> &'static str {
+  match status {
+    200 => "OK",
+    202 => "Accepted",
+    400 => "Bad Request",
+    404 => "Not Found",
+    405 => "Method Not Allowed",
+    409 => "Conflict",
+    413 => "Content Too Large",
+    431 => "Request Header Fields Too Large",
+    503 => "Service Unavailable",
+    _ => "Internal Server Error",
+  }
+}
+
+fn parse_target(target: &str) -> Result<Request, Failure> {
+  let (path, query) = target.split_once('?').unwrap_or((target, ""));
+  let parameters: HashMap<&str, &str> = query.split('&').filter_map(|pair| pair.split_once('=')).collect();
+
+  if path == "/render" {
+    parse_render(&parameters)
+  } else if let Some(tile) = path.strip_prefix("/tile/").and_then(|tile| tile.strip_suffix(".png")) {
+    parse_tile(tile, &parameters)
+  } else {
+    Err((404, format!("no such endpoint '{}'; try /render or /tile/{{z}}/{{x}}/{{y}}.png", path)))
+  }
+}
+
+fn parse_render(parameters: &HashMap<&str, &str>) -> Result<Request, Failure> {
+  let number = |name: &str| -> Result<f64, Failure> {
+    let value = parameters.get(name).ok_or((400, format!("missing parameter '{}'", name)))?;
+    f64::from_str(value).ok().filter(|value| value.is_finite()).ok_or((400, format!("parameter '{}' is not a number", name)))
+  };
+  let side = |name: &str| -> Result<usize, Failure> {
+    match parameters.get(name).map(|value| usize::from_str(value)) {
+      Some(Ok(side)) if side > 0 && side <= MAX_SIDE => Ok(side),
+      _ => Err((400, format!("parameter '{}' must be a size from 1 to {}", name, MAX_SIDE))),
+    }
+  };
+
+  let center = Complex { re: number("cx")?, im: number("cy")? };
+  let zoom = number("zoom")?;
+  if zoom <= 0.0 {
+    return Err((400, "zoom must be positive".to_string()));
+  }
+  let bounds = (side("w")?, side("h")?);
+
+  let width = 4.0 / zoom;
+  let half = Complex { re: width / 2.0, im: width * bounds.1 as f64 / bounds.0 as f64 / 2.0 };
+  Ok(Request {
+    bounds,
+    upper_left: Complex { re: center.re - half.re, im: center.im + half.im },
+    lower_right: Complex { re: center.re + half.re, im: center.im - half.im },
+    limit: parse_limit(parameters, width)?,
+  })
+}
+
+fn parse_tile(tile: &str, parameters: &HashMap<&str, &str>) -> Result<Request, Failure> {
+  let invalid = || (400, format!("bad tile '{}'; expected /tile/{{z}}/{{x}}/{{y}}.png", tile));
+  let fields: Vec<u64> = tile.split('/').map(|field| field.parse().ok()).collect::<Option<_>>().ok_or_else(invalid)?;
+  let &[zoom, x, y] = fields.as_slice() else {
+    return Err(invalid());
+  };
+  if zoom > MAX_TILE_ZOOM as u64 || x >> zoom != 0 || y >> zoom != 0 {
+    return Err((404, format!("tile {}/{}/{} is out of range", zoom, x, y)));
+  }
+
+  let size = 4.0 / (1u64 << zoom) as f64;
+  let upper_left = Complex { re: -2.0 + x as f64 * size, im: 2.0 - y as f64 * size };
+  Ok(Request {
+    bounds: (TILE_SIZE, TILE_SIZE),
+    upper_left,
+    lower_right: Complex { re: upper_left.re + size, im: upper_left.im - size },
+    limit: parse_limit(parameters, size)?,
+  })
+}
+
+fn parse_limit(parameters: &HashMap<&str, &str>, view_width: f64) -> Result<usize, Failure> {
+  match parameters.get("max_iter").map(|value| usize::from_str(value)) {
+    None => Ok(auto_max_iter(view_width)),
+    Some(Ok(limit)) if limit > 0 && limit <= MAX_ITER => Ok(limit),
+    Some(_) => Err((400, format!("max_iter must be a number from 1 to {}", MAX_ITER))),
+  }
+}
+
+fn render_png(request: &Request, threads: usize, cache: Option<&TileCache>, slots: &Slots) -> Result<Vec<u8>, Failure> {
+  let bounds = request.bounds;
+  let plane = Plane::mandelbrot(bounds, request.upper_left, request.lower_right, request.limit);
+  // Same key format as render_cached, so a whole-image tile can be shared with renders.
+  let key = plane.tile_key(0, 0).map(|key| format!("{} size {}x{}", key, bounds.0, bounds.1));
+
+  let cached = cache.zip(key.as_ref()).and_then(|(cache, key)| cache.get(key, bounds.0 * bounds.1));
+  let pixels = match cached {
+    Some(pixels) => pixels,
+    None => {
+      let mut pixels = vec![0; bounds.0 * bounds.1];
+      let result = slots.run(|| {
+        let start = std::time::Instant::now();
+        let result = render_parallel(&mut pixels, bounds, &plane, threads, 0, 1, true);
+        metrics::rendered(pixels.len() as u64, start.elapsed().as_secs_f64(), result.is_ok());
+        result
+      })?;
+      result.map_err(|message| (500, message))?;
+      if let Some((cache, key)) = cache.zip(key.as_ref()) {
+        if let Err(e) = cache.put(key, &pixels) {
+          log::warn(&format!("could not cache image: {}", e));
+        }
+      }
+      pixels
+    }
+  };
+
+  let mut png = Vec::new();
+  PNGEncoder::new(&mut png).encode(&shades(&pixels, request.limit), bounds.0 as u32, bounds.1 as u32, ColorType::Gray(8))
+    .map_err(|e| (500, format!("error encoding PNG: {}", e)))?;
+  Ok(png)
+}
+
+#[test]
+fn test_parse_targets() {
+  let render = parse_target("/render?cx=-0.5&cy=0&zoom=2&w=40&h=20&max_iter=100").unwrap();
+  assert_eq!(render, Request {
+    bounds: (40, 20),
+    upper_left: Complex { re: -1.5, im: 0.5 },
+    lower_right: Complex { re: 0.5, im: -0.5 },
+    limit: 100,
+  });
+
+  let tile = parse_target("/tile/1/1/0.png").unwrap();
+  assert_eq!((tile.upper_left, tile.lower_right), (Complex { re: 0.0, im: 2.0 }, Complex { re: 2.0, im: 0.0 }));
+
+  assert_eq!(parse_target("/tile/1/2/0.png").unwrap_err().0, 404);
+  assert_eq!(parse_target("/render?cx=0&cy=0&zoom=1&w=0&h=10").unwrap_err().0, 400);
+  assert_eq!(parse_target("/nothing").unwrap_err().0, 404);
+  assert_eq!(parse_target("/tile/0/0/0.png?max_iter=100000").unwrap().limit, MAX_ITER);
+  assert_eq!(parse_target("/tile/0/0/0.png?max_iter=100001").unwrap_err().0, 400);
+}
+
+#[test]
+fn test_serves_png_tiles() {
+  use std::io::Read;
+
+  let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+  let address = listener.local_addr().unwrap();
+  std::thread::spawn(move || serve_listener(listener, 2, 1, None));
+
+  // Requests at once take turns at the one render slot.
+  let requests: Vec<_> = (0..3).map(|_| std::thread::spawn(move || {
+    let mut stream = TcpStream::connect(address).unwrap();
+    write!(stream, "GET /tile/0/0/0.png?max_iter=50 HTTP/1.1\r\nHost: localhost\r\n\r\n").unwrap();
+    let mut response = Vec::new();
+    stream.read_to_end(&mut response).unwrap();
+    response
+  })).collect();
+  for request in requests {
+    let response = request.join().unwrap();
+    assert!(response.starts_with(b"HTTP/1.1 200 OK\r\n"));
+    let body = response.windows(4).position(|window| window == b"\r\n\r\n").unwrap() + 4;
+    assert!(response[body..].starts_with(b"\x89PNG"));
+  }
+
+  let slots = Slots::new(1);
+  slots.waiting.store(MAX_WAITING, Ordering::SeqCst);
+  assert_eq!(slots.run(|| ()).unwrap_err().0, 503);
+}
+
+#[test]
+fn test_limits_request_heads() {
+  let head = read_head(&mut io::Cursor::new(b"GET / HTTP/1.1\r\nHost: x\r\n\r\nbody".as_slice())).unwrap();
+  assert_eq!(head, Ok(("GET / HTTP/1.1\r\n".to_string(), vec!["Host: x\r\n".to_string()])));
+  let endless = vec![b'a'; MAX_HEAD as usize * 2];
+  assert_eq!(read_head(&mut io::Cursor::new(endless)).unwrap().unwrap_err().0, 431);
+  let headers = format!("GET / HTTP/1.1\r\n{}\r\n", "X-Padding: yes\r\n".repeat(MAX_HEAD as usize / 8));
+  assert_eq!(read_head(&mut io::Cursor::new(headers.into_bytes())).unwrap().unwrap_err().0, 431);
+}
@@ -0,0 +1,141 @@
+// Splitting
+// `mandel split JOB --shards N --out DIR` cuts the render a job describes into N shards,
+// a grid of pieces as nearly square as N allows, and writes each as a job of its own,
+// DIR/shard-001.json and on, along with DIR/manifest.json listing what each covers. The
+// shards can then be rendered anywhere the plain command line runs, with `mandel batch`
+// or `render --stdin`, each writing shard-001.png and on where it is run, and put back
+// together with `mandel stitch`. Shard corners are worked out in exact decimal
+// arithmetic, as for a crop, so the pieces of a deep zoom meet exactly. Stitching can't
+// line up turned pixels, so jobs with a rotation or a location file's view are refused.
+
+use std::path::Path;
+
+use crate::crop::{crop_corners, Rect};
+use crate::json::{self, Value};
+use crate::{job, log, parse_pair};
+
+// Fields that give the view, and so are replaced in every shard.
+const VIEW_FIELDS: [&str; 6] = ["output", "size", "upper_left", "lower_right", "center", "width"];
+
+pub fn main(arguments: &[String]) -> Result<Vec<(String, Value)>, String> {
+  let (mut path, mut shards, mut out, mut force) = (None, None, "shards", false);
+  let mut options = arguments.iter();
+  while let Some(option) = options.next() {
+    match option.as_str() {
+      "--shards" => shards = Some(options.next().and_then(|value| value.parse::<usize>().ok()).filter(|&shards| shards > 0).ok_or("--shards expects a positive number")?),
+      "--out" => out = options.next().ok_or("--out expects a directory")?.as_str(),
+      "--force" => force = true,
+      job if path.is_none() && !job.starts_with("--") => path = Some(job),
+      _ => return Err("split accepts JOB, --shards N, --out DIR and --force".to_string()),
+    }
+  }
+  let path = path.ok_or("split expects a job file to split")?;
+  let shards = shards.ok_or("split needs --shards N")?;
+  let text = std::fs::read_to_string(path).map_err(|e| format!("error reading job '{}': {}", path, e))?;
+  let job = json::parse(&text).map_err(|e| format!("job '{}': {}", path, e))?;
+  let Value::Object(members) = &job else {
+    return Err(format!("job '{}': expected a job object, got {}", path, job));
+  };
+  for refused in ["location", "rotate"] {
+    if members.iter().any(|(name, _)| name == refused) {
+      return Err(format!("job.{}: split can only cut up unturned views given by corners or by center and width", refused));
+    }
+  }
+  let arguments = job::arguments(&job, "job")?;
+  let bounds = parse_pair::<usize>(&arguments[1], 'x').unwrap();
+  let (columns, rows) = grid(shards, bounds).ok_or(format!("a {}x{} image can't be cut into {} shards", bounds.0, bounds.1, shards))?;
+
+  let manifest = Path::new(out).join("manifest.json");
+  if !force && manifest.exists() {
+    return Err(format!("'{}' already exists; pass --force to overwrite it", manifest.display()));
+  }
+  std::fs::create_dir_all(out).map_err(|e| format!("error creating shard directory '{}': {}", out, e))?;
+  let kept: Vec<(String, Value)> = members.iter().filter(|(name, _)| !VIEW_FIELDS.contains(&name.as_str())).cloned().collect();
+  let mut listed = Vec::new();
+  for (i, rect) in rects(bounds, columns, rows).into_iter().enumerate() {
+    let (upper_left, lower_right) = crop_corners(&arguments[2], &arguments[3], bounds, rect, 0.0)?;
+    let (name, image) = (format!("shard-{:03}.json", i + 1), format!("shard-{:03}.png", i + 1));
+    let mut shard = vec![
+      ("output".to_string(), image.as_str().into()),
+      ("size".to_string(), Value::Array(vec![rect.2.into(), rect.3.into()])),
+      ("upper_left".to_string(), upper_left.as_str().into()),
+      ("lower_right".to_string(), lower_right.as_str().into()),
+    ];
+    shard.extend(kept.iter().cloned());
+    write(&Path::new(out).join(&name), &Value::Object(shard))?;
+    listed.push(Value::Object(vec![
+      ("job".to_string(), name.as_str().into()),
+      ("image".to_string(), image.as_str().into()),
+      ("rect".to_string(), Value::Array(vec![rect.0.into(), rect.1.into(), rect.2.into(), rect.3.into()])),
+    ]));
+  }
+  let images: Vec<String> = (1..=listed.len()).map(|i| format!("shard-{:03}.png", i)).collect();
+  let stitch = format!("mandel stitch {} --out {}", images.join(" "), arguments[0]);
+  write(&manifest, &Value::Object(vec![
+    ("output".to_string(), arguments[0].as_str().into()),
+    ("size".to_string(), Value::Array(vec![bounds.0.into(), bounds.1.into()])),
+    ("upper_left".to_string(), arguments[2].as_str().into()),
+    ("lower_right".to_string(), arguments[3].as_str().into()),
+    ("grid".to_string(), Value::Array(vec![columns.into(), rows.into()])),
+    ("shards".to_string(), Value::Array(listed)),
+    ("stitch".to_string(), stitch.as_str().into()),
+  ]))?;
+  log::info(&format!("split {}x{} '{}' into {}x{} shards in '{}'; once rendered, put them together with: {}", bounds.0, bounds.1, arguments[0], columns, rows, out, stitch));
+  Ok(vec![("manifest".to_string(), manifest.to_string_lossy().as_ref().into()), ("shards".to_string(), images.len().into())])
+}
+
+fn write(path: &Path, value: &Value) -> Result<(), String> {
+  std::fs::write(path, value.pretty() + "\n").map_err(|e| format!("error writing '{}': {}", path.display(), e))
+}
+
+// The columns and rows of `shards` shards of an image of size `bounds`, choosing the way
+// of splitting that makes them most nearly square, or None if some shard would be empty.
+fn grid(shards: usize, bounds: (usize, usize)) -> Option<(usize, usize)> {
+  let squareness = |&(columns, rows): &(usize, usize)| ((bounds.0 as f64 / columns as f64) / (bounds.1 as f64 / rows as f64)).ln().abs();
+  (1..=shards).filter(|columns| shards.is_multiple_of(*columns)).map(|columns| (columns, shards / columns))
+    .filter(|&(columns, rows)| columns <= bounds.0 && rows <= bounds.1)
+    .min_by(|a, b| squareness(a).total_cmp(&squareness(b)))
+}
+
+// The pixel rectangles of the shards, row by row, the first ones a pixel wider or higher
+// where the image doesn't divide evenly.
+fn rects(bounds: (usize, usize), columns: usize, rows: usize) -> Vec<Rect> {
+  let spans = |length: usize, parts: usize| -> Vec<(usize, usize)> {
+    (0..parts).map(|i| (i * (length / parts) + i.min(length % parts), length / parts + usize::from(i < length % parts))).collect()
+  };
+  let (across, down) = (spans(bounds.0, columns), spans(bounds.1, rows));
+  down.iter().flat_map(|&(y, height)| across.iter().map(move |&(x, width)| (x, y, width, height))).collect()
+}
+
+#[test]
+fn test_splitting() {
+  assert_eq!(grid(4, (400, 100)), Some((4, 1)));
+  assert_eq!(grid(4, (200, 200)), Some((2, 2)));
+  assert_eq!(grid(6, (300, 200)), Some((3, 2)));
+  assert_eq!(grid(7, (3, 3)), None);
+  assert_eq!(rects((5, 4), 2, 2), [(0, 0, 3, 2), (3, 0, 2, 2), (0, 2, 3, 2), (3, 2, 2, 2)]);
+
+  // Rendered and stitched together, the shards make the same image as the job.
+  let dir = tempfile::tempdir().unwrap();
+  let path = |name: &str| dir.path().join(name).to_str().unwrap().to_string();
+  std::fs::write(path("job.json"), format!(r#"{{"schema_version": 1, "output": "{}", "size": [41, 30], "center": "-0.6,0.1", "width": 3, "max_iter": 60}}"#, path("whole.png"))).unwrap();
+  let fields = main(&[path("job.json"), "--shards".to_string(), "6".to_string(), "--out".to_string(), path("shards")]).unwrap();
+  assert_eq!(fields[1].1, Value::from(6usize));
+  assert!(main(&[path("job.json"), "--shards".to_string(), "6".to_string(), "--out".to_string(), path("shards")]).unwrap_err().contains("already exists"));
+  let mut tiles = Vec::new();
+  for i in 1..=6 {
+    let shard = json::parse(&std::fs::read_to_string(path(&format!("shards/shard-{:03}.json", i))).unwrap()).unwrap();
+    let mut arguments = job::arguments(&shard, "shard").unwrap();
+    arguments[0] = path(&arguments[0]);
+    arguments.push("--no-progress".to_string());
+    crate::render(crate::parse_arguments(&arguments).unwrap()).unwrap();
+    tiles.push(arguments[0].clone());
+  }
+  let job = json::parse(&std::fs::read_to_string(path("job.json")).unwrap()).unwrap();
+  crate::render(crate::parse_arguments(&[job::arguments(&job, "job").unwrap(), vec!["--no-progress".to_string()]].concat()).unwrap()).unwrap();
+  crate::stitch::main(&[tiles, vec!["--out".to_string(), path("stitched.png")]].concat()).unwrap();
+  assert_eq!(crate::read_image(&path("stitched.png")).unwrap().pixels, crate::read_image(&path("whole.png")).unwrap().pixels);
+
+  std::fs::write(path("turned.json"), r#"{"schema_version": 1, "output": "a.png", "size": [40, 30], "center": "0,0", "width": 3, "rotate": 10}"#).unwrap();
+  assert!(main(&[path("turned.json"), "--shards".to_string(), "2".to_string()]).unwrap_err().starts_with("job.rotate"));
+}
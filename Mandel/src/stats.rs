@@ -0,0 +1,264 @@
+// Render statistics
+// Every shaded sample bumps counters local to its thread; render threads fold them into
+// process-wide totals when they finish, along with how long they spent working. The
+// report puts numbers on what the various rendering options actually buy. With
+// --histogram they also count the samples escaping at each iteration, for a CSV file.
+// The counts of the latest render alone go into the --stats-json sidecar.
+
+use std::cell::{Cell, RefCell};
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Mutex;
+use std::time::Duration;
+
+use crate::json::Value;
+use crate::log;
+
+#[derive(Clone, Copy)]
+struct Counts {
+  samples: u64,
+  interior: u64,
+  iterations: u64,
+  // Iterations of the samples that escaped, and the fewest and most any took.
+  escaping: u64,
+  fewest: u64,
+  most: u64,
+}
+
+const NO_COUNTS: Counts = Counts { samples: 0, interior: 0, iterations: 0, escaping: 0, fewest: u64::MAX, most: 0 };
+
+impl Counts {
+  fn add(&mut self, other: Counts) {
+    self.samples += other.samples;
+    self.interior += other.interior;
+    self.iterations += other.iterations;
+    self.escaping += other.escaping;
+    self.fewest = self.fewest.min(other.fewest);
+    self.most = self.most.max(other.most);
+  }
+}
+
+thread_local! {
+  static COUNTS: Cell<Counts> = const { Cell::new(NO_COUNTS) };
+  static HISTOGRAM: RefCell<Histogram> = const { RefCell::new(Histogram { escapes: Vec::new(), interior: 0 }) };
+}
+
+#[derive(Default)]
+struct Histogram {
+  // Samples that escaped after each number of iterations.
+  escapes: Vec<u64>,
+  interior: u64,
+}
+
+struct Totals {
+  counts: Counts,
+  // Since begin_render.
+  render: Counts,
+  // Busy time per render thread, indexed by the thread's position in its pool.
+  busy: Vec<Duration>,
+  histogram: Histogram,
+}
+
+static TOTALS: Mutex<Totals> = Mutex::new(Totals { counts: NO_COUNTS, render: NO_COUNTS, busy: Vec::new(), histogram: Histogram { escapes: Vec::new(), interior: 0 } });
+
+// Whether to count escapes by iteration, which only --histogram needs.
+static KEEP_HISTOGRAM: AtomicBool = AtomicBool::new(false);
+
+pub fn keep_histogram() {
+  KEEP_HISTOGRAM.store(true, Ordering::Relaxed);
+}
+
+// Counts one sample that escaped after `escape` iterations, or none within `limit`.
+pub fn record(escape: Option<usize>, limit: usize) {
+  COUNTS.with(|counts| {
+    let mut c = counts.get();
+    c.samples += 1;
+    match escape {
+      Some(count) => {
+        c.iterations += count as u64;
+        c.escaping += count as u64;
+        c.fewest = c.fewest.min(count as u64);
+        c.most = c.most.max(count as u64);
+      }
+      None => {
+        c.interior += 1;
+        c.iterations += limit as u64;
+      }
+    }
+    counts.set(c);
+  });
+  if KEEP_HISTOGRAM.load(Ordering::Relaxed) {
+    HISTOGRAM.with_borrow_mut(|histogram| match escape {
+      Some(count) => {
+        if histogram.escapes.len() <= count {
+          histogram.escapes.resize(count + 1, 0);
+        }
+        histogram.escapes[count] += 1;
+      }
+      None => histogram.interior += 1,
+    });
+  }
+}
+
+// Adds this thread's counts, and `busy` to the time of pool thread `thread`.
+pub fn flush(thread: usize, busy: Duration) {
+  let counts = COUNTS.with(|counts| counts.replace(NO_COUNTS));
+  let histogram = HISTOGRAM.take();
+  let mut totals = TOTALS.lock().unwrap();
+  let escapes = &mut totals.histogram.escapes;
+  if escapes.len() < histogram.escapes.len() {
+    escapes.resize(histogram.escapes.len(), 0);
+  }
+  for (total, count) in escapes.iter_mut().zip(histogram.escapes) {
+    *total += count;
+  }
+  totals.histogram.interior += histogram.interior;
+  totals.counts.add(counts);
+  totals.render.add(counts);
+  if totals.busy.len() <= thread {
+    totals.busy.resize(thread + 1, Duration::ZERO);
+  }
+  totals.busy[thread] += busy;
+}
+
+// Starts the counts that render_counts reports afresh.
+pub fn begin_render() {
+  TOTALS.lock().unwrap().render = NO_COUNTS;
+}
+
+// The counts since begin_render, for the --stats-json sidecar: samples, the fewest, most
+// and mean iterations of those that escaped, and the fraction that didn't.
+pub fn render_counts() -> Vec<(String, Value)> {
+  let Counts { samples, interior, escaping, fewest, most, .. } = TOTALS.lock().unwrap().render;
+  let escaped = samples - interior;
+  let iterations = match escaped {
+    0 => Value::Null,
+    _ => Value::Object(vec![
+      ("min".to_string(), (fewest as usize).into()),
+      ("max".to_string(), (most as usize).into()),
+      ("mean".to_string(), (escaping as f64 / escaped as f64).into()),
+    ]),
+  };
+  vec![
+    ("samples".to_string(), (samples as usize).into()),
+    ("iterations".to_string(), iterations),
+    ("interior_fraction".to_string(), (interior as f64 / samples.max(1) as f64).into()),
+  ]
+}
+
+// Iterations counted so far, by every render in the process.
+pub fn iterations() -> u64 {
+  TOTALS.lock().unwrap().counts.iterations
+}
+
+// Prints the totals gathered so far for a render of `pixels` pixels that took `wall`.
+pub fn report(pixels: usize, wall: Duration) {
+  let totals = TOTALS.lock().unwrap();
+  let Counts { samples, interior, iterations, .. } = totals.counts;
+  let seconds = wall.as_secs_f64();
+
+  log::info(&format!("wall time      {:.3} s", seconds));
+  for (thread, busy) in totals.busy.iter().enumerate() {
+    log::info(&format!("thread {:<7} {:.3} s busy ({:.0}%)", thread, busy.as_secs_f64(), 100.0 * busy.as_secs_f64() / seconds));
+  }
+  log::info(&format!("samples        {} ({:.2} per pixel)", samples, samples as f64 / pixels as f64));
+  log::info(&format!("iterations     {}", iterations));
+  log::info(&format!("iterations/s   {:.3e}", iterations as f64 / seconds));
+  log::info(&format!("pixels/s       {:.3e}", pixels as f64 / seconds));
+  log::info(&format!("interior       {:.1}% of samples", 100.0 * interior as f64 / samples.max(1) as f64));
+}
+
+// Writes how many samples escaped after each number of iterations to `path` as CSV, with
+// each count's fraction of all samples and the cumulative fraction, then a last row for
+// the interior: the samples that never escaped.
+pub fn write_histogram(path: &str) -> Result<(), String> {
+  let totals = TOTALS.lock().unwrap();
+  let Histogram { escapes, interior } = &totals.histogram;
+  let (escaped, interior) = (escapes.iter().sum::<u64>(), *interior);
+  let samples = (escaped + interior).max(1) as f64;
+  let failed = |e: std::io::Error| format!("error writing histogram '{}': {}", path, e);
+  let mut csv = BufWriter::new(File::create(path).map_err(failed)?);
+  writeln!(csv, "iterations,samples,fraction,cumulative").map_err(failed)?;
+  let mut cumulative = 0;
+  for (iterations, &count) in escapes.iter().enumerate() {
+    cumulative += count;
+    writeln!(csv, "{},{},{},{}", iterations, count, count as f64 / samples, cumulative as f64 / samples).map_err(failed)?;
+  }
+  writeln!(csv, "interior,{},{},{}", interior, interior as f64 / samples, (cumulative + interior) as f64 / samples).map_err(failed)?;
+  csv.flush().map_err(failed)?;
+
+  // Iteration limits well past where nearly everything has escaped only find interior.
+  let mut running = 0;
+  if let Some(at) = escapes.iter().position(|&count| {
+    running += count;
+    running as f64 >= 0.99 * escaped as f64
+  }) {
+    log::info(&format!("99% of escaping samples escaped within {} iterations; {:.1}% of samples never did", at + 1, 100.0 * interior as f64 / samples));
+  }
+  Ok(())
+}
+
+#[test]
+fn test_flush_moves_thread_counts_into_totals() {
+  // Run on a fresh thread so other tests' samples don't leak into these counts.
+  std::thread::spawn(|| {
+    record(Some(10), 100);
+    record(None, 100);
+    let before = TOTALS.lock().unwrap().counts.iterations;
+    flush(0, Duration::from_millis(5));
+    assert!(TOTALS.lock().unwrap().counts.iterations >= before + 110);
+    assert_eq!(COUNTS.with(Cell::get).samples, 0);
+  }).join().unwrap();
+}
+
+#[test]
+fn test_render_counts_cover_escaping_samples() {
+  let mut counts = NO_COUNTS;
+  for (escape, samples) in [(Some(4), 1), (Some(10), 2), (None, 3)] {
+    let mut more = NO_COUNTS;
+    more.samples = samples;
+    if let Some(count) = escape {
+      (more.escaping, more.fewest, more.most) = (count * samples, count, count);
+    } else {
+      more.interior = samples;
+    }
+    counts.add(more);
+  }
+  assert_eq!((counts.samples, counts.interior, counts.escaping, counts.fewest, counts.most), (6, 3, 24, 4, 10));
+
+  std::thread::spawn(|| {
+    begin_render();
+    record(Some(7), 100);
+    record(None, 100);
+    flush(0, Duration::ZERO);
+  }).join().unwrap();
+  // Other tests may flush samples of their own in between, so only check the shape.
+  let fields = render_counts();
+  let names: Vec<&str> = fields.iter().map(|(name, _)| name.as_str()).collect();
+  assert_eq!(names, ["samples", "iterations", "interior_fraction"]);
+  assert!(matches!(fields[1].1, Value::Object(_)));
+}
+
+#[test]
+fn test_histogram_counts_escapes_by_iteration() {
+  std::thread::spawn(|| {
+    keep_histogram();
+    for escape in [Some(0), Some(3), Some(3), None] {
+      record(escape, 10);
+    }
+    flush(0, Duration::ZERO);
+  }).join().unwrap();
+
+  let dir = tempfile::tempdir().unwrap();
+  let path = dir.path().join("histogram.csv");
+  write_histogram(path.to_str().unwrap()).unwrap();
+  let csv = std::fs::read_to_string(&path).unwrap();
+  let rows: Vec<Vec<&str>> = csv.lines().map(|line| line.split(',').collect()).collect();
+  assert_eq!(rows[0], ["iterations", "samples", "fraction", "cumulative"]);
+  // Other tests' samples land in the same totals, so look for at least these.
+  let samples = |row: &Vec<&str>| row[1].parse::<u64>().unwrap();
+  assert!(samples(&rows[1]) >= 1 && samples(&rows[4]) >= 2);
+  let last = rows.last().unwrap();
+  assert!(last[0] == "interior" && samples(last) >= 1 && last[3] == "1");
+}
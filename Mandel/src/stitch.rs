@@ -0,0 +1,190 @@
+// Stitching
+// `mandel stitch TILE.png... --out FILE.png` puts a view rendered a piece at a time back
+// together, say from pieces rendered on different machines. Each tile's metadata says
+// which corners it covers; the tiles must have been rendered with the same options, at
+// the same pixel spacing, and on one grid of pixels, and between them they must cover the
+// rectangle around them all. Where tiles overlap, the later one's pixels win. FILE records
+// the whole view, so `mandel rerender` can render it again. A legend appended below a
+// tile is left out.
+
+use std::fs::File;
+use std::io::BufWriter;
+
+use dashu_float::DBig;
+
+use crate::crop::options_but_view;
+use crate::location::exact_corner;
+use crate::metadata::{self, Provenance};
+use crate::{log, parse_arguments, read_image, Image};
+
+// How far, in pixels, a tile may sit off the grid or its spacing differ across the image.
+const TOLERANCE: f64 = 1e-3;
+
+struct Tile {
+  path: String,
+  upper_left: (DBig, DBig),
+  lower_right: (DBig, DBig),
+  bounds: (usize, usize),
+  // The options it was rendered with, but its size and corners.
+  options: Vec<String>,
+  // 1 for gray, 3 for RGB.
+  channels: usize,
+  pixels: Vec<u8>,
+}
+
+pub fn main(arguments: &[String]) -> Result<(), String> {
+  let (mut paths, mut out, mut force) = (Vec::new(), None, false);
+  let mut options = arguments.iter();
+  while let Some(option) = options.next() {
+    match option.as_str() {
+      "--out" => out = Some(options.next().ok_or("--out expects a file name")?.as_str()),
+      "--force" => force = true,
+      path if !path.starts_with("--") => paths.push(path),
+      _ => return Err("stitch accepts TILE.png files, --out FILE.png and --force".to_string()),
+    }
+  }
+  let out = out.ok_or("stitch needs --out FILE.png")?;
+  if paths.is_empty() {
+    return Err("stitch needs the tiles to stitch".to_string());
+  }
+  if !force && std::path::Path::new(out).exists() {
+    return Err(format!("'{}' already exists; pass --force to overwrite it", out));
+  }
+
+  let tiles = paths.iter().map(|path| read_tile(path)).collect::<Result<Vec<Tile>, String>>()?;
+  let first = &tiles[0];
+  for tile in &tiles[1..] {
+    if tile.options != first.options {
+      return Err(format!("'{}' was rendered with options '{}', but '{}' with '{}'", tile.path, tile.options.join(" "), first.path, first.options.join(" ")));
+    }
+    if tile.channels != first.channels {
+      return Err(format!("'{}' and '{}' aren't both gray or both colored", tile.path, first.path));
+    }
+  }
+  let Stitched { image, bounds, upper_left, lower_right } = stitch(&tiles)?;
+
+  let arguments = [vec![format!("{}x{}", bounds.0, bounds.1), upper_left.clone(), lower_right.clone()], first.options.clone()].concat();
+  let args = parse_arguments(&[vec![out.to_string()], arguments.clone()].concat())?;
+  let provenance = Provenance {
+    title: args.title.as_deref(),
+    author: args.author.as_deref(),
+    size: bounds,
+    upper_left: &upper_left,
+    lower_right: &lower_right,
+    arguments,
+  };
+  let written = (|| {
+    let mut encoder = png::Encoder::new(BufWriter::new(File::create(out)?), bounds.0 as u32, bounds.1 as u32);
+    encoder.set_color(if first.channels == 3 { png::ColorType::Rgb } else { png::ColorType::Grayscale });
+    encoder.set_depth(png::BitDepth::Eight);
+    provenance.add_to(&mut encoder)?;
+    let mut writer = encoder.write_header()?;
+    writer.write_image_data(&image)?;
+    writer.finish()
+  })();
+  written.map_err(|e| format!("error writing '{}': {}", out, e))?;
+  log::info(&format!("stitched {} tiles into {}x{} '{}', from {} to {}", tiles.len(), bounds.0, bounds.1, out, upper_left, lower_right));
+  Ok(())
+}
+
+fn read_tile(path: &str) -> Result<Tile, String> {
+  let (recorded, bounds) = metadata::arguments(path)?;
+  let args = parse_arguments(&[vec![path.to_string()], recorded.clone()].concat())?;
+  if args.rotation != 0.0 {
+    return Err(format!("'{}' is of a rotated view, whose pixels don't line up with another's", path));
+  }
+  let Image { mut pixels, channels, .. } = read_image(path)?;
+  // Anything below the rendered rows is a legend.
+  pixels.truncate(bounds.0 * bounds.1 * channels);
+  Ok(Tile {
+    path: path.to_string(),
+    upper_left: exact_corner(&args.upper_left)?,
+    lower_right: exact_corner(&args.lower_right)?,
+    bounds,
+    options: options_but_view(recorded, bounds, path)?,
+    channels,
+    pixels,
+  })
+}
+
+// The image tiles make up.
+struct Stitched {
+  image: Vec<u8>,
+  bounds: (usize, usize),
+  upper_left: String,
+  lower_right: String,
+}
+
+fn stitch(tiles: &[Tile]) -> Result<Stitched, String> {
+  let extreme = |pick: fn(&Tile) -> &DBig, larger: bool| {
+    tiles.iter().map(pick).fold(pick(&tiles[0]).clone(), |best, value| if (value > &best) == larger { value.clone() } else { best })
+  };
+  let (left, top) = (extreme(|tile| &tile.upper_left.0, false), extreme(|tile| &tile.upper_left.1, true));
+  let (right, bottom) = (extreme(|tile| &tile.lower_right.0, true), extreme(|tile| &tile.lower_right.1, false));
+  let span = |from: &DBig, to: &DBig| (to - from).to_f64().value();
+
+  // Every tile's pixels must be as far apart as the first's.
+  let first = &tiles[0];
+  let pitch = (span(&first.upper_left.0, &first.lower_right.0) / first.bounds.0 as f64, span(&first.lower_right.1, &first.upper_left.1) / first.bounds.1 as f64);
+  let pixels = |distance: f64, pitch: f64| {
+    let count = distance / pitch;
+    ((count - count.round()).abs() < TOLERANCE).then_some(count.round() as usize)
+  };
+  let bounds = (pixels(span(&left, &right), pitch.0), pixels(span(&bottom, &top), pitch.1));
+  let (Some(width), Some(height)) = bounds else {
+    return Err(format!("the tiles span a whole number of '{}''s pixels neither across nor down", first.path));
+  };
+  let channels = first.channels;
+
+  let mut image = vec![0; width * height * channels];
+  let mut covered = vec![false; width * height];
+  for tile in tiles {
+    let place = (pixels(span(&left, &tile.upper_left.0), pitch.0), pixels(span(&tile.upper_left.1, &top), pitch.1));
+    let size = (pixels(span(&tile.upper_left.0, &tile.lower_right.0), pitch.0), pixels(span(&tile.lower_right.1, &tile.upper_left.1), pitch.1));
+    let (Some(x), Some(y)) = place else {
+      return Err(format!("'{}' doesn't sit on the grid of '{}''s pixels", tile.path, first.path));
+    };
+    if size != (Some(tile.bounds.0), Some(tile.bounds.1)) {
+      return Err(format!("'{}' has pixels spaced differently from '{}''s", tile.path, first.path));
+    }
+    for row in 0..tile.bounds.1 {
+      let from = row * tile.bounds.0 * channels;
+      let to = ((y + row) * width + x) * channels;
+      image[to..to + tile.bounds.0 * channels].copy_from_slice(&tile.pixels[from..from + tile.bounds.0 * channels]);
+      covered[(y + row) * width + x..][..tile.bounds.0].fill(true);
+    }
+  }
+  let gaps = covered.iter().filter(|&&covered| !covered).count();
+  if gaps > 0 {
+    return Err(format!("the tiles leave {} of the {}x{} image's pixels uncovered", gaps, width, height));
+  }
+  Ok(Stitched { image, bounds: (width, height), upper_left: format!("{},{}", left, top), lower_right: format!("{},{}", right, bottom) })
+}
+
+#[test]
+fn test_stitching() {
+  let dir = tempfile::tempdir().unwrap();
+  let path = |name: &str| dir.path().join(name).to_str().unwrap().to_string();
+  let render = |name: &str, size: &str, upper_left: &str, lower_right: &str| {
+    crate::render(crate::test_arguments(&[&path(name), size, upper_left, lower_right, "--max-iter", "60", "--no-progress"]).unwrap()).unwrap();
+  };
+  render("whole.png", "40x20", "-2,1", "2,-1");
+  render("left.png", "20x20", "-2,1", "0,-1");
+  render("top-right.png", "20x10", "0,1", "2,0");
+  render("bottom-right.png", "20x10", "0,0", "2,-1");
+
+  let tiles = ["left.png", "top-right.png", "bottom-right.png"].map(|name| read_tile(&path(name)).unwrap());
+  let stitched = stitch(&tiles).unwrap();
+  assert_eq!((stitched.bounds, stitched.upper_left.as_str(), stitched.lower_right.as_str()), ((40, 20), "-2,1", "2,-1"));
+  assert_eq!(stitched.image, read_tile(&path("whole.png")).unwrap().pixels);
+
+  assert!(stitch(&tiles[..2]).err().unwrap().contains("200 of the 40x20"));
+  // Half a pixel off the others' grid.
+  render("shifted.png", "10x10", "0.05,0", "1.05,-1");
+  let shifted = ["left.png", "shifted.png", "bottom-right.png"].map(|name| read_tile(&path(name)).unwrap());
+  assert!(stitch(&shifted).err().unwrap().contains("doesn't sit on the grid"));
+
+  main(&["--out", &path("stitched.png"), &path("left.png"), &path("top-right.png"), &path("bottom-right.png")].map(String::from)).unwrap();
+  let (recorded, size) = metadata::arguments(&path("stitched.png")).unwrap();
+  assert_eq!((size, &recorded[..3]), ((40, 20), &["40x20", "-2,1", "2,-1"].map(String::from)[..]));
+}
@@ -0,0 +1,260 @@
+// Terminal display
+// Raw mode plus xterm mouse reporting turn the terminal into an interactive display for
+// half-block images (see preview.rs) that works anywhere a terminal does, including
+// over SSH.
+
+use std::io::{self, Write};
+use std::time::Duration;
+
+use crate::palette::Palette;
+use crate::preview::encode_half_blocks;
+
+const STDIN: libc::c_int = 0;
+const STDOUT: libc::c_int = 1;
+
+// Alternate screen, hidden cursor, reporting of all mouse motion in SGR encoding, and
+// on terminals with the Kitty keyboard protocol, distinct codes for modified keys such
+// as Shift+Backspace.
+const ENTER: &str = "\x1b[?1049h\x1b[?25l\x1b[?1003h\x1b[?1006h\x1b[>1u";
+const LEAVE: &str = "\x1b[<u\x1b[?1006l\x1b[?1003l\x1b[?25h\x1b[?1049l";
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Event {
+  Key(char),
+  Enter,
+  Tab,
+  Escape,
+  Backspace,
+  // Only terminals that report modified keys tell this apart from Backspace.
+  ShiftBackspace,
+  Up,
+  Down,
+  Left,
+  Right,
+  Mouse(Mouse),
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Mouse {
+  pub action: MouseAction,
+  // Zero-based cell position.
+  pub column: usize,
+  pub row: usize,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum MouseAction {
+  Press(Button),
+  // Moving with a button held.
+  Drag,
+  // Moving with no button held.
+  Move,
+  Release,
+  WheelUp,
+  WheelDown,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Button {
+  Left,
+  Middle,
+  Right,
+}
+
+// The terminal in raw mode on the alternate screen; dropping it restores the original
+// state, including when unwinding from a panic.
+pub struct Terminal {
+  original: libc::termios,
+  input: Vec<u8>,
+}
+
+impl Terminal {
+  pub fn open() -> Result<Terminal, io::Error> {
+    // SAFETY: termios is plain data, filled in by tcgetattr before it is used.
+    let original = unsafe {
+      let mut original: libc::termios = std::mem::zeroed();
+      if libc::tcgetattr(STDIN, &mut original) != 0 {
+        return Err(io::Error::other("standard input is not a terminal"));
+      }
+      let mut raw = original;
+      libc::cfmakeraw(&mut raw);
+      if libc::tcsetattr(STDIN, libc::TCSANOW, &raw) != 0 {
+        return Err(io::Error::last_os_error());
+      }
+      original
+    };
+
+    let terminal = Terminal { original, input: Vec::new() };
+    write_all(ENTER.as_bytes())?;
+    Ok(terminal)
+  }
+
+  // Columns and rows of text cells.
+  pub fn size(&self) -> (usize, usize) {
+    // SAFETY: winsize is plain data that TIOCGWINSZ fills in.
+    unsafe {
+      let mut size: libc::winsize = std::mem::zeroed();
+      if libc::ioctl(STDOUT, libc::TIOCGWINSZ, &mut size) == 0 && size.ws_col > 0 && size.ws_row > 0 {
+        (size.ws_col as usize, size.ws_row as usize)
+      } else {
+        (80, 24)
+      }
+    }
+  }
+
+  // Whether input arrives within `timeout`.
+  pub fn poll(&self, timeout: Duration) -> Result<bool, io::Error> {
+    let mut descriptor = libc::pollfd { fd: STDIN, events: libc::POLLIN, revents: 0 };
+    // SAFETY: one valid pollfd.
+    match unsafe { libc::poll(&mut descriptor, 1, timeout.as_millis() as libc::c_int) } {
+      -1 if io::Error::last_os_error().kind() == io::ErrorKind::Interrupted => Ok(false),
+      -1 => Err(io::Error::last_os_error()),
+      ready => Ok(ready > 0),
+    }
+  }
+
+  // Reads whatever input is waiting and returns the complete events in it. Blocks until
+  // some input arrives.
+  pub fn read_events(&mut self) -> Result<Vec<Event>, io::Error> {
+    let mut buffer = [0u8; 256];
+    // SAFETY: reads into a buffer of the length given.
+    let count = unsafe { libc::read(STDIN, buffer.as_mut_ptr() as *mut libc::c_void, buffer.len()) };
+    if count < 0 {
+      return Err(io::Error::last_os_error());
+    }
+    self.input.extend_from_slice(&buffer[..count as usize]);
+    Ok(parse_events(&mut self.input))
+  }
+
+  // Draws `pixels` from the top-left cell down, two image rows per text row, with the
+  // `panel` lines to its right and `status` on the line below.
+  pub fn draw(&mut self, pixels: &[u8], bounds: (usize, usize), palette: Palette, panel: &[String], status: &str) -> Result<(), io::Error> {
+    let mut frame = Vec::new();
+    frame.extend_from_slice(b"\x1b[H");
+    encode_half_blocks(&mut frame, pixels, bounds, palette);
+    let columns = self.size().0;
+    for (row, line) in panel.iter().enumerate() {
+      let room = columns.saturating_sub(bounds.0 + 1);
+      write!(frame, "\x1b[{};{}H\x1b[K{}", row + 1, bounds.0 + 2, truncate(line, room))?;
+    }
+    write!(frame, "\x1b[{};1H\x1b[0m\x1b[2K{}", bounds.1.div_ceil(2) + 1, truncate(status, columns))?;
+    write_all(&frame)
+  }
+
+  // Draws `pixels` as half blocks with their top-left cell at the zero-based `cell`,
+  // over whatever is there.
+  pub fn draw_inset(&mut self, pixels: &[u8], bounds: (usize, usize), palette: Palette, cell: (usize, usize)) -> Result<(), io::Error> {
+    let mut frame = Vec::new();
+    for (line, rows) in pixels.chunks(2 * bounds.0).enumerate() {
+      write!(frame, "\x1b[{};{}H", cell.1 + line + 1, cell.0 + 1)?;
+      encode_half_blocks(&mut frame, rows, (bounds.0, rows.len() / bounds.0), palette);
+    }
+    write_all(&frame)
+  }
+}
+
+impl Drop for Terminal {
+  fn drop(&mut self) {
+    let _ = write_all(LEAVE.as_bytes());
+    // SAFETY: restores the settings saved in open.
+    unsafe {
+      libc::tcsetattr(STDIN, libc::TCSANOW, &self.original);
+    }
+  }
+}
+
+fn write_all(bytes: &[u8]) -> Result<(), io::Error> {
+  let mut out = io::stdout().lock();
+  out.write_all(bytes)?;
+  out.flush()
+}
+
+fn truncate(text: &str, columns: usize) -> &str {
+  match text.char_indices().nth(columns) {
+    Some((end, _)) => &text[..end],
+    None => text,
+  }
+}
+
+// Decodes the complete key and mouse events at the front of `input`, leaving any
+// partial escape sequence behind for the next read.
+fn parse_events(input: &mut Vec<u8>) -> Vec<Event> {
+  let mut events = Vec::new();
+  let mut i = 0;
+
+  while i < input.len() {
+    match input[i] {
+      0x1b if input.get(i + 1) == Some(&b'[') => {
+        let Some(length) = input[i + 2..].iter().position(|byte| (0x40..=0x7e).contains(byte)) else {
+          break;
+        };
+        let end = i + 2 + length;
+        events.extend(control_sequence(&input[i + 2..end], input[end]));
+        i = end + 1;
+        continue;
+      }
+      0x1b => events.push(Event::Escape),
+      0x7f | 0x08 => events.push(Event::Backspace),
+      b'\r' | b'\n' => events.push(Event::Enter),
+      b'\t' => events.push(Event::Tab),
+      byte if byte.is_ascii_graphic() || byte == b' ' => events.push(Event::Key(byte as char)),
+      _ => {}
+    }
+    i += 1;
+  }
+
+  input.drain(..i);
+  events
+}
+
+fn control_sequence(parameters: &[u8], last: u8) -> Option<Event> {
+  match (parameters, last) {
+    (b"", b'A') => Some(Event::Up),
+    (b"", b'B') => Some(Event::Down),
+    (b"", b'C') => Some(Event::Right),
+    (b"", b'D') => Some(Event::Left),
+    // Kitty keyboard protocol key codes, with 2 for Shift.
+    (b"27", b'u') => Some(Event::Escape),
+    (b"127;2", b'u') => Some(Event::ShiftBackspace),
+    ([b'<', mouse @ ..], b'M' | b'm') => {
+      let fields: Vec<usize> = std::str::from_utf8(mouse).ok()?.split(';').map(|field| field.parse().ok()).collect::<Option<_>>()?;
+      let &[code, column, row] = fields.as_slice() else {
+        return None;
+      };
+      let action = match (code, last) {
+        (_, b'm') => MouseAction::Release,
+        (64, _) => MouseAction::WheelUp,
+        (65, _) => MouseAction::WheelDown,
+        (0, _) => MouseAction::Press(Button::Left),
+        (1, _) => MouseAction::Press(Button::Middle),
+        (2, _) => MouseAction::Press(Button::Right),
+        (32..=34, _) => MouseAction::Drag,
+        (35, _) => MouseAction::Move,
+        _ => return None,
+      };
+      Some(Event::Mouse(Mouse { action, column: column.checked_sub(1)?, row: row.checked_sub(1)? }))
+    }
+    _ => None,
+  }
+}
+
+#[test]
+fn test_parse_events() {
+  let mut input = b"q\t\r\x1b[A\x1b[<0;10;5M\x1b[<32;11;6M\x1b[<0;10;5m\x1b[<65;1;1M\x7f\x1b[127;2u\x1b[<2;3".to_vec();
+  assert_eq!(parse_events(&mut input), [
+    Event::Key('q'),
+    Event::Tab,
+    Event::Enter,
+    Event::Up,
+    Event::Mouse(Mouse { action: MouseAction::Press(Button::Left), column: 9, row: 4 }),
+    Event::Mouse(Mouse { action: MouseAction::Drag, column: 10, row: 5 }),
+    Event::Mouse(Mouse { action: MouseAction::Release, column: 9, row: 4 }),
+    Event::Mouse(Mouse { action: MouseAction::WheelDown, column: 0, row: 0 }),
+    Event::Backspace,
+    Event::ShiftBackspace,
+  ]);
+  // The unfinished sequence waits for the rest of its bytes.
+  assert_eq!(input, b"\x1b[<2;3");
+  input.extend_from_slice(b";4M");
+  assert_eq!(parse_events(&mut input), [Event::Mouse(Mouse { action: MouseAction::Press(Button::Right), column: 2, row: 3 })]);
+}
@@ -0,0 +1,240 @@
+// Tuning
+// `mandel tune` times renders of a reference view on this machine with a range of chunk
+// sizes and both ways of handing chunks to threads, and times the two backends that can
+// render views too deep for f64, then writes the fastest of each to a tuning file that
+// every later run reads at startup:
+//
+//   # mandel tune, 8 threads
+//   chunk-rows 16
+//   schedule interleaved
+//   deep fixed128
+//
+// Chunks are handed out dynamically, to whichever thread is free next, or interleaved,
+// thread k taking every threads'th chunk from the k'th, which skips the shared channel
+// but leaves threads idle once their own chunks are done. With a deep backend, a render
+// whose pixels f64 can't tell apart and that says nothing of its arithmetic is rendered
+// with it rather than refused. None of this changes an image, only how fast it comes.
+//
+// The file is $MANDEL_TUNING if set, otherwise mandel/tuning under $XDG_CONFIG_HOME or
+// ~/.config. Delete it to go back to the defaults.
+
+use std::io;
+use std::path::PathBuf;
+use std::str::FromStr;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+use num::Complex;
+
+use crate::json::Value;
+use crate::{build_sampler, log, parse_arguments, parse_threads, render_parallel, report, Plane};
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum Schedule {
+  Dynamic,
+  Interleaved,
+}
+
+// How to render views too deep for f64.
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum Deep {
+  Perturbation,
+  Fixed128,
+}
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct Tuning {
+  pub chunk_rows: usize,
+  pub schedule: Schedule,
+  pub deep: Option<Deep>,
+}
+
+pub const DEFAULT: Tuning = Tuning { chunk_rows: crate::CHUNK_ROWS, schedule: Schedule::Dynamic, deep: None };
+
+static TUNING: Mutex<Tuning> = Mutex::new(DEFAULT);
+
+const CHUNK_ROWS_TRIED: [usize; 6] = [2, 4, 8, 16, 32, 64];
+const SCHEDULES: [(Schedule, &str); 2] = [(Schedule::Dynamic, "dynamic"), (Schedule::Interleaved, "interleaved")];
+const DEEP: [(Deep, &str); 2] = [(Deep::Perturbation, "perturbation"), (Deep::Fixed128, "fixed128")];
+
+// The view the chunk sizes and schedules are timed on: seahorse valley, where rows near
+// the set take far longer than the rest.
+const SIZE: (usize, usize) = (480, 360);
+const VIEW: (Complex<f64>, Complex<f64>) = (Complex { re: -0.80, im: 0.20 }, Complex { re: -0.70, im: 0.125 });
+const LIMIT: usize = 1000;
+
+// And the deep backends, on a view 1e-21 wide: its size, corners and iteration limit.
+const DEEP_VIEW: (&str, &str, &str, &str) = ("240x180", "-0.7436438870371587047500,0.1318259042053119704975", "-0.7436438870371587047490,0.1318259042053119704967", "1000");
+
+pub fn path() -> Result<PathBuf, String> {
+  if let Some(path) = std::env::var_os("MANDEL_TUNING") {
+    return Ok(PathBuf::from(path));
+  }
+  let config = std::env::var_os("XDG_CONFIG_HOME").map(PathBuf::from)
+    .or_else(|| std::env::var_os("HOME").map(|home| PathBuf::from(home).join(".config")))
+    .ok_or("can't find the tuning file: set MANDEL_TUNING or HOME")?;
+  Ok(config.join("mandel").join("tuning"))
+}
+
+// The tuning in effect.
+pub fn current() -> Tuning {
+  *TUNING.lock().unwrap()
+}
+
+pub fn configure(tuning: Tuning) {
+  *TUNING.lock().unwrap() = tuning;
+}
+
+// Puts the machine's tuning file into effect, if it has one. A file that can't be read
+// only costs speed, so it gets a warning rather than stopping the run.
+pub fn load() {
+  let loaded = path().and_then(|path| match std::fs::read_to_string(&path) {
+    Ok(text) => parse(&text).map(Some).map_err(|message| format!("{}:{}", path.display(), message)),
+    Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(None),
+    Err(e) => Err(format!("error reading tuning '{}': {}", path.display(), e)),
+  });
+  match loaded {
+    Ok(Some(tuning)) => configure(tuning),
+    Ok(None) => {}
+    Err(message) => log::warn(&format!("{}; using the default tuning", message)),
+  }
+}
+
+fn parse(text: &str) -> Result<Tuning, String> {
+  let mut tuning = DEFAULT;
+  for (i, line) in text.lines().enumerate() {
+    let line = line.trim();
+    if line.is_empty() || line.starts_with('#') {
+      continue;
+    }
+    let malformed = || format!("{}: malformed tuning '{}'", i + 1, line);
+    let (key, value) = line.split_once(' ').ok_or_else(malformed)?;
+    match (key, value.trim()) {
+      ("chunk-rows", rows) => tuning.chunk_rows = usize::from_str(rows).ok().filter(|&rows| rows > 0).ok_or_else(malformed)?,
+      ("schedule", name) => tuning.schedule = SCHEDULES.iter().find(|(_, n)| *n == name).ok_or_else(malformed)?.0,
+      ("deep", name) => tuning.deep = Some(DEEP.iter().find(|(_, n)| *n == name).ok_or_else(malformed)?.0),
+      _ => return Err(malformed()),
+    }
+  }
+  Ok(tuning)
+}
+
+fn format(tuning: &Tuning, threads: usize) -> String {
+  let mut text = format!("# mandel tune, {} threads\nchunk-rows {}\nschedule {}\n", threads, tuning.chunk_rows, schedule_name(tuning.schedule));
+  if let Some(deep) = tuning.deep {
+    text += &format!("deep {}\n", deep_name(deep));
+  }
+  text
+}
+
+fn schedule_name(schedule: Schedule) -> &'static str {
+  SCHEDULES.iter().find(|(s, _)| *s == schedule).unwrap().1
+}
+
+fn deep_name(deep: Deep) -> &'static str {
+  DEEP.iter().find(|(d, _)| *d == deep).unwrap().1
+}
+
+pub fn main(arguments: &[String], threads: usize) -> Result<Vec<(String, Value)>, String> {
+  let mut threads = threads;
+  let mut out = None;
+  let mut options = arguments.iter().map(String::as_str);
+  while let Some(option) = options.next() {
+    match option {
+      "--threads" => threads = parse_threads(options.next())?,
+      "--out" => out = Some(PathBuf::from(options.next().ok_or("--out expects a file name")?)),
+      _ => return Err("tune accepts --threads N and --out FILE".to_string()),
+    }
+  }
+  let out = match out {
+    Some(out) => out,
+    None => path()?,
+  };
+
+  let table = |line: String| if !report::json() { println!("{}", line) };
+  table(format!("{:<11} {:>5} {:>10}", "schedule", "rows", "time (ms)"));
+  let plane = Plane::mandelbrot(SIZE, VIEW.0, VIEW.1, LIMIT);
+  let mut best = (Duration::MAX, DEFAULT);
+  for (schedule, name) in SCHEDULES {
+    for chunk_rows in CHUNK_ROWS_TRIED {
+      let tuning = Tuning { chunk_rows, schedule, deep: None };
+      let elapsed = time_render(&plane, SIZE, threads, tuning)?;
+      table(format!("{:<11} {:>5} {:>10.1}", name, chunk_rows, elapsed.as_secs_f64() * 1e3));
+      if elapsed < best.0 {
+        best = (elapsed, tuning);
+      }
+    }
+  }
+
+  table(format!("{:<17} {:>10}", "deep backend", "time (ms)"));
+  let mut deepest = (Duration::MAX, best.1);
+  for (deep, name) in DEEP {
+    let backend: &[&str] = if deep == Deep::Fixed128 { &["--precision", "fixed128"] } else { &["--perturbation"] };
+    let arguments: Vec<String> = ["tune.png", DEEP_VIEW.0, DEEP_VIEW.1, DEEP_VIEW.2, "--max-iter", DEEP_VIEW.3].iter().chain(backend).map(|s| s.to_string()).collect();
+    let args = parse_arguments(&arguments)?;
+    let bounds = crate::parse_pair(&args.pixels, 'x').unwrap();
+    let sampler = build_sampler(&args, bounds)?;
+    let elapsed = time_render(sampler.as_ref(), bounds, threads, best.1)?;
+    table(format!("{:<17} {:>10.1}", name, elapsed.as_secs_f64() * 1e3));
+    if elapsed < deepest.0 {
+      deepest = (elapsed, Tuning { deep: Some(deep), ..best.1 });
+    }
+  }
+  let tuning = deepest.1;
+
+  if let Some(dir) = out.parent().filter(|dir| !dir.as_os_str().is_empty()) {
+    std::fs::create_dir_all(dir).map_err(|e| format!("error creating '{}': {}", dir.display(), e))?;
+  }
+  std::fs::write(&out, format(&tuning, threads)).map_err(|e| format!("error writing tuning '{}': {}", out.display(), e))?;
+  configure(tuning);
+  log::info(&format!("wrote {}: {} rows per chunk, {} schedule, {} for deep views",
+                     out.display(), tuning.chunk_rows, schedule_name(tuning.schedule), tuning.deep.map_or("nothing", deep_name)));
+  Ok(vec![
+    ("file".to_string(), out.to_string_lossy().as_ref().into()),
+    ("chunk_rows".to_string(), tuning.chunk_rows.into()),
+    ("schedule".to_string(), schedule_name(tuning.schedule).into()),
+    ("deep".to_string(), tuning.deep.map_or(Value::Null, |deep| deep_name(deep).into())),
+  ])
+}
+
+// Best of three renders with `tuning` in effect, to keep scheduling noise out of it.
+fn time_render(sampler: &dyn crate::Sampler, bounds: (usize, usize), threads: usize, tuning: Tuning) -> Result<Duration, String> {
+  configure(tuning);
+  let mut pixels = vec![0; bounds.0 * bounds.1];
+  let mut best = Duration::MAX;
+  for _ in 0..3 {
+    let start = Instant::now();
+    if render_parallel(&mut pixels, bounds, sampler, threads, 0, 1, true)? < bounds.1 {
+      return Err("interrupted".to_string());
+    }
+    best = best.min(start.elapsed());
+  }
+  Ok(best)
+}
+
+#[test]
+fn test_tuning_files() {
+  let tuning = Tuning { chunk_rows: 8, schedule: Schedule::Interleaved, deep: Some(Deep::Fixed128) };
+  assert_eq!(parse(&format(&tuning, 4)), Ok(tuning));
+  assert_eq!(parse(&format(&DEFAULT, 4)), Ok(DEFAULT));
+  assert_eq!(parse("\n# nothing\n"), Ok(DEFAULT));
+  assert_eq!(parse("schedule interleaved\n"), Ok(Tuning { schedule: Schedule::Interleaved, ..DEFAULT }));
+  assert_eq!(parse("chunk-rows 0\n"), Err("1: malformed tuning 'chunk-rows 0'".to_string()));
+  assert_eq!(parse("# tuned\ndeep fast\n"), Err("2: malformed tuning 'deep fast'".to_string()));
+  assert!(parse("threads 4\n").is_err());
+
+  // Every tuning renders the same image.
+  let bounds = (37, 29);
+  let plane = Plane::mandelbrot(bounds, VIEW.0, VIEW.1, 200);
+  let mut expected = vec![0; bounds.0 * bounds.1];
+  crate::render_pass(&mut expected, bounds, &plane, 0, 1, true);
+  for (schedule, _) in SCHEDULES {
+    for chunk_rows in [1, 5, 64] {
+      let mut pixels = vec![0; bounds.0 * bounds.1];
+      configure(Tuning { chunk_rows, schedule, deep: None });
+      render_parallel(&mut pixels, bounds, &plane, 3, 0, 1, true).unwrap();
+      assert!(pixels == expected, "{:?} {}", schedule, chunk_rows);
+    }
+  }
+  configure(DEFAULT);
+}
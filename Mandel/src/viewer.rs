@@ -0,0 +1,799 @@
+// Interactive viewer
+// `mandel view` explores the set in the terminal. Each view is rendered with the same
+// coarse-to-fine passes as --progressive, redrawing after every pass and dropping the
+// remaining passes as soon as input arrives, so navigation never waits on a full render.
+// A panel beside the image shows the view's parameters and lets them be edited.
+// Arrow keys pan by a tenth of the view, re-rendering only the newly exposed edge.
+// The status bar reads out the point under the mouse, its escape time and the pixel
+// pitch, for hunting down places worth zooming into. B saves the view as a bookmark
+// (see bookmarks.rs) and G goes to one. Backspace steps back through the views visited
+// and Shift+Backspace (or Y) forward again; --history FILE carries them between sessions.
+// E renders the view at --export-size in the background and saves it to a file whose
+// name records the view. J shows the Julia set of the point under the mouse beneath the
+// panel, following the mouse as it moves.
+
+use std::path::Path;
+use std::str::FromStr;
+use std::thread::JoinHandle;
+use std::time::Duration;
+
+use num::Complex;
+
+use crate::bookmarks::{self, Bookmark};
+use crate::fractal::{Fractal, FRACTALS};
+use crate::palette::{Palette, PALETTES};
+use crate::terminal::{Button, Event, MouseAction, Terminal};
+use crate::{centered_corners, escape_count, incremental, log, parse_complex, parse_pair, parse_threads, pixel_to_point, render_parallel, shades, write_color_image, Plane, Sampler,
+            DEFAULT_MAX_ITER, PASSES};
+
+// How often to check for a resized terminal while waiting for input.
+const RESIZE_POLL: Duration = Duration::from_millis(250);
+
+// Wheel steps and the + and - keys zoom by this factor about the cursor.
+const WHEEL_ZOOM: f64 = 1.25;
+
+// Arrow keys pan by this fraction of the view.
+const PAN_FRACTION: usize = 10;
+
+// Real-axis extent of the Julia inset, centered on the origin.
+const JULIA_WIDTH: f64 = 3.2;
+
+// Most text rows the Julia inset takes below the panel.
+const JULIA_ROWS: usize = 12;
+
+// Export image size unless --export-size says otherwise.
+const DEFAULT_EXPORT_SIZE: (usize, usize) = (1920, 1080);
+
+// Views kept in each direction of the history.
+const HISTORY_LIMIT: usize = 200;
+
+// Columns taken by the parameter panel, which is left out on terminals narrower than
+// twice this.
+const PANEL_WIDTH: usize = 32;
+
+// The part of the plane on screen: its center and real-axis extent, with the imaginary
+// extent following from the display's aspect ratio.
+#[derive(Clone, Copy, Debug, PartialEq)]
+struct View {
+  center: Complex<f64>,
+  width: f64,
+  limit: usize,
+  fractal: Fractal,
+  palette: Palette,
+}
+
+impl View {
+  fn corners(&self, bounds: (usize, usize)) -> (Complex<f64>, Complex<f64>) {
+    centered_corners(self.center, self.width, bounds)
+  }
+
+  fn point(&self, bounds: (usize, usize), pixel: (f64, f64)) -> Complex<f64> {
+    let (upper_left, lower_right) = self.corners(bounds);
+    pixel_to_point(bounds, pixel, upper_left, lower_right)
+  }
+
+  // Zooms to show all of a pixel rectangle, given by its top-left and bottom-right
+  // corners. The display's aspect ratio wins, so the rectangle fills it in one
+  // direction and gets extra room in the other.
+  fn zoom_to(&mut self, bounds: (usize, usize), rectangle: ((usize, usize), (usize, usize))) {
+    let ((left, top), (right, bottom)) = rectangle;
+    let upper_left = self.point(bounds, (left as f64, top as f64));
+    let lower_right = self.point(bounds, (right as f64, bottom as f64));
+    self.center = (upper_left + lower_right) / 2.0;
+    self.width *= ((right - left) as f64 / bounds.0 as f64).max((bottom - top) as f64 / bounds.1 as f64);
+  }
+
+  // Zooms in by `factor` (out, if below one), keeping the point under `pixel` in place.
+  fn zoom(&mut self, bounds: (usize, usize), pixel: (f64, f64), factor: f64) {
+    let fixed = self.point(bounds, pixel);
+    self.center = fixed + (self.center - fixed) / factor;
+    self.width /= factor;
+  }
+
+  fn bookmark(&self, name: &str) -> Bookmark {
+    Bookmark { name: name.to_string(), center: self.center, width: self.width, limit: self.limit, palette: self.palette }
+  }
+
+  // This view moved to a bookmark, which leaves the fractal as it is.
+  fn at(self, bookmark: &Bookmark) -> View {
+    View { center: bookmark.center, width: bookmark.width, limit: bookmark.limit, palette: bookmark.palette, ..self }
+  }
+
+  fn sampler(&self, bounds: (usize, usize)) -> Plane<f64> {
+    let (upper_left, lower_right) = self.corners(bounds);
+    Plane { bounds, upper_left, lower_right, limit: self.limit, fractal: self.fractal, bailout: self.fractal.bailout(), turn: None }
+  }
+}
+
+// The parameters the panel lists, in order.
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Field {
+  MaxIter,
+  Palette,
+  Fractal,
+  CenterRe,
+  CenterIm,
+  Width,
+}
+
+const FIELDS: [Field; 6] = [Field::MaxIter, Field::Palette, Field::Fractal, Field::CenterRe, Field::CenterIm, Field::Width];
+
+impl Field {
+  fn label(self) -> &'static str {
+    match self {
+      Field::MaxIter => "max iterations",
+      Field::Palette => "palette",
+      Field::Fractal => "fractal",
+      Field::CenterRe => "center re",
+      Field::CenterIm => "center im",
+      Field::Width => "width",
+    }
+  }
+
+  fn value(self, view: &View) -> String {
+    match self {
+      Field::MaxIter => view.limit.to_string(),
+      Field::Palette => view.palette.name().to_string(),
+      Field::Fractal => view.fractal.name().to_string(),
+      Field::CenterRe => view.center.re.to_string(),
+      Field::CenterIm => view.center.im.to_string(),
+      Field::Width => format!("{:e}", view.width),
+    }
+  }
+
+  // Sets the field from typed text, returning whether it parsed.
+  fn set(self, view: &mut View, text: &str) -> bool {
+    let text = text.trim();
+    match self {
+      Field::MaxIter => match usize::from_str(text) {
+        Ok(limit) if limit > 0 => view.limit = limit,
+        _ => return false,
+      },
+      Field::Palette => match PALETTES.iter().find(|palette| palette.name() == text) {
+        Some(&palette) => view.palette = palette,
+        None => return false,
+      },
+      Field::Fractal => match FRACTALS.iter().find(|fractal| fractal.name() == text) {
+        Some(&fractal) => view.fractal = fractal,
+        None => return false,
+      },
+      Field::CenterRe | Field::CenterIm | Field::Width => match f64::from_str(text) {
+        Ok(value) if value.is_finite() => match self {
+          Field::CenterRe => view.center.re = value,
+          Field::CenterIm => view.center.im = value,
+          _ if value > 0.0 => view.width = value,
+          _ => return false,
+        },
+        _ => return false,
+      },
+    }
+    true
+  }
+
+  // Nudges the field up or down: doubling or halving numbers, cycling through choices
+  // and moving the center by a tenth of the view.
+  fn step(self, view: &mut View, up: bool) {
+    let cycle = |index: usize, len: usize| if up { (index + 1) % len } else { (index + len - 1) % len };
+    let sign = if up { 1.0 } else { -1.0 };
+    match self {
+      Field::MaxIter => view.limit = if up { view.limit * 2 } else { (view.limit / 2).max(1) },
+      Field::Palette => {
+        let index = PALETTES.iter().position(|&palette| palette == view.palette).unwrap_or(0);
+        view.palette = PALETTES[cycle(index, PALETTES.len())];
+      }
+      Field::Fractal => {
+        let index = FRACTALS.iter().position(|&fractal| fractal == view.fractal).unwrap_or(0);
+        view.fractal = FRACTALS[cycle(index, FRACTALS.len())];
+      }
+      Field::CenterRe => view.center.re += sign * view.width / 10.0,
+      Field::CenterIm => view.center.im += sign * view.width / 10.0,
+      Field::Width => view.width *= if up { 2.0 } else { 0.5 },
+    }
+  }
+}
+
+// The Julia set of `fractal` for the point `c`.
+#[derive(Clone, Copy, PartialEq)]
+struct Julia {
+  bounds: (usize, usize),
+  c: Complex<f64>,
+  limit: usize,
+  fractal: Fractal,
+}
+
+impl Sampler for Julia {
+  fn sample(&self, x: f64, y: f64) -> u32 {
+    let (upper_left, lower_right) = centered_corners(Complex { re: 0.0, im: 0.0 }, JULIA_WIDTH, self.bounds);
+    let z = pixel_to_point(self.bounds, (x, y), upper_left, lower_right);
+    escape_count(self.fractal.julia_escape_time(z, self.c, self.limit), self.limit)
+  }
+
+  fn limit(&self) -> usize {
+    self.limit
+  }
+}
+
+// A name being typed into the status bar.
+struct Prompt {
+  save: bool,
+  text: String,
+}
+
+impl Prompt {
+  fn line(&self) -> String {
+    if self.save {
+      format!(" save bookmark as: {}_", self.text)
+    } else {
+      let names = bookmarks::path().and_then(|path| bookmarks::load(&path)).unwrap_or_default();
+      let names: Vec<_> = names.iter().map(|bookmark| bookmark.name.as_str()).collect();
+      format!(" go to bookmark: {}_   ({})", self.text, names.join(" "))
+    }
+  }
+
+  // Saves the view under the typed name, or moves it to the named bookmark, returning
+  // a message saying which.
+  fn finish(&self, view: &mut View) -> Result<String, String> {
+    let name = self.text.trim();
+    if self.save {
+      let path = bookmarks::path()?;
+      bookmarks::save(&path, &view.bookmark(name))?;
+      Ok(format!(" saved bookmark '{}' to {}", name, path.display()))
+    } else {
+      *view = view.at(&bookmarks::find(name)?);
+      Ok(format!(" went to bookmark '{}'", name))
+    }
+  }
+}
+
+// The views before and after the current one, for stepping back and forth.
+#[derive(Debug, Default, PartialEq)]
+struct History {
+  back: Vec<View>,
+  forward: Vec<View>,
+  current: Option<View>,
+}
+
+impl History {
+  // Notes the view on screen. One that differs from the last becomes a new step,
+  // dropping the steps that were ahead of it.
+  fn track(&mut self, view: View) {
+    match self.current {
+      Some(current) if current != view => {
+        self.back.push(current);
+        if self.back.len() > HISTORY_LIMIT {
+          self.back.remove(0);
+        }
+        self.forward.clear();
+      }
+      _ => {}
+    }
+    self.current = Some(view);
+  }
+
+  fn undo(&mut self) -> Option<View> {
+    let previous = self.back.pop()?;
+    self.forward.extend(self.current.replace(previous));
+    Some(previous)
+  }
+
+  fn redo(&mut self) -> Option<View> {
+    let next = self.forward.pop()?;
+    self.back.extend(self.current.replace(next));
+    Some(next)
+  }
+
+  // Reads a history saved by `save`; a missing file is an empty history. Saved views
+  // don't record the fractal and come back as Mandelbrot views.
+  fn load(path: &Path) -> Result<History, String> {
+    let mut history = History::default();
+    let start = View { center: Complex { re: 0.0, im: 0.0 }, width: 1.0, limit: 1, fractal: Fractal::Mandelbrot, palette: Palette::Gray };
+    for bookmark in bookmarks::load(path)? {
+      let view = start.at(&bookmark);
+      match bookmark.name.as_str() {
+        "back" => history.back.push(view),
+        "forward" => history.forward.push(view),
+        _ => history.current = Some(view),
+      }
+    }
+    Ok(history)
+  }
+
+  // Writes the history as bookmark lines named after the direction they lie in.
+  fn save(&self, path: &Path) -> Result<(), String> {
+    let lines: Vec<String> = self.back.iter().map(|view| view.bookmark("back"))
+      .chain(self.current.map(|view| view.bookmark("current")))
+      .chain(self.forward.iter().map(|view| view.bookmark("forward")))
+      .map(|bookmark| bookmarks::format(&bookmark) + "\n")
+      .collect();
+    std::fs::write(path, lines.concat()).map_err(|e| format!("error writing history '{}': {}", path.display(), e))
+  }
+}
+
+// Full-size renders of views, running on their own threads while exploring goes on.
+struct Exporter {
+  size: (usize, usize),
+  // Iteration limit for exports; the view's own if not given.
+  limit: Option<usize>,
+  threads: usize,
+  pending: Vec<JoinHandle<Result<String, String>>>,
+}
+
+impl Exporter {
+  // Starts exporting `view`, returning the file it will be saved to.
+  fn start(&mut self, view: &View) -> String {
+    let view = View { limit: self.limit.unwrap_or(view.limit), ..*view };
+    let (size, threads) = (self.size, self.threads);
+    let file = export_name(&view, size);
+    let path = file.clone();
+    self.pending.push(std::thread::spawn(move || {
+      let mut pixels = vec![0; size.0 * size.1];
+      render_parallel(&mut pixels, size, &view.sampler(size), threads, 0, 1, true).map_err(|message| format!(" error exporting '{}': {}", path, message))?;
+      write_color_image(&path, &shades(&pixels, view.limit), size, view.palette).map_err(|e| format!(" error writing '{}': {}", path, e))?;
+      Ok(format!(" exported {}", path))
+    }));
+    file
+  }
+
+  // The outcome of an export that has finished since last asked, if any.
+  fn finished(&mut self) -> Option<String> {
+    let done = self.pending.iter().position(JoinHandle::is_finished)?;
+    Some(outcome(self.pending.remove(done)))
+  }
+
+  // Waits for the exports still running, returning their outcomes.
+  fn finish_all(&mut self) -> Vec<String> {
+    self.pending.drain(..).map(outcome).collect()
+  }
+}
+
+fn outcome(export: JoinHandle<Result<String, String>>) -> String {
+  export.join().unwrap_or_else(|_| Err(" export failed".to_string())).unwrap_or_else(|e| e)
+}
+
+// A file name that says what the export shows, so exports can be told apart and redone.
+fn export_name(view: &View, size: (usize, usize)) -> String {
+  format!("mandel-{}-{}x{}-re{}-im{}-width{:e}-limit{}-{}.png", view.fractal.name(), size.0, size.1,
+          view.center.re, view.center.im, view.width, view.limit, view.palette.name())
+}
+
+// Which field is selected, and the text being typed into it, if any.
+#[derive(Default)]
+struct Panel {
+  selected: usize,
+  editing: Option<String>,
+}
+
+impl Panel {
+  fn lines(&self, view: &View) -> Vec<String> {
+    let mut lines = vec!["Parameters".to_string(), String::new()];
+    for (i, field) in FIELDS.iter().enumerate() {
+      let marker = if i == self.selected { '>' } else { ' ' };
+      let value = match &self.editing {
+        Some(text) if i == self.selected => format!("[{}_]", text),
+        _ => field.value(view),
+      };
+      lines.push(format!("{} {:<15}{}", marker, field.label(), value));
+    }
+    lines.extend([String::new(), "Tab     next field".to_string(), "< >     adjust".to_string(), "Enter   type a value".to_string()]);
+    lines
+  }
+
+  // Applies a key to the panel, returning false for keys it doesn't use.
+  fn handle(&mut self, event: Event, view: &mut View) -> bool {
+    let field = FIELDS[self.selected];
+    match (&mut self.editing, event) {
+      (Some(text), Event::Key(c)) => text.push(c),
+      (Some(text), Event::Backspace) => {
+        text.pop();
+      }
+      (Some(text), Event::Enter) => {
+        // Leave bad input in place so it can be corrected.
+        if field.set(view, text) {
+          self.editing = None;
+        }
+      }
+      (Some(_), Event::Escape) => self.editing = None,
+      (Some(_), _) => {}
+      (None, Event::Tab) => self.selected = (self.selected + 1) % FIELDS.len(),
+      (None, Event::Enter) => self.editing = Some(field.value(view)),
+      (None, Event::Key('<')) => field.step(view, false),
+      (None, Event::Key('>')) => field.step(view, true),
+      (None, _) => return false,
+    }
+    true
+  }
+}
+
+// Runs `mandel view [--threads N] [--max-iter N] [--history FILE] [--export-size WxH] [--export-max-iter N]
+// [--bookmark NAME | UPPERLEFT LOWERRIGHT]`.
+pub fn main(arguments: &[String], threads: usize) -> Result<(), String> {
+  let mut threads = threads;
+  let mut limit = None;
+  let mut corners = Vec::new();
+  let mut bookmark = None;
+  let mut history_file = None;
+  let mut exporter = Exporter { size: DEFAULT_EXPORT_SIZE, limit: None, threads, pending: Vec::new() };
+
+  let mut options = arguments.iter().map(String::as_str);
+  while let Some(option) = options.next() {
+    match option {
+      "--threads" => threads = parse_threads(options.next())?,
+      "--max-iter" => match options.next().map(usize::from_str) {
+        Some(Ok(n)) if n > 0 => limit = Some(n),
+        _ => return Err("--max-iter expects a positive number".to_string()),
+      },
+      "--bookmark" => bookmark = Some(bookmarks::find(options.next().ok_or("--bookmark expects a bookmark name")?)?),
+      "--history" => history_file = Some(Path::new(options.next().ok_or("--history expects a file name")?)),
+      "--export-size" => {
+        exporter.size = match options.next().and_then(|size| parse_pair(size, 'x')) {
+          Some((width, height)) if width > 0 && height > 0 => (width, height),
+          _ => return Err("--export-size expects WIDTHxHEIGHT".to_string()),
+        }
+      }
+      "--export-max-iter" => match options.next().map(usize::from_str) {
+        Some(Ok(n)) if n > 0 => exporter.limit = Some(n),
+        _ => return Err("--export-max-iter expects a positive number".to_string()),
+      },
+      corner => corners.push(parse_complex(corner).ok_or(format!("invalid corner point '{}'", corner))?),
+    }
+  }
+
+  let mut history = match history_file {
+    Some(path) => History::load(path)?,
+    None => History::default(),
+  };
+
+  let view = match (corners.as_slice(), bookmark) {
+    // Carry on where the saved history left off.
+    ([], None) if history.current.is_some() => history.current.unwrap(),
+    ([], None) => View { center: Complex { re: -0.6, im: 0.0 }, width: 3.2, limit: limit.unwrap_or(DEFAULT_MAX_ITER), fractal: Fractal::Mandelbrot, palette: Palette::Gray },
+    ([], Some(bookmark)) => View {
+      center: bookmark.center,
+      width: bookmark.width,
+      limit: limit.unwrap_or(bookmark.limit),
+      fractal: Fractal::Mandelbrot,
+      palette: bookmark.palette,
+    },
+    ([upper_left, lower_right], None) => View {
+      center: (upper_left + lower_right) / 2.0,
+      width: lower_right.re - upper_left.re,
+      limit: limit.unwrap_or(DEFAULT_MAX_ITER),
+      fractal: Fractal::Mandelbrot,
+      palette: Palette::Gray,
+    },
+    _ => return Err("view expects no corners, UPPERLEFT LOWERRIGHT, or --bookmark NAME".to_string()),
+  };
+
+  let mut view = view;
+  exporter.threads = threads;
+  let bounds = run(&mut view, threads, &mut history, &mut exporter).map_err(|e| format!("viewer failed: {}", e))?;
+  if !exporter.pending.is_empty() {
+    log::info(&format!("waiting for {} export(s) to finish", exporter.pending.len()));
+  }
+  for outcome in exporter.finish_all() {
+    log::info(outcome.trim_start());
+  }
+  if let Some(path) = history_file {
+    history.track(view);
+    history.save(path)?;
+  }
+  // Leave the last view's corners behind, ready to paste into a render command.
+  let (upper_left, lower_right) = view.corners(bounds);
+  println!("{},{} {},{}", upper_left.re, upper_left.im, lower_right.re, lower_right.im);
+  Ok(())
+}
+
+fn run(view: &mut View, threads: usize, history: &mut History, exporter: &mut Exporter) -> Result<(usize, usize), std::io::Error> {
+  let mut terminal = Terminal::open()?;
+  let mut panel = Panel::default();
+  let initial = *view;
+  let mut drawn = None;
+  let mut pixels = Vec::new();
+  // Whether `pixels` holds every pass of the drawn view, which panning relies on.
+  let mut complete = false;
+  // The last pixel the mouse was over, which keyboard zooms center on.
+  let mut cursor = None;
+  // The cells where a left-button drag started and where it is now.
+  let mut selection: Option<((usize, usize), (usize, usize))> = None;
+  let mut prompt: Option<Prompt> = None;
+  // The outcome of the last bookmark command or export, shown until the next input.
+  let mut message: Option<String> = None;
+  // Whether to show the Julia inset, and the last one rendered.
+  let mut show_julia = false;
+  let mut julia: Option<(Julia, Vec<u8>)> = None;
+  let mut redraw = true;
+
+  loop {
+    // Input that arrives together, like a burst of wheel steps, makes one step.
+    history.track(*view);
+    let (columns, rows) = terminal.size();
+    let image_columns = if columns >= 2 * PANEL_WIDTH { columns - PANEL_WIDTH } else { columns };
+    // Two pixels per cell vertically, leaving the last line for the status bar.
+    let bounds = (image_columns, 2 * rows.saturating_sub(1).max(1));
+    let mut panel_lines = if image_columns < columns { panel.lines(view) } else { Vec::new() };
+
+    // The inset goes below the panel, as room allows.
+    let julia_rows = JULIA_ROWS.min(rows.saturating_sub(panel_lines.len() + 3));
+    let inset = match cursor {
+      Some(pixel) if show_julia && !panel_lines.is_empty() && julia_rows >= 2 => {
+        let c = view.point(bounds, pixel);
+        panel_lines.extend([String::new(), format!("Julia set at {:.4},{:.4}", c.re, c.im)]);
+        Some(Julia { bounds: (PANEL_WIDTH - 2, 2 * julia_rows), c, limit: view.limit, fractal: view.fractal })
+      }
+      _ => None,
+    };
+    if let Some(inset) = inset.filter(|&inset| julia.as_ref().map(|(drawn, _)| *drawn) != Some(inset)) {
+      let mut inset_pixels = vec![0; inset.bounds.0 * inset.bounds.1];
+      render_parallel(&mut inset_pixels, inset.bounds, &inset, threads, 0, 1, true).map_err(std::io::Error::other)?;
+      julia = Some((inset, shades(&inset_pixels, inset.limit)));
+    }
+    let status_line = match (&prompt, &message) {
+      (Some(prompt), _) => prompt.line(),
+      (None, Some(message)) => message.clone(),
+      (None, None) => status(view, bounds, cursor),
+    };
+
+    // Only the plane parameters call for new pixels; a palette change just redraws.
+    let rendered = View { palette: Palette::Gray, ..*view };
+    let drawing = redraw || drawn != Some((rendered, bounds));
+    if drawn != Some((rendered, bounds)) {
+      (pixels, complete) = render(&mut terminal, view, bounds, threads, &panel_lines, &status_line)?;
+      drawn = Some((rendered, bounds));
+    } else if redraw {
+      let shaded = shades(&pixels, view.limit);
+      let overlay = selection.map(|(start, end)| outline(&shaded, bounds, selected_pixels(start, end)));
+      terminal.draw(overlay.as_ref().unwrap_or(&shaded), bounds, view.palette, &panel_lines, &status_line)?;
+    }
+    if let (true, Some(_), Some((inset, inset_pixels))) = (drawing, inset, &julia) {
+      terminal.draw_inset(inset_pixels, inset.bounds, view.palette, (image_columns + 1, panel_lines.len()))?;
+    }
+    redraw = false;
+
+    if let Some(outcome) = exporter.finished() {
+      message = Some(outcome);
+      redraw = true;
+    }
+    if !terminal.poll(RESIZE_POLL)? {
+      continue;
+    }
+    for event in terminal.read_events()? {
+      redraw = true;
+      if !matches!(event, Event::Mouse(mouse) if mouse.action == MouseAction::Move) {
+        message = None;
+      }
+      if let Some(typing) = &mut prompt {
+        match event {
+          Event::Key(c) => typing.text.push(c),
+          Event::Backspace => {
+            typing.text.pop();
+          }
+          Event::Enter => {
+            message = Some(typing.finish(view).unwrap_or_else(|e| format!(" {}", e)));
+            prompt = None;
+          }
+          Event::Escape => prompt = None,
+          _ => {}
+        }
+        continue;
+      }
+      if panel.handle(event, view) {
+        continue;
+      }
+      let cursor_pixel = cursor.unwrap_or((bounds.0 as f64 / 2.0, bounds.1 as f64 / 2.0));
+      match event {
+        Event::Key('q') | Event::Escape => return Ok(bounds),
+        Event::Key('+') | Event::Key('=') => view.zoom(bounds, cursor_pixel, WHEEL_ZOOM),
+        Event::Key('-') => view.zoom(bounds, cursor_pixel, 1.0 / WHEEL_ZOOM),
+        Event::Key(']') => view.limit *= 2,
+        Event::Key('[') => view.limit = (view.limit / 2).max(1),
+        Event::Key('r') | Event::Key('R') => *view = View { palette: view.palette, ..initial },
+        Event::Key('b') | Event::Key('B') => prompt = Some(Prompt { save: true, text: String::new() }),
+        Event::Key('g') | Event::Key('G') => prompt = Some(Prompt { save: false, text: String::new() }),
+        Event::Key('j') | Event::Key('J') => show_julia = !show_julia,
+        Event::Key('e') | Event::Key('E') => message = Some(format!(" exporting {}", exporter.start(view))),
+        Event::Backspace => *view = history.undo().unwrap_or(*view),
+        Event::ShiftBackspace | Event::Key('y') | Event::Key('Y') => *view = history.redo().unwrap_or(*view),
+        Event::Left | Event::Right | Event::Up | Event::Down => {
+          let (x, y) = ((bounds.0 / PAN_FRACTION).max(1) as isize, (bounds.1 / PAN_FRACTION).max(1) as isize);
+          let (dx, dy) = match event {
+            Event::Left => (-x, 0),
+            Event::Right => (x, 0),
+            Event::Up => (0, -y),
+            _ => (0, y),
+          };
+          let current = drawn == Some((View { palette: Palette::Gray, ..*view }, bounds));
+          let pitch = view.width / bounds.0 as f64;
+          view.center += Complex { re: dx as f64 * pitch, im: -dy as f64 * pitch };
+          if current && complete {
+            incremental::pan(&mut pixels, bounds, dx, dy, &view.sampler(bounds), threads).map_err(std::io::Error::other)?;
+            drawn = Some((View { palette: Palette::Gray, ..*view }, bounds));
+          }
+        }
+        Event::Mouse(mouse) => {
+          let cell = (mouse.column.min(bounds.0 - 1), mouse.row.min(bounds.1 / 2 - 1));
+          let pixel = (cell.0 as f64 + 0.5, 2.0 * cell.1 as f64 + 1.0);
+          cursor = Some(pixel);
+          match mouse.action {
+            MouseAction::Press(Button::Left) => selection = Some((cell, cell)),
+            MouseAction::Drag => {
+              if let Some((_, end)) = &mut selection {
+                *end = cell;
+              }
+            }
+            MouseAction::Release => match selection.take() {
+              Some((start, end)) if start != end => view.zoom_to(bounds, selected_pixels(start, end)),
+              Some(_) => view.zoom(bounds, pixel, 2.0),
+              None => {}
+            },
+            MouseAction::Press(Button::Right) => view.zoom(bounds, pixel, 0.5),
+            MouseAction::WheelUp => view.zoom(bounds, pixel, WHEEL_ZOOM),
+            MouseAction::WheelDown => view.zoom(bounds, pixel, 1.0 / WHEEL_ZOOM),
+            _ => {}
+          }
+        }
+        _ => {}
+      }
+    }
+  }
+}
+
+// The pixel rectangle, as top-left and bottom-right corners, covering the cells
+// between two corners of a selection.
+fn selected_pixels(start: (usize, usize), end: (usize, usize)) -> ((usize, usize), (usize, usize)) {
+  ((start.0.min(end.0), 2 * start.1.min(end.1)), (start.0.max(end.0) + 1, 2 * start.1.max(end.1) + 2))
+}
+
+// A copy of `pixels` with the border of `rectangle` drawn in the brightest shade.
+fn outline(pixels: &[u8], bounds: (usize, usize), rectangle: ((usize, usize), (usize, usize))) -> Vec<u8> {
+  let ((left, top), (right, bottom)) = rectangle;
+  let mut copy = pixels.to_vec();
+  for y in top..bottom {
+    for x in left..right {
+      if y == top || y == bottom - 1 || x == left || x == right - 1 {
+        copy[y * bounds.0 + x] = 255;
+      }
+    }
+  }
+  copy
+}
+
+// Renders and draws `view` pass by pass, stopping early if input is waiting. Returns
+// the pixels and whether every pass finished.
+fn render(terminal: &mut Terminal, view: &View, bounds: (usize, usize), threads: usize, panel: &[String], status: &str) -> Result<(Vec<u32>, bool), std::io::Error> {
+  let sampler = view.sampler(bounds);
+  let mut pixels = vec![0; bounds.0 * bounds.1];
+
+  for (pass, &step) in PASSES.iter().enumerate() {
+    render_parallel(&mut pixels, bounds, &sampler, threads, 0, step, pass == 0).map_err(std::io::Error::other)?;
+    terminal.draw(&shades(&pixels, view.limit), bounds, view.palette, panel, status)?;
+    if pass + 1 < PASSES.len() && terminal.poll(Duration::ZERO)? {
+      return Ok((pixels, false));
+    }
+  }
+  Ok((pixels, true))
+}
+
+// The status bar: the point under `cursor` and its escape time, or the view's corners
+// before the mouse has moved, then the pixel pitch, the limit and the key help.
+fn status(view: &View, bounds: (usize, usize), cursor: Option<(f64, f64)>) -> String {
+  let pitch = view.width / bounds.0 as f64;
+  // Enough decimals to tell neighboring pixels apart.
+  let digits = (-pitch.log10()).ceil().max(0.0) as usize + 1;
+  let location = match cursor {
+    Some(pixel) => {
+      let point = view.point(bounds, pixel);
+      let escape = match view.fractal.escape_time(point, view.limit) {
+        Some(count) => format!("escapes after {}", count),
+        None => "inside".to_string(),
+      };
+      format!("{:.*},{:.*}  {}", digits, point.re, digits, point.im, escape)
+    }
+    None => {
+      let (upper_left, lower_right) = view.corners(bounds);
+      format!("{:.*},{:.*} {:.*},{:.*}", digits, upper_left.re, digits, upper_left.im, digits, lower_right.re, digits, lower_right.im)
+    }
+  };
+  format!(" {}  pitch {:.2e}  limit {}  | drag: zoom to box  click/+/-: zoom  arrows: pan  [ ]: limit  r: reset  bksp/y: back/forward  b/g: save/go to bookmark  e: export  j: julia  q: quit",
+          location, pitch, view.limit)
+}
+
+#[test]
+fn test_zoom_keeps_point_under_cursor() {
+  let bounds = (80, 60);
+  let mut view = View { center: Complex { re: -0.5, im: 0.0 }, width: 3.0, limit: 255, fractal: Fractal::Mandelbrot, palette: Palette::Gray };
+  let pixel = (20.0, 45.0);
+  let before = view.point(bounds, pixel);
+
+  view.zoom(bounds, pixel, 2.0);
+  assert_eq!(view.width, 1.5);
+  let after = view.point(bounds, pixel);
+  assert!((after - before).norm() < 1e-12);
+
+  let (upper_left, lower_right) = view.corners(bounds);
+  assert!(((upper_left.im - lower_right.im) - 1.5 * 60.0 / 80.0).abs() < 1e-12);
+}
+
+#[test]
+fn test_panel_edits_and_steps_fields() {
+  let mut view = View { center: Complex { re: -0.5, im: 0.0 }, width: 3.0, limit: 255, fractal: Fractal::Mandelbrot, palette: Palette::Gray };
+  let mut panel = Panel::default();
+
+  // Type a new iteration limit over the current one.
+  panel.handle(Event::Enter, &mut view);
+  for _ in 0..3 {
+    panel.handle(Event::Backspace, &mut view);
+  }
+  for c in "1000".chars() {
+    panel.handle(Event::Key(c), &mut view);
+  }
+  panel.handle(Event::Enter, &mut view);
+  assert_eq!(view.limit, 1000);
+  assert_eq!(panel.editing, None);
+
+  // Cycle the palette, and leave keys the panel doesn't use to the viewer.
+  panel.handle(Event::Tab, &mut view);
+  panel.handle(Event::Key('>'), &mut view);
+  assert_eq!(view.palette, Palette::Fire);
+  assert!(!panel.handle(Event::Key('q'), &mut view));
+
+  // A value that doesn't parse stays in the editor.
+  panel.handle(Event::Enter, &mut view);
+  panel.handle(Event::Key('x'), &mut view);
+  panel.handle(Event::Enter, &mut view);
+  assert_eq!(panel.editing.as_deref(), Some("firex"));
+}
+
+#[test]
+fn test_zoom_to_rectangle_keeps_aspect_ratio() {
+  let bounds = (80, 40);
+  let mut view = View { center: Complex { re: 0.0, im: 0.0 }, width: 4.0, limit: 255, fractal: Fractal::Mandelbrot, palette: Palette::Gray };
+
+  // A tall box from (10, 10) to (20, 30): its height decides the zoom.
+  view.zoom_to(bounds, ((10, 10), (20, 30)));
+  assert!((view.center - Complex { re: -1.25, im: 0.0 }).norm() < 1e-12);
+  assert!((view.width - 2.0).abs() < 1e-12);
+
+  assert_eq!(selected_pixels((5, 3), (2, 1)), ((2, 2), (6, 8)));
+}
+
+#[test]
+fn test_status_reads_out_point_under_cursor() {
+  let bounds = (80, 40);
+  let view = View { center: Complex { re: -1.0, im: 0.0 }, width: 4.0, limit: 100, fractal: Fractal::Mandelbrot, palette: Palette::Gray };
+
+  // The pitch is 0.05, which gets three decimals.
+  assert!(status(&view, bounds, Some((40.0, 20.0))).starts_with(" -1.000,0.000  inside  pitch 5.00e-2  limit 100  |"));
+  assert!(status(&view, bounds, Some((80.0, 0.0))).starts_with(" 1.000,1.000  escapes after 2  "));
+  assert!(status(&view, bounds, None).starts_with(" -3.000,1.000 1.000,-1.000  pitch"));
+}
+
+#[test]
+fn test_history_steps_back_and_forward() {
+  let view = |re| View { center: Complex { re, im: 0.0 }, width: 1.0, limit: 100, fractal: Fractal::Mandelbrot, palette: Palette::Gray };
+  let mut history = History::default();
+  for re in [0.0, 1.0, 1.0, 2.0] {
+    history.track(view(re));
+  }
+  assert_eq!(history.back, [view(0.0), view(1.0)]);
+
+  assert_eq!(history.undo(), Some(view(1.0)));
+  assert_eq!(history.undo(), Some(view(0.0)));
+  assert_eq!(history.undo(), None);
+  assert_eq!(history.redo(), Some(view(1.0)));
+  // A new view after stepping back drops the steps ahead.
+  history.track(view(3.0));
+  assert_eq!(history.redo(), None);
+  assert_eq!(history.back, [view(0.0), view(1.0)]);
+
+  let path = std::env::temp_dir().join(format!("mandel-history-{}", std::process::id()));
+  history.undo();
+  history.save(&path).unwrap();
+  assert_eq!(History::load(&path).unwrap(), history);
+  std::fs::remove_file(path).unwrap();
+}
+
+#[test]
+fn test_export_names_record_the_view() {
+  let view = View { center: Complex { re: -0.75, im: 0.1 }, width: 0.005, limit: 1000, fractal: Fractal::Mandelbrot, palette: Palette::Fire };
+  assert_eq!(export_name(&view, (1920, 1080)), "mandel-mandelbrot-1920x1080-re-0.75-im0.1-width5e-3-limit1000-fire.png");
+}
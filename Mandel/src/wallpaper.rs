@@ -0,0 +1,138 @@
+// Wallpapers
+// `mandel wallpaper --monitors 2560x1440+0+0,1920x1080+2560+200` renders one view across
+// a layout of monitors, each given as its size and the offset of its top left corner, as
+// xrandr reports them, and writes each monitor its own image. The view is centered on
+// --center and --width across the layout's bounding box; by default it shows the whole
+// set. Every monitor's corners are worked out from the layout's as a crop would, so the
+// images meet seamlessly across monitor edges, even of a deep or rotated view, and
+// no pixels are rendered for the gaps between monitors. The images are written into
+// --output DIR as wallpaper-1.png, wallpaper-2.png, ... in the order the monitors were
+// given. --set COMMAND runs COMMAND with the images' paths after its own arguments, say
+// "feh --bg-fill", to put them on the desktop. Other options apply to every render.
+
+use std::path::Path;
+use std::process::Command;
+
+use num::Complex;
+
+use crate::area::WHOLE_SET;
+use crate::crop::{crop_corners, Rect};
+use crate::json::Value;
+use crate::location::Location;
+use crate::{log, parse_arguments, parse_complex, parse_pair, render};
+
+// A monitor's size, and where its top left corner sits in the layout.
+#[derive(Clone, Copy, Debug, PartialEq)]
+struct Monitor {
+  size: (usize, usize),
+  at: (i64, i64),
+}
+
+pub fn main(arguments: &[String]) -> Result<Vec<(String, Value)>, String> {
+  let (mut monitors, mut center, mut width, mut rotation, mut output, mut set) = (None, None, None, 0.0, ".", None);
+  let mut passed = Vec::new();
+  let mut options = arguments.iter();
+  while let Some(option) = options.next() {
+    match option.as_str() {
+      "--monitors" => monitors = Some(options.next().and_then(|layout| parse_layout(layout))
+        .ok_or("--monitors expects WIDTHxHEIGHT+X+Y for each monitor, separated by commas")?),
+      "--center" => center = Some(options.next().filter(|point| parse_complex(point).is_some()).ok_or("--center expects a point RE,IM")?.as_str()),
+      "--width" => width = Some(options.next().and_then(|value| value.parse::<f64>().ok()).filter(|&width| width > 0.0)
+        .ok_or("--width expects a positive extent of the real axis")?),
+      "--rotate" => rotation = options.next().and_then(|value| value.parse::<f64>().ok()).ok_or("--rotate expects an angle in degrees")?,
+      "--output" => output = options.next().ok_or("--output expects a directory")?.as_str(),
+      "--set" => set = Some(options.next().map(|command| command.split_whitespace().collect::<Vec<&str>>()).filter(|words| !words.is_empty())
+        .ok_or("--set expects the command that sets the wallpaper")?),
+      _ => passed.push(option.clone()),
+    }
+  }
+  let monitors = monitors.ok_or("wallpaper needs --monitors")?;
+
+  let (left, top) = (monitors.iter().map(|m| m.at.0).min().unwrap(), monitors.iter().map(|m| m.at.1).min().unwrap());
+  let right = monitors.iter().map(|m| m.at.0 + m.size.0 as i64).max().unwrap();
+  let bottom = monitors.iter().map(|m| m.at.1 + m.size.1 as i64).max().unwrap();
+  let bounds = ((right - left) as usize, (bottom - top) as usize);
+  let aspect = bounds.0 as f64 / bounds.1 as f64;
+  let set_extent = (WHOLE_SET.1.re - WHOLE_SET.0.re, WHOLE_SET.0.im - WHOLE_SET.1.im);
+  // The whole set fits the layout by default.
+  let width = width.unwrap_or(set_extent.0.max(set_extent.1 * aspect));
+  let center = match center {
+    Some(center) => center.split_once(',').map(|(re, im)| (re.trim().to_string(), im.trim().to_string())).unwrap(),
+    None => {
+      let middle: Complex<f64> = (WHOLE_SET.0 + WHOLE_SET.1) / 2.0;
+      (middle.re.to_string(), middle.im.to_string())
+    }
+  };
+  let layout = Location { center, height: width / aspect, limit: None, rotation, map: None };
+  let (upper_left, lower_right) = layout.corners(bounds);
+
+  std::fs::create_dir_all(output).map_err(|e| format!("error creating output directory '{}': {}", output, e))?;
+  let mut files = Vec::new();
+  for (i, monitor) in monitors.iter().enumerate() {
+    let rect: Rect = ((monitor.at.0 - left) as usize, (monitor.at.1 - top) as usize, monitor.size.0, monitor.size.1);
+    let (corner, opposite) = crop_corners(&upper_left, &lower_right, bounds, rect, rotation)?;
+    let file = Path::new(output).join(format!("wallpaper-{}.png", i + 1)).to_string_lossy().into_owned();
+    log::info(&format!("{} of {}: rendering {}x{} '{}'", i + 1, monitors.len(), monitor.size.0, monitor.size.1, file));
+    let mut arguments = vec![file.clone(), format!("{}x{}", monitor.size.0, monitor.size.1), corner, opposite];
+    if rotation != 0.0 {
+      arguments.extend(["--rotate".to_string(), rotation.to_string()]);
+    }
+    arguments.extend(passed.iter().cloned());
+    render(parse_arguments(&arguments)?)?;
+    files.push(file);
+  }
+
+  if let Some(words) = set {
+    let status = Command::new(words[0]).args(&words[1..]).args(&files).status().map_err(|e| format!("error running '{}': {}", words[0], e))?;
+    if !status.success() {
+      return Err(format!("'{}' failed setting the wallpaper: {}", words.join(" "), status));
+    }
+  }
+  Ok(vec![("files".to_string(), Value::Array(files.iter().map(|file| file.as_str().into()).collect()))])
+}
+
+// Monitors given as WIDTHxHEIGHT+X+Y, separated by commas; the offsets may be negative.
+fn parse_layout(layout: &str) -> Option<Vec<Monitor>> {
+  layout.split(',').map(|monitor| {
+    let monitor = monitor.trim();
+    let split = monitor.find(['+', '-'])?;
+    let (size, offsets) = monitor.split_at(split);
+    let size = parse_pair::<usize>(size, 'x').filter(|&(width, height)| width > 0 && height > 0)?;
+    // The second offset's sign ends the first.
+    let second = offsets[1..].find(['+', '-'])? + 1;
+    let (x, y) = offsets.split_at(second);
+    let offset = |text: &str| text.strip_prefix('+').unwrap_or(text).parse::<i64>().ok();
+    Some(Monitor { size, at: (offset(x)?, offset(y)?) })
+  }).collect()
+}
+
+#[test]
+fn test_wallpapers() {
+  assert_eq!(parse_layout("2560x1440+0+0, 1920x1080+2560+200"),
+             Some(vec![Monitor { size: (2560, 1440), at: (0, 0) }, Monitor { size: (1920, 1080), at: (2560, 200) }]));
+  assert_eq!(parse_layout("1920x1080-1920+0"), Some(vec![Monitor { size: (1920, 1080), at: (-1920, 0) }]));
+  assert_eq!(parse_layout("1920x1080"), None);
+  assert_eq!(parse_layout("1920x1080+0"), None);
+  assert_eq!(parse_layout("0x1080+0+0"), None);
+
+  // Two monitors side by side, the right one lower, make the same pixels as one render of
+  // the layout's bounding box where they lie.
+  let dir = tempfile::tempdir().unwrap();
+  let path = |name: &str| dir.path().join(name).to_str().unwrap().to_string();
+  let options = ["--max-iter", "50", "--no-progress", "--force"].map(String::from);
+  let layout = ["--monitors", "40x30+0+0,20x20+40+10", "--center", "-0.6,0.1", "--width", "3", "--output", dir.path().to_str().unwrap()].map(String::from);
+  let fields = main(&[layout.to_vec(), options.to_vec()].concat()).unwrap();
+  assert_eq!(fields[0].1, Value::Array(vec![path("wallpaper-1.png").as_str().into(), path("wallpaper-2.png").as_str().into()]));
+
+  let layout = Location { center: ("-0.6".to_string(), "0.1".to_string()), height: 3.0 * 30.0 / 60.0, limit: None, rotation: 0.0, map: None };
+  let (upper_left, lower_right) = layout.corners((60, 30));
+  render(parse_arguments(&[[path("whole.png"), "60x30".to_string(), upper_left, lower_right].to_vec(), options.to_vec()].concat()).unwrap()).unwrap();
+  let whole = crate::read_image(&path("whole.png")).unwrap();
+  for (file, rect) in [("wallpaper-1.png", (0, 0, 40, 30)), ("wallpaper-2.png", (40, 10, 20, 20))] {
+    let monitor = crate::read_image(&path(file)).unwrap();
+    assert_eq!(monitor.bounds, (rect.2, rect.3));
+    for y in 0..rect.3 {
+      assert_eq!(monitor.pixels[y * rect.2..][..rect.2], whole.pixels[(rect.1 + y) * 60 + rect.0..][..rect.2], "{} row {}", file, y);
+    }
+  }
+}
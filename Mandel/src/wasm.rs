@@ -0,0 +1,1113 @@
+// WebAssembly formulas
+// `--formula FILE.wasm` renders with an iteration supplied as a WebAssembly module, so
+// formulas can be added without rebuilding the renderer. The module exports
+//
+//   iterate(re: f64, im: f64, max_iter: i32) -> i32
+//
+// or the same returning (i32, f64, f64), the multi-value form that adds the final z.
+// The i32 is the iteration at which the point c = re + im·i escaped; max_iter or more,
+// or a negative count, means it never did. A fourth parameter, bailout: f64, gets the
+// radius orbits escape beyond, --bailout or 2; formulas without it test their own.
+//
+// Modules run in a small interpreter of their own: they can't import anything, so they
+// see nothing of the host but the arguments, their memory is capped at MAX_PAGES, and
+// every call has a budget of instructions and operand stack, so a formula that loops
+// forever traps instead of hanging the render. The interpreter covers the WebAssembly
+// 1.0 instruction set (less tables beyond indirect calls) plus the extensions compilers
+// emit by default: multi-value, sign extension, saturating conversions and bulk memory
+// copy and fill. Pixels whose call traps are left black, with a warning on the first,
+// and the instance the call trapped in is thrown away rather than reused with its state
+// half-updated. Modules whose start function takes or returns values are refused.
+
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Mutex;
+
+use num::Complex;
+
+use crate::sha256::{self, Sha256};
+use crate::log;
+
+// 16 MiB of linear memory at most.
+const MAX_PAGES: u32 = 256;
+const PAGE_SIZE: usize = 65536;
+
+// Instructions a call may execute, besides INSTRUCTIONS_PER_ITERATION for each of its
+// iterations.
+const BASE_FUEL: u64 = 1_000_000;
+const INSTRUCTIONS_PER_ITERATION: u64 = 1_000;
+
+// Deepest nesting of calls within a module.
+const MAX_CALL_DEPTH: usize = 256;
+
+// Most values a call's operand stack may hold; with MAX_CALL_DEPTH, operand stacks take
+// 8 MiB at most, as memory is capped.
+const MAX_STACK_HEIGHT: usize = 4096;
+
+const I32: u8 = 0x7f;
+const F64: u8 = 0x7c;
+
+#[derive(Clone, Debug, PartialEq)]
+struct FuncType {
+  params: Vec<u8>,
+  results: Vec<u8>,
+}
+
+// Decoded instructions. Block structure is resolved to instruction indexes at load time.
+#[derive(Clone, Debug, PartialEq)]
+enum Op {
+  Unreachable,
+  Nop,
+  Block { params: usize, results: usize, end: usize },
+  Loop { params: usize },
+  If { params: usize, results: usize, otherwise: Option<usize>, end: usize },
+  // The end of the `if` it belongs to.
+  Else(usize),
+  End,
+  Br(u32),
+  BrIf(u32),
+  BrTable(Box<[u32]>, u32),
+  Return,
+  Call(u32),
+  CallIndirect(u32),
+  Drop,
+  Select,
+  LocalGet(u32),
+  LocalSet(u32),
+  LocalTee(u32),
+  GlobalGet(u32),
+  GlobalSet(u32),
+  // The opcode and offset of a memory access.
+  Load(u8, u32),
+  Store(u8, u32),
+  MemorySize,
+  MemoryGrow,
+  MemoryCopy,
+  MemoryFill,
+  // Any constant, as the bits of its value.
+  Const(u64),
+  // Numeric instructions, by opcode, and saturating conversions by their 0xfc subcode.
+  Numeric(u8),
+  Saturating(u8),
+}
+
+struct Function {
+  type_index: u32,
+  // How many locals follow the parameters.
+  locals: usize,
+  code: Vec<Op>,
+}
+
+struct Module {
+  types: Vec<FuncType>,
+  functions: Vec<Function>,
+  table: Vec<Option<u32>>,
+  iterate: u32,
+  start: Option<u32>,
+}
+
+// The mutable state of a module: its memory and globals. Values of all types are
+// kept as u64s holding their bits, i32s zero-extended.
+#[derive(Clone)]
+struct Instance {
+  memory: Vec<u8>,
+  max_pages: u32,
+  globals: Vec<u64>,
+  fuel: u64,
+}
+
+type Trap = String;
+
+pub struct Formula {
+  module: Module,
+  // Instances not in use by a render thread; each thread takes one for a sample.
+  idle: Mutex<Vec<Instance>>,
+  initial: Instance,
+  // Whether the warning for a trapping pixel has been given.
+  warned: AtomicBool,
+  // Whether iterate returns the final z as well.
+  final_z: bool,
+  // Whether iterate takes the bailout radius.
+  pub takes_bailout: bool,
+  // SHA-256 of the module, which tile cache keys go by.
+  pub digest: String,
+}
+
+// A byte reader over a module's binary encoding.
+struct Reader<'a> {
+  bytes: &'a [u8],
+  at: usize,
+}
+
+impl<'a> Reader<'a> {
+  fn byte(&mut self) -> Result<u8, String> {
+    let byte = *self.bytes.get(self.at).ok_or("unexpected end of the module")?;
+    self.at += 1;
+    Ok(byte)
+  }
+
+  fn take(&mut self, count: usize) -> Result<&'a [u8], String> {
+    let end = self.at.checked_add(count).filter(|&end| end <= self.bytes.len()).ok_or("unexpected end of the module")?;
+    let bytes = &self.bytes[self.at..end];
+    self.at = end;
+    Ok(bytes)
+  }
+
+  fn done(&self) -> bool {
+    self.at == self.bytes.len()
+  }
+
+  fn unsigned(&mut self) -> Result<u32, String> {
+    let value = self.signed(32, false)?;
+    u32::try_from(value).map_err(|_| "malformed integer".to_string())
+  }
+
+  fn count(&mut self) -> Result<usize, String> {
+    self.unsigned().map(|count| count as usize)
+  }
+
+  // A LEB128 integer of at most `bits` bits.
+  fn signed(&mut self, bits: u32, signed: bool) -> Result<i64, String> {
+    let (mut value, mut shift) = (0i128, 0);
+    loop {
+      let byte = self.byte()?;
+      value |= ((byte & 0x7f) as i128) << shift;
+      shift += 7;
+      if byte & 0x80 == 0 {
+        if signed && shift < 128 && byte & 0x40 != 0 {
+          value |= -1i128 << shift;
+        }
+        break;
+      }
+      if shift >= bits + 7 {
+        return Err("malformed integer".to_string());
+      }
+    }
+    let (low, high) = if signed { (-(1i128 << (bits - 1)), (1i128 << (bits - 1)) - 1) } else { (0, (1i128 << bits) - 1) };
+    if value < low || value > high {
+      return Err("malformed integer".to_string());
+    }
+    Ok(value as i64)
+  }
+
+  fn name(&mut self) -> Result<String, String> {
+    let length = self.count()?;
+    String::from_utf8(self.take(length)?.to_vec()).map_err(|_| "malformed name".to_string())
+  }
+
+  fn value_types(&mut self) -> Result<Vec<u8>, String> {
+    (0..self.count()?).map(|_| self.value_type()).collect()
+  }
+
+  fn value_type(&mut self) -> Result<u8, String> {
+    match self.byte()? {
+      kind @ 0x7c..=0x7f => Ok(kind),
+      kind => Err(format!("unsupported value type 0x{:02x}", kind)),
+    }
+  }
+
+  // Minimum and maximum of a memory or table.
+  fn limits(&mut self) -> Result<(u32, Option<u32>), String> {
+    match self.byte()? {
+      0 => Ok((self.unsigned()?, None)),
+      1 => Ok((self.unsigned()?, Some(self.unsigned()?))),
+      _ => Err("malformed limits".to_string()),
+    }
+  }
+
+  // A constant expression: a single constant, or a global's value, then end.
+  fn constant(&mut self, globals: &[u64]) -> Result<u64, String> {
+    let value = match self.byte()? {
+      0x41 => self.signed(32, true)? as i32 as u32 as u64,
+      0x42 => self.signed(64, true)? as u64,
+      0x43 => u32::from_le_bytes(self.take(4)?.try_into().unwrap()) as u64,
+      0x44 => u64::from_le_bytes(self.take(8)?.try_into().unwrap()),
+      0x23 => *globals.get(self.count()?).ok_or("unknown global in a constant")?,
+      op => return Err(format!("unsupported constant instruction 0x{:02x}", op)),
+    };
+    match self.byte()? {
+      0x0b => Ok(value),
+      _ => Err("constant expressions hold one instruction".to_string()),
+    }
+  }
+}
+
+impl Formula {
+  pub fn load(path: &str) -> Result<Formula, String> {
+    let bytes = std::fs::read(path).map_err(|e| format!("error reading formula '{}': {}", path, e))?;
+    Formula::new(&bytes).map_err(|message| format!("formula '{}': {}", path, message))
+  }
+
+  fn new(bytes: &[u8]) -> Result<Formula, String> {
+    let (module, mut initial) = decode(bytes)?;
+    let mut sha = Sha256::new();
+    sha.update(bytes);
+    let iterate = &module.types[module.functions[module.iterate as usize].type_index as usize];
+    let takes_bailout = match iterate.params.as_slice() {
+      [F64, F64, I32] => false,
+      [F64, F64, I32, F64] => true,
+      _ => return Err("iterate has to take (f64, f64, i32), or (f64, f64, i32, f64)".to_string()),
+    };
+    let final_z = match iterate.results.as_slice() {
+      [I32] => false,
+      [I32, F64, F64] => true,
+      _ => return Err("iterate has to return i32, or (i32, f64, f64)".to_string()),
+    };
+    if let Some(start) = module.start {
+      initial.fuel = BASE_FUEL;
+      call(&module, &mut initial, start, &[], 0).map_err(|trap| format!("the start function trapped: {}", trap))?;
+    }
+    let formula = Formula { module, idle: Mutex::new(Vec::new()), initial, warned: AtomicBool::new(false), final_z, takes_bailout, digest: sha256::hex(&sha.finish()) };
+    // Try it once, so modules that can't run fail before the render starts.
+    formula.iterate(Complex { re: 0.0, im: 0.0 }, 10, 2.0).map_err(|trap| format!("iterate trapped: {}", trap))?;
+    Ok(formula)
+  }
+
+  // The escape count and final z of `c` at a bailout of `radius`, as the module reports
+  // them.
+  fn iterate(&self, c: Complex<f64>, limit: usize, radius: f64) -> Result<(i32, Option<Complex<f64>>), Trap> {
+    let mut instance = self.idle.lock().unwrap().pop().unwrap_or_else(|| self.initial.clone());
+    instance.fuel = BASE_FUEL.saturating_add(INSTRUCTIONS_PER_ITERATION.saturating_mul(limit as u64));
+    let limit = i32::try_from(limit).unwrap_or(i32::MAX);
+    let arguments = [c.re.to_bits(), c.im.to_bits(), limit as u32 as u64, radius.to_bits()];
+    let results = call(&self.module, &mut instance, self.module.iterate, &arguments[..3 + self.takes_bailout as usize], 0)?;
+    self.idle.lock().unwrap().push(instance);
+    let z = self.final_z.then(|| Complex { re: f64::from_bits(results[1]), im: f64::from_bits(results[2]) });
+    Ok((results[0] as u32 as i32, z))
+  }
+
+  pub fn escape_time(&self, c: Complex<f64>, limit: usize, radius: f64) -> Option<usize> {
+    match self.iterate(c, limit, radius) {
+      Ok((count, _)) => usize::try_from(count).ok().filter(|&count| count < limit),
+      Err(trap) => {
+        if !self.warned.swap(true, Ordering::Relaxed) {
+          log::warn(&format!("the formula trapped at {} + {}i: {}; pixels where it traps are left black", c.re, c.im, trap));
+        }
+        None
+      }
+    }
+  }
+}
+
+fn decode(bytes: &[u8]) -> Result<(Module, Instance), String> {
+  let mut reader = Reader { bytes, at: 0 };
+  if reader.take(8).ok() != Some(b"\0asm\x01\0\0\0") {
+    return Err("not a WebAssembly 1.0 module".to_string());
+  }
+
+  let mut types = Vec::new();
+  let mut function_types = Vec::new();
+  let mut functions = Vec::new();
+  let mut table = Vec::new();
+  let mut memory = (0, Some(0));
+  let mut globals = Vec::new();
+  let mut exports = Vec::new();
+  let mut start = None;
+  let mut elements = Vec::new();
+  let mut data = Vec::new();
+
+  while !reader.done() {
+    let id = reader.byte()?;
+    let size = reader.count()?;
+    let mut section = Reader { bytes: reader.take(size)?, at: 0 };
+    match id {
+      0 | 12 => continue,
+      1 => for _ in 0..section.count()? {
+        if section.byte()? != 0x60 {
+          return Err("malformed function type".to_string());
+        }
+        types.push(FuncType { params: section.value_types()?, results: section.value_types()? });
+      },
+      2 => if section.count()? > 0 {
+        return Err("formulas can't import anything from the host".to_string());
+      },
+      3 => function_types = (0..section.count()?).map(|_| section.unsigned()).collect::<Result<_, _>>()?,
+      4 => for _ in 0..section.count()? {
+        if section.byte()? != 0x70 {
+          return Err("only tables of functions are supported".to_string());
+        }
+        let (minimum, _) = section.limits()?;
+        table.resize(table.len().max(minimum.min(1 << 16) as usize), None);
+      },
+      5 => for _ in 0..section.count()? {
+        memory = section.limits()?;
+        if memory.0 > MAX_PAGES {
+          return Err(format!("the module wants {} pages of memory; formulas get at most {}", memory.0, MAX_PAGES));
+        }
+      },
+      6 => for _ in 0..section.count()? {
+        section.value_type()?;
+        section.byte()?;
+        let value = section.constant(&globals)?;
+        globals.push(value);
+      },
+      7 => for _ in 0..section.count()? {
+        let name = section.name()?;
+        let (kind, index) = (section.byte()?, section.unsigned()?);
+        exports.push((name, kind, index));
+      },
+      8 => start = Some(section.unsigned()?),
+      9 => for _ in 0..section.count()? {
+        if section.unsigned()? != 0 {
+          return Err("only active element segments are supported".to_string());
+        }
+        let offset = section.constant(&globals)? as u32 as usize;
+        let indexes: Vec<u32> = (0..section.count()?).map(|_| section.unsigned()).collect::<Result<_, _>>()?;
+        elements.push((offset, indexes));
+      },
+      10 => for i in 0..section.count()? {
+        let length = section.count()?;
+        let mut body = Reader { bytes: section.take(length)?, at: 0 };
+        let type_index = *function_types.get(i).ok_or("more function bodies than functions")?;
+        functions.push(decode_body(&mut body, type_index, &types)?);
+      },
+      11 => for _ in 0..section.count()? {
+        let offset = match section.unsigned()? {
+          0 => Some(section.constant(&globals)?),
+          1 => None,
+          _ => Some(section.unsigned().and_then(|_| section.constant(&globals))?),
+        };
+        let length = section.count()?;
+        let bytes = section.take(length)?;
+        data.extend(offset.map(|offset| (offset as u32 as usize, bytes)));
+      },
+      id => return Err(format!("unknown section {}", id)),
+    }
+    if !section.done() {
+      return Err(format!("malformed section {}", id));
+    }
+  }
+
+  if functions.len() != function_types.len() {
+    return Err("functions without bodies".to_string());
+  }
+  let valid_function = |index: u32, what: &str| -> Result<u32, String> {
+    functions.get(index as usize).map(|_| index).ok_or(format!("{} names an unknown function", what))
+  };
+  for (offset, indexes) in elements {
+    for (i, &index) in indexes.iter().enumerate() {
+      *table.get_mut(offset + i).ok_or("element segment outside the table")? = Some(valid_function(index, "an element segment")?);
+    }
+  }
+  for function in &functions {
+    for op in &function.code {
+      match *op {
+        Op::Call(index) => { valid_function(index, "a call")?; }
+        Op::CallIndirect(index) if index as usize >= types.len() => return Err("an indirect call names an unknown type".to_string()),
+        Op::GlobalGet(index) | Op::GlobalSet(index) if index as usize >= globals.len() => return Err("an instruction names an unknown global".to_string()),
+        _ => {}
+      }
+    }
+  }
+  let iterate = exports.iter().find(|(name, kind, _)| name == "iterate" && *kind == 0).map(|&(_, _, index)| index)
+    .ok_or("the module doesn't export an iterate function")?;
+  let iterate = valid_function(iterate, "the iterate export")?;
+  if let Some(start) = start {
+    let start_type = &types[functions[valid_function(start, "the start section")? as usize].type_index as usize];
+    if !start_type.params.is_empty() || !start_type.results.is_empty() {
+      return Err("the start function has to take and return nothing".to_string());
+    }
+  }
+
+  let max_pages = memory.1.unwrap_or(MAX_PAGES).min(MAX_PAGES);
+  let mut instance = Instance { memory: vec![0; memory.0 as usize * PAGE_SIZE], max_pages, globals, fuel: 0 };
+  for (offset, bytes) in data {
+    instance.memory.get_mut(offset..offset + bytes.len()).ok_or("data segment outside memory")?.copy_from_slice(bytes);
+  }
+  Ok((Module { types, functions, table, iterate, start }, instance))
+}
+
+fn decode_body(body: &mut Reader, type_index: u32, types: &[FuncType]) -> Result<Function, String> {
+  let function_type = types.get(type_index as usize).ok_or("a function names an unknown type")?;
+  let mut locals = 0usize;
+  for _ in 0..body.count()? {
+    locals = locals.checked_add(body.count()?).filter(|&locals| locals <= 50_000).ok_or("too many locals")?;
+    body.value_type()?;
+  }
+  let local_count = function_type.params.len() + locals;
+
+  // Indexes of the block, loop and if instructions still open.
+  let mut open: Vec<usize> = Vec::new();
+  let mut code = Vec::new();
+  loop {
+    let at = code.len();
+    let opcode = body.byte()?;
+    let op = match opcode {
+      0x00 => Op::Unreachable,
+      0x01 => Op::Nop,
+      0x02..=0x04 => {
+        let (params, results) = block_type(body, types)?;
+        open.push(at);
+        match opcode {
+          0x02 => Op::Block { params, results, end: 0 },
+          0x03 => Op::Loop { params },
+          _ => Op::If { params, results, otherwise: None, end: 0 },
+        }
+      }
+      0x05 => {
+        match open.last().map(|&index| &mut code[index]) {
+          Some(Op::If { otherwise: otherwise @ None, .. }) => *otherwise = Some(at),
+          _ => return Err("else without if".to_string()),
+        }
+        Op::Else(0)
+      }
+      0x0b => {
+        match open.pop() {
+          None => {
+            code.push(Op::End);
+            break;
+          }
+          Some(index) => match &mut code[index] {
+            Op::Block { end, .. } => *end = at,
+            Op::If { otherwise, end, .. } => {
+              *end = at;
+              if let Some(otherwise) = *otherwise {
+                code[otherwise] = Op::Else(at);
+              }
+            }
+            _ => {}
+          },
+        }
+        Op::End
+      }
+      0x0c => Op::Br(body.unsigned()?),
+      0x0d => Op::BrIf(body.unsigned()?),
+      0x0e => {
+        let labels = (0..body.count()?).map(|_| body.unsigned()).collect::<Result<Vec<_>, _>>()?;
+        Op::BrTable(labels.into(), body.unsigned()?)
+      }
+      0x0f => Op::Return,
+      0x10 => Op::Call(body.unsigned()?),
+      0x11 => {
+        let type_index = body.unsigned()?;
+        if body.unsigned()? != 0 {
+          return Err("only table 0 is supported".to_string());
+        }
+        Op::CallIndirect(type_index)
+      }
+      0x1a => Op::Drop,
+      0x1b => Op::Select,
+      0x1c => {
+        body.value_types()?;
+        Op::Select
+      }
+      0x20..=0x22 => {
+        let index = body.unsigned()?;
+        if index as usize >= local_count {
+          return Err("an instruction names an unknown local".to_string());
+        }
+        [Op::LocalGet, Op::LocalSet, Op::LocalTee][opcode as usize - 0x20](index)
+      }
+      0x23 => Op::GlobalGet(body.unsigned()?),
+      0x24 => Op::GlobalSet(body.unsigned()?),
+      0x28..=0x3e => {
+        body.unsigned()?;
+        let offset = body.unsigned()?;
+        if opcode <= 0x35 { Op::Load(opcode, offset) } else { Op::Store(opcode, offset) }
+      }
+      0x3f | 0x40 => {
+        body.byte()?;
+        if opcode == 0x3f { Op::MemorySize } else { Op::MemoryGrow }
+      }
+      0x41 => Op::Const(body.signed(32, true)? as i32 as u32 as u64),
+      0x42 => Op::Const(body.signed(64, true)? as u64),
+      0x43 => Op::Const(u32::from_le_bytes(body.take(4)?.try_into().unwrap()) as u64),
+      0x44 => Op::Const(u64::from_le_bytes(body.take(8)?.try_into().unwrap())),
+      0x45..=0xc4 => Op::Numeric(opcode),
+      0xfc => match body.unsigned()? {
+        code @ 0..=7 => Op::Saturating(code as u8),
+        10 => {
+          body.take(2)?;
+          Op::MemoryCopy
+        }
+        11 => {
+          body.byte()?;
+          Op::MemoryFill
+        }
+        code => return Err(format!("unsupported instruction 0xfc {}", code)),
+      },
+      _ => return Err(format!("unsupported instruction 0x{:02x}", opcode)),
+    };
+    code.push(op);
+  }
+  if !body.done() {
+    return Err("code after the end of a function".to_string());
+  }
+  Ok(Function { type_index, locals, code })
+}
+
+// The parameter and result counts of a block.
+fn block_type(body: &mut Reader, types: &[FuncType]) -> Result<(usize, usize), String> {
+  match body.bytes.get(body.at) {
+    Some(0x40) => {
+      body.at += 1;
+      Ok((0, 0))
+    }
+    Some(0x7c..=0x7f) => {
+      body.at += 1;
+      Ok((0, 1))
+    }
+    _ => {
+      let index = body.signed(33, true)?;
+      let block = usize::try_from(index).ok().and_then(|index| types.get(index)).ok_or("a block names an unknown type")?;
+      Ok((block.params.len(), block.results.len()))
+    }
+  }
+}
+
+// A block, loop or if being executed.
+struct Label {
+  // The operand stack's height under the block's parameters.
+  height: usize,
+  // How many values a branch to it carries: a loop's parameters, a block's results.
+  arity: usize,
+  // Where a branch to it goes: the start of a loop, or a block's end.
+  target: usize,
+  is_loop: bool,
+}
+
+fn call(module: &Module, instance: &mut Instance, index: u32, arguments: &[u64], depth: usize) -> Result<Vec<u64>, Trap> {
+  if depth >= MAX_CALL_DEPTH {
+    return Err("call stack exhausted".to_string());
+  }
+  let function = &module.functions[index as usize];
+  let function_type = &module.types[function.type_index as usize];
+  if arguments.len() != function_type.params.len() {
+    return Err("call with the wrong number of arguments".to_string());
+  }
+  let mut locals = arguments.to_vec();
+  locals.resize(arguments.len() + function.locals, 0);
+  let mut stack: Vec<u64> = Vec::new();
+  let mut labels: Vec<Label> = Vec::new();
+  let results = function_type.results.len();
+  let code = &function.code;
+
+  let underflow = || "operand stack underflow".to_string();
+  macro_rules! pop {
+    () => { stack.pop().ok_or_else(underflow)? };
+  }
+
+  let mut pc = 0;
+  loop {
+    instance.fuel = instance.fuel.checked_sub(1).ok_or("the formula ran too long")?;
+    if stack.len() > MAX_STACK_HEIGHT {
+      return Err("operand stack exhausted".to_string());
+    }
+    let op = &code[pc];
+    pc += 1;
+    match *op {
+      Op::Unreachable => return Err("unreachable executed".to_string()),
+      Op::Nop => {}
+      Op::Block { params, results, end } => labels.push(Label { height: stack.len().checked_sub(params).ok_or_else(underflow)?, arity: results, target: end, is_loop: false }),
+      Op::Loop { params } => labels.push(Label { height: stack.len().checked_sub(params).ok_or_else(underflow)?, arity: params, target: pc - 1, is_loop: true }),
+      Op::If { params, results, otherwise, end } => {
+        let condition = pop!() as u32;
+        labels.push(Label { height: stack.len().checked_sub(params).ok_or_else(underflow)?, arity: results, target: end, is_loop: false });
+        if condition == 0 {
+          pc = otherwise.map_or(end, |otherwise| otherwise + 1);
+        }
+      }
+      // Reached the end of the if's first arm.
+      Op::Else(end) => pc = end,
+      Op::End => {
+        if labels.pop().is_none() {
+          break;
+        }
+      }
+      Op::Br(depth) => pc = branch(&mut stack, &mut labels, depth)?.unwrap_or(code.len() - 1),
+      Op::BrIf(depth) => {
+        if pop!() as u32 != 0 {
+          pc = branch(&mut stack, &mut labels, depth)?.unwrap_or(code.len() - 1);
+        }
+      }
+      Op::BrTable(ref targets, default) => {
+        let which = pop!() as u32 as usize;
+        pc = branch(&mut stack, &mut labels, *targets.get(which).unwrap_or(&default))?.unwrap_or(code.len() - 1);
+      }
+      Op::Return => {
+        labels.clear();
+        pc = code.len() - 1;
+      }
+      Op::Call(callee) => {
+        let params = module.types[module.functions[callee as usize].type_index as usize].params.len();
+        let arguments = stack.split_off(stack.len().checked_sub(params).ok_or_else(underflow)?);
+        stack.extend(call(module, instance, callee, &arguments, depth + 1)?);
+      }
+      Op::CallIndirect(type_index) => {
+        let slot = pop!() as u32 as usize;
+        let callee = module.table.get(slot).copied().flatten().ok_or("indirect call to an empty table slot")?;
+        let expected = &module.types[type_index as usize];
+        if module.types[module.functions[callee as usize].type_index as usize] != *expected {
+          return Err("indirect call to a function of the wrong type".to_string());
+        }
+        let arguments = stack.split_off(stack.len().checked_sub(expected.params.len()).ok_or_else(underflow)?);
+        stack.extend(call(module, instance, callee, &arguments, depth + 1)?);
+      }
+      Op::Drop => {
+        pop!();
+      }
+      Op::Select => {
+        let (condition, second, first) = (pop!() as u32, pop!(), pop!());
+        stack.push(if condition != 0 { first } else { second });
+      }
+      Op::LocalGet(index) => stack.push(locals[index as usize]),
+      Op::LocalSet(index) => locals[index as usize] = pop!(),
+      Op::LocalTee(index) => locals[index as usize] = *stack.last().ok_or_else(underflow)?,
+      Op::GlobalGet(index) => stack.push(instance.globals[index as usize]),
+      Op::GlobalSet(index) => instance.globals[index as usize] = pop!(),
+      Op::Load(opcode, offset) => {
+        let address = pop!() as u32;
+        stack.push(load(&instance.memory, opcode, address as u64 + offset as u64)?);
+      }
+      Op::Store(opcode, offset) => {
+        let (value, address) = (pop!(), pop!() as u32);
+        store(&mut instance.memory, opcode, address as u64 + offset as u64, value)?;
+      }
+      Op::MemorySize => stack.push((instance.memory.len() / PAGE_SIZE) as u64),
+      Op::MemoryGrow => {
+        let (pages, old) = (pop!() as u32, (instance.memory.len() / PAGE_SIZE) as u32);
+        match old.checked_add(pages).filter(|&total| total <= instance.max_pages) {
+          Some(total) => {
+            instance.memory.resize(total as usize * PAGE_SIZE, 0);
+            stack.push(old as u64);
+          }
+          None => stack.push(u32::MAX as u64),
+        }
+      }
+      Op::MemoryCopy => {
+        let (length, source, destination) = (pop!() as u32 as usize, pop!() as u32 as usize, pop!() as u32 as usize);
+        if source.max(destination) + length > instance.memory.len() {
+          return Err("out of bounds memory access".to_string());
+        }
+        instance.memory.copy_within(source..source + length, destination);
+      }
+      Op::MemoryFill => {
+        let (length, value, destination) = (pop!() as u32 as usize, pop!() as u8, pop!() as u32 as usize);
+        instance.memory.get_mut(destination..destination + length).ok_or("out of bounds memory access")?.fill(value);
+      }
+      Op::Const(value) => stack.push(value),
+      Op::Numeric(opcode) => numeric(&mut stack, opcode)?,
+      Op::Saturating(code) => {
+        let value = pop!();
+        let x = if code % 4 < 2 { f32::from_bits(value as u32) as f64 } else { f64::from_bits(value) };
+        stack.push(match code {
+          0 | 2 => x as i32 as u32 as u64,
+          1 | 3 => x as u32 as u64,
+          4 | 6 => x as i64 as u64,
+          _ => x as u64,
+        });
+      }
+    }
+  }
+
+  if stack.len() < results {
+    return Err(underflow());
+  }
+  Ok(stack.split_off(stack.len() - results))
+}
+
+// Branches to the label `depth` out, and returns where execution goes on, or None to
+// return from the function.
+fn branch(stack: &mut Vec<u64>, labels: &mut Vec<Label>, depth: u32) -> Result<Option<usize>, Trap> {
+  let Some(index) = labels.len().checked_sub(depth as usize + 1) else {
+    labels.clear();
+    return Ok(None);
+  };
+  let label = &labels[index];
+  let carried = stack.split_off(stack.len().checked_sub(label.arity).ok_or("operand stack underflow")?);
+  stack.truncate(label.height);
+  stack.extend(carried);
+  // A loop's label stays, to be branched to again; a block's end pops its own.
+  let next = if label.is_loop { label.target + 1 } else { label.target };
+  labels.truncate(index + 1);
+  Ok(Some(next))
+}
+
+fn load(memory: &[u8], opcode: u8, address: u64) -> Result<u64, Trap> {
+  let size = match opcode {
+    0x29 | 0x2b => 8,
+    0x28 | 0x2a | 0x34 | 0x35 => 4,
+    0x2e | 0x2f | 0x32 | 0x33 => 2,
+    _ => 1,
+  };
+  let start = usize::try_from(address).map_err(|_| "out of bounds memory access")?;
+  let bytes = memory.get(start..start + size).ok_or("out of bounds memory access")?;
+  let mut raw = [0; 8];
+  raw[..size].copy_from_slice(bytes);
+  let value = u64::from_le_bytes(raw);
+  Ok(match opcode {
+    0x2c => value as i8 as i32 as u32 as u64,
+    0x2e => value as i16 as i32 as u32 as u64,
+    0x30 => value as i8 as i64 as u64,
+    0x32 => value as i16 as i64 as u64,
+    0x34 => value as i32 as i64 as u64,
+    _ => value,
+  })
+}
+
+fn store(memory: &mut [u8], opcode: u8, address: u64, value: u64) -> Result<(), Trap> {
+  let size = match opcode {
+    0x37 | 0x39 => 8,
+    0x36 | 0x38 | 0x3e => 4,
+    0x3b | 0x3d => 2,
+    _ => 1,
+  };
+  let start = usize::try_from(address).map_err(|_| "out of bounds memory access")?;
+  memory.get_mut(start..start + size).ok_or("out of bounds memory access")?.copy_from_slice(&value.to_le_bytes()[..size]);
+  Ok(())
+}
+
+// Wasm's min and max: NaN if either is, and -0 below +0.
+fn minimum(a: f64, b: f64) -> f64 {
+  if a.is_nan() || b.is_nan() { f64::NAN } else if a == b { if a.is_sign_negative() { a } else { b } } else { a.min(b) }
+}
+
+fn maximum(a: f64, b: f64) -> f64 {
+  if a.is_nan() || b.is_nan() { f64::NAN } else if a == b { if a.is_sign_positive() { a } else { b } } else { a.max(b) }
+}
+
+// Truncates `x` to an integer within [low, high), or traps.
+fn truncate(x: f64, low: f64, high: f64) -> Result<f64, Trap> {
+  if x.is_nan() {
+    return Err("invalid conversion to integer".to_string());
+  }
+  let x = x.trunc();
+  if x < low || x >= high {
+    return Err("integer overflow".to_string());
+  }
+  Ok(x)
+}
+
+fn numeric(stack: &mut Vec<u64>, opcode: u8) -> Result<(), Trap> {
+  let underflow = || "operand stack underflow".to_string();
+  let mut pop = || stack.pop().ok_or_else(underflow);
+  let bool = |b: bool| b as u64;
+  let value = match opcode {
+    // Tests and comparisons.
+    0x45 => bool(pop()? as u32 == 0),
+    0x50 => bool(pop()? == 0),
+    0x46..=0x4f => {
+      let (b, a) = (pop()? as u32, pop()? as u32);
+      let (sa, sb) = (a as i32, b as i32);
+      bool([a == b, a != b, sa < sb, a < b, sa > sb, a > b, sa <= sb, a <= b, sa >= sb, a >= b][opcode as usize - 0x46])
+    }
+    0x51..=0x5a => {
+      let (b, a) = (pop()?, pop()?);
+      let (sa, sb) = (a as i64, b as i64);
+      bool([a == b, a != b, sa < sb, a < b, sa > sb, a > b, sa <= sb, a <= b, sa >= sb, a >= b][opcode as usize - 0x51])
+    }
+    0x5b..=0x60 => {
+      let (b, a) = (f32::from_bits(pop()? as u32), f32::from_bits(pop()? as u32));
+      bool([a == b, a != b, a < b, a > b, a <= b, a >= b][opcode as usize - 0x5b])
+    }
+    0x61..=0x66 => {
+      let (b, a) = (f64::from_bits(pop()?), f64::from_bits(pop()?));
+      bool([a == b, a != b, a < b, a > b, a <= b, a >= b][opcode as usize - 0x61])
+    }
+
+    // i32 arithmetic.
+    0x67 => (pop()? as u32).leading_zeros() as u64,
+    0x68 => (pop()? as u32).trailing_zeros() as u64,
+    0x69 => (pop()? as u32).count_ones() as u64,
+    0x6a..=0x78 => {
+      let (b, a) = (pop()? as u32, pop()? as u32);
+      let zero = || if b == 0 { Err("integer divide by zero".to_string()) } else { Ok(()) };
+      (match opcode {
+        0x6a => a.wrapping_add(b),
+        0x6b => a.wrapping_sub(b),
+        0x6c => a.wrapping_mul(b),
+        0x6d => {
+          zero()?;
+          (a as i32).checked_div(b as i32).ok_or("integer overflow")? as u32
+        }
+        0x6e => {
+          zero()?;
+          a / b
+        }
+        0x6f => {
+          zero()?;
+          (a as i32).wrapping_rem(b as i32) as u32
+        }
+        0x70 => {
+          zero()?;
+          a % b
+        }
+        0x71 => a & b,
+        0x72 => a | b,
+        0x73 => a ^ b,
+        0x74 => a.wrapping_shl(b),
+        0x75 => (a as i32).wrapping_shr(b) as u32,
+        0x76 => a.wrapping_shr(b),
+        0x77 => a.rotate_left(b % 32),
+        _ => a.rotate_right(b % 32),
+      }) as u64
+    }
+
+    // i64 arithmetic.
+    0x79 => pop()?.leading_zeros() as u64,
+    0x7a => pop()?.trailing_zeros() as u64,
+    0x7b => pop()?.count_ones() as u64,
+    0x7c..=0x8a => {
+      let (b, a) = (pop()?, pop()?);
+      let zero = || if b == 0 { Err("integer divide by zero".to_string()) } else { Ok(()) };
+      match opcode {
+        0x7c => a.wrapping_add(b),
+        0x7d => a.wrapping_sub(b),
+        0x7e => a.wrapping_mul(b),
+        0x7f => {
+          zero()?;
+          (a as i64).checked_div(b as i64).ok_or("integer overflow")? as u64
+        }
+        0x80 => {
+          zero()?;
+          a / b
+        }
+        0x81 => {
+          zero()?;
+          (a as i64).wrapping_rem(b as i64) as u64
+        }
+        0x82 => {
+          zero()?;
+          a % b
+        }
+        0x83 => a & b,
+        0x84 => a | b,
+        0x85 => a ^ b,
+        0x86 => a.wrapping_shl(b as u32),
+        0x87 => (a as i64).wrapping_shr(b as u32) as u64,
+        0x88 => a.wrapping_shr(b as u32),
+        0x89 => a.rotate_left((b % 64) as u32),
+        _ => a.rotate_right((b % 64) as u32),
+      }
+    }
+
+    // f32 arithmetic.
+    0x8b..=0x91 => {
+      let a = f32::from_bits(pop()? as u32);
+      let result = match opcode {
+        0x8b => a.abs(),
+        0x8c => -a,
+        0x8d => a.ceil(),
+        0x8e => a.floor(),
+        0x8f => a.trunc(),
+        0x90 => a.round_ties_even(),
+        _ => a.sqrt(),
+      };
+      result.to_bits() as u64
+    }
+    0x92..=0x98 => {
+      let (b, a) = (f32::from_bits(pop()? as u32), f32::from_bits(pop()? as u32));
+      let result = match opcode {
+        0x92 => a + b,
+        0x93 => a - b,
+        0x94 => a * b,
+        0x95 => a / b,
+        0x96 => minimum(a as f64, b as f64) as f32,
+        0x97 => maximum(a as f64, b as f64) as f32,
+        _ => a.copysign(b),
+      };
+      result.to_bits() as u64
+    }
+
+    // f64 arithmetic.
+    0x99..=0x9f => {
+      let a = f64::from_bits(pop()?);
+      let result = match opcode {
+        0x99 => a.abs(),
+        0x9a => -a,
+        0x9b => a.ceil(),
+        0x9c => a.floor(),
+        0x9d => a.trunc(),
+        0x9e => a.round_ties_even(),
+        _ => a.sqrt(),
+      };
+      result.to_bits()
+    }
+    0xa0..=0xa6 => {
+      let (b, a) = (f64::from_bits(pop()?), f64::from_bits(pop()?));
+      let result = match opcode {
+        0xa0 => a + b,
+        0xa1 => a - b,
+        0xa2 => a * b,
+        0xa3 => a / b,
+        0xa4 => minimum(a, b),
+        0xa5 => maximum(a, b),
+        _ => a.copysign(b),
+      };
+      result.to_bits()
+    }
+
+    // Conversions.
+    0xa7 => pop()? as u32 as u64,
+    0xa8..=0xab => {
+      let value = pop()?;
+      let x = if opcode < 0xaa { f32::from_bits(value as u32) as f64 } else { f64::from_bits(value) };
+      if opcode.is_multiple_of(2) {
+        truncate(x, -2147483648.0, 2147483648.0)? as i32 as u32 as u64
+      } else {
+        truncate(x, 0.0, 4294967296.0)? as u32 as u64
+      }
+    }
+    0xac => pop()? as u32 as i32 as i64 as u64,
+    0xad => pop()? as u32 as u64,
+    0xae..=0xb1 => {
+      let value = pop()?;
+      let x = if opcode < 0xb0 { f32::from_bits(value as u32) as f64 } else { f64::from_bits(value) };
+      if opcode.is_multiple_of(2) {
+        truncate(x, -9223372036854775808.0, 9223372036854775808.0)? as i64 as u64
+      } else {
+        truncate(x, 0.0, 18446744073709551616.0)? as u64
+      }
+    }
+    0xb2 => (pop()? as u32 as i32 as f32).to_bits() as u64,
+    0xb3 => (pop()? as u32 as f32).to_bits() as u64,
+    0xb4 => (pop()? as i64 as f32).to_bits() as u64,
+    0xb5 => (pop()? as f32).to_bits() as u64,
+    0xb6 => (f64::from_bits(pop()?) as f32).to_bits() as u64,
+    0xb7 => (pop()? as u32 as i32 as f64).to_bits(),
+    0xb8 => (pop()? as u32 as f64).to_bits(),
+    0xb9 => (pop()? as i64 as f64).to_bits(),
+    0xba => (pop()? as f64).to_bits(),
+    0xbb => (f32::from_bits(pop()? as u32) as f64).to_bits(),
+    // Reinterpretations keep the bits as they are.
+    0xbc..=0xbf => pop()?,
+    0xc0 => pop()? as i8 as i32 as u32 as u64,
+    0xc1 => pop()? as i16 as i32 as u32 as u64,
+    0xc2 => pop()? as i8 as i64 as u64,
+    0xc3 => pop()? as i16 as i64 as u64,
+    _ => pop()? as i32 as i64 as u64,
+  };
+  stack.push(value);
+  Ok(())
+}
+
+// Builds a module exporting `body` as iterate, of the type `params` -> `results`, with
+// locals of the types `locals` after the parameters.
+#[cfg(test)]
+fn test_module(params: &[u8], results: &[u8], locals: &[u8], body: &[u8]) -> Vec<u8> {
+  let leb = |mut n: usize| {
+    let mut bytes = Vec::new();
+    loop {
+      let byte = (n & 0x7f) as u8;
+      n >>= 7;
+      if n == 0 {
+        bytes.push(byte);
+        return bytes;
+      }
+      bytes.push(byte | 0x80);
+    }
+  };
+  let section = |id: u8, contents: Vec<u8>| [vec![id], leb(contents.len()), contents].concat();
+  let function_type = [vec![1, 0x60, params.len() as u8], params.to_vec(), vec![results.len() as u8], results.to_vec()].concat();
+  let locals: Vec<u8> = std::iter::once(locals.len() as u8).chain(locals.iter().flat_map(|&kind| [1, kind])).collect();
+  let code = [locals, body.to_vec(), vec![0x0b]].concat();
+  [
+    b"\0asm\x01\0\0\0".to_vec(),
+    section(1, function_type),
+    section(3, vec![1, 0]),
+    section(7, [vec![1, 7], b"iterate".to_vec(), vec![0, 0]].concat()),
+    section(10, [vec![1], leb(code.len()), code].concat()),
+  ].concat()
+}
+
+#[test]
+fn test_mandelbrot_formula_matches_the_builtin_iteration() {
+  // Locals 0 to 2 are re, im and max_iter; 3 to 5 are zr, zi and a scratch f64, and 6
+  // counts iterations.
+  let mut body = vec![0x02, 0x40, 0x03, 0x40];
+  // Break out once zr² + zi² > 4, or after max_iter iterations.
+  body.extend([0x20, 3, 0x20, 3, 0xa2, 0x20, 4, 0x20, 4, 0xa2, 0xa0, 0x44]);
+  body.extend(4.0f64.to_le_bytes());
+  body.extend([0x64, 0x0d, 1, 0x20, 6, 0x20, 2, 0x4e, 0x0d, 1]);
+  // scratch = zr² - zi² + re; zi = (zr + zr)·zi + im; zr = scratch; count += 1.
+  body.extend([0x20, 3, 0x20, 3, 0xa2, 0x20, 4, 0x20, 4, 0xa2, 0xa1, 0x20, 0, 0xa0, 0x21, 5]);
+  body.extend([0x20, 3, 0x20, 3, 0xa0, 0x20, 4, 0xa2, 0x20, 1, 0xa0, 0x21, 4, 0x20, 5, 0x21, 3]);
+  body.extend([0x20, 6, 0x41, 1, 0x6a, 0x21, 6, 0x0c, 0, 0x0b, 0x0b]);
+  // Return the count and the final z.
+  body.extend([0x20, 6, 0x20, 3, 0x20, 4]);
+  let formula = Formula::new(&test_module(&[F64, F64, I32], &[I32, F64, F64], &[F64, F64, F64, I32], &body)).unwrap();
+  assert_eq!(formula.digest.len(), 64);
+
+  for (re, im) in [(-2.5, 0.0), (0.3, 0.5), (-0.75, 0.1), (-1.2, 0.35), (0.0, 0.0), (-0.1, 0.8), (0.26, 0.0)] {
+    let c = Complex { re, im };
+    assert_eq!(formula.escape_time(c, 200, 2.0), crate::escape_time(c, 200), "at {:?}", c);
+    let (count, z) = formula.iterate(c, 200, 2.0).unwrap();
+    assert_eq!(count == 200, z.unwrap().norm_sqr() <= 4.0);
+  }
+  assert!(!formula.warned.load(Ordering::Relaxed));
+
+  // A formula taking a fourth parameter gets the bailout radius.
+  let radius = Formula::new(&test_module(&[F64, F64, I32, F64], &[I32], &[], &[0x20, 3, 0xaa])).unwrap();
+  assert!(radius.takes_bailout);
+  assert_eq!(radius.iterate(Complex { re: 0.0, im: 0.0 }, 10, 5.0), Ok((5, None)));
+}
+
+#[test]
+fn test_formulas_are_sandboxed() {
+  // An infinite loop runs out of fuel.
+  let spin = test_module(&[F64, F64, I32], &[I32], &[], &[0x03, 0x40, 0x0c, 0, 0x0b, 0x41, 0]);
+  assert!(Formula::new(&spin).err().unwrap().contains("ran too long"));
+
+  // 1 / (trunc(re) - 2) traps at re = 2, and leaves that pixel black.
+  let divide = test_module(&[F64, F64, I32], &[I32], &[], &[0x41, 1, 0x20, 0, 0xaa, 0x41, 2, 0x6b, 0x6d]);
+  let formula = Formula::new(&divide).unwrap();
+  assert_eq!(formula.escape_time(Complex { re: 0.0, im: 0.0 }, 10, 2.0), Some(0));
+  assert_eq!(formula.iterate(Complex { re: 2.0, im: 0.0 }, 10, 2.0).unwrap_err(), "integer divide by zero");
+  assert_eq!(formula.escape_time(Complex { re: 2.0, im: 0.0 }, 10, 2.0), None);
+  assert!(formula.warned.load(Ordering::Relaxed));
+
+  // So does an operand stack that grows without bound.
+  let tall = test_module(&[F64, F64, I32], &[I32], &[], &[0x41, 0].repeat(MAX_STACK_HEIGHT + 1));
+  assert_eq!(Formula::new(&tall).err().unwrap(), "iterate trapped: operand stack exhausted");
+
+  // A call that traps after setting a global leaves no trace in the next: global 0 is
+  // returned, after being set to 1 and trapping when re = 2.
+  let mut stateful = test_module(&[F64, F64, I32], &[I32], &[], &[
+    0x23, 0, 0x20, 0, 0x44, 0, 0, 0, 0, 0, 0, 0, 0x40, 0x61, 0x04, 0x40, 0x41, 1, 0x24, 0, 0x00, 0x0b,
+  ]);
+  let exports = stateful.windows(11).position(|window| window == b"\x07\x0b\x01\x07iterate").unwrap();
+  stateful.splice(exports..exports, [6, 6, 1, I32, 1, 0x41, 0, 0x0b]);
+  let formula = Formula::new(&stateful).unwrap();
+  assert_eq!(formula.iterate(Complex { re: 2.0, im: 0.0 }, 10, 2.0).unwrap_err(), "unreachable executed");
+  assert_eq!(formula.iterate(Complex { re: 0.0, im: 0.0 }, 10, 2.0), Ok((0, None)));
+
+  // Memory outside the module's own traps rather than reaching anything else.
+  let wild = test_module(&[F64, F64, I32], &[I32], &[], &[0x41, 0, 0x28, 2, 0]);
+  assert_eq!(Formula::new(&wild).err().unwrap(), "iterate trapped: out of bounds memory access");
+
+  let mut imports = test_module(&[F64, F64, I32], &[I32], &[], &[0x41, 0]);
+  imports.splice(8..8, [2, 1, 1]);
+  assert_eq!(Formula::new(&imports).err().unwrap(), "formulas can't import anything from the host");
+
+  // Malformed modules are refused when they load, not when they run: a start function
+  // that takes arguments, and locals past a function's own.
+  let mut started = test_module(&[F64, F64, I32], &[I32], &[], &[0x20, 2]);
+  started.extend([8, 1, 0]);
+  assert_eq!(Formula::new(&started).err().unwrap(), "the start function has to take and return nothing");
+  let unknown = test_module(&[F64, F64, I32], &[I32], &[I32], &[0x20, 4]);
+  assert_eq!(Formula::new(&unknown).err().unwrap(), "an instruction names an unknown local");
+  assert!(Formula::new(&test_module(&[F64, F64, I32], &[I32], &[I32], &[0x41, 0, 0x21, 4, 0x20, 3])).is_err());
+  assert!(Formula::new(&test_module(&[F64, F64], &[I32], &[], &[0x41, 0])).err().unwrap().contains("(f64, f64, i32), or (f64, f64, i32, f64)"));
+  assert!(Formula::new(b"\0asm\x02\0\0\0").is_err());
+}
+
+#[test]
+fn test_numeric_semantics() {
+  let run = |opcode: u8, operands: &[u64]| {
+    let mut stack = operands.to_vec();
+    numeric(&mut stack, opcode).map(|()| stack[0])
+  };
+  let i32 = |n: i32| n as u32 as u64;
+  assert_eq!(run(0x6d, &[i32(-7), 2]), Ok(i32(-3)));
+  assert_eq!(run(0x6f, &[i32(-7), 2]), Ok(i32(-1)));
+  assert_eq!(run(0x6d, &[i32(i32::MIN), i32(-1)]).unwrap_err(), "integer overflow");
+  assert_eq!(run(0x6e, &[1, 0]).unwrap_err(), "integer divide by zero");
+  assert_eq!(run(0x74, &[1, 33]), Ok(2));
+  assert_eq!(run(0xaa, &[f64::NAN.to_bits()]).unwrap_err(), "invalid conversion to integer");
+  assert_eq!(run(0xaa, &[(-2147483648.5f64).to_bits()]), Ok(i32(i32::MIN)));
+  assert_eq!(run(0xab, &[(-0.5f64).to_bits()]), Ok(0));
+  assert_eq!(run(0x9e, &[2.5f64.to_bits()]), Ok(2.0f64.to_bits()));
+  assert_eq!(run(0xa4, &[0.0f64.to_bits(), (-0.0f64).to_bits()]), Ok((-0.0f64).to_bits()));
+  assert!(f64::from_bits(run(0xa5, &[f64::NAN.to_bits(), 1.0f64.to_bits()]).unwrap()).is_nan());
+  assert_eq!(run(0xc0, &[0x80]), Ok(0xffff_ff80));
+  assert_eq!(run(0xac, &[i32(-1)]), Ok(u64::MAX));
+}
@@ -0,0 +1,98 @@
+// Golden images
+// Renders a set of small reference scenes with the built program and compares them with
+// the images stored in tests/golden. Direct f64 rendering has to match exactly; paths
+// whose floating-point operations may be reordered by a new backend get a tolerance.
+//
+// After a deliberate change to the output, regenerate the images with
+//
+//   MANDEL_BLESS=1 cargo test --test golden
+//
+// and look at them before committing.
+
+use std::fs::File;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+struct Scene {
+  name: &'static str,
+  arguments: &'static [&'static str],
+  // Largest difference allowed in any pixel, and how many pixels may differ at all.
+  tolerance: u8,
+  max_differing: usize,
+}
+
+const EXACT: (u8, usize) = (0, 0);
+
+const fn scene(name: &'static str, arguments: &'static [&'static str], (tolerance, max_differing): (u8, usize)) -> Scene {
+  Scene { name, arguments, tolerance, max_differing }
+}
+
+const SCENES: [Scene; 8] = [
+  scene("full-set", &["96x72", "-2.2,1.2", "1,-1.2"], EXACT),
+  scene("book-example", &["96x72", "-1.20,0.35", "-1,0.20"], EXACT),
+  scene("seahorse-antialiased", &["96x72", "-0.80,0.20", "-0.70,0.125", "--antialias", "adaptive"], EXACT),
+  scene("progressive", &["96x72", "-1.20,0.35", "-1,0.20", "--progressive"], EXACT),
+  scene("strips", &["96x72", "-1.20,0.35", "-1,0.20", "--strip-rows", "10", "--antialias", "adaptive"], EXACT),
+  scene("rotated", &["96x72", "-1.20,0.35", "-1,0.20", "--rotate", "30"], (1, 16)),
+  scene("single-precision", &["96x72", "-1.20,0.35", "-1,0.20", "--precision", "single"], (1, 16)),
+  scene("perturbation", &["96x72", "-0.7436438870,0.1318259042", "-0.7436438860,0.1318259035", "--perturbation", "--max-iter", "2000"], (2, 32)),
+];
+
+fn golden_dir() -> PathBuf {
+  Path::new(env!("CARGO_MANIFEST_DIR")).join("tests").join("golden")
+}
+
+fn render(scene: &Scene, output: &Path) {
+  let status = Command::new(env!("CARGO_BIN_EXE_Mandel"))
+    .arg(output)
+    .args(scene.arguments)
+    .args(["--force", "--no-progress", "--threads", "3"])
+    .status()
+    .unwrap();
+  assert!(status.success(), "{}: render failed with {}", scene.name, status);
+}
+
+// The size and gray pixels of a PNG.
+fn read_png(path: &Path) -> ((u32, u32), Vec<u8>) {
+  let decoder = png::Decoder::new(File::open(path).unwrap_or_else(|e| panic!("reading {}: {}", path.display(), e)));
+  let mut reader = decoder.read_info().unwrap();
+  let mut pixels = vec![0; reader.output_buffer_size()];
+  let info = reader.next_frame(&mut pixels).unwrap();
+  assert_eq!((info.color_type, info.bit_depth), (png::ColorType::Grayscale, png::BitDepth::Eight), "{}", path.display());
+  pixels.truncate(info.buffer_size());
+  ((info.width, info.height), pixels)
+}
+
+#[test]
+fn test_scenes_match_golden_images() {
+  let output = std::env::temp_dir().join(format!("mandel-golden-{}", std::process::id()));
+  std::fs::create_dir_all(&output).unwrap();
+  let bless = std::env::var_os("MANDEL_BLESS").is_some();
+
+  let mut failures = Vec::new();
+  for scene in &SCENES {
+    let rendered = output.join(format!("{}.png", scene.name));
+    let golden = golden_dir().join(format!("{}.png", scene.name));
+    render(scene, &rendered);
+    if bless {
+      std::fs::copy(&rendered, &golden).unwrap();
+      continue;
+    }
+
+    let (size, pixels) = read_png(&rendered);
+    let (expected_size, expected) = read_png(&golden);
+    if size != expected_size {
+      failures.push(format!("{}: rendered {:?}, expected {:?}", scene.name, size, expected_size));
+      continue;
+    }
+    let differences: Vec<u8> = pixels.iter().zip(&expected).map(|(a, b)| a.abs_diff(*b)).filter(|&d| d > 0).collect();
+    let worst = differences.iter().copied().max().unwrap_or(0);
+    if worst > scene.tolerance || differences.len() > scene.max_differing {
+      failures.push(format!("{}: {} pixel(s) differ, by up to {} (allowed: {}, by up to {}); see {}",
+                            scene.name, differences.len(), worst, scene.max_differing, scene.tolerance, rendered.display()));
+    }
+  }
+
+  assert!(failures.is_empty(), "golden images differ:\n{}", failures.join("\n"));
+  std::fs::remove_dir_all(&output).unwrap();
+}
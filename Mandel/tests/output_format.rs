@@ -0,0 +1,34 @@
+// --output-format json
+// Runs the built program and checks the report it leaves on standard output, which is
+// all a script driving it should need to read.
+
+use std::process::Command;
+
+fn run(arguments: &[&str]) -> (bool, String) {
+  let output = Command::new(env!("CARGO_BIN_EXE_Mandel")).args(arguments).args(["--output-format", "json", "--no-progress"]).output().unwrap();
+  (output.status.success(), String::from_utf8(output.stdout).unwrap())
+}
+
+#[test]
+fn test_json_reports() {
+  let dir = std::env::temp_dir().join(format!("mandel-report-{}", std::process::id()));
+  std::fs::create_dir_all(&dir).unwrap();
+  let file = dir.join("report.png");
+  let name = file.to_str().unwrap();
+
+  let (ok, report) = run(&[name, "40x30", "-1.2,0.35", "-1,0.2", "--print-hash"]);
+  assert!(ok, "{}", report);
+  assert_eq!(report.lines().count(), 1, "{}", report);
+  let expected = format!(r#"{{"status":"ok","command":"render","file":{:?},"size":[40,30],"upper_left":"-1.2,0.35","lower_right":"-1,0.2","#, name);
+  assert!(report.starts_with(&expected), "{}", report);
+  assert!(report.contains(r#","timings":{"render":"#) && report.contains(r#","hash":""#), "{}", report);
+
+  let (ok, report) = run(&[name, "40x30", "-1.2,0.35", "-1,0.2", "--max-iter", "lots"]);
+  assert!(!ok);
+  assert_eq!(report.trim_end(), r#"{"status":"error","code":"usage","message":"--max-iter expects a positive number or 'auto'","argument":"--max-iter"}"#);
+
+  let (ok, report) = run(&[name, "40x30", "-1.2,0.35", "-1,0.2"]);
+  assert!(!ok);
+  assert!(report.starts_with(r#"{"status":"error","code":"failed","message":"'"#) && report.trim_end().ends_with(&format!(r#""argument":{:?}}}"#, name)), "{}", report);
+  std::fs::remove_dir_all(&dir).unwrap();
+}
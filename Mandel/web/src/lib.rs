@@ -0,0 +1,73 @@
+// Mandelbrot plotting in the browser
+// The render core compiled to WebAssembly with no bindings crate: the module exports
+// plain functions over its own linear memory, and index.html's web workers each render
+// a band of rows into an RGBA buffer that the page copies onto a canvas.
+
+#[path = "../../src/palette.rs"]
+pub mod palette;
+
+use palette::PALETTES;
+
+// Reserves `len` bytes in the module's memory for the caller to render into. The
+// buffer lives as long as the module; workers allocate one and reuse it.
+#[no_mangle]
+pub extern "C" fn alloc(len: usize) -> *mut u8 {
+  Box::leak(vec![0u8; len].into_boxed_slice()).as_mut_ptr()
+}
+
+/// Renders rows `top..top + rows` of a `width` x `height` view of the plane from
+/// upper-left (ul_re, ul_im) to lower-right (lr_re, lr_im) as RGBA into `pixels`.
+/// `palette` indexes the palettes of the native build.
+///
+/// # Safety
+/// `pixels` must come from `alloc` with at least width · rows · 4 bytes.
+#[no_mangle]
+#[allow(clippy::too_many_arguments)]
+pub unsafe extern "C" fn render(pixels: *mut u8, width: usize, height: usize, top: usize, rows: usize,
+                                ul_re: f64, ul_im: f64, lr_re: f64, lr_im: f64, limit: usize, palette: usize) {
+  let pixels = std::slice::from_raw_parts_mut(pixels, width * rows * 4);
+  let palette = PALETTES[palette % PALETTES.len()];
+  let pitch = ((lr_re - ul_re) / width as f64, (ul_im - lr_im) / height as f64);
+
+  for (i, rgba) in pixels.chunks_exact_mut(4).enumerate() {
+    let (x, y) = (i % width, top + i / width);
+    let c = (ul_re + x as f64 * pitch.0, ul_im - y as f64 * pitch.1);
+    let [r, g, b] = palette.color(shade(escape_time(c, limit), limit));
+    rgba.copy_from_slice(&[r, g, b, 255]);
+  }
+}
+
+// The native build's escape_time and shade, on plain f64s.
+fn escape_time(c: (f64, f64), limit: usize) -> Option<usize> {
+  let (mut re, mut im, mut re2, mut im2) = (0.0, 0.0, 0.0, 0.0);
+  for i in 0..limit {
+    if re2 + im2 > 4.0 {
+      return Some(i);
+    }
+    im = (re + re) * im + c.1;
+    re = re2 - im2 + c.0;
+    re2 = re * re;
+    im2 = im * im;
+  }
+  None
+}
+
+fn shade(escape: Option<usize>, limit: usize) -> u8 {
+  match escape {
+    None => 0,
+    Some(count) => 255 - (count * 255 / limit) as u8
+  }
+}
+
+#[test]
+fn test_render_fills_rgba_rows() {
+  let pixels = alloc(4 * 2 * 4);
+  // SAFETY: the buffer holds 4 x 2 RGBA pixels.
+  let rgba = unsafe {
+    render(pixels, 4, 4, 2, 2, -2.0, 1.0, 2.0, -1.0, 50, 0);
+    std::slice::from_raw_parts(pixels, 4 * 2 * 4)
+  };
+  // Gray palette, opaque, and the origin at (2, 2) is inside the set.
+  assert!(rgba.chunks(4).all(|p| p[0] == p[1] && p[1] == p[2] && p[3] == 255));
+  assert_eq!(&rgba[8..12], &[0, 0, 0, 255]);
+}
@@ -0,0 +1,23 @@
+// Renders bands of rows with the WebAssembly module, one band per message.
+// Messages carry the view and a band; replies carry the band's RGBA pixels.
+
+const module = WebAssembly.instantiateStreaming(
+  fetch("target/wasm32-unknown-unknown/release/mandel_web.wasm"));
+
+let buffer = { pointer: 0, length: 0 };
+
+onmessage = async (event) => {
+  const { exports } = (await module).instance;
+  const { id, width, height, top, rows, upperLeft, lowerRight, limit, palette } = event.data;
+
+  const length = width * rows * 4;
+  if (length > buffer.length) {
+    buffer = { pointer: exports.alloc(length), length };
+  }
+  exports.render(buffer.pointer, width, height, top, rows,
+                 upperLeft[0], upperLeft[1], lowerRight[0], lowerRight[1], limit, palette);
+
+  // Copy out of wasm memory, which may move when the module next allocates.
+  const pixels = new Uint8ClampedArray(exports.memory.buffer, buffer.pointer, length).slice();
+  postMessage({ id, top, rows, pixels }, [pixels.buffer]);
+};